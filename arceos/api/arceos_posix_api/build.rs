@@ -57,6 +57,7 @@ typedef struct {{
         let allow_vars = [
             "CLOCK_.*",
             "O_.*",
+            "AT_.*",
             "AF_.*",
             "SOCK_.*",
             "IPPROTO_.*",
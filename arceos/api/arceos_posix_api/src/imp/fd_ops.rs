@@ -21,86 +21,257 @@ pub trait FileLike: Send + Sync {
     fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult;
 }
 
-lazy_static::lazy_static! {
-    static ref FD_TABLE: RwLock<FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>> = {
-        let mut fd_table = FlattenObjects::new();
-        fd_table.add_at(0, Arc::new(stdin()) as _).unwrap(); // stdin
-        fd_table.add_at(1, Arc::new(stdout()) as _).unwrap(); // stdout
-        fd_table.add_at(2, Arc::new(stdout()) as _).unwrap(); // stderr
-        RwLock::new(fd_table)
-    };
+#[derive(Clone)]
+struct FdEntry {
+    file: Arc<dyn FileLike>,
+    cloexec: bool,
+}
+
+/// A file descriptor table.
+///
+/// By default, all tasks share a global table. With the `task-fd-table`
+/// feature, the table of the current task is provided by the kernel through
+/// [`FdTableIf`].
+pub struct FdTable {
+    entries: FlattenObjects<FdEntry, AX_FILE_LIMIT>,
+    /// The maximum file descriptor number plus one (`RLIMIT_NOFILE`).
+    limit: usize,
+}
+
+impl FdTable {
+    /// Creates a new table with stdin, stdout and stderr opened.
+    pub fn new() -> Self {
+        Self::with_stdio([Arc::new(stdin()), Arc::new(stdout()), Arc::new(stdout())])
+    }
+
+    /// Creates a new table with `stdio` opened as stdin, stdout and stderr
+    /// (e.g., a terminal).
+    pub fn with_stdio(stdio: [Arc<dyn FileLike>; 3]) -> Self {
+        let mut entries = FlattenObjects::new();
+        for (fd, file) in stdio.into_iter().enumerate() {
+            let entry = FdEntry {
+                file,
+                cloexec: false,
+            };
+            entries.add_at(fd, entry).unwrap();
+        }
+        Self {
+            entries,
+            limit: AX_FILE_LIMIT,
+        }
+    }
+
+    /// Returns the file opened as `fd`.
+    pub fn get(&self, fd: c_int) -> Option<Arc<dyn FileLike>> {
+        self.entries.get(fd as usize).map(|e| e.file.clone())
+    }
+
+    fn add(&mut self, file: Arc<dyn FileLike>, cloexec: bool) -> LinuxResult<c_int> {
+        let fd = self
+            .entries
+            .add(FdEntry { file, cloexec })
+            .ok_or(LinuxError::EMFILE)?;
+        if fd >= self.limit {
+            self.entries.remove(fd);
+            return Err(LinuxError::EMFILE);
+        }
+        Ok(fd as c_int)
+    }
+
+    /// Puts `file` at `fd`, closing the file previously opened as `fd`.
+    fn replace(&mut self, fd: c_int, file: Arc<dyn FileLike>, cloexec: bool) -> LinuxResult {
+        if fd < 0 || fd as usize >= self.limit {
+            return Err(LinuxError::EBADF);
+        }
+        self.entries.remove(fd as usize);
+        self.entries
+            .add_at(fd as usize, FdEntry { file, cloexec })
+            .ok_or(LinuxError::EMFILE)?;
+        Ok(())
+    }
+
+    fn remove(&mut self, fd: c_int) -> Option<Arc<dyn FileLike>> {
+        self.entries.remove(fd as usize).map(|e| e.file)
+    }
+
+    fn entry_mut(&mut self, fd: c_int) -> LinuxResult<&mut FdEntry> {
+        self.entries.get_mut(fd as usize).ok_or(LinuxError::EBADF)
+    }
+
+    /// Closes all file descriptors with the close-on-exec flag set.
+    pub fn close_on_exec(&mut self) {
+        for fd in 0..AX_FILE_LIMIT {
+            if self.entries.get(fd).is_some_and(|e| e.cloexec) {
+                self.entries.remove(fd);
+            }
+        }
+    }
+
+    /// Returns the `RLIMIT_NOFILE` limit.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Sets the `RLIMIT_NOFILE` limit.
+    ///
+    /// Returns `EPERM` if the limit exceeds [`AX_FILE_LIMIT`].
+    pub fn set_limit(&mut self, limit: usize) -> LinuxResult {
+        if limit > AX_FILE_LIMIT {
+            return Err(LinuxError::EPERM);
+        }
+        self.limit = limit;
+        Ok(())
+    }
+}
+
+impl Default for FdTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for FdTable {
+    /// Duplicates all file descriptors (e.g., for `fork`). The opened files
+    /// are shared between the two tables.
+    fn clone(&self) -> Self {
+        let mut entries = FlattenObjects::new();
+        for fd in 0..AX_FILE_LIMIT {
+            if let Some(entry) = self.entries.get(fd) {
+                entries.add_at(fd, entry.clone()).unwrap();
+            }
+        }
+        Self {
+            entries,
+            limit: self.limit,
+        }
+    }
+}
+
+/// The interface for the kernel to provide the file descriptor table of the
+/// current task.
+#[cfg(feature = "task-fd-table")]
+#[crate_interface::def_interface]
+pub trait FdTableIf {
+    /// Returns the file descriptor table of the current task.
+    fn current_fd_table() -> Arc<RwLock<FdTable>>;
+}
+
+/// Returns the file descriptor table of the current task.
+#[cfg(feature = "task-fd-table")]
+pub fn current_fd_table() -> Arc<RwLock<FdTable>> {
+    crate_interface::call_interface!(FdTableIf::current_fd_table())
+}
+
+/// Returns the file descriptor table of the current task.
+#[cfg(not(feature = "task-fd-table"))]
+pub fn current_fd_table() -> Arc<RwLock<FdTable>> {
+    lazy_static::lazy_static! {
+        static ref FD_TABLE: Arc<RwLock<FdTable>> = Arc::new(RwLock::new(FdTable::new()));
+    }
+    FD_TABLE.clone()
+}
+
+/// The interface for the kernel to tell whether a blocking operation of the
+/// current task should be interrupted (e.g., by a signal).
+#[cfg(feature = "task-fd-table")]
+#[crate_interface::def_interface]
+pub trait InterruptIf {
+    /// Whether the current task should stop blocking.
+    fn is_interrupted() -> bool;
+}
+
+/// Whether the current task should stop blocking, see [`InterruptIf`].
+#[cfg(feature = "task-fd-table")]
+pub fn is_interrupted() -> bool {
+    crate_interface::call_interface!(InterruptIf::is_interrupted())
+}
+
+/// Whether the current task should stop blocking, which never happens
+/// without a kernel to tell.
+#[cfg(not(feature = "task-fd-table"))]
+pub fn is_interrupted() -> bool {
+    false
 }
 
 pub fn get_file_like(fd: c_int) -> LinuxResult<Arc<dyn FileLike>> {
-    FD_TABLE
-        .read()
-        .get(fd as usize)
-        .cloned()
-        .ok_or(LinuxError::EBADF)
+    current_fd_table().read().get(fd).ok_or(LinuxError::EBADF)
 }
 
 pub fn add_file_like(f: Arc<dyn FileLike>) -> LinuxResult<c_int> {
-    Ok(FD_TABLE.write().add(f).ok_or(LinuxError::EMFILE)? as c_int)
+    current_fd_table().write().add(f, false)
 }
 
 pub fn close_file_like(fd: c_int) -> LinuxResult {
-    let f = FD_TABLE
+    let f = current_fd_table()
         .write()
-        .remove(fd as usize)
+        .remove(fd)
         .ok_or(LinuxError::EBADF)?;
     drop(f);
     Ok(())
 }
 
+/// Sets the close-on-exec flag of `fd`.
+pub fn set_cloexec(fd: c_int, cloexec: bool) -> LinuxResult {
+    current_fd_table().write().entry_mut(fd)?.cloexec = cloexec;
+    Ok(())
+}
+
 /// Close a file by `fd`.
 pub fn sys_close(fd: c_int) -> c_int {
     debug!("sys_close <= {}", fd);
+    // The global table is shared by all tasks, do not let one of them close
+    // the standard streams for the others.
+    #[cfg(not(feature = "task-fd-table"))]
     if (0..=2).contains(&fd) {
         return 0; // stdin, stdout, stderr
     }
     syscall_body!(sys_close, close_file_like(fd).map(|_| 0))
 }
 
-fn dup_fd(old_fd: c_int) -> LinuxResult<c_int> {
+fn dup_fd(old_fd: c_int, cloexec: bool) -> LinuxResult<c_int> {
     let f = get_file_like(old_fd)?;
-    let new_fd = add_file_like(f)?;
-    Ok(new_fd)
+    current_fd_table().write().add(f, cloexec)
 }
 
 /// Duplicate a file descriptor.
 pub fn sys_dup(old_fd: c_int) -> c_int {
     debug!("sys_dup <= {}", old_fd);
-    syscall_body!(sys_dup, dup_fd(old_fd))
+    syscall_body!(sys_dup, dup_fd(old_fd, false))
 }
 
 /// Duplicate a file descriptor, but it uses the file descriptor number specified in `new_fd`.
 ///
-/// TODO: `dup2` should forcibly close new_fd if it is already opened.
+/// The file previously opened as `new_fd` is closed.
 pub fn sys_dup2(old_fd: c_int, new_fd: c_int) -> c_int {
     debug!("sys_dup2 <= old_fd: {}, new_fd: {}", old_fd, new_fd);
-    syscall_body!(sys_dup2, {
+    syscall_body!(sys_dup2, dup3_fd(old_fd, new_fd, false))
+}
+
+/// Like [`sys_dup2`], but sets the close-on-exec flag of `new_fd` if `flags`
+/// contains `O_CLOEXEC`.
+///
+/// Return `EINVAL` if `old_fd` equals `new_fd`.
+pub fn sys_dup3(old_fd: c_int, new_fd: c_int, flags: c_int) -> c_int {
+    debug!(
+        "sys_dup3 <= old_fd: {}, new_fd: {}, flags: {:#x}",
+        old_fd, new_fd, flags
+    );
+    syscall_body!(sys_dup3, {
         if old_fd == new_fd {
-            let r = sys_fcntl(old_fd, ctypes::F_GETFD as _, 0);
-            if r >= 0 {
-                return Ok(old_fd);
-            } else {
-                return Ok(r);
-            }
+            return Err(LinuxError::EINVAL);
         }
-        if new_fd as usize >= AX_FILE_LIMIT {
-            return Err(LinuxError::EBADF);
-        }
-
-        let f = get_file_like(old_fd)?;
-        FD_TABLE
-            .write()
-            .add_at(new_fd as usize, f)
-            .ok_or(LinuxError::EMFILE)?;
-
-        Ok(new_fd)
+        dup3_fd(old_fd, new_fd, flags as u32 & ctypes::O_CLOEXEC != 0)
     })
 }
 
+fn dup3_fd(old_fd: c_int, new_fd: c_int, cloexec: bool) -> LinuxResult<c_int> {
+    let f = get_file_like(old_fd)?;
+    if old_fd != new_fd {
+        current_fd_table().write().replace(new_fd, f, cloexec)?;
+    }
+    Ok(new_fd)
+}
+
 /// Manipulate file descriptor.
 ///
 /// TODO: `SET/GET` command is ignored, hard-code stdin/stdout
@@ -108,10 +279,25 @@ pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> c_int {
     debug!("sys_fcntl <= fd: {} cmd: {} arg: {}", fd, cmd, arg);
     syscall_body!(sys_fcntl, {
         match cmd as u32 {
-            ctypes::F_DUPFD => dup_fd(fd),
-            ctypes::F_DUPFD_CLOEXEC => {
-                // TODO: Change fd flags
-                dup_fd(fd)
+            ctypes::F_DUPFD => dup_fd(fd, false),
+            ctypes::F_DUPFD_CLOEXEC => dup_fd(fd, true),
+            ctypes::F_GETFD => {
+                let table = current_fd_table();
+                let cloexec = table
+                    .read()
+                    .entries
+                    .get(fd as usize)
+                    .ok_or(LinuxError::EBADF)?
+                    .cloexec;
+                Ok(if cloexec {
+                    ctypes::FD_CLOEXEC as c_int
+                } else {
+                    0
+                })
+            }
+            ctypes::F_SETFD => {
+                set_cloexec(fd, arg & ctypes::FD_CLOEXEC as usize != 0)?;
+                Ok(0)
             }
             ctypes::F_SETFL => {
                 if fd == 0 || fd == 1 || fd == 2 {
@@ -1,8 +1,8 @@
-use alloc::sync::Arc;
-use core::ffi::{c_char, c_int};
+use alloc::{format, string::String, sync::Arc};
+use core::ffi::{c_char, c_int, c_void};
 
 use axerrno::{LinuxError, LinuxResult};
-use axfs::fops::OpenOptions;
+use axfs::fops::{DirEntry, FileAttr, FileNodeRef, OpenOptions};
 use axio::{PollState, SeekFrom};
 use axsync::Mutex;
 
@@ -11,12 +11,14 @@ use crate::{ctypes, utils::char_ptr_to_str};
 
 pub struct File {
     inner: Mutex<axfs::fops::File>,
+    path: String,
 }
 
 impl File {
-    fn new(inner: axfs::fops::File) -> Self {
+    fn new(inner: axfs::fops::File, path: String) -> Self {
         Self {
             inner: Mutex::new(inner),
+            path,
         }
     }
 
@@ -30,33 +32,182 @@ impl File {
             .downcast::<Self>()
             .map_err(|_| LinuxError::EINVAL)
     }
+
+    /// Returns the absolute path of the file.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+/// The interface for the kernel to keep the cached pages of the mapped files
+/// coherent with the accesses to the files through file descriptors.
+///
+/// All paths are absolute.
+#[cfg(feature = "task-fd-table")]
+#[crate_interface::def_interface]
+pub trait PageCacheIf {
+    /// Called after `buf` is read from the file at `path` at `offset`, to
+    /// copy the pages written through the mappings over it.
+    fn file_read(path: &str, offset: usize, buf: &mut [u8]);
+
+    /// Called after `data` is written to the file at `path` at `offset`.
+    fn file_written(path: &str, offset: usize, data: &[u8]);
+
+    /// Called after the file at `path` is truncated to `size` bytes.
+    fn file_truncated(path: &str, size: usize);
+
+    /// Called after the file at `path` is removed.
+    fn file_removed(path: &str);
+
+    /// Called after the file (or the directory) at `old` is renamed to `new`.
+    fn file_renamed(old: &str, new: &str);
+}
+
+/// Reports the accesses to the files to the kernel, see [`PageCacheIf`].
+#[cfg(feature = "task-fd-table")]
+mod page_cache {
+    use super::PageCacheIf;
+
+    pub(super) fn read(path: &str, offset: usize, buf: &mut [u8]) {
+        crate_interface::call_interface!(PageCacheIf::file_read(path, offset, buf))
+    }
+
+    pub(super) fn written(path: &str, offset: usize, data: &[u8]) {
+        crate_interface::call_interface!(PageCacheIf::file_written(path, offset, data))
+    }
+
+    pub(super) fn truncated(path: &str, size: usize) {
+        crate_interface::call_interface!(PageCacheIf::file_truncated(path, size))
+    }
+
+    pub(super) fn removed(path: &str) {
+        crate_interface::call_interface!(PageCacheIf::file_removed(path))
+    }
+
+    pub(super) fn renamed(old: &str, new: &str) {
+        crate_interface::call_interface!(PageCacheIf::file_renamed(old, new))
+    }
+}
+
+/// Files are not mapped without a kernel, so there is nothing to report.
+#[cfg(not(feature = "task-fd-table"))]
+mod page_cache {
+    pub(super) fn read(_path: &str, _offset: usize, _buf: &mut [u8]) {}
+    pub(super) fn written(_path: &str, _offset: usize, _data: &[u8]) {}
+    pub(super) fn truncated(_path: &str, _size: usize) {}
+    pub(super) fn removed(_path: &str) {}
+    pub(super) fn renamed(_old: &str, _new: &str) {}
+}
+
+/// Directory wrapper for `axfs::fops::Directory`.
+pub struct Directory {
+    inner: Mutex<DirectoryInner>,
+    path: String,
+}
+
+struct DirectoryInner {
+    dir: axfs::fops::Directory,
+    /// An entry that has been read from the directory but not yet consumed
+    /// by [`sys_getdents64`].
+    pending: Option<DirEntry>,
+}
+
+impl Directory {
+    fn open(path: &str) -> LinuxResult<Self> {
+        let mut options = OpenOptions::new();
+        options.read(true);
+        let dir = axfs::fops::Directory::open_dir(path, &options)?;
+        Ok(Self {
+            inner: Mutex::new(DirectoryInner { dir, pending: None }),
+            path: axfs::api::canonicalize(path)?,
+        })
+    }
+
+    fn add_to_fd_table(self) -> LinuxResult<c_int> {
+        super::fd_ops::add_file_like(Arc::new(self))
+    }
+
+    fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
+        let f = super::fd_ops::get_file_like(fd)?;
+        f.into_any()
+            .downcast::<Self>()
+            .map_err(|_| LinuxError::ENOTDIR)
+    }
+
+    /// Returns the absolute path of the directory.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl FileLike for Directory {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EISDIR)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EISDIR)
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        let mut options = OpenOptions::new();
+        options.read(true);
+        let file = axfs::fops::File::open(&self.path, &options)?;
+        Ok(attr_to_stat(file.get_attr()?))
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+fn attr_to_stat(metadata: FileAttr) -> ctypes::stat {
+    let ty = metadata.file_type() as u8;
+    let perm = metadata.perm().bits() as u32;
+    let st_mode = ((ty as u32) << 12) | perm;
+    ctypes::stat {
+        st_ino: 1,
+        st_nlink: 1,
+        st_mode,
+        st_uid: 1000,
+        st_gid: 1000,
+        st_size: metadata.size() as _,
+        st_blocks: metadata.blocks() as _,
+        st_blksize: 512,
+        ..Default::default()
+    }
 }
 
 impl FileLike for File {
     fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
-        Ok(self.inner.lock().read(buf)?)
+        let mut inner = self.inner.lock();
+        let n = inner.read(buf)?;
+        let offset = inner.seek(SeekFrom::Current(0))? as usize - n;
+        page_cache::read(&self.path, offset, &mut buf[..n]);
+        Ok(n)
     }
 
     fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
-        Ok(self.inner.lock().write(buf)?)
+        let mut inner = self.inner.lock();
+        let n = inner.write(buf)?;
+        let offset = inner.seek(SeekFrom::Current(0))? as usize - n;
+        page_cache::written(&self.path, offset, &buf[..n]);
+        Ok(n)
     }
 
     fn stat(&self) -> LinuxResult<ctypes::stat> {
-        let metadata = self.inner.lock().get_attr()?;
-        let ty = metadata.file_type() as u8;
-        let perm = metadata.perm().bits() as u32;
-        let st_mode = ((ty as u32) << 12) | perm;
-        Ok(ctypes::stat {
-            st_ino: 1,
-            st_nlink: 1,
-            st_mode,
-            st_uid: 1000,
-            st_gid: 1000,
-            st_size: metadata.size() as _,
-            st_blocks: metadata.blocks() as _,
-            st_blksize: 512,
-            ..Default::default()
-        })
+        Ok(attr_to_stat(self.inner.lock().get_attr()?))
     }
 
     fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
@@ -102,17 +253,185 @@ fn flags_to_options(flags: c_int, _mode: ctypes::mode_t) -> OpenOptions {
     options
 }
 
+fn is_dir(path: &str) -> bool {
+    axfs::api::metadata(path).is_ok_and(|m| m.is_dir())
+}
+
+fn open_path(path: &str, flags: c_int, mode: ctypes::mode_t) -> LinuxResult<c_int> {
+    let fd = if flags as u32 & ctypes::O_DIRECTORY != 0 || is_dir(path) {
+        Directory::open(path)?.add_to_fd_table()?
+    } else {
+        let options = flags_to_options(flags, mode);
+        let file = axfs::fops::File::open(path, &options)?;
+        let path = axfs::api::canonicalize(path)?;
+        if flags as u32 & ctypes::O_TRUNC != 0 {
+            page_cache::truncated(&path, 0);
+        }
+        File::new(file, path).add_to_fd_table()?
+    };
+    if flags as u32 & ctypes::O_CLOEXEC != 0 {
+        super::fd_ops::set_cloexec(fd, true)?;
+    }
+    Ok(fd)
+}
+
+/// Resolve `path` relative to the directory indicated by `dirfd`.
+///
+/// Absolute paths and `AT_FDCWD` leave `path` as it is, so that it is
+/// resolved relative to the current directory.
+fn resolve_at(dirfd: c_int, path: &str) -> LinuxResult<String> {
+    if path.starts_with('/') || dirfd == ctypes::AT_FDCWD {
+        return Ok(path.into());
+    }
+    let dir = Directory::from_fd(dirfd)?;
+    Ok(format!("{}/{}", dir.path().trim_end_matches('/'), path))
+}
+
 /// Open a file by `filename` and insert it into the file descriptor table.
 ///
+/// Directories are opened as well (always with `O_DIRECTORY`, or when the
+/// path refers to a directory), so that they can be listed by
+/// [`sys_getdents64`].
+///
 /// Return its index in the file table (`fd`). Return `EMFILE` if it already
 /// has the maximum number of files open.
 pub fn sys_open(filename: *const c_char, flags: c_int, mode: ctypes::mode_t) -> c_int {
     let filename = char_ptr_to_str(filename);
     debug!("sys_open <= {:?} {:#o} {:#o}", filename, flags, mode);
-    syscall_body!(sys_open, {
-        let options = flags_to_options(flags, mode);
-        let file = axfs::fops::File::open(filename?, &options)?;
-        File::new(file).add_to_fd_table()
+    syscall_body!(sys_open, open_path(filename?, flags, mode))
+}
+
+/// Open a file by `filename` relative to the directory `dirfd`, and insert it
+/// into the file descriptor table.
+///
+/// It behaves like [`sys_open`] if `dirfd` is `AT_FDCWD` or `filename` is
+/// absolute.
+pub fn sys_openat(
+    dirfd: c_int,
+    filename: *const c_char,
+    flags: c_int,
+    mode: ctypes::mode_t,
+) -> c_int {
+    let filename = char_ptr_to_str(filename);
+    debug!(
+        "sys_openat <= {} {:?} {:#o} {:#o}",
+        dirfd, filename, flags, mode
+    );
+    syscall_body!(sys_openat, {
+        let path = resolve_at(dirfd, filename?)?;
+        open_path(&path, flags, mode)
+    })
+}
+
+/// Read data from the file indicated by `fd` at the given `offset`.
+///
+/// The file cursor is not changed. Return the read size if success.
+pub fn sys_pread(
+    fd: c_int,
+    buf: *mut c_void,
+    count: usize,
+    offset: ctypes::off_t,
+) -> ctypes::ssize_t {
+    debug!(
+        "sys_pread <= {} {:#x} {} {}",
+        fd, buf as usize, count, offset
+    );
+    syscall_body!(sys_pread, {
+        if buf.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        if offset < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let dst = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, count) };
+        let file = File::from_fd(fd)?;
+        let n = file.inner.lock().read_at(offset as u64, dst)?;
+        page_cache::read(&file.path, offset as usize, &mut dst[..n]);
+        Ok(n as ctypes::ssize_t)
+    })
+}
+
+/// Returns the VFS node and the absolute path of the file indicated by `fd`,
+/// e.g., to map the file into memory.
+///
+/// The file must be opened for reading, and also for writing if `writable` is
+/// `true`. Otherwise `EACCES` is returned.
+pub fn get_file_node(fd: c_int, writable: bool) -> LinuxResult<(FileNodeRef, String)> {
+    // Only regular files can be mapped.
+    let file = get_file_like(fd)?
+        .into_any()
+        .downcast::<File>()
+        .map_err(|_| LinuxError::ENODEV)?;
+    let node = file
+        .inner
+        .lock()
+        .node(writable)
+        .map_err(|_| LinuxError::EACCES)?
+        .clone();
+    Ok((node, file.path.clone()))
+}
+
+/// `struct linux_dirent64`, followed by a NUL-terminated name.
+#[repr(C)]
+struct LinuxDirent64 {
+    d_ino: u64,
+    d_off: i64,
+    d_reclen: u16,
+    d_type: u8,
+    d_name: [u8; 0],
+}
+
+/// Read directory entries of `fd` into `buf` in the `linux_dirent64` format.
+///
+/// Return the number of bytes written, or 0 at the end of the directory.
+pub unsafe fn sys_getdents64(fd: c_int, buf: *mut c_void, len: usize) -> ctypes::ssize_t {
+    debug!("sys_getdents64 <= {} {:#x} {}", fd, buf as usize, len);
+    syscall_body!(sys_getdents64, {
+        if buf.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let dir = Directory::from_fd(fd)?;
+        let mut inner = dir.inner.lock();
+        let name_offset = core::mem::offset_of!(LinuxDirent64, d_name);
+        let mut written = 0;
+        loop {
+            let entry = match inner.pending.take() {
+                Some(entry) => entry,
+                None => {
+                    let mut entries = [DirEntry::default()];
+                    if inner.dir.read_dir(&mut entries)? == 0 {
+                        break;
+                    }
+                    let [entry] = entries;
+                    entry
+                }
+            };
+            let name = entry.name_as_bytes();
+            // header + name + NUL, aligned to 8 bytes
+            let reclen = (name_offset + name.len() + 1 + 7) & !7;
+            if written + reclen > len {
+                inner.pending = Some(entry);
+                if written == 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                break;
+            }
+            unsafe {
+                let dirent = (buf as *mut u8).add(written);
+                core::ptr::write_bytes(dirent, 0, reclen);
+                (dirent as *mut LinuxDirent64).write_unaligned(LinuxDirent64 {
+                    d_ino: 1,
+                    d_off: (written + reclen) as i64,
+                    d_reclen: reclen as u16,
+                    // `VfsNodeType` values are the same as `DT_*` values.
+                    d_type: entry.entry_type() as u8,
+                    d_name: [],
+                });
+                core::ptr::copy_nonoverlapping(name.as_ptr(), dirent.add(name_offset), name.len());
+            }
+            written += reclen;
+        }
+        Ok(written as ctypes::ssize_t)
     })
 }
 
@@ -145,8 +464,9 @@ pub unsafe fn sys_stat(path: *const c_char, buf: *mut ctypes::stat) -> c_int {
         }
         let mut options = OpenOptions::new();
         options.read(true);
-        let file = axfs::fops::File::open(path?, &options)?;
-        let st = File::new(file).stat()?;
+        let path = path?;
+        let file = axfs::fops::File::open(path, &options)?;
+        let st = File::new(file, axfs::api::canonicalize(path)?).stat()?;
         unsafe { *buf = st };
         Ok(0)
     })
@@ -167,6 +487,40 @@ pub unsafe fn sys_fstat(fd: c_int, buf: *mut ctypes::stat) -> c_int {
     })
 }
 
+/// Get the file metadata by `path` relative to the directory `dirfd` and
+/// write into `buf`.
+///
+/// If `flags` contains `AT_EMPTY_PATH` and `path` is empty, get the metadata
+/// of `dirfd` itself. Return 0 if success.
+pub unsafe fn sys_fstatat(
+    dirfd: c_int,
+    path: *const c_char,
+    buf: *mut ctypes::stat,
+    flags: c_int,
+) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!(
+        "sys_fstatat <= {} {:?} {:#x} {:#x}",
+        dirfd, path, buf as usize, flags
+    );
+    syscall_body!(sys_fstatat, {
+        if buf.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let path = path?;
+        if path.is_empty() && flags as u32 & ctypes::AT_EMPTY_PATH != 0 {
+            unsafe { *buf = get_file_like(dirfd)?.stat()? };
+            return Ok(0);
+        }
+        let path = resolve_at(dirfd, path)?;
+        let mut options = OpenOptions::new();
+        options.read(true);
+        let file = axfs::fops::File::open(&path, &options)?;
+        unsafe { *buf = attr_to_stat(file.get_attr()?) };
+        Ok(0)
+    })
+}
+
 /// Get the metadata of the symbolic link and write into `buf`.
 ///
 /// Return 0 if success.
@@ -211,7 +565,74 @@ pub fn sys_rename(old: *const c_char, new: *const c_char) -> c_int {
         let old_path = char_ptr_to_str(old)?;
         let new_path = char_ptr_to_str(new)?;
         debug!("sys_rename <= old: {:?}, new: {:?}", old_path, new_path);
+        let (old_abs, new_abs) = (
+            axfs::api::canonicalize(old_path)?,
+            axfs::api::canonicalize(new_path)?,
+        );
         axfs::api::rename(old_path, new_path)?;
+        page_cache::renamed(&old_abs, &new_abs);
         Ok(0)
     })
 }
+
+/// Create a directory by `path` relative to the directory `dirfd`.
+///
+/// `mode` is ignored. Return 0 if success.
+pub fn sys_mkdirat(dirfd: c_int, path: *const c_char, mode: ctypes::mode_t) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!("sys_mkdirat <= {} {:?} {:#o}", dirfd, path, mode);
+    syscall_body!(sys_mkdirat, {
+        axfs::api::create_dir(&resolve_at(dirfd, path?)?)?;
+        Ok(0)
+    })
+}
+
+/// Remove a file (or a directory if `flags` contains `AT_REMOVEDIR`) by
+/// `path` relative to the directory `dirfd`.
+///
+/// Return 0 if success.
+pub fn sys_unlinkat(dirfd: c_int, path: *const c_char, flags: c_int) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!("sys_unlinkat <= {} {:?} {:#x}", dirfd, path, flags);
+    syscall_body!(sys_unlinkat, {
+        let path = resolve_at(dirfd, path?)?;
+        if flags as u32 & ctypes::AT_REMOVEDIR != 0 {
+            axfs::api::remove_dir(&path)?;
+        } else {
+            axfs::api::remove_file(&path)?;
+            page_cache::removed(&axfs::api::canonicalize(&path)?);
+        }
+        Ok(0)
+    })
+}
+
+/// Check whether the file by `path` relative to the directory `dirfd` can be
+/// accessed.
+///
+/// There are no permissions, so only the existence of the file is checked,
+/// whatever `mode` and `flags` are. Return 0 if success.
+pub fn sys_faccessat(dirfd: c_int, path: *const c_char, mode: c_int, flags: c_int) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!(
+        "sys_faccessat <= {} {:?} {:#o} {:#x}",
+        dirfd, path, mode, flags
+    );
+    syscall_body!(sys_faccessat, {
+        axfs::api::metadata(&resolve_at(dirfd, path?)?)?;
+        Ok(0)
+    })
+}
+
+/// Read the target of the symbolic link by `path` relative to the directory
+/// `dirfd`.
+///
+/// There are no symbolic links, so it fails with `EINVAL` if the file
+/// exists.
+pub fn sys_readlinkat(dirfd: c_int, path: *const c_char) -> c_int {
+    let path = char_ptr_to_str(path);
+    debug!("sys_readlinkat <= {} {:?}", dirfd, path);
+    syscall_body!(sys_readlinkat, {
+        axfs::api::metadata(&resolve_at(dirfd, path?)?)?;
+        Err::<c_int, _>(LinuxError::EINVAL)
+    })
+}
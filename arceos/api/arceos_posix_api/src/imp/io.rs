@@ -70,3 +70,28 @@ pub unsafe fn sys_writev(fd: c_int, iov: *const ctypes::iovec, iocnt: c_int) ->
         Ok(ret)
     })
 }
+
+/// Read a vector.
+pub unsafe fn sys_readv(fd: c_int, iov: *const ctypes::iovec, iocnt: c_int) -> ctypes::ssize_t {
+    debug!("sys_readv <= fd: {}", fd);
+    syscall_body!(sys_readv, {
+        if !(0..=1024).contains(&iocnt) {
+            return Err(LinuxError::EINVAL);
+        }
+
+        let iovs = unsafe { core::slice::from_raw_parts(iov, iocnt as usize) };
+        let mut ret = 0;
+        for iov in iovs.iter() {
+            let n = sys_read(fd, iov.iov_base, iov.iov_len);
+            if n < 0 {
+                return if ret > 0 { Ok(ret) } else { Ok(n) };
+            }
+            ret += n;
+            if (n as usize) < iov.iov_len {
+                break;
+            }
+        }
+
+        Ok(ret)
+    })
+}
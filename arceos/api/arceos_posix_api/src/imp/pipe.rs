@@ -5,7 +5,7 @@ use axerrno::{LinuxError, LinuxResult};
 use axio::PollState;
 use axsync::Mutex;
 
-use super::fd_ops::{add_file_like, close_file_like, FileLike};
+use super::fd_ops::{add_file_like, close_file_like, is_interrupted, FileLike};
 use crate::ctypes;
 
 #[derive(Copy, Clone, PartialEq)]
@@ -121,6 +121,13 @@ impl FileLike for Pipe {
                     return Ok(read_size);
                 }
                 drop(ring_buffer);
+                if is_interrupted() {
+                    return if read_size > 0 {
+                        Ok(read_size)
+                    } else {
+                        Err(LinuxError::EINTR)
+                    };
+                }
                 // Data not ready, wait for write end
                 crate::sys_sched_yield(); // TODO: use synconize primitive
                 continue;
@@ -146,6 +153,13 @@ impl FileLike for Pipe {
             let loop_write = ring_buffer.available_write();
             if loop_write == 0 {
                 drop(ring_buffer);
+                if is_interrupted() {
+                    return if write_size > 0 {
+                        Ok(write_size)
+                    } else {
+                        Err(LinuxError::EINTR)
+                    };
+                }
                 // Buffer is full, wait for read end to consume
                 crate::sys_sched_yield(); // TODO: use synconize primitive
                 continue;
@@ -24,7 +24,7 @@ pub unsafe fn sys_getrlimit(resource: c_int, rlimits: *mut ctypes::rlimit) -> c_
             },
             #[cfg(feature = "fd")]
             ctypes::RLIMIT_NOFILE => unsafe {
-                (*rlimits).rlim_cur = super::fd_ops::AX_FILE_LIMIT as _;
+                (*rlimits).rlim_cur = super::fd_ops::current_fd_table().read().limit() as _;
                 (*rlimits).rlim_max = super::fd_ops::AX_FILE_LIMIT as _;
             },
             _ => {}
@@ -35,6 +35,8 @@ pub unsafe fn sys_getrlimit(resource: c_int, rlimits: *mut ctypes::rlimit) -> c_
 
 /// Set resource limitations
 ///
+/// Only `RLIMIT_NOFILE` takes effect, other limits are silently ignored.
+///
 /// TODO: support more resource types
 pub unsafe fn sys_setrlimit(resource: c_int, rlimits: *mut crate::ctypes::rlimit) -> c_int {
     debug!("sys_setrlimit <= {} {:#x}", resource, rlimits as usize);
@@ -45,7 +47,15 @@ pub unsafe fn sys_setrlimit(resource: c_int, rlimits: *mut crate::ctypes::rlimit
             crate::ctypes::RLIMIT_NOFILE => {}
             _ => return Err(LinuxError::EINVAL),
         }
-        // Currently do not support set resources
+        if rlimits.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        #[cfg(feature = "fd")]
+        if resource as u32 == crate::ctypes::RLIMIT_NOFILE {
+            let limit = unsafe { (*rlimits).rlim_cur };
+            let limit = usize::try_from(limit).unwrap_or(usize::MAX);
+            super::fd_ops::current_fd_table().write().set_limit(limit)?;
+        }
         Ok(0)
     })
 }
@@ -30,16 +30,27 @@ pub mod config {
 #[allow(dead_code, non_snake_case, non_camel_case_types, non_upper_case_globals, clippy::upper_case_acronyms, missing_docs)]
 pub mod ctypes;
 
-pub use imp::io::{sys_read, sys_write, sys_writev};
+pub use imp::io::{sys_read, sys_readv, sys_write, sys_writev};
 pub use imp::resources::{sys_getrlimit, sys_setrlimit};
 pub use imp::sys::sys_sysconf;
 pub use imp::task::{sys_exit, sys_getpid, sys_sched_yield};
 pub use imp::time::{sys_clock_gettime, sys_nanosleep};
 
 #[cfg(feature = "fd")]
-pub use imp::fd_ops::{sys_close, sys_dup, sys_dup2, sys_fcntl, get_file_like};
+pub use imp::fd_ops::{
+    add_file_like, current_fd_table, get_file_like, set_cloexec, sys_close, sys_dup, sys_dup2,
+    sys_dup3, sys_fcntl, FdTable, FileLike,
+};
+#[cfg(feature = "task-fd-table")]
+pub use imp::fd_ops::{FdTableIf, InterruptIf};
+#[cfg(all(feature = "fs", feature = "task-fd-table"))]
+pub use imp::fs::PageCacheIf;
 #[cfg(feature = "fs")]
-pub use imp::fs::{sys_fstat, sys_getcwd, sys_lseek, sys_lstat, sys_open, sys_rename, sys_stat};
+pub use imp::fs::{
+    get_file_node, sys_faccessat, sys_fstat, sys_fstatat, sys_getcwd, sys_getdents64, sys_lseek,
+    sys_lstat, sys_mkdirat, sys_open, sys_openat, sys_pread, sys_readlinkat, sys_rename, sys_stat,
+    sys_unlinkat,
+};
 #[cfg(feature = "select")]
 pub use imp::io_mpx::sys_select;
 #[cfg(feature = "epoll")]
@@ -0,0 +1,509 @@
+//! ELF core dumps of user processes.
+//!
+//! When a process is terminated by a signal whose default action is to dump
+//! core (e.g., `SIGSEGV` on an unhandled page fault, or `SIGABRT`), its
+//! memory and the registers of the thread that received the signal are
+//! written to an `ET_CORE` ELF file, so that the crash can be inspected
+//! offline with `gdb <program> <core>` on the host.
+//!
+//! The file starts with a `PT_NOTE` segment holding the following notes:
+//!
+//! - `NT_PRSTATUS`: the signal, the IDs and the general registers (from the
+//!   [`TrapFrame`] of the thread);
+//! - `NT_PRPSINFO`: the name and the arguments of the program;
+//! - `NT_AUXV`: the auxiliary vector of the program (see
+//!   [`Process::auxv`](crate::process::Process::auxv)).
+//!
+//! Then there is a `PT_LOAD` segment for each mapped area of the address
+//! space. The contents of shared file mappings and inaccessible areas are
+//! not dumped. Pages that have never been accessed are dumped as zeros,
+//! while swapped-out pages and pages of private file mappings are read in.
+//! Floating-point registers and other threads are not dumped. The other
+//! threads exit before the dump is written, so the memory does not change in
+//! the meantime.
+//!
+//! The path of the file is given by a pattern, see [`set_core_pattern`].
+
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write as _;
+use core::mem::size_of;
+
+use axerrno::AxResult;
+use axfs::api::File;
+use axhal::arch::TrapFrame;
+use axhal::mem::{MemoryAddr, VirtAddr, PAGE_SIZE_4K};
+use axhal::paging::MappingFlags;
+use axio::Write;
+use axmm::{AddrSpace, AreaInfo};
+use axtask::TaskExtRef;
+use spin::Mutex;
+
+use crate::process::{Pid, Process};
+
+#[cfg(target_arch = "x86_64")]
+mod arch {
+    use axhal::arch::TrapFrame;
+
+    pub const ELF_MACHINE: u16 = 62; // EM_X86_64
+
+    /// `struct user_regs_struct` of x86_64.
+    pub type GRegs = [u64; 27];
+
+    pub fn gregs(tf: &TrapFrame) -> GRegs {
+        [
+            tf.r15,
+            tf.r14,
+            tf.r13,
+            tf.r12,
+            tf.rbp,
+            tf.rbx,
+            tf.r11,
+            tf.r10,
+            tf.r9,
+            tf.r8,
+            tf.rax,
+            tf.rcx,
+            tf.rdx,
+            tf.rsi,
+            tf.rdi,
+            u64::MAX, // orig_rax, not in a syscall
+            tf.rip,
+            tf.cs,
+            tf.rflags,
+            tf.rsp,
+            tf.ss,
+            // User tasks keep their TLS pointer in `FS_BASE` in the kernel.
+            axhal::arch::read_thread_pointer() as u64,
+            0, // gs_base
+            0, // ds
+            0, // es
+            0, // fs
+            0, // gs
+        ]
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    use axhal::arch::TrapFrame;
+
+    pub const ELF_MACHINE: u16 = 183; // EM_AARCH64
+
+    /// `struct user_pt_regs` of aarch64: `x0`-`x30`, `sp`, `pc`, `pstate`.
+    pub type GRegs = [u64; 34];
+
+    pub fn gregs(tf: &TrapFrame) -> GRegs {
+        let mut regs = [0; 34];
+        regs[..31].copy_from_slice(&tf.r);
+        regs[31] = tf.usp;
+        regs[32] = tf.elr;
+        regs[33] = tf.spsr;
+        regs
+    }
+}
+
+#[cfg(target_arch = "riscv64")]
+mod arch {
+    use axhal::arch::TrapFrame;
+
+    pub const ELF_MACHINE: u16 = 243; // EM_RISCV
+
+    /// `struct user_regs_struct` of riscv64: `pc`, then `x1`-`x31`.
+    pub type GRegs = [u64; 32];
+
+    pub fn gregs(tf: &TrapFrame) -> GRegs {
+        let r = &tf.regs;
+        [
+            tf.sepc, r.ra, r.sp, r.gp, r.tp, r.t0, r.t1, r.t2, r.s0, r.s1, r.a0, r.a1, r.a2, r.a3,
+            r.a4, r.a5, r.a6, r.a7, r.s2, r.s3, r.s4, r.s5, r.s6, r.s7, r.s8, r.s9, r.s10, r.s11,
+            r.t3, r.t4, r.t5, r.t6,
+        ]
+        .map(|reg| reg as u64)
+    }
+}
+
+const ET_CORE: u16 = 4;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+const NT_AUXV: u32 = 6;
+
+/// `Elf64_Ehdr`.
+#[repr(C)]
+struct ElfHeader {
+    ident: [u8; 16],
+    ty: u16,
+    machine: u16,
+    version: u32,
+    entry: u64,
+    phoff: u64,
+    shoff: u64,
+    flags: u32,
+    ehsize: u16,
+    phentsize: u16,
+    phnum: u16,
+    shentsize: u16,
+    shnum: u16,
+    shstrndx: u16,
+}
+
+/// `Elf64_Phdr`.
+#[repr(C)]
+struct ProgramHeader {
+    ty: u32,
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    paddr: u64,
+    filesz: u64,
+    memsz: u64,
+    align: u64,
+}
+
+/// `struct elf_prstatus` of the Linux ABI.
+///
+/// The padding is explicit, as the structures are written as raw bytes.
+#[repr(C)]
+struct PrStatus {
+    /// `si_signo`, `si_code` and `si_errno`.
+    info: [i32; 3],
+    cursig: i16,
+    _pad0: i16,
+    sigpend: u64,
+    sighold: u64,
+    pid: i32,
+    ppid: i32,
+    pgrp: i32,
+    sid: i32,
+    /// User, system, and children's user and system times.
+    times: [[i64; 2]; 4],
+    reg: arch::GRegs,
+    fpvalid: i32,
+    _pad1: i32,
+}
+
+/// `struct elf_prpsinfo` of the Linux ABI.
+#[repr(C)]
+struct PrPsInfo {
+    state: i8,
+    sname: u8,
+    zomb: i8,
+    nice: i8,
+    _pad0: [u8; 4],
+    flag: u64,
+    uid: u32,
+    gid: u32,
+    pid: i32,
+    ppid: i32,
+    pgrp: i32,
+    sid: i32,
+    fname: [u8; 16],
+    psargs: [u8; 80],
+}
+
+/// Where the core dumps are written, if no pattern is set.
+const DEFAULT_CORE_PATTERN: &str = "core.%p";
+
+static CORE_PATTERN: Mutex<Cow<'static, str>> = Mutex::new(Cow::Borrowed(DEFAULT_CORE_PATTERN));
+
+/// Sets the path of the core dumps to `pattern`, where `%p` is replaced by
+/// the PID, `%s` by the signal number, `%e` by the name of the program, `%t`
+/// by the time of the dump in seconds, and `%%` by `%`.
+///
+/// A relative path is relative to the current directory. Core dumps are
+/// disabled if `pattern` is empty. The default pattern is `core.%p`.
+pub fn set_core_pattern(pattern: &str) {
+    *CORE_PATTERN.lock() = Cow::Owned(pattern.into());
+}
+
+/// Expands the core pattern for the process `process` killed by `signo`.
+fn core_path(pattern: &str, process: &Process, signo: usize) -> String {
+    let now = axhal::time::wall_time().as_secs();
+    expand_core_pattern(pattern, process.pid(), signo, &process.comm(), now)
+}
+
+/// Expands the specifiers in `pattern` (see [`set_core_pattern`]), for the
+/// process `pid` running `comm` killed by `signo` at `time` in seconds.
+fn expand_core_pattern(pattern: &str, pid: Pid, signo: usize, comm: &str, time: u64) -> String {
+    let mut path = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            path.push(c);
+            continue;
+        }
+        let _ = match chars.next() {
+            Some('p') => write!(path, "{}", pid),
+            Some('s') => write!(path, "{}", signo),
+            Some('e') => write!(path, "{}", comm),
+            Some('t') => write!(path, "{}", time),
+            Some('%') => write!(path, "%"),
+            // Unknown specifiers are dropped, as on Linux.
+            _ => Ok(()),
+        };
+    }
+    path
+}
+
+/// Returns the raw bytes of `value`, which must have no implicit padding.
+fn as_bytes<T>(value: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+}
+
+/// Appends a note with the name `CORE` to `notes`.
+fn push_note(notes: &mut Vec<u8>, ty: u32, desc: &[u8]) {
+    const NAME: &[u8] = b"CORE\0\0\0\0";
+    notes.extend_from_slice(&5u32.to_le_bytes());
+    notes.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    notes.extend_from_slice(&ty.to_le_bytes());
+    notes.extend_from_slice(NAME);
+    notes.extend_from_slice(desc);
+    notes.resize(notes.len().next_multiple_of(4), 0);
+}
+
+/// Builds the notes of the current thread, which received `signo` with the
+/// user context `tf`.
+fn build_notes(tf: &TrapFrame, signo: usize) -> Vec<u8> {
+    let curr = axtask::current();
+    let ext = curr.task_ext();
+    let process = &ext.process;
+    let ppid = process.parent().map_or(0, |parent| parent.pid());
+
+    let status = PrStatus {
+        info: [signo as i32, 0, 0],
+        cursig: signo as i16,
+        _pad0: 0,
+        sigpend: ext.signals.pending().0 | process.signals.pending().0,
+        sighold: ext.signals.blocked().0,
+        pid: curr.id().as_u64() as i32,
+        ppid: ppid as i32,
+        pgrp: process.pgid() as i32,
+        sid: process.sid() as i32,
+        times: [[0; 2]; 4],
+        reg: arch::gregs(tf),
+        fpvalid: 0,
+        _pad1: 0,
+    };
+
+    let mut info = PrPsInfo {
+        state: 0,
+        sname: b'R',
+        zomb: 0,
+        nice: 0,
+        _pad0: [0; 4],
+        flag: 0,
+        uid: 0,
+        gid: 0,
+        pid: process.pid() as i32,
+        ppid: ppid as i32,
+        pgrp: process.pgid() as i32,
+        sid: process.sid() as i32,
+        fname: [0; 16],
+        psargs: [0; 80],
+    };
+    // Both are NUL-terminated, and truncated if too long.
+    let comm = process.comm();
+    let len = comm.len().min(info.fname.len() - 1);
+    info.fname[..len].copy_from_slice(&comm.as_bytes()[..len]);
+    let args = process.cmdline.lock().join(" ");
+    let len = args.len().min(info.psargs.len() - 1);
+    info.psargs[..len].copy_from_slice(&args.as_bytes()[..len]);
+
+    let auxv = process
+        .auxv()
+        .iter()
+        .flat_map(|word| word.to_ne_bytes())
+        .collect::<Vec<_>>();
+
+    let mut notes = Vec::new();
+    push_note(&mut notes, NT_PRSTATUS, as_bytes(&status));
+    push_note(&mut notes, NT_PRPSINFO, as_bytes(&info));
+    push_note(&mut notes, NT_AUXV, &auxv);
+    notes
+}
+
+/// Whether the contents of `area` are dumped.
+fn is_dumped(area: &AreaInfo) -> bool {
+    let shared_file = area.shared && area.file_offset.is_some();
+    area.flags.contains(MappingFlags::READ) && !shared_file
+}
+
+/// Reads the page at `vaddr` into `buf`.
+///
+/// A page that is swapped out or in a file mapping is faulted in. Other
+/// pages that are not populated are read as zeros.
+fn read_page(aspace: &mut AddrSpace, vaddr: VirtAddr, file_backed: bool, buf: &mut [u8]) {
+    // Swapped-out pages are swapped in by `read`.
+    if aspace.read(vaddr, buf).is_ok() {
+        return;
+    }
+    if file_backed
+        && aspace.handle_page_fault(vaddr, MappingFlags::READ)
+        && aspace.read(vaddr, buf).is_ok()
+    {
+        return;
+    }
+    buf.fill(0);
+}
+
+/// Writes the core dump of the current process, killed by `signo` with the
+/// user context `tf`, to `path`.
+fn write_core(path: &str, tf: &TrapFrame, signo: usize) -> AxResult {
+    let notes = build_notes(tf, signo);
+    let curr = axtask::current();
+    let mut aspace = curr.task_ext().aspace.lock();
+    let areas = aspace.areas().collect::<Vec<_>>();
+
+    let phnum = areas.len() + 1;
+    let notes_offset = size_of::<ElfHeader>() + size_of::<ProgramHeader>() * phnum;
+    let mut ident = [0; 16];
+    // ELFMAG, ELFCLASS64, ELFDATA2LSB, EV_CURRENT
+    ident[..7].copy_from_slice(b"\x7fELF\x02\x01\x01");
+    let header = ElfHeader {
+        ident,
+        ty: ET_CORE,
+        machine: arch::ELF_MACHINE,
+        version: 1,
+        entry: 0,
+        phoff: size_of::<ElfHeader>() as u64,
+        shoff: 0,
+        flags: 0,
+        ehsize: size_of::<ElfHeader>() as u16,
+        phentsize: size_of::<ProgramHeader>() as u16,
+        phnum: phnum as u16,
+        shentsize: 0,
+        shnum: 0,
+        shstrndx: 0,
+    };
+
+    let mut headers = Vec::with_capacity(notes_offset);
+    headers.extend_from_slice(as_bytes(&header));
+    let note = ProgramHeader {
+        ty: PT_NOTE,
+        flags: 0,
+        offset: notes_offset as u64,
+        vaddr: 0,
+        paddr: 0,
+        filesz: notes.len() as u64,
+        memsz: 0,
+        align: 4,
+    };
+    headers.extend_from_slice(as_bytes(&note));
+    // The contents of the areas start from the next page.
+    let data_offset = (notes_offset + notes.len()).align_up_4k();
+    let mut offset = data_offset;
+    for area in &areas {
+        let size = area.end - area.start;
+        let filesz = if is_dumped(area) { size } else { 0 };
+        let mut flags = 0;
+        for (mapping_flag, flag) in [
+            (MappingFlags::READ, PF_R),
+            (MappingFlags::WRITE, PF_W),
+            (MappingFlags::EXECUTE, PF_X),
+        ] {
+            if area.flags.contains(mapping_flag) {
+                flags |= flag;
+            }
+        }
+        let load = ProgramHeader {
+            ty: PT_LOAD,
+            flags,
+            offset: offset as u64,
+            vaddr: area.start.as_usize() as u64,
+            paddr: 0,
+            filesz: filesz as u64,
+            memsz: size as u64,
+            align: PAGE_SIZE_4K as u64,
+        };
+        headers.extend_from_slice(as_bytes(&load));
+        offset += filesz;
+    }
+
+    let mut file = File::create(path)?;
+    file.write_all(&headers)?;
+    file.write_all(&notes)?;
+    file.write_all(&alloc::vec![0; data_offset - notes_offset - notes.len()])?;
+    let mut page = alloc::vec![0; PAGE_SIZE_4K];
+    for area in areas.iter().filter(|area| is_dumped(area)) {
+        let file_backed = area.file_offset.is_some();
+        for vaddr in (area.start.as_usize()..area.end.as_usize()).step_by(PAGE_SIZE_4K) {
+            read_page(&mut aspace, VirtAddr::from(vaddr), file_backed, &mut page);
+            file.write_all(&page)?;
+        }
+    }
+    Ok(())
+}
+
+/// Dumps the core of the current process, which is terminated by `signo`
+/// with the user context `tf` of the current thread.
+///
+/// Returns whether the core has been dumped.
+pub(crate) fn dump_current(tf: &TrapFrame, signo: usize) -> bool {
+    let pattern = CORE_PATTERN.lock().clone();
+    if pattern.is_empty() {
+        return false;
+    }
+    let curr = axtask::current();
+    let path = core_path(&pattern, &curr.task_ext().process, signo);
+    match write_core(&path, tf, signo) {
+        Ok(()) => {
+            info!("{}: core dumped to {:?}", curr.id_name(), path);
+            true
+        }
+        Err(err) => {
+            warn!(
+                "{}: cannot dump core to {:?}: {:?}",
+                curr.id_name(),
+                path,
+                err
+            );
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(pattern: &str) -> String {
+        expand_core_pattern(pattern, 42, 11, "prog", 1700000000)
+    }
+
+    #[test]
+    fn test_default_pattern() {
+        assert_eq!(expand(DEFAULT_CORE_PATTERN), "core.42");
+    }
+
+    #[test]
+    fn test_specifiers() {
+        assert_eq!(expand("/tmp/%e-%p-%s-%t"), "/tmp/prog-42-11-1700000000");
+        assert_eq!(expand("%p%p"), "4242");
+    }
+
+    #[test]
+    fn test_percent() {
+        assert_eq!(expand("100%%.%p"), "100%.42");
+        assert_eq!(expand("%%p"), "%p");
+    }
+
+    #[test]
+    fn test_unknown_specifiers() {
+        // Unknown specifiers and a trailing `%` are dropped.
+        assert_eq!(expand("core.%x%p"), "core.42");
+        assert_eq!(expand("core%"), "core");
+    }
+
+    #[test]
+    fn test_no_specifiers() {
+        assert_eq!(expand(""), "");
+        assert_eq!(expand("core"), "core");
+    }
+}
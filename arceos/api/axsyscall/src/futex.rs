@@ -0,0 +1,270 @@
+//! Fast user-space locking (futex).
+//!
+//! Futexes in shared mappings are keyed by the physical address of the futex
+//! word, so that they are found by the same key from different processes.
+//! Other futexes are keyed by the address space and the virtual address, so
+//! that a copy-on-write page that is copied between a wait and a wake does
+//! not change the key. Only read access to the futex word is needed.
+//!
+//! The futex word is faulted in when its key is resolved, before the global
+//! [`FUTEX_TABLE`] is locked, and its frame is pinned until the word is
+//! dropped. With the table locked, the word is only read through that frame,
+//! which never faults, so no futex operation waits for the page faults (and
+//! the swap-ins) of another task. The frame is not freed even if the page is
+//! unmapped or swapped out in the meantime.
+//!
+//! Each waiter sleeps on its own [`WaitQueue`], so that it can be woken up
+//! selectively (by bitset) and moved to another futex by `FUTEX_REQUEUE`. A
+//! wait is interrupted by signals (see [`signal::wait_interruptible`]).
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use core::time::Duration;
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::mem::{virt_to_phys, MemoryAddr, PhysAddr, VirtAddr};
+use axhal::paging::MappingFlags;
+use axsync::Mutex;
+use axtask::{TaskExtRef, WaitQueue};
+
+use crate::mm::with_user_u32;
+use crate::signal;
+
+/// The bitset that matches any waiter.
+pub const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
+/// The key of a futex: the address space (or 0 for shared mappings) and the
+/// virtual (or physical for shared mappings) address of the futex word.
+type FutexKey = (usize, usize);
+
+struct Waiter {
+    /// The key of the futex that the waiter is queued on, which is only
+    /// accessed with [`FUTEX_TABLE`] locked.
+    key: spin::Mutex<FutexKey>,
+    bitset: u32,
+    woken: AtomicBool,
+    wq: WaitQueue,
+}
+
+impl Waiter {
+    fn wake(&self) {
+        self.woken.store(true, Ordering::Release);
+        self.wq.notify_one(false);
+    }
+}
+
+/// Waiters of all futexes, indexed by the keys.
+static FUTEX_TABLE: Mutex<BTreeMap<FutexKey, VecDeque<Arc<Waiter>>>> = Mutex::new(BTreeMap::new());
+
+/// A futex word of the current task, resolved by [`resolve_futex`].
+struct FutexWord {
+    key: FutexKey,
+    /// The kernel address of the word, in the linear mapping of its frame.
+    kaddr: usize,
+    /// The frame of the word, pinned by [`axmm::pin_frame`].
+    frame: PhysAddr,
+}
+
+impl FutexWord {
+    /// Reads the word through its pinned frame, which never faults.
+    ///
+    /// If the page is replaced after the key is resolved (e.g., copied on
+    /// write), the value is read from the previous frame, as if it were read
+    /// before that.
+    fn value(&self) -> u32 {
+        unsafe { &*(self.kaddr as *const AtomicU32) }.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for FutexWord {
+    fn drop(&mut self) {
+        axmm::unpin_frame(self.frame);
+    }
+}
+
+/// Resolves the futex word at `uaddr` of the current task, faulting it in if
+/// needed.
+fn resolve_futex(uaddr: usize) -> LinuxResult<FutexWord> {
+    if uaddr % 4 != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let curr = axtask::current();
+    let aspace = &curr.task_ext().aspace;
+    let vaddr = VirtAddr::from(uaddr);
+    let shared = aspace
+        .lock()
+        .areas()
+        .any(|area| area.shared && area.start <= vaddr && vaddr < area.end);
+    // The word is accessed to check that it is readable, and its frame is
+    // pinned while the address space is still locked.
+    let kaddr = with_user_u32(uaddr, MappingFlags::READ, |word| {
+        let kaddr = word as *const _ as usize;
+        axmm::pin_frame(virt_to_phys(VirtAddr::from(kaddr)).align_down_4k());
+        kaddr
+    })
+    .ok_or(LinuxError::EFAULT)?;
+    let paddr = virt_to_phys(VirtAddr::from(kaddr));
+    let key = if shared {
+        (0, paddr.as_usize())
+    } else {
+        (Arc::as_ptr(aspace) as usize, uaddr)
+    };
+    Ok(FutexWord {
+        key,
+        kaddr,
+        frame: paddr.align_down_4k(),
+    })
+}
+
+/// Removes and returns at most `max` waiters of `key` that match `bitset`.
+fn take_waiters(
+    table: &mut BTreeMap<FutexKey, VecDeque<Arc<Waiter>>>,
+    key: FutexKey,
+    max: usize,
+    bitset: u32,
+) -> VecDeque<Arc<Waiter>> {
+    let mut taken = VecDeque::new();
+    if let Some(waiters) = table.get_mut(&key) {
+        waiters.retain(|waiter| {
+            if taken.len() < max && waiter.bitset & bitset != 0 {
+                taken.push_back(waiter.clone());
+                false
+            } else {
+                true
+            }
+        });
+        if waiters.is_empty() {
+            table.remove(&key);
+        }
+    }
+    taken
+}
+
+/// Waits on the futex at `uaddr` if it still contains `val`.
+///
+/// Returns `EAGAIN` if the value has changed, `ETIMEDOUT` if the waiter is
+/// not woken up within `timeout`, or `EINTR` if it is interrupted by a
+/// signal.
+pub(crate) fn futex_wait(
+    uaddr: usize,
+    val: u32,
+    timeout: Option<Duration>,
+    bitset: u32,
+) -> LinuxResult {
+    if bitset == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let word = resolve_futex(uaddr)?;
+    let key = word.key;
+    let waiter = Arc::new(Waiter {
+        key: spin::Mutex::new(key),
+        bitset,
+        woken: AtomicBool::new(false),
+        wq: WaitQueue::new(),
+    });
+    {
+        // Checking the value under the lock, so that a wake-up after the
+        // value is changed is not missed.
+        let mut table = FUTEX_TABLE.lock();
+        if word.value() != val {
+            return Err(LinuxError::EAGAIN);
+        }
+        table.entry(key).or_default().push_back(waiter.clone());
+    }
+    drop(word);
+
+    let err = match signal::wait_interruptible(&waiter.wq, timeout, || {
+        waiter.woken.load(Ordering::Acquire)
+    }) {
+        Ok(true) => return Ok(()),
+        Ok(false) => LinuxError::ETIMEDOUT,
+        Err(err) => err,
+    };
+
+    // Timed out or interrupted, but it may be woken up in the meantime.
+    let mut table = FUTEX_TABLE.lock();
+    let key = *waiter.key.lock();
+    if let Some(waiters) = table.get_mut(&key) {
+        if let Some(pos) = waiters.iter().position(|w| Arc::ptr_eq(w, &waiter)) {
+            waiters.remove(pos);
+            if waiters.is_empty() {
+                table.remove(&key);
+            }
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Wakes up at most `max` waiters of the futex at `uaddr` that match
+/// `bitset`.
+///
+/// Returns the number of woken waiters.
+pub(crate) fn futex_wake(uaddr: usize, max: usize, bitset: u32) -> LinuxResult<usize> {
+    if bitset == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let key = resolve_futex(uaddr)?.key;
+    let waiters = take_waiters(&mut FUTEX_TABLE.lock(), key, max, bitset);
+    for waiter in &waiters {
+        waiter.wake();
+    }
+    Ok(waiters.len())
+}
+
+/// Wakes up at most `max_wake` waiters of the futex at `uaddr`, and moves at
+/// most `max_requeue` of the remaining waiters to the futex at `uaddr2`.
+///
+/// If `cmp` is given, returns `EAGAIN` if the futex at `uaddr` does not
+/// contain it.
+///
+/// Returns the number of woken waiters and the number of requeued waiters.
+pub(crate) fn futex_requeue(
+    uaddr: usize,
+    max_wake: usize,
+    uaddr2: usize,
+    max_requeue: usize,
+    cmp: Option<u32>,
+) -> LinuxResult<(usize, usize)> {
+    let word = resolve_futex(uaddr)?;
+    let key = word.key;
+    let key2 = resolve_futex(uaddr2)?.key;
+    let mut table = FUTEX_TABLE.lock();
+    if cmp.is_some_and(|val| word.value() != val) {
+        return Err(LinuxError::EAGAIN);
+    }
+    let woken = take_waiters(&mut table, key, max_wake, FUTEX_BITSET_MATCH_ANY);
+    let requeued = take_waiters(&mut table, key, max_requeue, FUTEX_BITSET_MATCH_ANY);
+    let num_requeued = requeued.len();
+    if num_requeued > 0 {
+        for waiter in &requeued {
+            *waiter.key.lock() = key2;
+        }
+        table.entry(key2).or_default().extend(requeued);
+    }
+    drop(table);
+
+    for waiter in &woken {
+        waiter.wake();
+    }
+    Ok((woken.len(), num_requeued))
+}
+
+/// Clears the `clear_child_tid` word of the current task and wakes up a
+/// waiter on it, as the task is exiting.
+///
+/// It is how `pthread_join` gets notified.
+pub(crate) fn clear_child_tid() {
+    let curr = axtask::current();
+    let addr = curr.task_ext().clear_child_tid() as usize;
+    if addr == 0
+        || with_user_u32(addr, MappingFlags::WRITE, |word| {
+            word.store(0, Ordering::SeqCst)
+        })
+        .is_none()
+    {
+        return;
+    }
+    let _ = futex_wake(addr, 1, FUTEX_BITSET_MATCH_ANY);
+}
@@ -0,0 +1,86 @@
+use core::ffi::c_int;
+
+use arceos_posix_api::{self as api, ctypes};
+use axerrno::{LinuxError, LinuxResult};
+
+use crate::ptr::UserPtr;
+use crate::shm::Memfd;
+
+pub(crate) fn sys_close(fd: c_int) -> isize {
+    api::sys_close(fd) as _
+}
+
+pub(crate) fn sys_dup(old_fd: c_int) -> isize {
+    api::sys_dup(old_fd) as _
+}
+
+pub(crate) fn sys_dup2(old_fd: c_int, new_fd: c_int) -> isize {
+    api::sys_dup2(old_fd, new_fd) as _
+}
+
+pub(crate) fn sys_dup3(old_fd: c_int, new_fd: c_int, flags: c_int) -> isize {
+    api::sys_dup3(old_fd, new_fd, flags) as _
+}
+
+pub(crate) fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> isize {
+    api::sys_fcntl(fd, cmd, arg) as _
+}
+
+/// Truncates or extends the file of `fd` to `length` bytes.
+///
+/// Memfd files are supported, and regular files as well with the `fs`
+/// feature.
+pub(crate) fn sys_ftruncate(fd: c_int, length: i64) -> isize {
+    syscall_body!(sys_ftruncate, {
+        if length < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        if let Ok(memfd) = Memfd::from_fd(fd) {
+            memfd.pages().resize(length as usize);
+            return Ok(0);
+        }
+        #[cfg(feature = "fs")]
+        {
+            let (node, path) = api::get_file_node(fd, true)?;
+            node.truncate(length as u64)?;
+            axmm::truncate_file_cache(&path, length as usize);
+            Ok(0)
+        }
+        #[cfg(not(feature = "fs"))]
+        Err(LinuxError::EINVAL)
+    })
+}
+
+/// Creates a pipe, and writes the file descriptors of its read end and its
+/// write end to `fds`.
+///
+/// Only `O_CLOEXEC` and `O_NONBLOCK` are supported in `flags`.
+pub(crate) fn sys_pipe2(fds: UserPtr<[c_int; 2]>, flags: c_int) -> isize {
+    syscall_body!(sys_pipe2, {
+        let flags = flags as u32;
+        if flags & !(ctypes::O_CLOEXEC | ctypes::O_NONBLOCK) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let mut kfds = [0; 2];
+        let ret = api::sys_pipe(&mut kfds);
+        if ret < 0 {
+            return Ok(ret as isize);
+        }
+        let res: LinuxResult = kfds.iter().try_for_each(|&fd| {
+            if flags & ctypes::O_CLOEXEC != 0 {
+                api::set_cloexec(fd, true)?;
+            }
+            if flags & ctypes::O_NONBLOCK != 0 {
+                api::get_file_like(fd)?.set_nonblocking(true)?;
+            }
+            Ok(())
+        });
+        if let Err(err) = res.and_then(|_| fds.write(kfds)) {
+            for fd in kfds {
+                api::sys_close(fd);
+            }
+            return Err(err);
+        }
+        Ok(0)
+    })
+}
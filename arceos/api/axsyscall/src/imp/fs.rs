@@ -0,0 +1,242 @@
+use alloc::vec;
+use core::ffi::{c_char, c_int};
+
+use arceos_posix_api::{self as api, ctypes};
+use axerrno::LinuxError;
+
+use super::io::MAX_IO_SIZE;
+use crate::ptr::{UserCStr, UserPtr, UserSlice};
+
+/// The `struct stat` of the generic Linux ABI (riscv64 and aarch64).
+///
+/// It differs from [`ctypes::stat`], which follows the layout of `axlibc`.
+#[cfg(not(target_arch = "x86_64"))]
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct Kstat {
+    pub st_dev: u64,
+    pub st_ino: u64,
+    pub st_mode: u32,
+    pub st_nlink: u32,
+    pub st_uid: u32,
+    pub st_gid: u32,
+    pub st_rdev: u64,
+    pub __pad1: u64,
+    pub st_size: i64,
+    pub st_blksize: i32,
+    pub __pad2: i32,
+    pub st_blocks: i64,
+    pub st_atime_sec: i64,
+    pub st_atime_nsec: i64,
+    pub st_mtime_sec: i64,
+    pub st_mtime_nsec: i64,
+    pub st_ctime_sec: i64,
+    pub st_ctime_nsec: i64,
+    pub __unused: [u32; 2],
+}
+
+/// The `struct stat` of the x86_64 Linux ABI.
+///
+/// It differs from [`ctypes::stat`], which follows the layout of `axlibc`.
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct Kstat {
+    pub st_dev: u64,
+    pub st_ino: u64,
+    pub st_nlink: u64,
+    pub st_mode: u32,
+    pub st_uid: u32,
+    pub st_gid: u32,
+    pub __pad0: u32,
+    pub st_rdev: u64,
+    pub st_size: i64,
+    pub st_blksize: i64,
+    pub st_blocks: i64,
+    pub st_atime_sec: i64,
+    pub st_atime_nsec: i64,
+    pub st_mtime_sec: i64,
+    pub st_mtime_nsec: i64,
+    pub st_ctime_sec: i64,
+    pub st_ctime_nsec: i64,
+    pub __unused: [i64; 3],
+}
+
+impl From<ctypes::stat> for Kstat {
+    fn from(st: ctypes::stat) -> Self {
+        Self {
+            st_dev: st.st_dev as _,
+            st_ino: st.st_ino as _,
+            st_mode: st.st_mode as _,
+            st_nlink: st.st_nlink as _,
+            st_uid: st.st_uid as _,
+            st_gid: st.st_gid as _,
+            st_rdev: st.st_rdev as _,
+            st_size: st.st_size as _,
+            st_blksize: st.st_blksize as _,
+            st_blocks: st.st_blocks as _,
+            st_atime_sec: st.st_atime.tv_sec as _,
+            st_atime_nsec: st.st_atime.tv_nsec as _,
+            st_mtime_sec: st.st_mtime.tv_sec as _,
+            st_mtime_nsec: st.st_mtime.tv_nsec as _,
+            st_ctime_sec: st.st_ctime.tv_sec as _,
+            st_ctime_nsec: st.st_ctime.tv_nsec as _,
+            ..Default::default()
+        }
+    }
+}
+
+/// Opens a file relative to the directory `dirfd`.
+///
+/// The terminal devices (`/dev/tty`, `/dev/ptmx`, ...) are opened by
+/// [`crate::tty::open_device`], the other files by the filesystem.
+pub(crate) fn sys_openat(
+    dirfd: c_int,
+    path: UserCStr,
+    flags: c_int,
+    mode: ctypes::mode_t,
+) -> isize {
+    syscall_body!(sys_openat, {
+        if let Some(file) = crate::tty::open_device(&path.read()?) {
+            let file = file?;
+            if flags as u32 & ctypes::O_NONBLOCK != 0 {
+                file.set_nonblocking(true)?;
+            }
+            let fd = api::add_file_like(file)?;
+            if flags as u32 & ctypes::O_CLOEXEC != 0 {
+                api::set_cloexec(fd, true)?;
+            }
+            return Ok(fd);
+        }
+        let path = path.read_cstring()?;
+        Ok(api::sys_openat(dirfd, path.as_ptr(), flags, mode))
+    })
+}
+
+pub(crate) fn sys_lseek(fd: c_int, offset: ctypes::off_t, whence: c_int) -> isize {
+    api::sys_lseek(fd, offset, whence) as _
+}
+
+pub(crate) fn sys_pread64(fd: c_int, buf: UserSlice<u8>, offset: ctypes::off_t) -> isize {
+    syscall_body!(sys_pread64, {
+        let mut kbuf = vec![0; buf.len().min(MAX_IO_SIZE)];
+        let ret = api::sys_pread(fd, kbuf.as_mut_ptr() as _, kbuf.len(), offset);
+        if ret > 0 {
+            buf.write(&kbuf[..ret as usize])?;
+        }
+        Ok(ret)
+    })
+}
+
+pub(crate) fn sys_fstat(fd: c_int, statbuf: UserPtr<Kstat>) -> isize {
+    syscall_body!(sys_fstat, {
+        let mut st = ctypes::stat::default();
+        let ret = unsafe { api::sys_fstat(fd, &mut st) };
+        if ret == 0 {
+            statbuf.write(st.into())?;
+        }
+        Ok(ret)
+    })
+}
+
+pub(crate) fn sys_newfstatat(
+    dirfd: c_int,
+    path: UserCStr,
+    statbuf: UserPtr<Kstat>,
+    flags: c_int,
+) -> isize {
+    syscall_body!(sys_newfstatat, {
+        let path = path.read_cstring()?;
+        let mut st = ctypes::stat::default();
+        let ret = unsafe { api::sys_fstatat(dirfd, path.as_ptr(), &mut st, flags) };
+        if ret == 0 {
+            statbuf.write(st.into())?;
+        }
+        Ok(ret)
+    })
+}
+
+pub(crate) fn sys_getdents64(fd: c_int, buf: UserSlice<u8>) -> isize {
+    syscall_body!(sys_getdents64, {
+        let mut kbuf = vec![0; buf.len().min(MAX_IO_SIZE)];
+        let ret = unsafe { api::sys_getdents64(fd, kbuf.as_mut_ptr() as _, kbuf.len()) };
+        if ret > 0 {
+            buf.write(&kbuf[..ret as usize])?;
+        }
+        Ok(ret)
+    })
+}
+
+pub(crate) fn sys_getcwd(buf: UserSlice<c_char>) -> isize {
+    syscall_body!(sys_getcwd, {
+        if buf.is_empty() {
+            return Err(LinuxError::ERANGE);
+        }
+        let mut kbuf = vec![0; buf.len().min(MAX_IO_SIZE)];
+        let ret = api::sys_getcwd(kbuf.as_mut_ptr(), kbuf.len()) as isize;
+        if ret < 0 {
+            return Ok(ret);
+        }
+        // The Linux syscall returns the length of the path (including NUL).
+        let len = unsafe { core::ffi::CStr::from_ptr(kbuf.as_ptr()) }
+            .to_bytes()
+            .len();
+        buf.write(&kbuf[..len + 1])?;
+        Ok(len as isize + 1)
+    })
+}
+
+pub(crate) fn sys_mkdirat(dirfd: c_int, path: UserCStr, mode: ctypes::mode_t) -> isize {
+    syscall_body!(sys_mkdirat, {
+        let path = path.read_cstring()?;
+        Ok(api::sys_mkdirat(dirfd, path.as_ptr(), mode))
+    })
+}
+
+pub(crate) fn sys_unlinkat(dirfd: c_int, path: UserCStr, flags: c_int) -> isize {
+    syscall_body!(sys_unlinkat, {
+        let path = path.read_cstring()?;
+        Ok(api::sys_unlinkat(dirfd, path.as_ptr(), flags))
+    })
+}
+
+pub(crate) fn sys_faccessat(dirfd: c_int, path: UserCStr, mode: c_int, flags: c_int) -> isize {
+    syscall_body!(sys_faccessat, {
+        let path = path.read_cstring()?;
+        Ok(api::sys_faccessat(dirfd, path.as_ptr(), mode, flags))
+    })
+}
+
+/// Reads the target of a symbolic link, which always fails as there are no
+/// symbolic links (see [`api::sys_readlinkat`]).
+pub(crate) fn sys_readlinkat(dirfd: c_int, path: UserCStr, _buf: UserSlice<u8>) -> isize {
+    syscall_body!(sys_readlinkat, {
+        let path = path.read_cstring()?;
+        Ok(api::sys_readlinkat(dirfd, path.as_ptr()))
+    })
+}
+
+/// Enables swapping to the regular file at `path`, whose whole size is used
+/// for the swap slots. `flags` (the priority and the discard policy) is
+/// ignored.
+#[cfg(feature = "swap")]
+pub(crate) fn sys_swapon(path: UserCStr, flags: c_int) -> isize {
+    use axfs::fops::{File, OpenOptions};
+    use axfs_vfs::VfsNodeOps;
+
+    syscall_body!(sys_swapon, {
+        let path = path.read()?;
+        info!("sys_swapon <= {:?} {:#x}", path, flags);
+        let mut opts = OpenOptions::new();
+        opts.read(true);
+        opts.write(true);
+        let node = File::open(&path, &opts)?.node(true)?.clone();
+        let attr = node.get_attr()?;
+        if !attr.is_file() || (attr.size() as usize) < axhal::mem::PAGE_SIZE_4K {
+            return Err(LinuxError::EINVAL);
+        }
+        let file = axmm::swap::SwapFile::new(node, attr.size() as usize)?;
+        axmm::swap::swap_on(file).map_err(|_| LinuxError::EBUSY)?;
+        Ok(0)
+    })
+}
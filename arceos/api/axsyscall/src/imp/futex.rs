@@ -0,0 +1,84 @@
+use core::time::Duration;
+
+use arceos_posix_api::ctypes;
+use axerrno::{LinuxError, LinuxResult};
+
+use crate::futex::{futex_requeue, futex_wait, futex_wake, FUTEX_BITSET_MATCH_ANY};
+use crate::ptr::UserPtr;
+
+const FUTEX_WAIT: u32 = 0;
+const FUTEX_WAKE: u32 = 1;
+const FUTEX_REQUEUE: u32 = 3;
+const FUTEX_CMP_REQUEUE: u32 = 4;
+const FUTEX_WAIT_BITSET: u32 = 9;
+const FUTEX_WAKE_BITSET: u32 = 10;
+
+/// The futex is only used by the calling process. Futexes are keyed by the
+/// kind of their mappings instead, so the flag makes no difference.
+const FUTEX_PRIVATE_FLAG: u32 = 128;
+/// The absolute timeout of `FUTEX_WAIT_BITSET` is measured by
+/// `CLOCK_REALTIME` instead of `CLOCK_MONOTONIC`.
+const FUTEX_CLOCK_REALTIME: u32 = 256;
+
+fn read_timespec(ts: UserPtr<ctypes::timespec>) -> LinuxResult<Option<Duration>> {
+    if ts.is_null() {
+        return Ok(None);
+    }
+    let ts = ts.read()?;
+    if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(Some(ts.into()))
+}
+
+/// Fast user-space locking.
+///
+/// Supports `FUTEX_WAIT`, `FUTEX_WAKE`, `FUTEX_REQUEUE`, `FUTEX_CMP_REQUEUE`,
+/// `FUTEX_WAIT_BITSET` and `FUTEX_WAKE_BITSET`. For the requeue operations,
+/// `timeout` is the maximum number of waiters to requeue.
+pub(crate) fn sys_futex(
+    uaddr: usize,
+    futex_op: u32,
+    val: u32,
+    timeout: usize,
+    uaddr2: usize,
+    val3: u32,
+) -> isize {
+    syscall_body!(sys_futex, {
+        let realtime = futex_op & FUTEX_CLOCK_REALTIME != 0;
+        match futex_op & !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME) {
+            FUTEX_WAIT => {
+                let timeout = read_timespec(timeout.into())?;
+                futex_wait(uaddr, val, timeout, FUTEX_BITSET_MATCH_ANY)?;
+                Ok(0)
+            }
+            FUTEX_WAIT_BITSET => {
+                // The timeout is an absolute time.
+                let deadline = read_timespec(timeout.into())?;
+                let now = if realtime {
+                    axhal::time::wall_time()
+                } else {
+                    axhal::time::monotonic_time()
+                };
+                let timeout = deadline.map(|deadline| deadline.saturating_sub(now));
+                futex_wait(uaddr, val, timeout, val3)?;
+                Ok(0)
+            }
+            FUTEX_WAKE => Ok(futex_wake(uaddr, val as usize, FUTEX_BITSET_MATCH_ANY)? as isize),
+            FUTEX_WAKE_BITSET => Ok(futex_wake(uaddr, val as usize, val3)? as isize),
+            FUTEX_REQUEUE => {
+                let (woken, _) = futex_requeue(uaddr, val as usize, uaddr2, timeout, None)?;
+                Ok(woken as isize)
+            }
+            FUTEX_CMP_REQUEUE => {
+                let (woken, requeued) =
+                    futex_requeue(uaddr, val as usize, uaddr2, timeout, Some(val3))?;
+                Ok((woken + requeued) as isize)
+            }
+            _ => {
+                warn!("sys_futex: unsupported operation {:#x}", futex_op);
+                Err(LinuxError::ENOSYS)
+            }
+        }
+    })
+}
@@ -0,0 +1,246 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ffi::c_int;
+
+use arceos_posix_api::{self as api, ctypes};
+use axerrno::{LinuxError, LinuxResult};
+
+use crate::ptr::{UserPtr, UserSlice};
+
+/// The maximum number of bytes read or written at a time, through a buffer
+/// in the kernel.
+///
+/// A larger read returns fewer bytes, which is allowed by POSIX, while a
+/// larger write is done in several parts.
+pub(super) const MAX_IO_SIZE: usize = 0x10000;
+
+/// The maximum number of buffers in an I/O vector.
+const IOV_MAX: c_int = 1024;
+
+/// Reads from `fd` into the user buffer `buf`, returns the number of bytes
+/// read, or the negative error code of the read.
+fn read_to_user(fd: c_int, buf: UserSlice<u8>) -> LinuxResult<isize> {
+    let mut kbuf = vec![0; buf.len().min(MAX_IO_SIZE)];
+    let ret = api::sys_read(fd, kbuf.as_mut_ptr() as _, kbuf.len());
+    if ret > 0 {
+        buf.write(&kbuf[..ret as usize])?;
+    }
+    Ok(ret)
+}
+
+/// Writes the user buffer `buf` to `fd`, returns the number of bytes
+/// written, or the negative error code of the write if nothing is written.
+fn write_from_user(fd: c_int, buf: UserSlice<u8>) -> LinuxResult<isize> {
+    let mut written = 0;
+    loop {
+        let kbuf = buf.sub(written, MAX_IO_SIZE).read()?;
+        let ret = api::sys_write(fd, kbuf.as_ptr() as _, kbuf.len());
+        if ret < 0 {
+            return Ok(if written > 0 { written as isize } else { ret });
+        }
+        written += ret as usize;
+        if (ret as usize) < kbuf.len() || written == buf.len() {
+            return Ok(written as isize);
+        }
+    }
+}
+
+pub(crate) fn sys_read(fd: c_int, buf: UserSlice<u8>) -> isize {
+    syscall_body!(sys_read, read_to_user(fd, buf))
+}
+
+pub(crate) fn sys_write(fd: c_int, buf: UserSlice<u8>) -> isize {
+    syscall_body!(sys_write, write_from_user(fd, buf))
+}
+
+/// Copies the I/O vector of `iocnt` buffers from user space.
+fn read_iovec(iov: UserPtr<ctypes::iovec>, iocnt: c_int) -> LinuxResult<Vec<ctypes::iovec>> {
+    if !(0..=IOV_MAX).contains(&iocnt) {
+        return Err(LinuxError::EINVAL);
+    }
+    UserSlice::new(iov.address(), iocnt as usize).read()
+}
+
+/// Reads from `fd` into the buffers of the I/O vector one by one, until a
+/// buffer is not filled.
+pub(crate) fn sys_readv(fd: c_int, iov: UserPtr<ctypes::iovec>, iocnt: c_int) -> isize {
+    syscall_body!(sys_readv, {
+        let iovs = read_iovec(iov, iocnt)?;
+        let mut total = 0;
+        for iov in iovs {
+            let buf = UserSlice::<u8>::new(iov.iov_base as usize, iov.iov_len);
+            let ret = read_to_user(fd, buf)?;
+            if ret < 0 {
+                return Ok(if total > 0 { total } else { ret });
+            }
+            total += ret;
+            if (ret as usize) < buf.len() {
+                break;
+            }
+        }
+        Ok(total)
+    })
+}
+
+/// Writes the buffers of the I/O vector to `fd` one by one, until a buffer
+/// is not fully written.
+pub(crate) fn sys_writev(fd: c_int, iov: UserPtr<ctypes::iovec>, iocnt: c_int) -> isize {
+    syscall_body!(sys_writev, {
+        let iovs = read_iovec(iov, iocnt)?;
+        let mut total = 0;
+        for iov in iovs {
+            let buf = UserSlice::<u8>::new(iov.iov_base as usize, iov.iov_len);
+            let ret = write_from_user(fd, buf)?;
+            if ret < 0 {
+                return Ok(if total > 0 { total } else { ret });
+            }
+            total += ret;
+            if (ret as usize) < buf.len() {
+                break;
+            }
+        }
+        Ok(total)
+    })
+}
+
+/// Manipulates the underlying device parameters of special files.
+///
+/// Only the terminals support it (see [`crate::tty`]), besides `FIONBIO`,
+/// which sets the non-blocking mode of any file.
+#[cfg(feature = "fd")]
+pub(crate) fn sys_ioctl(fd: c_int, op: usize, arg: usize) -> isize {
+    use crate::tty;
+
+    syscall_body!(sys_ioctl, {
+        let file = api::get_file_like(fd)?;
+        if op == tty::FIONBIO {
+            let nonblocking = UserPtr::<c_int>::from(arg).read()? != 0;
+            file.set_nonblocking(nonblocking)?;
+            return Ok(0);
+        }
+        tty::ioctl(file, op, arg)
+    })
+}
+
+/// Manipulates the underlying device parameters of special files.
+///
+/// TODO: it is ignored without the `fd` feature, and always returns 0.
+#[cfg(not(feature = "fd"))]
+pub(crate) fn sys_ioctl(fd: c_int, op: usize, _arg: usize) -> isize {
+    info!("Ignore SYS_IOCTL: fd={}, op={:#x}", fd, op);
+    0
+}
+
+/// `struct pollfd` of the Linux ABI.
+#[cfg(feature = "fd")]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct PollFd {
+    fd: c_int,
+    events: i16,
+    revents: i16,
+}
+
+#[cfg(feature = "fd")]
+const POLLIN: i16 = 0x001;
+#[cfg(feature = "fd")]
+const POLLOUT: i16 = 0x004;
+#[cfg(feature = "fd")]
+const POLLERR: i16 = 0x008;
+#[cfg(feature = "fd")]
+const POLLNVAL: i16 = 0x020;
+
+/// The maximum number of files polled at a time.
+#[cfg(feature = "fd")]
+const POLL_MAX: usize = 1024;
+
+/// Waits until some of the `nfds` files of `fds` are ready, or until
+/// `timeout` expires (never if it is `None`), and returns the number of
+/// ready files.
+///
+/// The files are polled each time the task is scheduled, like `select`, as
+/// they do not wake up the pollers.
+#[cfg(feature = "fd")]
+fn poll_files(
+    fds: UserPtr<PollFd>,
+    nfds: usize,
+    timeout: Option<core::time::Duration>,
+) -> LinuxResult<isize> {
+    if nfds > POLL_MAX {
+        return Err(LinuxError::EINVAL);
+    }
+    let fds = UserSlice::new(fds.address(), nfds);
+    let mut kfds = fds.read()?;
+    let deadline = timeout.map(|timeout| axhal::time::monotonic_time() + timeout);
+    loop {
+        let mut ready = 0;
+        for pfd in kfds.iter_mut() {
+            pfd.revents = 0;
+            if pfd.fd < 0 {
+                continue;
+            }
+            pfd.revents = match api::get_file_like(pfd.fd).and_then(|file| file.poll()) {
+                Ok(state) => {
+                    let mut revents = 0;
+                    if state.readable {
+                        revents |= POLLIN;
+                    }
+                    if state.writable {
+                        revents |= POLLOUT;
+                    }
+                    revents & pfd.events
+                }
+                Err(LinuxError::EBADF) => POLLNVAL,
+                Err(_) => POLLERR,
+            };
+            if pfd.revents != 0 {
+                ready += 1;
+            }
+        }
+        if ready > 0 || deadline.is_some_and(|ddl| axhal::time::monotonic_time() >= ddl) {
+            fds.write(&kfds)?;
+            return Ok(ready);
+        }
+        if crate::signal::has_pending_signal() {
+            return Err(LinuxError::EINTR);
+        }
+        axtask::yield_now();
+    }
+}
+
+/// Waits for some of the files of `fds` to be ready, for at most `timeout`
+/// milliseconds (forever if it is negative).
+#[cfg(all(feature = "fd", target_arch = "x86_64"))]
+pub(crate) fn sys_poll(fds: UserPtr<PollFd>, nfds: usize, timeout: c_int) -> isize {
+    syscall_body!(sys_poll, {
+        let timeout = u64::try_from(timeout)
+            .ok()
+            .map(core::time::Duration::from_millis);
+        poll_files(fds, nfds, timeout)
+    })
+}
+
+/// Waits for some of the files of `fds` to be ready, for at most `timeout`
+/// (forever if it is NULL).
+///
+/// The signal mask is not replaced during the wait, `sigmask` is ignored.
+#[cfg(feature = "fd")]
+pub(crate) fn sys_ppoll(
+    fds: UserPtr<PollFd>,
+    nfds: usize,
+    timeout: UserPtr<ctypes::timespec>,
+    _sigmask: usize,
+) -> isize {
+    syscall_body!(sys_ppoll, {
+        let timeout = if timeout.is_null() {
+            None
+        } else {
+            let ts = timeout.read()?;
+            if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+                return Err(LinuxError::EINVAL);
+            }
+            Some(ts.into())
+        };
+        poll_files(fds, nfds, timeout)
+    })
+}
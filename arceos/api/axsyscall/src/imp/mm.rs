@@ -0,0 +1,382 @@
+#[cfg(feature = "fs")]
+use alloc::string::String;
+use alloc::sync::Arc;
+
+use axerrno::{AxError, AxResult, LinuxError, LinuxResult};
+use axhal::mem::{MemoryAddr, VirtAddr, PAGE_SIZE_4K};
+use axhal::paging::MappingFlags;
+use axmm::{AddrSpace, SharedPages};
+use axtask::{current, TaskExtRef};
+use memory_addr::{align_up_4k, is_aligned_4k, VirtAddrRange};
+
+const MADV_NORMAL: i32 = 0;
+const MADV_RANDOM: i32 = 1;
+const MADV_SEQUENTIAL: i32 = 2;
+const MADV_WILLNEED: i32 = 3;
+const MADV_HUGEPAGE: i32 = 14;
+const MADV_NOHUGEPAGE: i32 = 15;
+
+bitflags::bitflags! {
+    #[derive(Debug)]
+    /// permissions for sys_mmap
+    ///
+    /// See <https://github.com/bminor/glibc/blob/master/bits/mman.h>
+    struct MmapProt: i32 {
+        /// Page can be read.
+        const PROT_READ = 1 << 0;
+        /// Page can be written.
+        const PROT_WRITE = 1 << 1;
+        /// Page can be executed.
+        const PROT_EXEC = 1 << 2;
+    }
+}
+
+impl From<MmapProt> for MappingFlags {
+    fn from(value: MmapProt) -> Self {
+        let mut flags = MappingFlags::USER;
+        if value.contains(MmapProt::PROT_READ) {
+            flags |= MappingFlags::READ;
+        }
+        if value.contains(MmapProt::PROT_WRITE) {
+            flags |= MappingFlags::WRITE;
+        }
+        if value.contains(MmapProt::PROT_EXEC) {
+            flags |= MappingFlags::EXECUTE;
+        }
+        flags
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(Debug)]
+    /// flags for sys_mmap
+    ///
+    /// See <https://github.com/bminor/glibc/blob/master/bits/mman.h>
+    struct MmapFlags: i32 {
+        /// Share changes
+        const MAP_SHARED = 1 << 0;
+        /// Changes private; copy pages on write.
+        const MAP_PRIVATE = 1 << 1;
+        /// Map address must be exactly as requested, no matter whether it is available.
+        const MAP_FIXED = 1 << 4;
+        /// Don't use a file.
+        const MAP_ANONYMOUS = 1 << 5;
+        /// Don't check for reservations.
+        const MAP_NORESERVE = 1 << 14;
+        /// Allocation is for a stack.
+        const MAP_STACK = 0x20000;
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(Debug)]
+    /// flags for sys_mremap
+    ///
+    /// See <https://man7.org/linux/man-pages/man2/mremap.2.html>
+    struct MremapFlags: i32 {
+        /// The mapping may be moved to a new address.
+        const MREMAP_MAYMOVE = 1 << 0;
+        /// The mapping is moved to `new_address` (not supported).
+        const MREMAP_FIXED = 1 << 1;
+    }
+}
+
+/// Changes the program break of the calling process to `addr`.
+///
+/// Returns the new program break on success, or the current one on failure
+/// (including when `addr` is 0, which is used to query the break).
+pub(crate) fn sys_brk(addr: usize) -> isize {
+    let curr = current();
+    let mut aspace = curr.task_ext().aspace.lock();
+    let mut heap = curr.task_ext().process.heap.lock();
+    if addr != 0 {
+        heap.set_top(&mut aspace, VirtAddr::from(addr));
+    }
+    debug!("sys_brk <= {:#x} => {:#x}", addr, heap.top());
+    heap.top().as_usize() as _
+}
+
+/// Gives advice about the use of the memory `[addr, addr + length)`.
+///
+/// Only `MADV_HUGEPAGE` and `MADV_NOHUGEPAGE` take effect, which enable or
+/// disable huge pages for the anonymous mappings in the range (see
+/// [`AddrSpace::advise_huge_pages`]). The access pattern hints are ignored,
+/// and other advice is not supported.
+///
+/// [`AddrSpace::advise_huge_pages`]: axmm::AddrSpace::advise_huge_pages
+pub(crate) fn sys_madvise(addr: usize, length: usize, advice: i32) -> isize {
+    syscall_body!(sys_madvise, {
+        let start = VirtAddr::from(addr);
+        if !start.is_aligned_4k() {
+            return Err(LinuxError::EINVAL);
+        }
+        let size = length
+            .checked_add(addr)
+            .ok_or(LinuxError::EINVAL)?
+            .align_up_4k()
+            - addr;
+        let huge = match advice {
+            MADV_NORMAL | MADV_RANDOM | MADV_SEQUENTIAL | MADV_WILLNEED => return Ok(0),
+            MADV_HUGEPAGE => true,
+            MADV_NOHUGEPAGE => false,
+            _ => return Err(LinuxError::EINVAL),
+        };
+        if size == 0 {
+            return Ok(0);
+        }
+        let curr = current();
+        let mut aspace = curr.task_ext().aspace.lock();
+        aspace.advise_huge_pages(start, size, huge)?;
+        Ok(0)
+    })
+}
+
+/// Maps files or anonymous memory into the address space of the calling
+/// process.
+///
+/// The mapping is placed at `addr` if `MAP_FIXED` is set (replacing the
+/// mappings there), or at a free area from `addr` otherwise. Pages are read
+/// in on demand. File mappings are either shared with other `MAP_SHARED`
+/// mappings of the file, or private. Shared anonymous mappings are backed by
+/// new [`SharedPages`], so they stay shared with the children after `fork`.
+/// Private mappings of memfd files get a copy of the file content at once.
+pub(crate) fn sys_mmap(
+    addr: usize,
+    length: usize,
+    prot: i32,
+    flags: i32,
+    fd: i32,
+    offset: isize,
+) -> isize {
+    syscall_body!(sys_mmap, {
+        let prot = MmapProt::from_bits(prot).ok_or(LinuxError::EINVAL)?;
+        let flags = MmapFlags::from_bits_truncate(flags);
+        let shared = flags.contains(MmapFlags::MAP_SHARED);
+        if !shared && !flags.contains(MmapFlags::MAP_PRIVATE) {
+            return Err(LinuxError::EINVAL);
+        }
+        if length == 0 || offset < 0 || !is_aligned_4k(offset as usize) {
+            return Err(LinuxError::EINVAL);
+        }
+        let size = page_len(length)?;
+
+        // Look up the file first, so that a bad `fd` fails before anything is
+        // replaced by `MAP_FIXED`.
+        let anonymous = flags.contains(MmapFlags::MAP_ANONYMOUS);
+        let memfd = if anonymous { None } else { memfd_pages(fd) };
+        let file = if anonymous || memfd.is_some() {
+            None
+        } else {
+            let writable = shared && prot.contains(MmapProt::PROT_WRITE);
+            Some(MappedFile::from_fd(fd, writable)?)
+        };
+
+        let curr = current();
+        let mut aspace = curr.task_ext().aspace.lock();
+        let start = if flags.contains(MmapFlags::MAP_FIXED) {
+            let (start, _) = user_range(addr, length)?;
+            aspace.unmap(start, size)?;
+            crate::shm::update_attachments(&aspace);
+            start
+        } else {
+            // Mappings are placed from the (randomized) mmap base if no
+            // address is given.
+            let hint = if addr == 0 {
+                aspace.mmap_base()
+            } else {
+                VirtAddr::from(addr).align_down_4k()
+            };
+            aspace
+                .find_free_area(
+                    hint,
+                    size,
+                    VirtAddrRange::from_start_size(aspace.base(), aspace.size()),
+                )
+                .ok_or(LinuxError::ENOMEM)?
+        };
+
+        if let Some(pages) = memfd {
+            if shared {
+                aspace.map_shared(start, size, prot.into(), pages, offset as usize)?;
+            } else {
+                aspace.map_alloc(start, size, prot.into(), true)?;
+                let mut buf = [0; PAGE_SIZE_4K];
+                for off in (0..size).step_by(PAGE_SIZE_4K) {
+                    let n = pages.read_at(offset as usize + off, &mut buf);
+                    if n == 0 {
+                        break;
+                    }
+                    aspace.write(start + off, &buf[..n])?;
+                }
+            }
+        } else if let Some(file) = file {
+            file.map(&mut aspace, start, size, prot.into(), offset as usize, shared)?;
+        } else if shared {
+            let pages = Arc::new(SharedPages::new(size));
+            aspace.map_shared(start, size, prot.into(), pages, 0)?;
+        } else {
+            aspace.map_alloc(start, size, prot.into(), false)?;
+        }
+        Ok(start.as_usize())
+    })
+}
+
+/// Writes the written pages of the shared file mappings in
+/// `[addr, addr + length)` back to the files.
+///
+/// The write-back is always synchronous, so `flags` is ignored.
+pub(crate) fn sys_msync(addr: usize, length: usize, _flags: i32) -> isize {
+    syscall_body!(sys_msync, {
+        let (start, size) = user_range(addr, length)?;
+        current().task_ext().aspace.lock().sync(start, size)?;
+        Ok(0)
+    })
+}
+
+/// Rounds `length` up to whole pages.
+fn page_len(length: usize) -> LinuxResult<usize> {
+    if length > usize::MAX - PAGE_SIZE_4K {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(align_up_4k(length))
+}
+
+/// Checks that `addr` is page aligned, and returns the range of `length`
+/// bytes (rounded up to whole pages) from it.
+fn user_range(addr: usize, length: usize) -> LinuxResult<(VirtAddr, usize)> {
+    if !is_aligned_4k(addr) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok((VirtAddr::from(addr), page_len(length)?))
+}
+
+/// Removes the mappings in `[addr, addr + length)`, splitting the areas that
+/// are partially covered.
+pub(crate) fn sys_munmap(addr: usize, length: usize) -> isize {
+    syscall_body!(sys_munmap, {
+        let (start, size) = user_range(addr, length)?;
+        if size == 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let curr = current();
+        let mut aspace = curr.task_ext().aspace.lock();
+        aspace.unmap(start, size)?;
+        crate::shm::update_attachments(&aspace);
+        Ok(0)
+    })
+}
+
+/// Changes the access protection of the pages in `[addr, addr + length)`,
+/// which must be fully mapped.
+pub(crate) fn sys_mprotect(addr: usize, length: usize, prot: i32) -> isize {
+    syscall_body!(sys_mprotect, {
+        let (start, size) = user_range(addr, length)?;
+        let prot = MmapProt::from_bits(prot).ok_or(LinuxError::EINVAL)?;
+        current()
+            .task_ext()
+            .aspace
+            .lock()
+            .protect(start, size, prot.into())?;
+        Ok(0)
+    })
+}
+
+/// Expands or shrinks the mapping at `[old_addr, old_addr + old_size)`, and
+/// moves it if `MREMAP_MAYMOVE` is set and it cannot be expanded in place.
+///
+/// `MREMAP_FIXED` is not supported, so `new_addr` is ignored.
+pub(crate) fn sys_mremap(
+    old_addr: usize,
+    old_size: usize,
+    new_size: usize,
+    flags: i32,
+    _new_addr: usize,
+) -> isize {
+    syscall_body!(sys_mremap, {
+        let flags = MremapFlags::from_bits(flags).ok_or(LinuxError::EINVAL)?;
+        if flags.contains(MremapFlags::MREMAP_FIXED) {
+            return Err(LinuxError::EINVAL);
+        }
+        let (old_start, old_size) = user_range(old_addr, old_size)?;
+        let new_size = page_len(new_size)?;
+        if new_size == 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let may_move = flags.contains(MremapFlags::MREMAP_MAYMOVE);
+        let curr = current();
+        let mut aspace = curr.task_ext().aspace.lock();
+        let new_start = aspace
+            .remap(old_start, old_size, new_size, may_move)
+            .map_err(|err| match err {
+                AxError::BadAddress => LinuxError::EFAULT,
+                AxError::NoMemory => LinuxError::ENOMEM,
+                _ => LinuxError::EINVAL,
+            })?;
+        crate::shm::update_attachments(&aspace);
+        Ok(new_start.as_usize())
+    })
+}
+
+/// Returns the pages of the memfd file of `fd`, if it is one.
+#[cfg(feature = "fd")]
+fn memfd_pages(fd: i32) -> Option<Arc<SharedPages>> {
+    crate::shm::memfd_pages(fd)
+}
+
+#[cfg(not(feature = "fd"))]
+fn memfd_pages(_fd: i32) -> Option<Arc<SharedPages>> {
+    None
+}
+
+/// A regular file to be mapped.
+#[cfg(feature = "fs")]
+struct MappedFile {
+    node: axfs::fops::FileNodeRef,
+    path: String,
+}
+
+/// Files cannot be mapped without the filesystem.
+#[cfg(not(feature = "fs"))]
+enum MappedFile {}
+
+impl MappedFile {
+    /// Looks up the regular file of `fd`, which must be opened for writing as
+    /// well if `writable` is `true`.
+    #[cfg(feature = "fs")]
+    fn from_fd(fd: i32, writable: bool) -> LinuxResult<Self> {
+        let (node, path) = arceos_posix_api::get_file_node(fd, writable)?;
+        Ok(Self { node, path })
+    }
+
+    #[cfg(not(feature = "fs"))]
+    fn from_fd(_fd: i32, _writable: bool) -> LinuxResult<Self> {
+        Err(LinuxError::EBADF)
+    }
+
+    /// Maps `[offset, offset + size)` of the file at `start` of `aspace`.
+    #[cfg(feature = "fs")]
+    fn map(
+        self,
+        aspace: &mut AddrSpace,
+        start: VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+        offset: usize,
+        shared: bool,
+    ) -> AxResult {
+        aspace.mmap_file(start, size, flags, self.node, &self.path, offset, shared)
+    }
+
+    #[cfg(not(feature = "fs"))]
+    fn map(
+        self,
+        _aspace: &mut AddrSpace,
+        _start: VirtAddr,
+        _size: usize,
+        _flags: MappingFlags,
+        _offset: usize,
+        _shared: bool,
+    ) -> AxResult {
+        match self {}
+    }
+}
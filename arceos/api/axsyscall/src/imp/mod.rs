@@ -0,0 +1,358 @@
+//! Built-in syscall handlers.
+
+mod futex;
+mod io;
+mod mm;
+mod shm;
+mod signal;
+mod sys;
+mod task;
+mod time;
+
+#[cfg(feature = "fd")]
+mod fd_ops;
+#[cfg(feature = "fs")]
+mod fs;
+
+use crate::num::*;
+use crate::ptr::UserSlice;
+use crate::table::SyscallTable;
+
+/// Builds the default syscall table of the Linux ABI of the target
+/// architecture.
+pub(crate) fn linux_syscall_table() -> SyscallTable {
+    let mut t = SyscallTable::new();
+
+    // io
+    t.set(SYS_READ, |tf| {
+        io::sys_read(tf.arg0() as _, UserSlice::new(tf.arg1(), tf.arg2()))
+    });
+    t.set(SYS_WRITE, |tf| {
+        io::sys_write(tf.arg0() as _, UserSlice::new(tf.arg1(), tf.arg2()))
+    });
+    t.set(SYS_READV, |tf| {
+        io::sys_readv(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _)
+    });
+    t.set(SYS_WRITEV, |tf| {
+        io::sys_writev(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _)
+    });
+    t.set(SYS_IOCTL, |tf| {
+        io::sys_ioctl(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _)
+    });
+
+    // fd_ops
+    #[cfg(feature = "fd")]
+    {
+        t.set(SYS_CLOSE, |tf| fd_ops::sys_close(tf.arg0() as _));
+        t.set(SYS_DUP, |tf| fd_ops::sys_dup(tf.arg0() as _));
+        t.set(SYS_DUP3, |tf| {
+            fd_ops::sys_dup3(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _)
+        });
+        t.set(SYS_FCNTL, |tf| {
+            fd_ops::sys_fcntl(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _)
+        });
+        t.set(SYS_FTRUNCATE, |tf| {
+            fd_ops::sys_ftruncate(tf.arg0() as _, tf.arg1() as _)
+        });
+        t.set(SYS_PIPE2, |tf| {
+            fd_ops::sys_pipe2(tf.arg0().into(), tf.arg1() as _)
+        });
+        t.set(SYS_PPOLL, |tf| {
+            io::sys_ppoll(
+                tf.arg0().into(),
+                tf.arg1() as _,
+                tf.arg2().into(),
+                tf.arg3() as _,
+            )
+        });
+    }
+
+    // fs
+    #[cfg(feature = "fs")]
+    {
+        t.set(SYS_OPENAT, |tf| {
+            fs::sys_openat(
+                tf.arg0() as _,
+                tf.arg1().into(),
+                tf.arg2() as _,
+                tf.arg3() as _,
+            )
+        });
+        t.set(SYS_LSEEK, |tf| {
+            fs::sys_lseek(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _)
+        });
+        t.set(SYS_PREAD64, |tf| {
+            fs::sys_pread64(
+                tf.arg0() as _,
+                UserSlice::new(tf.arg1(), tf.arg2()),
+                tf.arg3() as _,
+            )
+        });
+        t.set(SYS_FSTAT, |tf| {
+            fs::sys_fstat(tf.arg0() as _, tf.arg1().into())
+        });
+        t.set(SYS_NEWFSTATAT, |tf| {
+            fs::sys_newfstatat(
+                tf.arg0() as _,
+                tf.arg1().into(),
+                tf.arg2().into(),
+                tf.arg3() as _,
+            )
+        });
+        t.set(SYS_GETDENTS64, |tf| {
+            fs::sys_getdents64(tf.arg0() as _, UserSlice::new(tf.arg1(), tf.arg2()))
+        });
+        t.set(SYS_GETCWD, |tf| {
+            fs::sys_getcwd(UserSlice::new(tf.arg0(), tf.arg1()))
+        });
+        t.set(SYS_MKDIRAT, |tf| {
+            fs::sys_mkdirat(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _)
+        });
+        t.set(SYS_UNLINKAT, |tf| {
+            fs::sys_unlinkat(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _)
+        });
+        t.set(SYS_FACCESSAT, |tf| {
+            fs::sys_faccessat(
+                tf.arg0() as _,
+                tf.arg1().into(),
+                tf.arg2() as _,
+                tf.arg3() as _,
+            )
+        });
+        t.set(SYS_READLINKAT, |tf| {
+            fs::sys_readlinkat(
+                tf.arg0() as _,
+                tf.arg1().into(),
+                UserSlice::new(tf.arg2(), tf.arg3()),
+            )
+        });
+        #[cfg(feature = "swap")]
+        t.set(SYS_SWAPON, |tf| {
+            fs::sys_swapon(tf.arg0().into(), tf.arg1() as _)
+        });
+    }
+
+    // task
+    t.set(SYS_EXIT, |tf| task::sys_exit(tf.arg0() as _));
+    t.set(SYS_EXIT_GROUP, |tf| task::sys_exit_group(tf.arg0() as _));
+    t.set(SYS_SET_TID_ADDRESS, |tf| {
+        task::sys_set_tid_address(tf.arg0() as _)
+    });
+    t.set(SYS_CLONE, |tf| {
+        // The last two arguments are swapped on x86_64.
+        #[cfg(not(target_arch = "x86_64"))]
+        let (tls, child_tid) = (tf.arg3(), tf.arg4());
+        #[cfg(target_arch = "x86_64")]
+        let (child_tid, tls) = (tf.arg3(), tf.arg4());
+        task::sys_clone(
+            tf,
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tls,
+            child_tid,
+        )
+    });
+    #[cfg(feature = "fs")]
+    t.set(SYS_EXECVE, |tf| {
+        task::sys_execve(tf.arg0().into(), tf.arg1().into(), tf.arg2().into())
+    });
+    t.set(SYS_WAIT4, |tf| {
+        task::sys_wait4(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2() as _,
+            tf.arg3() as _,
+        )
+    });
+    t.set(SYS_FUTEX, |tf| {
+        futex::sys_futex(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4() as _,
+            tf.arg5() as _,
+        )
+    });
+    t.set(SYS_GETPID, |_| task::sys_getpid());
+    t.set(SYS_GETPPID, |_| task::sys_getppid());
+    t.set(SYS_GETTID, |_| task::sys_gettid());
+    t.set(SYS_GETPGID, |tf| task::sys_getpgid(tf.arg0() as _));
+    t.set(SYS_SETPGID, |tf| {
+        task::sys_setpgid(tf.arg0() as _, tf.arg1() as _)
+    });
+    #[cfg(target_arch = "x86_64")]
+    t.set(SYS_GETPGRP, |_| task::sys_getpgid(0));
+    t.set(SYS_GETSID, |tf| task::sys_getsid(tf.arg0() as _));
+    t.set(SYS_SETSID, |_| task::sys_setsid());
+    t.set(SYS_SCHED_YIELD, |_| task::sys_sched_yield());
+
+    // mm
+    t.set(SYS_BRK, |tf| mm::sys_brk(tf.arg0() as _));
+    t.set(SYS_MMAP, |tf| {
+        mm::sys_mmap(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4() as _,
+            tf.arg5() as _,
+        )
+    });
+    t.set(SYS_MUNMAP, |tf| mm::sys_munmap(tf.arg0() as _, tf.arg1() as _));
+    t.set(SYS_MPROTECT, |tf| {
+        mm::sys_mprotect(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _)
+    });
+    t.set(SYS_MREMAP, |tf| {
+        mm::sys_mremap(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2() as _,
+            tf.arg3() as _,
+            tf.arg4() as _,
+        )
+    });
+    t.set(SYS_MSYNC, |tf| {
+        mm::sys_msync(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _)
+    });
+    t.set(SYS_MADVISE, |tf| {
+        mm::sys_madvise(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _)
+    });
+
+    // shm
+    t.set(SYS_SHMGET, |tf| {
+        shm::sys_shmget(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _)
+    });
+    t.set(SYS_SHMAT, |tf| {
+        shm::sys_shmat(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _)
+    });
+    t.set(SYS_SHMDT, |tf| shm::sys_shmdt(tf.arg0() as _));
+    t.set(SYS_SHMCTL, |tf| {
+        shm::sys_shmctl(tf.arg0() as _, tf.arg1() as _, tf.arg2().into())
+    });
+    #[cfg(feature = "fd")]
+    t.set(SYS_MEMFD_CREATE, |tf| {
+        shm::sys_memfd_create(tf.arg0().into(), tf.arg1() as _)
+    });
+
+    // signal
+    t.set(SYS_RT_SIGACTION, |tf| {
+        signal::sys_rt_sigaction(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2().into(),
+            tf.arg3() as _,
+        )
+    });
+    t.set(SYS_RT_SIGPROCMASK, |tf| {
+        signal::sys_rt_sigprocmask(
+            tf.arg0() as _,
+            tf.arg1().into(),
+            tf.arg2().into(),
+            tf.arg3() as _,
+        )
+    });
+    t.set(SYS_RT_SIGPENDING, |tf| {
+        signal::sys_rt_sigpending(tf.arg0().into(), tf.arg1() as _)
+    });
+    t.set(SYS_RT_SIGRETURN, signal::sys_rt_sigreturn);
+    t.set(SYS_KILL, |tf| {
+        signal::sys_kill(tf.arg0() as _, tf.arg1() as _)
+    });
+    t.set(SYS_TKILL, |tf| {
+        signal::sys_tkill(tf.arg0() as _, tf.arg1() as _)
+    });
+    t.set(SYS_TGKILL, |tf| {
+        signal::sys_tgkill(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _)
+    });
+
+    // time
+    t.set(SYS_CLOCK_GETTIME, |tf| {
+        time::sys_clock_gettime(tf.arg0() as _, tf.arg1().into())
+    });
+    t.set(SYS_NANOSLEEP, |tf| {
+        time::sys_nanosleep(tf.arg0().into(), tf.arg1().into())
+    });
+
+    // sys
+    t.set(SYS_UNAME, |tf| sys::sys_uname(tf.arg0().into()));
+    t.set(SYS_GETRLIMIT, |tf| {
+        sys::sys_getrlimit(tf.arg0() as _, tf.arg1().into())
+    });
+    t.set(SYS_SETRLIMIT, |tf| {
+        sys::sys_setrlimit(tf.arg0() as _, tf.arg1().into())
+    });
+    t.set(SYS_PRLIMIT64, |tf| {
+        sys::sys_prlimit64(
+            tf.arg0() as _,
+            tf.arg1() as _,
+            tf.arg2().into(),
+            tf.arg3().into(),
+        )
+    });
+    #[cfg(target_arch = "x86_64")]
+    t.set(SYS_ARCH_PRCTL, |tf| {
+        sys::sys_arch_prctl(tf.arg0() as _, tf.arg1())
+    });
+
+    #[cfg(target_arch = "x86_64")]
+    set_legacy_syscalls(&mut t);
+
+    t
+}
+
+/// Sets the legacy syscalls that only exist on x86_64 (and are still used by
+/// musl there), which are done by the handlers of their successors.
+#[cfg(target_arch = "x86_64")]
+fn set_legacy_syscalls(t: &mut SyscallTable) {
+    #[cfg(feature = "fd")]
+    {
+        t.set(SYS_DUP2, |tf| {
+            fd_ops::sys_dup2(tf.arg0() as _, tf.arg1() as _)
+        });
+        t.set(SYS_PIPE, |tf| fd_ops::sys_pipe2(tf.arg0().into(), 0));
+        t.set(SYS_POLL, |tf| {
+            io::sys_poll(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _)
+        });
+    }
+
+    #[cfg(feature = "fs")]
+    {
+        use arceos_posix_api::ctypes::{AT_FDCWD, AT_SYMLINK_NOFOLLOW};
+
+        t.set(SYS_OPEN, |tf| {
+            fs::sys_openat(AT_FDCWD, tf.arg0().into(), tf.arg1() as _, tf.arg2() as _)
+        });
+        t.set(SYS_STAT, |tf| {
+            fs::sys_newfstatat(AT_FDCWD, tf.arg0().into(), tf.arg1().into(), 0)
+        });
+        t.set(SYS_LSTAT, |tf| {
+            fs::sys_newfstatat(
+                AT_FDCWD,
+                tf.arg0().into(),
+                tf.arg1().into(),
+                AT_SYMLINK_NOFOLLOW as _,
+            )
+        });
+        t.set(SYS_ACCESS, |tf| {
+            fs::sys_faccessat(AT_FDCWD, tf.arg0().into(), tf.arg1() as _, 0)
+        });
+        t.set(SYS_MKDIR, |tf| {
+            fs::sys_mkdirat(AT_FDCWD, tf.arg0().into(), tf.arg1() as _)
+        });
+        t.set(SYS_UNLINK, |tf| {
+            fs::sys_unlinkat(AT_FDCWD, tf.arg0().into(), 0)
+        });
+        t.set(SYS_READLINK, |tf| {
+            fs::sys_readlinkat(
+                AT_FDCWD,
+                tf.arg0().into(),
+                UserSlice::new(tf.arg1(), tf.arg2()),
+            )
+        });
+    }
+
+    t.set(SYS_FORK, task::sys_fork);
+    t.set(SYS_VFORK, task::sys_vfork);
+}
@@ -0,0 +1,182 @@
+use axerrno::LinuxError;
+use axhal::paging::MappingFlags;
+use axtask::{current, TaskExtRef};
+
+use crate::ptr::UserPtr;
+use crate::shm::{find_segment, shm_get, shm_remove};
+
+const IPC_CREAT: i32 = 0o1000;
+const IPC_EXCL: i32 = 0o2000;
+
+const IPC_RMID: i32 = 0;
+const IPC_SET: i32 = 1;
+const IPC_STAT: i32 = 2;
+/// Set in `cmd` by some C libraries for the 64-bit `struct shmid64_ds`,
+/// which is the only layout supported.
+const IPC_64: i32 = 0x100;
+const SHM_LOCK: i32 = 11;
+const SHM_UNLOCK: i32 = 12;
+
+const SHM_RDONLY: i32 = 0o10000;
+const SHM_RND: i32 = 0o20000;
+const SHM_EXEC: i32 = 0o100000;
+
+/// The alignment of the attach address rounded down by `SHM_RND`.
+const SHMLBA: usize = 0x1000;
+
+/// `struct ipc64_perm` of the Linux ABI.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IpcPerm {
+    pub key: i32,
+    pub uid: u32,
+    pub gid: u32,
+    pub cuid: u32,
+    pub cgid: u32,
+    /// `mode` is 16-bit followed by 2 bytes of padding on x86_64, which has
+    /// the same layout in little endian.
+    pub mode: u32,
+    pub seq: u16,
+    pub __pad2: u16,
+    pub __unused1: u64,
+    pub __unused2: u64,
+}
+
+/// `struct shmid64_ds` of the Linux ABI.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ShmidDs {
+    pub shm_perm: IpcPerm,
+    pub shm_segsz: usize,
+    pub shm_atime: i64,
+    pub shm_dtime: i64,
+    pub shm_ctime: i64,
+    pub shm_cpid: i32,
+    pub shm_lpid: i32,
+    pub shm_nattch: u64,
+    pub __unused4: u64,
+    pub __unused5: u64,
+}
+
+/// Gets the System V shared memory segment with `key`, or creates it if
+/// `IPC_CREAT` is set in `shmflg`.
+///
+/// The low 9 bits of `shmflg` are the permissions of a new segment, which
+/// are recorded but not checked.
+pub(crate) fn sys_shmget(key: i32, size: usize, shmflg: i32) -> isize {
+    syscall_body!(sys_shmget, {
+        let id = shm_get(
+            key,
+            size,
+            shmflg as u32 & 0o777,
+            shmflg & IPC_CREAT != 0,
+            shmflg & IPC_EXCL != 0,
+        )?;
+        Ok(id as isize)
+    })
+}
+
+/// Attaches the shared memory segment `shmid` to the calling process.
+///
+/// It is attached at `shmaddr`, which is rounded down to `SHMLBA` if
+/// `SHM_RND` is set, or a free area if `shmaddr` is NULL. Attaching over
+/// existing mappings (`SHM_REMAP`) is not supported.
+pub(crate) fn sys_shmat(shmid: i32, shmaddr: usize, shmflg: i32) -> isize {
+    syscall_body!(sys_shmat, {
+        let addr = if shmflg & SHM_RND != 0 {
+            shmaddr & !(SHMLBA - 1)
+        } else {
+            shmaddr
+        };
+        let mut flags = MappingFlags::USER | MappingFlags::READ;
+        if shmflg & SHM_RDONLY == 0 {
+            flags |= MappingFlags::WRITE;
+        }
+        if shmflg & SHM_EXEC != 0 {
+            flags |= MappingFlags::EXECUTE;
+        }
+        let curr = current();
+        let process = &curr.task_ext().process;
+        let mut aspace = curr.task_ext().aspace.lock();
+        let start = process
+            .shm
+            .attach(&mut aspace, process.pid(), shmid, addr, flags)?;
+        Ok(start as isize)
+    })
+}
+
+/// Detaches the shared memory segment attached at `shmaddr` from the calling
+/// process.
+pub(crate) fn sys_shmdt(shmaddr: usize) -> isize {
+    syscall_body!(sys_shmdt, {
+        let curr = current();
+        let process = &curr.task_ext().process;
+        let mut aspace = curr.task_ext().aspace.lock();
+        process.shm.detach(&mut aspace, process.pid(), shmaddr)?;
+        Ok(0)
+    })
+}
+
+/// Controls the shared memory segment `shmid`.
+///
+/// Supports `IPC_STAT`, `IPC_SET` (only the permissions are changed) and
+/// `IPC_RMID`. `SHM_LOCK` and `SHM_UNLOCK` do nothing, as the pages of
+/// segments are never swapped out.
+pub(crate) fn sys_shmctl(shmid: i32, cmd: i32, buf: UserPtr<ShmidDs>) -> isize {
+    syscall_body!(sys_shmctl, {
+        match cmd & !IPC_64 {
+            IPC_STAT => {
+                let stat = find_segment(shmid)?.stat();
+                buf.write(ShmidDs {
+                    shm_perm: IpcPerm {
+                        key: stat.key,
+                        mode: stat.mode,
+                        ..Default::default()
+                    },
+                    shm_segsz: stat.size,
+                    shm_atime: stat.atime as _,
+                    shm_dtime: stat.dtime as _,
+                    shm_ctime: stat.ctime as _,
+                    shm_cpid: stat.cpid as _,
+                    shm_lpid: stat.lpid as _,
+                    shm_nattch: stat.nattch as _,
+                    ..Default::default()
+                })?;
+            }
+            IPC_SET => {
+                let seg = find_segment(shmid)?;
+                seg.set_mode(buf.read()?.shm_perm.mode);
+            }
+            IPC_RMID => shm_remove(shmid)?,
+            SHM_LOCK | SHM_UNLOCK => {
+                find_segment(shmid)?;
+            }
+            _ => return Err(LinuxError::EINVAL),
+        }
+        Ok(0)
+    })
+}
+
+/// Creates an anonymous file in memory, and returns its file descriptor.
+///
+/// Only the `MFD_CLOEXEC` flag is supported, sealing and huge pages are not.
+#[cfg(feature = "fd")]
+pub(crate) fn sys_memfd_create(name: crate::ptr::UserCStr, flags: u32) -> isize {
+    use alloc::sync::Arc;
+
+    use crate::shm::Memfd;
+
+    const MFD_CLOEXEC: u32 = 1;
+
+    syscall_body!(sys_memfd_create, {
+        if flags & !MFD_CLOEXEC != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let name = name.read()?;
+        let fd = arceos_posix_api::add_file_like(Arc::new(Memfd::new(name)))?;
+        if flags & MFD_CLOEXEC != 0 {
+            arceos_posix_api::set_cloexec(fd, true)?;
+        }
+        Ok(fd as isize)
+    })
+}
@@ -0,0 +1,181 @@
+use core::mem::size_of;
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::arch::{TrapFrame, UspaceContext};
+use axtask::{current, TaskExtRef};
+
+use crate::process::{Pid, Process};
+use crate::ptr::UserPtr;
+use crate::signal::{SigAction, SignalSet, NSIG, SIGKILL, SIGSTOP};
+
+// `how` of `rt_sigprocmask`.
+const SIG_BLOCK: i32 = 0;
+const SIG_UNBLOCK: i32 = 1;
+const SIG_SETMASK: i32 = 2;
+
+fn check_signo(signo: usize) -> LinuxResult {
+    if signo == 0 || signo > NSIG {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(())
+}
+
+fn check_sigsetsize(sigsetsize: usize) -> LinuxResult {
+    if sigsetsize != size_of::<SignalSet>() {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(())
+}
+
+/// Examines and changes the action of `signo`.
+///
+/// The actions of `SIGKILL` and `SIGSTOP` cannot be changed.
+pub(crate) fn sys_rt_sigaction(
+    signo: usize,
+    act: UserPtr<SigAction>,
+    oldact: UserPtr<SigAction>,
+    sigsetsize: usize,
+) -> isize {
+    syscall_body!(sys_rt_sigaction, {
+        check_signo(signo)?;
+        check_sigsetsize(sigsetsize)?;
+        let curr = current();
+        let signals = &curr.task_ext().process.signals;
+        let old = if act.is_null() {
+            signals.action(signo)
+        } else {
+            if signo == SIGKILL || signo == SIGSTOP {
+                return Err(LinuxError::EINVAL);
+            }
+            let mut act = act.read()?;
+            act.mask.0 &= !SignalSet::UNBLOCKABLE.0;
+            signals.set_action(signo, act)
+        };
+        if !oldact.is_null() {
+            oldact.write(old)?;
+        }
+        Ok(0)
+    })
+}
+
+/// Examines and changes the blocked signals of the calling task.
+pub(crate) fn sys_rt_sigprocmask(
+    how: i32,
+    set: UserPtr<SignalSet>,
+    oldset: UserPtr<SignalSet>,
+    sigsetsize: usize,
+) -> isize {
+    syscall_body!(sys_rt_sigprocmask, {
+        check_sigsetsize(sigsetsize)?;
+        let curr = current();
+        let signals = &curr.task_ext().signals;
+        let old = signals.blocked();
+        if !set.is_null() {
+            let set = set.read()?;
+            let new = match how {
+                SIG_BLOCK => SignalSet(old.0 | set.0),
+                SIG_UNBLOCK => SignalSet(old.0 & !set.0),
+                SIG_SETMASK => set,
+                _ => return Err(LinuxError::EINVAL),
+            };
+            signals.set_blocked(new);
+        }
+        if !oldset.is_null() {
+            oldset.write(old)?;
+        }
+        Ok(0)
+    })
+}
+
+/// Returns the signals that are pending but blocked.
+pub(crate) fn sys_rt_sigpending(set: UserPtr<SignalSet>, sigsetsize: usize) -> isize {
+    syscall_body!(sys_rt_sigpending, {
+        check_sigsetsize(sigsetsize)?;
+        let curr = current();
+        let ext = curr.task_ext();
+        let pending = ext.signals.pending().0 | ext.process.signals.pending().0;
+        set.write(SignalSet(pending & ext.signals.blocked().0))?;
+        Ok(0)
+    })
+}
+
+/// Sends `signo` to the process `pid`.
+///
+/// If `pid` is 0, it is sent to the process group of the calling process,
+/// and if `pid` is less than -1, to the process group `-pid`. Sending to all
+/// processes (-1) is not supported. If `signo` is 0, only the existence of
+/// the processes is checked.
+pub(crate) fn sys_kill(pid: i32, signo: usize) -> isize {
+    syscall_body!(sys_kill, {
+        if signo != 0 {
+            check_signo(signo)?;
+        }
+        let processes = match pid {
+            0 => Process::group_members(current().task_ext().process.pgid()),
+            -1 => return Err(LinuxError::ESRCH),
+            pid if pid < 0 => Process::group_members(pid.unsigned_abs() as Pid),
+            pid => Process::find(pid as Pid).into_iter().collect(),
+        };
+        if processes.is_empty() {
+            return Err(LinuxError::ESRCH);
+        }
+        if signo != 0 {
+            for process in processes.iter().filter(|p| !p.is_zombie()) {
+                process.send_signal(signo);
+            }
+        }
+        Ok(0)
+    })
+}
+
+/// Sends `signo` to the thread `tid` of the process `tgid`.
+///
+/// If `tgid` is `None` (for `tkill`), the process of the thread is not
+/// checked.
+fn send_thread_signal(tgid: Option<Pid>, tid: i32, signo: usize) -> LinuxResult<isize> {
+    if tid <= 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    if signo != 0 {
+        check_signo(signo)?;
+    }
+    let tid = tid as Pid;
+    let curr = current();
+    let process = match tgid {
+        Some(tgid) => Process::find(tgid).ok_or(LinuxError::ESRCH)?,
+        None if curr.task_ext().process.thread(tid).is_some() => curr.task_ext().process.clone(),
+        // The PID of a process is the TID of its first thread.
+        None => Process::find(tid).ok_or(LinuxError::ESRCH)?,
+    };
+    let thread = process.thread(tid).ok_or(LinuxError::ESRCH)?;
+    if signo != 0 {
+        thread.task_ext().signals.send(signo);
+    }
+    Ok(0)
+}
+
+/// Sends `signo` to the thread `tid` of the process `tgid`.
+pub(crate) fn sys_tgkill(tgid: i32, tid: i32, signo: usize) -> isize {
+    syscall_body!(sys_tgkill, {
+        if tgid <= 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        send_thread_signal(Some(tgid as Pid), tid, signo)
+    })
+}
+
+/// Like [`sys_tgkill`], without checking the thread group.
+pub(crate) fn sys_tkill(tid: i32, signo: usize) -> isize {
+    syscall_body!(sys_tkill, send_thread_signal(None, tid, signo))
+}
+
+/// Returns from a signal handler.
+///
+/// The user context saved in the signal frame (at the top of the user stack)
+/// is restored before returning to user space, so the return value is
+/// discarded.
+pub(crate) fn sys_rt_sigreturn(tf: &TrapFrame) -> isize {
+    let sp = UspaceContext::from(tf).get_sp();
+    current().task_ext().signals.set_sigreturn_frame(sp);
+    0
+}
@@ -0,0 +1,116 @@
+use core::ffi::c_int;
+
+use arceos_posix_api::{self as api, ctypes};
+use axerrno::LinuxError;
+use axtask::{current, TaskExtRef};
+
+use crate::ptr::UserPtr;
+
+/// `struct utsname` of the Linux ABI.
+#[repr(C)]
+pub struct UtsName {
+    pub sysname: [u8; 65],
+    pub nodename: [u8; 65],
+    pub release: [u8; 65],
+    pub version: [u8; 65],
+    pub machine: [u8; 65],
+    pub domainname: [u8; 65],
+}
+
+impl UtsName {
+    fn field(s: &str) -> [u8; 65] {
+        let mut buf = [0; 65];
+        let len = s.len().min(64);
+        buf[..len].copy_from_slice(&s.as_bytes()[..len]);
+        buf
+    }
+}
+
+impl Default for UtsName {
+    fn default() -> Self {
+        Self {
+            // Linux programs (e.g. libc) may check the system name and release
+            // version, so pretend to be a Linux kernel.
+            sysname: Self::field("Linux"),
+            nodename: Self::field("arceos"),
+            release: Self::field("5.10.0"),
+            version: Self::field(concat!("ArceOS ", env!("CARGO_PKG_VERSION"))),
+            machine: Self::field(option_env!("AX_ARCH").unwrap_or("riscv64")),
+            domainname: Self::field("localdomain"),
+        }
+    }
+}
+
+pub(crate) fn sys_uname(name: UserPtr<UtsName>) -> isize {
+    syscall_body!(sys_uname, {
+        name.write(UtsName::default())?;
+        Ok(0)
+    })
+}
+
+pub(crate) fn sys_getrlimit(resource: c_int, rlimits: UserPtr<ctypes::rlimit>) -> isize {
+    syscall_body!(sys_getrlimit, {
+        let mut rlimit = ctypes::rlimit::default();
+        let ret = unsafe { api::sys_getrlimit(resource, &mut rlimit) };
+        if ret == 0 {
+            rlimits.write(rlimit)?;
+        }
+        Ok(ret)
+    })
+}
+
+pub(crate) fn sys_setrlimit(resource: c_int, rlimits: UserPtr<ctypes::rlimit>) -> isize {
+    syscall_body!(sys_setrlimit, {
+        let mut rlimit = rlimits.read()?;
+        Ok(unsafe { api::sys_setrlimit(resource, &mut rlimit) })
+    })
+}
+
+/// Gets and sets the resource limits of a process.
+///
+/// Only the calling process (`pid` is 0 or its own PID) is supported.
+pub(crate) fn sys_prlimit64(
+    pid: i32,
+    resource: c_int,
+    new_limit: UserPtr<ctypes::rlimit>,
+    old_limit: UserPtr<ctypes::rlimit>,
+) -> isize {
+    syscall_body!(sys_prlimit64, {
+        if pid != 0 && pid as u64 != current().task_ext().process.pid() {
+            return Err(LinuxError::ESRCH);
+        }
+        if !old_limit.is_null() {
+            let ret = sys_getrlimit(resource, old_limit);
+            if ret < 0 {
+                return Ok(ret);
+            }
+        }
+        if !new_limit.is_null() {
+            return Ok(sys_setrlimit(resource, new_limit));
+        }
+        Ok(0)
+    })
+}
+
+/// Sets or gets the `FS`/`GS` base of the calling thread on x86_64.
+///
+/// Only the `FS` base, which points to the thread-local storage, is
+/// supported. It is saved and restored on context switches.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_arch_prctl(code: c_int, addr: usize) -> isize {
+    const ARCH_SET_FS: c_int = 0x1002;
+    const ARCH_GET_FS: c_int = 0x1003;
+    syscall_body!(sys_arch_prctl, {
+        match code {
+            ARCH_SET_FS => {
+                unsafe { axhal::arch::write_thread_pointer(addr) };
+                Ok(0)
+            }
+            ARCH_GET_FS => {
+                UserPtr::<usize>::from(addr).write(axhal::arch::read_thread_pointer())?;
+                Ok(0)
+            }
+            _ => Err(LinuxError::EINVAL),
+        }
+    })
+}
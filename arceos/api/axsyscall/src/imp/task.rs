@@ -0,0 +1,489 @@
+#[cfg(feature = "fs")]
+use alloc::{string::String, vec::Vec};
+
+use alloc::sync::Arc;
+
+#[cfg(feature = "fs")]
+use axerrno::AxError;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::arch::{TrapFrame, UspaceContext};
+#[cfg(feature = "fs")]
+use axmm::AddrSpace;
+use axsync::Mutex;
+use axtask::{current, TaskExtRef};
+
+use crate::process::{self, Pid, Process, WaitOptions, WaitTarget};
+#[cfg(feature = "fs")]
+use crate::ptr::UserCStr;
+use crate::ptr::UserPtr;
+#[cfg(target_arch = "x86_64")]
+use crate::signal::SIGCHLD;
+use crate::task::{new_user_task, TaskExt};
+
+bitflags::bitflags! {
+    /// Flags for [`sys_clone`].
+    ///
+    /// See <https://man7.org/linux/man-pages/man2/clone.2.html>
+    #[derive(Debug, Clone, Copy)]
+    struct CloneFlags: u32 {
+        /// The calling process and the child process run in the same memory
+        /// space.
+        const CLONE_VM = 0x0000_0100;
+        /// The caller and the child process share the same filesystem
+        /// information.
+        const CLONE_FS = 0x0000_0200;
+        /// The calling process and the child process share the same file
+        /// descriptor table.
+        const CLONE_FILES = 0x0000_0400;
+        /// The calling process and the child process share the same table of
+        /// signal handlers.
+        const CLONE_SIGHAND = 0x0000_0800;
+        /// The parent of the new child will be the same as that of the calling
+        /// process.
+        const CLONE_PARENT = 0x0000_8000;
+        /// The execution of the calling process is suspended until the child
+        /// releases its virtual memory resources.
+        const CLONE_VFORK = 0x0000_4000;
+        /// The child is placed in the same thread group as the calling process.
+        const CLONE_THREAD = 0x0001_0000;
+        /// Share System V semaphore adjustments, which are not supported, so
+        /// it is ignored.
+        const CLONE_SYSVSEM = 0x0004_0000;
+        /// The TLS (Thread Local Storage) descriptor is set to `tls`.
+        const CLONE_SETTLS = 0x0008_0000;
+        /// Store the child thread ID in the parent's memory at `parent_tid`.
+        const CLONE_PARENT_SETTID = 0x0010_0000;
+        /// Clear (zero) the child thread ID in the child's memory at
+        /// `child_tid` when the child exits.
+        const CLONE_CHILD_CLEARTID = 0x0020_0000;
+        /// Obsolete flag ignored by Linux, still passed by some libcs.
+        const CLONE_DETACHED = 0x0040_0000;
+        /// Store the child thread ID in the child's memory at `child_tid`.
+        const CLONE_CHILD_SETTID = 0x0100_0000;
+    }
+}
+
+pub(crate) fn sys_exit(exit_code: i32) -> ! {
+    info!("[SYS_EXIT]: task {} is exiting ..", current().id_name());
+    process::exit_current(exit_code)
+}
+
+pub(crate) fn sys_exit_group(exit_code: i32) -> ! {
+    info!(
+        "[SYS_EXIT_GROUP]: task {} is exiting ..",
+        current().id_name()
+    );
+    process::exit_group_current(exit_code)
+}
+
+pub(crate) fn sys_set_tid_address(tid_ptr: usize) -> isize {
+    let curr = current();
+    curr.task_ext().set_clear_child_tid(tid_ptr as _);
+    curr.id().as_u64() as isize
+}
+
+pub(crate) fn sys_getpid() -> isize {
+    current().task_ext().process.pid() as _
+}
+
+pub(crate) fn sys_getppid() -> isize {
+    current()
+        .task_ext()
+        .process
+        .parent()
+        .map_or(0, |parent| parent.pid() as _)
+}
+
+pub(crate) fn sys_gettid() -> isize {
+    current().id().as_u64() as _
+}
+
+/// Finds the process `pid`, or returns the calling process if `pid` is 0.
+fn find_process(pid: i32) -> LinuxResult<Arc<Process>> {
+    match pid {
+        0 => Ok(current().task_ext().process.clone()),
+        pid if pid > 0 => Process::find(pid as Pid).ok_or(LinuxError::ESRCH),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Returns the process group ID of the process `pid` (0 for the calling
+/// process).
+pub(crate) fn sys_getpgid(pid: i32) -> isize {
+    syscall_body!(sys_getpgid, Ok(find_process(pid)?.pgid() as isize))
+}
+
+/// Moves the process `pid` (0 for the calling process) into the process
+/// group `pgid` (0 for the group with the same ID as `pid`).
+///
+/// The process must be the calling process or one of its children, and not
+/// a session leader. The group must be in the same session as the calling
+/// process, or be created with the process as its leader.
+pub(crate) fn sys_setpgid(pid: i32, pgid: i32) -> isize {
+    syscall_body!(sys_setpgid, {
+        if pgid < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let curr = current().task_ext().process.clone();
+        let target = find_process(pid)?;
+        let is_child = target
+            .parent()
+            .is_some_and(|parent| Arc::ptr_eq(&parent, &curr));
+        if !Arc::ptr_eq(&target, &curr) && !is_child {
+            return Err(LinuxError::ESRCH);
+        }
+        if target.sid() != curr.sid() || target.sid() == target.pid() {
+            return Err(LinuxError::EPERM);
+        }
+        let pgid = if pgid == 0 { target.pid() } else { pgid as Pid };
+        if pgid != target.pid()
+            && !Process::group_members(pgid)
+                .iter()
+                .any(|proc| proc.sid() == curr.sid())
+        {
+            return Err(LinuxError::EPERM);
+        }
+        target.set_pgid(pgid);
+        Ok(0)
+    })
+}
+
+/// Returns the session ID of the process `pid` (0 for the calling process).
+pub(crate) fn sys_getsid(pid: i32) -> isize {
+    syscall_body!(sys_getsid, Ok(find_process(pid)?.sid() as isize))
+}
+
+/// Creates a new session led by the calling process, which has no
+/// controlling terminal yet.
+///
+/// Fails if the calling process is a process group leader already.
+pub(crate) fn sys_setsid() -> isize {
+    syscall_body!(sys_setsid, {
+        let process = current().task_ext().process.clone();
+        if !Process::group_members(process.pid()).is_empty() {
+            return Err(LinuxError::EPERM);
+        }
+        process.set_sid();
+        Ok(process.pid() as isize)
+    })
+}
+
+pub(crate) fn sys_sched_yield() -> isize {
+    arceos_posix_api::sys_sched_yield() as _
+}
+
+/// An option for [`sys_wait4`] to wait for all children regardless of their
+/// type, which makes no difference as all children are of the same type.
+const __WALL: u32 = 0x4000_0000;
+
+/// Creates a child process or a thread.
+///
+/// With `CLONE_THREAD`, the child is a new thread of the calling process,
+/// sharing its address space and signal actions (so `CLONE_VM` and
+/// `CLONE_SIGHAND` are required). Otherwise, the child is a new process with a
+/// copy-on-write duplicate of the address space of the caller, and inherits
+/// its signal actions. Sharing the address space with another process is not
+/// supported, except for `CLONE_VM | CLONE_VFORK` (as used by `posix_spawn`),
+/// where the child gets a copy-on-write duplicate as well: its writes are not
+/// seen by the caller. With `CLONE_VFORK`, the caller is suspended until the
+/// child calls `execve` or exits. The low byte of `flags` (the signal sent to
+/// the parent on exit) is ignored, `SIGCHLD` is always sent.
+///
+/// `CLONE_PARENT`, the namespace flags and other unknown flags are not
+/// supported, and fail with `EINVAL`.
+///
+/// With `CLONE_PARENT_SETTID`, the TID is stored at `parent_tid` before the
+/// address space is copied, so the child sees it as well.
+///
+/// In both cases, the child inherits the signal mask, and gets a copy of the
+/// file descriptor table unless `CLONE_FILES` is given to share it.
+pub(crate) fn sys_clone(
+    tf: &TrapFrame,
+    flags: usize,
+    stack: usize,
+    parent_tid: usize,
+    tls: usize,
+    child_tid: usize,
+) -> isize {
+    syscall_body!(sys_clone, {
+        let Some(flags) = CloneFlags::from_bits(flags as u32 & !0xff) else {
+            warn!("sys_clone: unsupported flags {:#x}", flags);
+            return Err(LinuxError::EINVAL);
+        };
+        let is_thread = flags.contains(CloneFlags::CLONE_THREAD);
+        let valid = if is_thread {
+            flags.contains(CloneFlags::CLONE_VM | CloneFlags::CLONE_SIGHAND)
+        } else {
+            !flags.intersects(CloneFlags::CLONE_SIGHAND | CloneFlags::CLONE_PARENT)
+                && (!flags.contains(CloneFlags::CLONE_VM)
+                    || flags.contains(CloneFlags::CLONE_VFORK))
+        };
+        if !valid {
+            warn!("sys_clone: unsupported flags {:?}", flags);
+            return Err(LinuxError::EINVAL);
+        }
+
+        let curr = current();
+        let mut task = new_user_task(curr.name());
+        let tid = task.id().as_u64();
+        // Written before anything is registered, so a bad pointer fails
+        // without leaving a child behind.
+        if flags.contains(CloneFlags::CLONE_PARENT_SETTID) && parent_tid != 0 {
+            UserPtr::<i32>::from(parent_tid).write(tid as _)?;
+        }
+
+        let aspace = if is_thread {
+            curr.task_ext().aspace.clone()
+        } else {
+            let aspace = axmm::fork_user_aspace(&mut curr.task_ext().aspace.lock())?;
+            let aspace = Arc::new(Mutex::new(aspace));
+            #[cfg(feature = "swap")]
+            axmm::swap::register_aspace(&aspace);
+            aspace
+        };
+
+        let mut uctx = UspaceContext::from(tf);
+        // The child returns from the syscall with 0. On riscv64, the saved
+        // `sepc` still points to the `ecall`, so it is skipped here.
+        #[cfg(target_arch = "riscv64")]
+        uctx.set_ip(uctx.get_ip() + 4);
+        uctx.set_retval(0);
+        if stack != 0 {
+            uctx.set_sp(stack);
+        }
+        if flags.contains(CloneFlags::CLONE_SETTLS) {
+            uctx.set_tls(tls);
+        }
+
+        task.ctx_mut()
+            .set_page_table_root(aspace.lock().page_table_root());
+        let process = if is_thread {
+            curr.task_ext().process.clone()
+        } else {
+            let process = Process::new(tid, Some(&curr.task_ext().process));
+            process.signals.inherit(&curr.task_ext().process.signals);
+            *process.heap.lock() = *curr.task_ext().process.heap.lock();
+            process.set_auxv(curr.task_ext().process.auxv());
+            process.shm.inherit(&curr.task_ext().process.shm, tid);
+            process
+        };
+        #[allow(unused_mut)]
+        let mut ext = TaskExt::new(process.clone(), uctx, aspace);
+        #[cfg(feature = "fd")]
+        if flags.contains(CloneFlags::CLONE_FILES) {
+            ext.set_fd_table(curr.task_ext().fd_table());
+        } else {
+            let fd_table = curr.task_ext().fd_table().read().clone();
+            ext.set_fd_table(Arc::new(spin::RwLock::new(fd_table)));
+        }
+        ext.signals.set_blocked(curr.task_ext().signals.blocked());
+        ext.set_traced(curr.task_ext().is_traced());
+        if flags.contains(CloneFlags::CLONE_CHILD_SETTID) {
+            ext.set_set_child_tid(child_tid as _);
+        }
+        if flags.contains(CloneFlags::CLONE_CHILD_CLEARTID) {
+            ext.set_clear_child_tid(child_tid as _);
+        }
+        task.init_task_ext(ext);
+        process.spawn_thread(task);
+        if flags.contains(CloneFlags::CLONE_VFORK) {
+            process.wait_vfork_done();
+        }
+        Ok(tid as isize)
+    })
+}
+
+/// Creates a child process, as `clone(SIGCHLD, 0)`.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_fork(tf: &TrapFrame) -> isize {
+    sys_clone(tf, SIGCHLD, 0, 0, 0, 0)
+}
+
+/// Creates a child process sharing the address space, and suspends the
+/// caller until the child exits or executes another program, as
+/// `clone(CLONE_VM | CLONE_VFORK | SIGCHLD, 0)`.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn sys_vfork(tf: &TrapFrame) -> isize {
+    let flags = CloneFlags::CLONE_VM | CloneFlags::CLONE_VFORK;
+    sys_clone(tf, flags.bits() as usize | SIGCHLD, 0, 0, 0, 0)
+}
+
+/// Reads a NULL-terminated array of C strings (e.g., `argv`).
+///
+/// Each string (including its NUL) and each pointer (including the NULL) is
+/// taken from the `space` left, and `E2BIG` is returned if it runs out, so
+/// that user space cannot make the kernel copy more than it can push onto
+/// the new user stack.
+#[cfg(feature = "fs")]
+fn read_str_array(ptr: UserPtr<usize>, space: &mut usize) -> LinuxResult<Vec<String>> {
+    let mut strs = Vec::new();
+    if ptr.is_null() {
+        return Ok(strs);
+    }
+    loop {
+        let ptr_size = core::mem::size_of::<usize>();
+        *space = space.checked_sub(ptr_size).ok_or(LinuxError::E2BIG)?;
+        let addr = ptr.address() + strs.len() * ptr_size;
+        let s = UserPtr::<usize>::from(addr).read()?;
+        if s == 0 {
+            break;
+        }
+        let s = UserCStr::from(s)
+            .read_cstring_max(*space)
+            .map_err(|err| match err {
+                LinuxError::ENAMETOOLONG => LinuxError::E2BIG,
+                err => err,
+            })?;
+        *space -= s.as_bytes_with_nul().len();
+        strs.push(s.into_string().map_err(|_| LinuxError::EINVAL)?);
+    }
+    Ok(strs)
+}
+
+/// Switches the current task to the page table of `aspace`.
+///
+/// # Safety
+///
+/// The previous page table must not be freed before the switch.
+#[cfg(feature = "fs")]
+unsafe fn switch_page_table(aspace: &AddrSpace) {
+    let root = aspace.page_table_root();
+    // Updates the saved root first, so a task switch in between still
+    // restores the new one.
+    (*current().ctx_mut_ptr()).set_page_table_root(root);
+    #[cfg(target_arch = "aarch64")]
+    axhal::arch::write_page_table_root0(root);
+    #[cfg(not(target_arch = "aarch64"))]
+    axhal::arch::write_page_table_root(root);
+}
+
+/// Replaces the address space of the current task with the ELF image at
+/// `path`, and returns the context to enter it.
+///
+/// The new image is built in a fresh address space, so the failures of
+/// loading it (e.g., `ENOEXEC` for a file that is not an executable ELF file,
+/// or `ENOENT` for a missing interpreter) are returned to the caller, whose
+/// image is kept. The other threads are killed and the image is replaced
+/// only after that.
+#[cfg(feature = "fs")]
+fn exec_user_app(
+    path: UserCStr,
+    argv: UserPtr<usize>,
+    envp: UserPtr<usize>,
+) -> LinuxResult<UspaceContext> {
+    use crate::loader::{init_user_stack, load_user_app, ARG_MAX};
+    use crate::mm::{read_auxv, Heap};
+
+    let path = path.read()?;
+    let mut space = ARG_MAX;
+    let args = read_str_array(argv, &mut space)?;
+    let envs = read_str_array(envp, &mut space)?;
+    info!("sys_execve <= {:?} {:?} {:?}", path, args, envs);
+    if !axfs::api::metadata(&path)?.is_file() {
+        return Err(LinuxError::EACCES);
+    }
+
+    let mut new_aspace = axmm::new_user_aspace()?;
+    let image = load_user_app(&path, &mut new_aspace).map_err(|err| {
+        warn!("sys_execve: failed to load {:?}: {:?}", path, err);
+        match err {
+            AxError::InvalidData | AxError::UnexpectedEof => LinuxError::ENOEXEC,
+            err => err.into(),
+        }
+    })?;
+    let ustack_top = init_user_stack(&mut new_aspace, &args, &envs, &image)?;
+    let auxv = read_auxv(&mut new_aspace, ustack_top.as_usize()).unwrap_or_default();
+
+    let curr = current();
+    // Other threads would run on the removed image, kill them first.
+    let process = &curr.task_ext().process;
+    process.kill_other_threads();
+    if !process.wait_other_threads() {
+        process::exit_killed();
+    }
+
+    // The `Arc` of the address space is kept, as it is registered for
+    // swapping.
+    let mut aspace = curr.task_ext().aspace.lock();
+    let old_aspace = core::mem::replace(&mut *aspace, new_aspace);
+    unsafe { switch_page_table(&aspace) };
+    drop(aspace);
+    drop(old_aspace);
+    process.shm.detach_all(process.pid());
+    process.release_vfork();
+    // A table shared with another process (`CLONE_FILES`) is not affected.
+    let fd_table = curr.task_ext().fd_table();
+    if Arc::strong_count(&fd_table) > 2 {
+        let unshared = fd_table.read().clone();
+        curr.task_ext()
+            .set_fd_table(Arc::new(spin::RwLock::new(unshared)));
+    }
+    drop(fd_table);
+    curr.task_ext().fd_table().write().close_on_exec();
+    process.signals.reset_on_exec();
+    *process.heap.lock() = Heap::new(image.brk);
+    process.set_auxv(auxv);
+    *process.cmdline.lock() = args;
+    Ok(UspaceContext::new(image.entry.as_usize(), ustack_top))
+}
+
+/// Executes the program at `path`.
+///
+/// The new image replaces the current address space only if it is loaded
+/// successfully. It never returns on success.
+#[cfg(feature = "fs")]
+pub(crate) fn sys_execve(path: UserCStr, argv: UserPtr<usize>, envp: UserPtr<usize>) -> isize {
+    // `syscall_body!` is not used as there is no return value on success.
+    match exec_user_app(path, argv, envp) {
+        Ok(uctx) => {
+            crate::trace::trace_no_return();
+            let kstack_top = current().kernel_stack_top().unwrap();
+            info!(
+                "Enter user space: entry={:#x}, ustack={:#x}, kstack={:#x}",
+                uctx.get_ip(),
+                uctx.get_sp(),
+                kstack_top,
+            );
+            unsafe { uctx.enter_uspace(kstack_top) }
+        }
+        Err(err) => {
+            info!("sys_execve => {:?}", err);
+            -err.code() as _
+        }
+    }
+}
+
+/// Waits for a child process to exit.
+///
+/// `pid` selects the children to wait for: the child with PID `pid` if it is
+/// positive, any child if it is -1, any child in the process group of the
+/// caller if it is 0, or any child in the process group `-pid` otherwise. The
+/// exit status is stored in `wstatus` (if not NULL), which can be decoded by
+/// `WIFEXITED`, `WIFSIGNALED` and so on.
+///
+/// With `WUNTRACED` or `WCONTINUED` in `options`, the children stopped or
+/// continued by signals are reported as well (see [`Process::wait_child`]).
+///
+/// Returns the PID of the child, or 0 if `WNOHANG` is set in `options` and no
+/// child has changed its state yet. Unsupported `options` fail with `EINVAL`.
+pub(crate) fn sys_wait4(pid: i32, wstatus: UserPtr<i32>, options: u32, _rusage: usize) -> isize {
+    syscall_body!(sys_wait4, {
+        let options = WaitOptions::from_bits(options & !__WALL).ok_or(LinuxError::EINVAL)?;
+        let process = current().task_ext().process.clone();
+        let target = match pid {
+            -1 => WaitTarget::Any,
+            0 => WaitTarget::Group(process.pgid()),
+            pid if pid > 0 => WaitTarget::Pid(pid as Pid),
+            pid => WaitTarget::Group(pid.unsigned_abs() as Pid),
+        };
+        match process.wait_child(target, options)? {
+            Some((pid, exit_status)) => {
+                if !wstatus.is_null() {
+                    wstatus.write(exit_status)?;
+                }
+                Ok(pid as isize)
+            }
+            None => Ok(0),
+        }
+    })
+}
@@ -0,0 +1,43 @@
+use core::time::Duration;
+
+use arceos_posix_api::{self as api, ctypes};
+use axerrno::LinuxError;
+use axtask::WaitQueue;
+
+use crate::ptr::UserPtr;
+use crate::signal;
+
+pub(crate) fn sys_clock_gettime(clk: ctypes::clockid_t, ts: UserPtr<ctypes::timespec>) -> isize {
+    syscall_body!(sys_clock_gettime, {
+        let mut kts = ctypes::timespec::default();
+        let ret = unsafe { api::sys_clock_gettime(clk, &mut kts) };
+        if ret == 0 {
+            ts.write(kts)?;
+        }
+        Ok(ret)
+    })
+}
+
+pub(crate) fn sys_nanosleep(
+    req: UserPtr<ctypes::timespec>,
+    rem: UserPtr<ctypes::timespec>,
+) -> isize {
+    syscall_body!(sys_nanosleep, {
+        let req = req.read()?;
+        if req.tv_sec < 0 || !(0..1_000_000_000).contains(&req.tv_nsec) {
+            return Err(LinuxError::EINVAL);
+        }
+        let dur = Duration::from(req);
+        let deadline = axhal::time::monotonic_time() + dur;
+        // Sleep on a queue of our own, which is only woken up by signals.
+        let wq = WaitQueue::new();
+        if let Err(err) = signal::wait_interruptible(&wq, Some(dur), || false) {
+            if !rem.is_null() {
+                let left = deadline.saturating_sub(axhal::time::monotonic_time());
+                rem.write(left.into())?;
+            }
+            return Err(err);
+        }
+        Ok(0)
+    })
+}
@@ -0,0 +1,72 @@
+//! Table-driven Linux syscall layer for [ArceOS] monolithic kernels.
+//!
+//! This crate registers the [`SYSCALL`] trap handler and dispatches each
+//! syscall through a numbered handler table. The table is pre-filled with the
+//! handlers of the Linux ABI of the target architecture (riscv64, aarch64 or
+//! x86_64) implemented here (see [`num`] for the numbers), so a new kernel
+//! only needs to [`register_syscall`] the ones it wants to add or override.
+//!
+//! It also provides the task extended data ([`task::TaskExt`]) shared by the
+//! monolithic kernels, as most syscalls need to access the state of the
+//! calling user task, and registers the [`PAGE_FAULT`] handler for user
+//! address spaces. POSIX [`signal`]s are delivered by the [`USER_RETURN`]
+//! handler, before the user tasks return to user space. User memory passed to
+//! syscalls is accessed through the checked pointers in [`ptr`].
+//!
+//! # Cargo Features
+//!
+//! - `fd`: Enable file descriptor related syscalls (`close`, `dup`, `fcntl`,
+//!   ...), and the terminals ([`tty`]) with pseudo-terminals.
+//! - `fs`: Enable filesystem related syscalls (`openat`, `fstat`,
+//!   `getdents64`, `execve`, ...), the ELF [`loader`], and the process entries
+//!   of `/proc` (`/proc/[pid]/stat`, `/proc/meminfo`, ...). It also enables
+//!   the `fd` feature.
+//! - `coredump`: Write an ELF core dump of a process killed by a signal
+//!   like `SIGSEGV` (see [`coredump`]). It also enables the `fs` feature.
+//! - `swap`: Allow the pages of user processes to be swapped out (see
+//!   [`axmm::swap`]).
+//! - `strace`: Log every syscall of all user tasks, like `strace` (see
+//!   [`trace`]).
+//!
+//! [ArceOS]: https://github.com/arceos-org/arceos
+//! [`SYSCALL`]: axhal::trap::SYSCALL
+//! [`PAGE_FAULT`]: axhal::trap::PAGE_FAULT
+//! [`USER_RETURN`]: axhal::trap::USER_RETURN
+
+#![no_std]
+
+#[macro_use]
+extern crate axlog;
+extern crate alloc;
+
+#[macro_use]
+mod macros;
+
+mod futex;
+mod imp;
+mod mm;
+#[cfg(feature = "fs")]
+mod procfs;
+mod table;
+
+#[cfg(feature = "coredump")]
+pub mod coredump;
+#[cfg(feature = "fs")]
+pub mod loader;
+pub mod num;
+pub mod process;
+pub mod ptr;
+pub mod shm;
+pub mod signal;
+pub mod task;
+pub mod trace;
+#[cfg(feature = "fd")]
+pub mod tty;
+
+pub use self::table::{register_syscall, syscall_handler, SyscallHandler, MAX_SYSCALL_NUM};
+
+#[doc(hidden)]
+pub mod __priv {
+    pub use axerrno;
+    pub use axlog::{debug, info};
+}
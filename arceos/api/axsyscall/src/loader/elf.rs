@@ -0,0 +1,343 @@
+//! Parsing ELF files and mapping their loadable segments.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ffi::CStr;
+
+use axerrno::{ax_err, ax_err_type, AxResult};
+use axfs::fops::{File, FileNodeRef, OpenOptions};
+use axhal::mem::{MemoryAddr, VirtAddr, PAGE_SIZE_4K};
+use axhal::paging::MappingFlags;
+use axmm::AddrSpace;
+use elf::abi::{ET_DYN, ET_EXEC, PF_R, PF_W, PF_X, PT_INTERP, PT_LOAD, PT_PHDR};
+use elf::endian::AnyEndian;
+use elf::parse::ParseAt;
+use elf::segment::{ProgramHeader, SegmentTable};
+use elf::ElfBytes;
+
+use crate::ptr::PATH_MAX;
+
+const ELF_HEAD_BUF_SIZE: usize = 256;
+
+/// An ELF file whose segments are loaded.
+pub(super) struct LoadedElf {
+    /// The load bias, which is zero for non-PIE executables.
+    pub base: usize,
+    /// The entry point.
+    pub entry: usize,
+    /// The address of the program headers, or `None` if they are not loaded
+    /// (i.e., there is no `PT_PHDR` and no loadable segment contains them).
+    pub phdr: Option<usize>,
+    /// The contents of the program headers.
+    pub phdr_data: Vec<u8>,
+    pub phnum: usize,
+    pub phentsize: usize,
+    /// The page-aligned end of the highest segment.
+    pub end: VirtAddr,
+    /// The path of the interpreter (`PT_INTERP`), if any.
+    pub interp: Option<String>,
+}
+
+/// A loadable segment, relocated by the load bias.
+struct Segment {
+    start: VirtAddr,
+    /// The end of the part initialized from the file.
+    file_end: VirtAddr,
+    end: VirtAddr,
+    /// The file offset of `start`.
+    offset: usize,
+    flags: MappingFlags,
+}
+
+/// Opens the executable at `path`, returns its VFS node and the absolute
+/// path.
+///
+/// Pages of file mappings are cached by the absolute paths, so the read-only
+/// pages of an executable are shared between the processes running it. The
+/// cached pages are updated when the file is written, and detached from the
+/// path when the file is replaced or removed, so a rebuilt executable is not
+/// run with the pages of the old one (see [`AddrSpace::mmap_file`]).
+fn open_exec(path: &str) -> AxResult<(FileNodeRef, String)> {
+    let path = axfs::api::canonicalize(path)?;
+    let mut opts = OpenOptions::new();
+    opts.read(true);
+    let node = File::open(&path, &opts)?.node(false)?.clone();
+    Ok((node, path))
+}
+
+/// Reads exactly `buf.len()` bytes at `offset` of the file.
+fn read_exact_at(node: &FileNodeRef, mut offset: u64, mut buf: &mut [u8]) -> AxResult {
+    while !buf.is_empty() {
+        match node.read_at(offset, buf)? {
+            0 => return ax_err!(UnexpectedEof, "failed to fill whole buffer"),
+            n => {
+                offset += n as u64;
+                buf = &mut buf[n..];
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Loads the segments of the ELF file at `path` into `uspace`.
+///
+/// Position-independent files (`ET_DYN`) are loaded at `dyn_base`, and
+/// executables (`ET_EXEC`) at their link addresses.
+///
+/// The segments are mapped through private file mappings, so their pages are
+/// read in on the first access, and the pages not written are shared between
+/// the processes running the same file.
+pub(super) fn load_elf(path: &str, dyn_base: usize, uspace: &mut AddrSpace) -> AxResult<LoadedElf> {
+    let (node, path) = open_exec(path)?;
+    let ehdr = load_elf_phdrs(&node)?;
+    let base = match ehdr.e_type {
+        ET_EXEC => 0,
+        ET_DYN => dyn_base,
+        _ => return ax_err!(InvalidData, "not an executable ELF file"),
+    };
+
+    // `PT_PHDR` gives the address of the program headers, if any.
+    let mut phdr_vaddr = ehdr
+        .phdrs
+        .iter()
+        .find(|phdr| phdr.p_type == PT_PHDR)
+        .map(|phdr| base + phdr.p_vaddr as usize);
+    let mut interp = None;
+    let mut segments: Vec<Segment> = Vec::new();
+    for phdr in &ehdr.phdrs {
+        debug!(
+            "phdr: offset: {:#X}=>{:#X} size: {:#X}=>{:#X} flags: {:#x}",
+            phdr.p_offset, phdr.p_vaddr, phdr.p_filesz, phdr.p_memsz, phdr.p_flags
+        );
+        if phdr.p_type == PT_INTERP {
+            if phdr.p_filesz > PATH_MAX as u64 {
+                return ax_err!(InvalidData, "interpreter path too long");
+            }
+            let mut buf = vec![0u8; phdr.p_filesz as usize];
+            read_exact_at(&node, phdr.p_offset, &mut buf)?;
+            let path = CStr::from_bytes_until_nul(&buf)
+                .ok()
+                .and_then(|path| path.to_str().ok())
+                .ok_or_else(|| ax_err_type!(InvalidData, "invalid interpreter path"))?;
+            interp = Some(String::from(path));
+            continue;
+        }
+        if phdr.p_type != PT_LOAD || phdr.p_memsz == 0 {
+            continue;
+        }
+        let segment = Segment::new(phdr, base, uspace)?;
+        // Otherwise, the program headers are located in the segment that
+        // contains them in the file.
+        let phoff = ehdr.phoff as u64;
+        if phdr_vaddr.is_none()
+            && phdr.p_offset <= phoff
+            && phdr
+                .p_offset
+                .checked_add(phdr.p_filesz)
+                .is_some_and(|file_end| phoff < file_end)
+        {
+            phdr_vaddr = Some(segment.start.as_usize() + (phoff - phdr.p_offset) as usize);
+        }
+        if segments.last().is_some_and(|prev| segment.start < prev.end) {
+            return ax_err!(InvalidData, "overlapping or unsorted segments");
+        }
+        segments.push(segment);
+        map_segment(&node, &path, &segments, uspace)?;
+    }
+
+    let end = match segments.last() {
+        Some(last) => last.end.align_up_4k(),
+        None => return ax_err!(InvalidData, "no loadable segment"),
+    };
+    Ok(LoadedElf {
+        base,
+        entry: base + ehdr.entry,
+        phdr: phdr_vaddr,
+        phdr_data: ehdr.data,
+        phnum: ehdr.phnum,
+        phentsize: ehdr.phentsize,
+        end,
+        interp,
+    })
+}
+
+/// Converts the `p_flags` of a segment to the mapping flags.
+fn segment_flags(p_flags: u32) -> MappingFlags {
+    let mut flags = MappingFlags::USER;
+    if p_flags & PF_R != 0 {
+        flags |= MappingFlags::READ;
+    }
+    if p_flags & PF_W != 0 {
+        flags |= MappingFlags::WRITE;
+    }
+    if p_flags & PF_X != 0 {
+        flags |= MappingFlags::EXECUTE;
+    }
+    flags
+}
+
+impl Segment {
+    /// Validates the program header, and relocates the segment by `base`.
+    fn new(phdr: &ProgramHeader, base: usize, uspace: &AddrSpace) -> AxResult<Self> {
+        if phdr.p_filesz > phdr.p_memsz {
+            return ax_err!(InvalidData, "segment file size exceeds memory size");
+        }
+        if phdr.p_offset as usize % PAGE_SIZE_4K != phdr.p_vaddr as usize % PAGE_SIZE_4K {
+            return ax_err!(InvalidData, "segment offset and address are not congruent");
+        }
+        let start = base
+            .checked_add(phdr.p_vaddr as usize)
+            .ok_or_else(|| ax_err_type!(InvalidData, "segment out of address space"))?;
+        let end = start
+            .checked_add(phdr.p_memsz as usize)
+            .filter(|end| *end <= usize::MAX - PAGE_SIZE_4K)
+            .ok_or_else(|| ax_err_type!(InvalidData, "segment out of address space"))?;
+        let (start, end) = (VirtAddr::from(start), VirtAddr::from(end));
+        if !uspace.contains_range(start, end - start) {
+            return ax_err!(InvalidData, "segment out of address space");
+        }
+        Ok(Self {
+            start,
+            file_end: start + phdr.p_filesz as usize,
+            end,
+            offset: phdr.p_offset as usize,
+            flags: segment_flags(phdr.p_flags),
+        })
+    }
+}
+
+/// Maps the last one of `segments`.
+///
+/// The pages that only contain the file contents are mapped from the file.
+/// The partial page at the end of the file contents, which also contains the
+/// beginning of the BSS, is copied into a private page, and the rest of the
+/// BSS is mapped to zero-filled pages on demand.
+///
+/// The last page of the previous segment may be shared with this one, in
+/// which case it is replaced by a private page with the contents and the flags
+/// of both.
+fn map_segment(
+    node: &FileNodeRef,
+    path: &str,
+    segments: &[Segment],
+    uspace: &mut AddrSpace,
+) -> AxResult {
+    let (seg, prev) = segments.split_last().unwrap();
+    let mut map_start = seg.start.align_down_4k();
+    let map_end = seg.end.align_up_4k();
+    debug!("{:#x} - {:#x} {:?}", map_start, map_end, seg.flags);
+    if prev
+        .last()
+        .is_some_and(|prev| map_start < prev.end.align_up_4k())
+    {
+        load_private_page(node, segments, map_start, uspace)?;
+        map_start += PAGE_SIZE_4K;
+    }
+
+    let file_map_end = if seg.end > seg.file_end {
+        seg.file_end.align_down_4k()
+    } else {
+        seg.file_end.align_up_4k()
+    };
+    if map_start < file_map_end {
+        let offset =
+            seg.offset - seg.start.align_offset_4k() + (map_start - seg.start.align_down_4k());
+        uspace.mmap_file(
+            map_start,
+            file_map_end - map_start,
+            seg.flags,
+            node.clone(),
+            path,
+            offset,
+            false,
+        )?;
+        map_start = file_map_end;
+    }
+    if map_start < map_end && map_start < seg.file_end {
+        load_private_page(node, segments, map_start, uspace)?;
+        map_start += PAGE_SIZE_4K;
+    }
+    if map_start < map_end {
+        uspace.map_alloc(map_start, map_end - map_start, seg.flags, false)?;
+    }
+    Ok(())
+}
+
+/// Maps a private page at `page`, with the flags of all `segments` in it and
+/// their file contents, replacing the existing mapping.
+fn load_private_page(
+    node: &FileNodeRef,
+    segments: &[Segment],
+    page: VirtAddr,
+    uspace: &mut AddrSpace,
+) -> AxResult {
+    let page_end = page + PAGE_SIZE_4K;
+    let mut buf = vec![0u8; PAGE_SIZE_4K];
+    let mut flags = MappingFlags::empty();
+    for seg in segments {
+        if seg.end <= page || seg.start >= page_end {
+            continue;
+        }
+        flags |= seg.flags;
+        let (start, end) = (seg.start.max(page), seg.file_end.min(page_end));
+        if start < end {
+            let offset = seg.offset + (start - seg.start);
+            read_exact_at(node, offset as u64, &mut buf[start - page..end - page])?;
+        }
+    }
+    uspace.unmap(page, PAGE_SIZE_4K)?;
+    // Writes through the page table, regardless of the mapping flags.
+    uspace.map_alloc(page, PAGE_SIZE_4K, flags, true)?;
+    uspace.write(page, &buf)
+}
+
+/// The program headers and the related fields of the ELF header.
+struct ElfPhdrs {
+    phdrs: Vec<ProgramHeader>,
+    /// The raw program header table.
+    data: Vec<u8>,
+    e_type: u16,
+    entry: usize,
+    phoff: usize,
+    phnum: usize,
+    phentsize: usize,
+}
+
+fn load_elf_phdrs(node: &FileNodeRef) -> AxResult<ElfPhdrs> {
+    let mut buf: [u8; ELF_HEAD_BUF_SIZE] = [0; ELF_HEAD_BUF_SIZE];
+    let _ = node.read_at(0, &mut buf)?;
+
+    let ehdr = ElfBytes::<AnyEndian>::parse_elf_header(&buf[..])
+        .map_err(|_| ax_err_type!(InvalidData, "invalid ELF header"))?;
+    info!("e_entry: {:#X}", ehdr.e_entry);
+
+    let phnum = ehdr.e_phnum as usize;
+    // Validate phentsize before trying to read the table so that we can error early for corrupted files
+    let entsize = ProgramHeader::validate_entsize(ehdr.class, ehdr.e_phentsize as usize)
+        .map_err(|_| ax_err_type!(InvalidData, "invalid program header size"))?;
+    let size = entsize
+        .checked_mul(phnum)
+        .ok_or_else(|| ax_err_type!(InvalidData, "too many program headers"))?;
+    if size == 0 || size > PAGE_SIZE_4K {
+        return ax_err!(InvalidData, "invalid program header table size");
+    }
+    let phoff = ehdr.e_phoff;
+    let mut buf = vec![0u8; size];
+    read_exact_at(node, phoff, &mut buf)?;
+    let phdrs = SegmentTable::new(ehdr.endianness, ehdr.class, &buf[..]);
+
+    let phdrs: Vec<ProgramHeader> = phdrs
+        .iter()
+        .filter(|phdr| matches!(phdr.p_type, PT_LOAD | PT_INTERP | PT_PHDR))
+        .collect();
+    Ok(ElfPhdrs {
+        phdrs,
+        data: buf,
+        e_type: ehdr.e_type,
+        entry: ehdr.e_entry as usize,
+        phoff: ehdr.e_phoff as usize,
+        phnum,
+        phentsize: entsize,
+    })
+}
@@ -0,0 +1,235 @@
+//! Loading ELF images and setting up the user stack.
+//!
+//! # Address space layout randomization
+//!
+//! Unless disabled (see [`set_aslr`]), the load bias of position-independent
+//! executables, the base of the dynamic linker, the start of the heap, the
+//! base of `mmap` (see [`AddrSpace::mmap_base`]) and the top of the user
+//! stack are shifted by random numbers of pages each time a program is
+//! loaded. The regions stay in the same order:
+//!
+//! | Region         | Base               | Random shift     |
+//! |----------------|--------------------|------------------|
+//! | PIE            | `0x1000_0000`      | < 256 MiB        |
+//! | heap           | end of the image   | < 32 MiB         |
+//! | `mmap`         | `0x10_0000_0000`   | < 16 GiB         |
+//! | dynamic linker | `0x20_0000_0000`   | < 16 GiB         |
+//! | stack (top)    | signal trampoline  | < 16 GiB (down)  |
+//!
+//! The stack is kept below the page of the signal trampoline (see
+//! [`SIGNAL_TRAMPOLINE`]), which is mapped on demand near the end of the
+//! space.
+//!
+//! [`SIGNAL_TRAMPOLINE`]: crate::signal::SIGNAL_TRAMPOLINE
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use axerrno::{ax_err, AxResult};
+use axhal::mem::{MemoryAddr, VirtAddr, PAGE_SIZE_4K};
+use axhal::paging::MappingFlags;
+use axmm::AddrSpace;
+
+use self::elf::load_elf;
+use crate::signal::SIGNAL_TRAMPOLINE;
+
+mod elf;
+
+/// The size of the user stack.
+pub const USER_STACK_SIZE: usize = 0x10000;
+
+/// The maximum total size of the arguments and environment variables passed
+/// to a program, including their pointers.
+///
+/// As on Linux, it is a quarter of the user stack, which leaves the rest of
+/// the stack to the program.
+pub const ARG_MAX: usize = USER_STACK_SIZE / 4;
+
+/// The base address where position-independent executables are loaded.
+const PIE_BASE: usize = 0x1000_0000;
+
+/// The base address where the dynamic linker (the interpreter in
+/// `PT_INTERP`) is loaded.
+const INTERP_BASE: usize = 0x20_0000_0000;
+
+/// The base address of the mappings without a given address, far above the
+/// program and its heap.
+const MMAP_BASE: usize = 0x10_0000_0000;
+
+// The numbers of random bits of the page offsets of the regions.
+const PIE_RANDOM_BITS: u32 = 16;
+const HEAP_RANDOM_BITS: u32 = 13;
+const MMAP_RANDOM_BITS: u32 = 22;
+const INTERP_RANDOM_BITS: u32 = 22;
+const STACK_RANDOM_BITS: u32 = 22;
+
+/// Whether the address space layout is randomized. It is enabled unless the
+/// `AX_ASLR` environment variable is `n` at build time (i.e., `make ASLR=n`).
+static ASLR: AtomicBool = AtomicBool::new(!matches!(option_env!("AX_ASLR"), Some("n")));
+
+/// Enables or disables address space layout randomization.
+///
+/// The initial setting is given by `make ASLR=y|n`. Disabling it keeps the
+/// addresses of the programs the same on each run, e.g., when reproducing a
+/// failure. It takes effect on the programs loaded afterwards.
+pub fn set_aslr(enabled: bool) {
+    ASLR.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns a random offset of less than `2 ^ bits` pages, or 0 if the layout
+/// is not randomized.
+fn random_offset(bits: u32) -> usize {
+    if !ASLR.load(Ordering::Relaxed) {
+        return 0;
+    }
+    (axhal::misc::random() as usize & ((1 << bits) - 1)) * PAGE_SIZE_4K
+}
+
+/// The number of clock ticks per second reported by `AT_CLKTCK`.
+const CLOCK_TICKS_PER_SEC: usize = 100;
+
+/// Auxiliary vector entry types.
+///
+/// See <https://man7.org/linux/man-pages/man3/getauxval.3.html>
+mod auxv {
+    pub const AT_PHDR: u8 = 3;
+    pub const AT_PHENT: u8 = 4;
+    pub const AT_PHNUM: u8 = 5;
+    pub const AT_PAGESZ: u8 = 6;
+    pub const AT_BASE: u8 = 7;
+    pub const AT_FLAGS: u8 = 8;
+    pub const AT_ENTRY: u8 = 9;
+    pub const AT_UID: u8 = 11;
+    pub const AT_EUID: u8 = 12;
+    pub const AT_GID: u8 = 13;
+    pub const AT_EGID: u8 = 14;
+    pub const AT_HWCAP: u8 = 16;
+    pub const AT_CLKTCK: u8 = 17;
+    pub const AT_SECURE: u8 = 23;
+}
+
+/// An ELF image loaded into a user address space.
+pub struct ElfImage {
+    /// The entry point, which is the entry of the dynamic linker for
+    /// dynamically linked programs.
+    pub entry: VirtAddr,
+    /// The auxiliary vector to be passed to the program.
+    pub auxv: BTreeMap<u8, usize>,
+    /// The initial program break, i.e., the page-aligned end of the highest
+    /// loadable segment, plus a random gap with address space layout
+    /// randomization.
+    pub brk: VirtAddr,
+    /// The program headers to be copied onto the user stack, as they are not
+    /// loaded with the program (`AT_PHDR` is not in `auxv` then).
+    phdrs: Option<Vec<u8>>,
+}
+
+/// Loads the ELF file at `path` into `uspace`.
+///
+/// If the program is dynamically linked, its interpreter (i.e., the dynamic
+/// linker such as `ld-musl`) is loaded as well at a separate base, and the
+/// program starts from the entry of the interpreter. The interpreter finds
+/// the program by the auxiliary vector (`AT_PHDR`, `AT_ENTRY`, ...).
+///
+/// The base of `mmap` of `uspace` is set as well, see the
+/// [module-level documentation](self) for the layout.
+pub fn load_user_app(path: &str, uspace: &mut AddrSpace) -> AxResult<ElfImage> {
+    let elf = load_elf(path, PIE_BASE + random_offset(PIE_RANDOM_BITS), uspace)?;
+    uspace.set_mmap_base(VirtAddr::from(MMAP_BASE + random_offset(MMAP_RANDOM_BITS)));
+
+    let mut auxv = BTreeMap::new();
+    let phdrs = match elf.phdr {
+        Some(phdr) => {
+            auxv.insert(auxv::AT_PHDR, phdr);
+            None
+        }
+        None => Some(elf.phdr_data),
+    };
+    auxv.insert(auxv::AT_PHENT, elf.phentsize);
+    auxv.insert(auxv::AT_PHNUM, elf.phnum);
+    auxv.insert(auxv::AT_PAGESZ, PAGE_SIZE_4K);
+    auxv.insert(auxv::AT_ENTRY, elf.entry);
+    auxv.insert(auxv::AT_FLAGS, 0);
+    auxv.insert(auxv::AT_UID, 0);
+    auxv.insert(auxv::AT_EUID, 0);
+    auxv.insert(auxv::AT_GID, 0);
+    auxv.insert(auxv::AT_EGID, 0);
+    auxv.insert(auxv::AT_HWCAP, 0);
+    auxv.insert(auxv::AT_CLKTCK, CLOCK_TICKS_PER_SEC);
+    auxv.insert(auxv::AT_SECURE, 0);
+
+    let entry = match &elf.interp {
+        Some(interp) => {
+            debug!("interpreter: {}", interp);
+            let interp_base = INTERP_BASE + random_offset(INTERP_RANDOM_BITS);
+            let ld = load_elf(interp, interp_base, uspace)?;
+            if ld.base == 0 {
+                return ax_err!(InvalidData, "the interpreter is not position-independent");
+            }
+            if ld.interp.is_some() {
+                return ax_err!(InvalidData, "the interpreter requests an interpreter");
+            }
+            auxv.insert(auxv::AT_BASE, ld.base);
+            ld.entry
+        }
+        None => {
+            auxv.insert(auxv::AT_BASE, 0);
+            elf.entry
+        }
+    };
+    Ok(ElfImage {
+        entry: entry.into(),
+        auxv,
+        brk: elf.end + random_offset(HEAP_RANDOM_BITS),
+        phdrs,
+    })
+}
+
+/// Maps the user stack below the signal trampoline page (and a random gap,
+/// see [`set_aslr`]), and pushes the arguments, the environment variables and
+/// the auxiliary vector of `image` onto it.
+///
+/// If the program headers of `image` are not loaded with it, they are copied
+/// to the top of the stack, where `AT_PHDR` points.
+///
+/// Returns the initial user stack pointer.
+pub fn init_user_stack(
+    uspace: &mut AddrSpace,
+    args: &[String],
+    envs: &[String],
+    image: &ElfImage,
+) -> AxResult<VirtAddr> {
+    let stack_end = uspace.end().min(VirtAddr::from(SIGNAL_TRAMPOLINE));
+    let ustack_top = stack_end - random_offset(STACK_RANDOM_BITS);
+    let ustack_vaddr = ustack_top - USER_STACK_SIZE;
+    debug!(
+        "Mapping user stack: {:#x?} -> {:#x?}",
+        ustack_vaddr, ustack_top
+    );
+    uspace.map_alloc(
+        ustack_vaddr,
+        USER_STACK_SIZE,
+        MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
+        true,
+    )?;
+
+    let mut auxv = image.auxv.clone();
+    let mut data_top = ustack_top;
+    if let Some(phdrs) = &image.phdrs {
+        data_top = (ustack_top - phdrs.len()).align_down(16usize);
+        uspace.write(data_top, phdrs)?;
+        auxv.insert(auxv::AT_PHDR, data_top.as_usize());
+    }
+    let (stack_data, ustack_pointer) = kernel_elf_parser::get_app_stack_region(
+        args,
+        envs,
+        &auxv,
+        ustack_vaddr,
+        data_top - ustack_vaddr,
+    );
+    uspace.write(VirtAddr::from_usize(ustack_pointer), stack_data.as_slice())?;
+
+    Ok(ustack_pointer.into())
+}
@@ -0,0 +1,23 @@
+/// Macro to generate syscall body
+///
+/// It will receive a function which return Result<_, LinuxError> and convert it to
+/// the type which is specified by the caller.
+#[macro_export]
+macro_rules! syscall_body {
+    ($fn: ident, $($stmt: tt)*) => {{
+        #[allow(clippy::redundant_closure_call)]
+        let res = (|| -> $crate::__priv::axerrno::LinuxResult<_> { $($stmt)* })();
+        match res {
+            Ok(_) | Err($crate::__priv::axerrno::LinuxError::EAGAIN) => {
+                $crate::__priv::debug!(concat!(stringify!($fn), " => {:?}"), res)
+            }
+            Err(_) => $crate::__priv::info!(concat!(stringify!($fn), " => {:?}"), res),
+        }
+        match res {
+            Ok(v) => v as _,
+            Err(e) => {
+                -e.code() as _
+            }
+        }
+    }};
+}
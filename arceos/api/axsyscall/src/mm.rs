@@ -0,0 +1,247 @@
+//! Page fault handling and the heap of user address spaces.
+
+use alloc::vec::Vec;
+use core::sync::atomic::AtomicU32;
+
+use axhal::mem::{phys_to_virt, MemoryAddr, VirtAddr, PAGE_SIZE_4K};
+use axhal::paging::MappingFlags;
+use axhal::trap::{register_trap_handler, PAGE_FAULT};
+use axmm::AddrSpace;
+use axtask::TaskExtRef;
+
+use crate::signal::{self, SIGSEGV};
+
+/// Handles page faults on the address space of the current user task.
+///
+/// Faults raised by the kernel itself are handled as well (e.g., when a
+/// syscall writes to a lazily allocated or copy-on-write user buffer).
+///
+/// A fault from user space that cannot be resolved raises `SIGSEGV`, which is
+/// delivered before returning to user space.
+#[register_trap_handler(PAGE_FAULT)]
+fn handle_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool) -> bool {
+    let curr = axtask::current();
+    if unsafe { curr.task_ext_ptr() }.is_null() {
+        // Kernel tasks have no user address space.
+        return false;
+    }
+    if curr
+        .task_ext()
+        .aspace
+        .lock()
+        .handle_page_fault(vaddr, access_flags)
+    {
+        debug!("{}: handle page fault OK @ {:#x}", curr.id_name(), vaddr);
+        return true;
+    }
+    if is_user {
+        warn!(
+            "{}: segmentation fault @ {:#x} ({:?})",
+            curr.id_name(),
+            vaddr,
+            access_flags
+        );
+        signal::force_signal(SIGSEGV);
+        return true;
+    }
+    false
+}
+
+/// Accesses the user memory `[start, start + size)` of the current task with
+/// `access_flags`.
+///
+/// The memory is accessed through the page table rather than by the user
+/// addresses, with the address space locked, so that it cannot be unmapped by
+/// other threads in the meantime, and the kernel never faults on it. `f` is
+/// called on each part of the memory within a page, with the kernel address
+/// of the part, its offset in the range, and its size. Pages that are not yet
+/// populated (or shared with copy-on-write) are faulted in first.
+///
+/// Returns `false` if some pages cannot be accessed.
+fn access_user(
+    start: usize,
+    size: usize,
+    access_flags: MappingFlags,
+    mut f: impl FnMut(*mut u8, usize, usize),
+) -> bool {
+    if start.checked_add(size).is_none() {
+        return false;
+    }
+    let access_flags = access_flags | MappingFlags::USER;
+    let curr = axtask::current();
+    let mut aspace = curr.task_ext().aspace.lock();
+    let mut offset = 0;
+    while offset < size {
+        let vaddr = VirtAddr::from(start + offset);
+        let query = |aspace: &AddrSpace| match aspace.page_table().query(vaddr) {
+            Ok((paddr, flags, _)) if flags.contains(access_flags) => Some(paddr),
+            _ => None,
+        };
+        let paddr = match query(&aspace) {
+            Some(paddr) => paddr,
+            None if aspace.handle_page_fault(vaddr, access_flags) => match query(&aspace) {
+                Some(paddr) => paddr,
+                None => return false,
+            },
+            None => return false,
+        };
+        let len = (PAGE_SIZE_4K - vaddr.align_offset_4k()).min(size - offset);
+        f(phys_to_virt(paddr).as_mut_ptr(), offset, len);
+        offset += len;
+    }
+    true
+}
+
+/// Copies `size` bytes from the user memory at `src` of the current task to
+/// `dst`.
+///
+/// Returns `false` if the user memory cannot be read.
+///
+/// # Safety
+///
+/// `dst` must be valid for writes of `size` bytes.
+pub(crate) unsafe fn copy_from_user(dst: *mut u8, src: usize, size: usize) -> bool {
+    access_user(src, size, MappingFlags::READ, |ptr, offset, len| unsafe {
+        core::ptr::copy_nonoverlapping(ptr, dst.add(offset), len)
+    })
+}
+
+/// Copies `size` bytes from `src` to the user memory at `dst` of the current
+/// task.
+///
+/// Returns `false` if the user memory cannot be written.
+///
+/// # Safety
+///
+/// `src` must be valid for reads of `size` bytes.
+pub(crate) unsafe fn copy_to_user(dst: usize, src: *const u8, size: usize) -> bool {
+    access_user(dst, size, MappingFlags::WRITE, |ptr, offset, len| unsafe {
+        core::ptr::copy_nonoverlapping(src.add(offset), ptr, len)
+    })
+}
+
+/// Calls `f` on the aligned 32-bit word at `addr` of the current task, which
+/// is accessed atomically (e.g., a futex word), with `access_flags`.
+///
+/// Returns `None` if the word cannot be accessed.
+pub(crate) fn with_user_u32<R>(
+    addr: usize,
+    access_flags: MappingFlags,
+    f: impl FnOnce(&AtomicU32) -> R,
+) -> Option<R> {
+    if addr % 4 != 0 {
+        return None;
+    }
+    // An aligned word is always within a page, so `f` is called only once.
+    let mut f = Some(f);
+    let mut ret = None;
+    access_user(addr, 4, access_flags, |ptr, _, _| {
+        ret = f.take().map(|f| f(unsafe { &*(ptr as *const AtomicU32) }));
+    });
+    ret
+}
+
+/// Reads the auxiliary vector from the initial user stack at `sp` in
+/// `aspace`, which holds `argc`, the `argv` and `envp` arrays, and then the
+/// auxiliary vector (as pushed by `init_user_stack` of the ELF loader).
+///
+/// Returns the key-value pairs up to and including `AT_NULL`, or `None` if
+/// the stack does not have this layout.
+pub(crate) fn read_auxv(aspace: &mut AddrSpace, sp: usize) -> Option<Vec<usize>> {
+    const WORD: usize = core::mem::size_of::<usize>();
+    let mut read_word = |addr: usize| {
+        let mut buf = [0; WORD];
+        aspace.read(VirtAddr::from(addr), &mut buf).ok()?;
+        Some(usize::from_ne_bytes(buf))
+    };
+    // Skip `argc`, `argv` and its terminating NULL, then `envp`.
+    let argc = read_word(sp)?;
+    let mut addr = sp.checked_add(argc.checked_add(2)?.checked_mul(WORD)?)?;
+    while read_word(addr)? != 0 {
+        addr += WORD;
+    }
+    addr += WORD;
+    let mut auxv = Vec::new();
+    loop {
+        let key = read_word(addr)?;
+        auxv.extend([key, read_word(addr + WORD)?]);
+        if key == 0 {
+            return Some(auxv);
+        }
+        addr += 2 * WORD;
+    }
+}
+
+/// The heap of a process, whose end is the program break.
+///
+/// The heap starts right after the highest loaded segment. Pages are mapped
+/// lazily (see [`AddrSpace::map_alloc`]) as the break grows, and unmapped as
+/// it shrinks.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Heap {
+    bottom: VirtAddr,
+    top: VirtAddr,
+    /// The end of the pages mapped for the heap.
+    mapped_end: VirtAddr,
+}
+
+impl Heap {
+    /// Creates an empty heap starting at `bottom`, which should be page
+    /// aligned.
+    pub const fn new(bottom: VirtAddr) -> Self {
+        Self {
+            bottom,
+            top: bottom,
+            mapped_end: bottom,
+        }
+    }
+
+    /// Returns the start of the heap.
+    pub const fn bottom(&self) -> VirtAddr {
+        self.bottom
+    }
+
+    /// Returns the current program break.
+    pub const fn top(&self) -> VirtAddr {
+        self.top
+    }
+
+    /// Moves the program break to `new_top`, growing or shrinking the heap
+    /// area in `aspace` as needed.
+    ///
+    /// The area is created by the first growth, and is grown in place
+    /// afterwards (see [`AddrSpace::grow_area`]), keeping its flags and
+    /// backend. Pages above the new break are unmapped on shrinking.
+    ///
+    /// The break cannot go below the start of the heap, out of the address
+    /// space, or into neighbouring mappings. Returns the new break, or the
+    /// current one if it cannot be moved.
+    pub fn set_top(&mut self, aspace: &mut AddrSpace, new_top: VirtAddr) -> VirtAddr {
+        if new_top < self.bottom || !aspace.contains_range(self.bottom, new_top - self.bottom) {
+            return self.top;
+        }
+        let new_end = new_top.align_up_4k();
+        if new_end > self.mapped_end {
+            let size = new_end - self.mapped_end;
+            let grown = if self.mapped_end > self.bottom {
+                aspace.grow_area(self.mapped_end, size)
+            } else {
+                let flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER;
+                aspace.map_alloc(self.mapped_end, size, flags, false)
+            };
+            if let Err(err) = grown {
+                debug!("cannot grow the heap to {:#x}: {:?}", new_top, err);
+                return self.top;
+            }
+            self.mapped_end = new_end;
+        } else if new_end < self.mapped_end {
+            if let Err(err) = aspace.unmap(new_end, self.mapped_end - new_end) {
+                debug!("cannot shrink the heap to {:#x}: {:?}", new_top, err);
+                return self.top;
+            }
+            self.mapped_end = new_end;
+        }
+        self.top = new_top;
+        self.top
+    }
+}
@@ -0,0 +1,123 @@
+//! Syscall numbers of the generic Linux ABI, used by riscv64 and aarch64.
+//!
+//! See <https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/unistd.h>.
+
+#![allow(missing_docs)]
+
+syscall_numbers! {
+    SYS_GETCWD = 17,
+    SYS_EVENTFD2 = 19,
+    SYS_EPOLL_CREATE1 = 20,
+    SYS_EPOLL_CTL = 21,
+    SYS_EPOLL_PWAIT = 22,
+    SYS_DUP = 23,
+    SYS_DUP3 = 24,
+    SYS_FCNTL = 25,
+    SYS_IOCTL = 29,
+    SYS_MKDIRAT = 34,
+    SYS_UNLINKAT = 35,
+    SYS_LINKAT = 37,
+    SYS_RENAMEAT = 38,
+    SYS_UMOUNT2 = 39,
+    SYS_MOUNT = 40,
+    SYS_STATFS = 43,
+    SYS_FTRUNCATE = 46,
+    SYS_FACCESSAT = 48,
+    SYS_CHDIR = 49,
+    SYS_FCHMODAT = 53,
+    SYS_FCHOWNAT = 54,
+    SYS_OPENAT = 56,
+    SYS_CLOSE = 57,
+    SYS_PIPE2 = 59,
+    SYS_GETDENTS64 = 61,
+    SYS_LSEEK = 62,
+    SYS_READ = 63,
+    SYS_WRITE = 64,
+    SYS_READV = 65,
+    SYS_WRITEV = 66,
+    SYS_PREAD64 = 67,
+    SYS_PWRITE64 = 68,
+    SYS_SENDFILE = 71,
+    SYS_PSELECT6 = 72,
+    SYS_PPOLL = 73,
+    SYS_READLINKAT = 78,
+    SYS_NEWFSTATAT = 79,
+    SYS_FSTAT = 80,
+    SYS_SYNC = 81,
+    SYS_FSYNC = 82,
+    SYS_UTIMENSAT = 88,
+    SYS_EXIT = 93,
+    SYS_EXIT_GROUP = 94,
+    SYS_WAITID = 95,
+    SYS_SET_TID_ADDRESS = 96,
+    SYS_FUTEX = 98,
+    SYS_SET_ROBUST_LIST = 99,
+    SYS_GET_ROBUST_LIST = 100,
+    SYS_NANOSLEEP = 101,
+    SYS_SETITIMER = 103,
+    SYS_CLOCK_GETTIME = 113,
+    SYS_CLOCK_GETRES = 114,
+    SYS_CLOCK_NANOSLEEP = 115,
+    SYS_SYSLOG = 116,
+    SYS_SCHED_YIELD = 124,
+    SYS_KILL = 129,
+    SYS_TKILL = 130,
+    SYS_TGKILL = 131,
+    SYS_SIGALTSTACK = 132,
+    SYS_RT_SIGSUSPEND = 133,
+    SYS_RT_SIGACTION = 134,
+    SYS_RT_SIGPROCMASK = 135,
+    SYS_RT_SIGPENDING = 136,
+    SYS_RT_SIGTIMEDWAIT = 137,
+    SYS_RT_SIGRETURN = 139,
+    SYS_SETGID = 144,
+    SYS_SETUID = 146,
+    SYS_TIMES = 153,
+    SYS_SETPGID = 154,
+    SYS_GETPGID = 155,
+    SYS_GETSID = 156,
+    SYS_SETSID = 157,
+    SYS_UNAME = 160,
+    SYS_GETRLIMIT = 163,
+    SYS_SETRLIMIT = 164,
+    SYS_GETRUSAGE = 165,
+    SYS_UMASK = 166,
+    SYS_PRCTL = 167,
+    SYS_GETTIMEOFDAY = 169,
+    SYS_GETPID = 172,
+    SYS_GETPPID = 173,
+    SYS_GETUID = 174,
+    SYS_GETEUID = 175,
+    SYS_GETGID = 176,
+    SYS_GETEGID = 177,
+    SYS_GETTID = 178,
+    SYS_SYSINFO = 179,
+    SYS_SHMGET = 194,
+    SYS_SHMCTL = 195,
+    SYS_SHMAT = 196,
+    SYS_SHMDT = 197,
+    SYS_SOCKET = 198,
+    SYS_BIND = 200,
+    SYS_LISTEN = 201,
+    SYS_ACCEPT = 202,
+    SYS_CONNECT = 203,
+    SYS_SENDTO = 206,
+    SYS_RECVFROM = 207,
+    SYS_BRK = 214,
+    SYS_MUNMAP = 215,
+    SYS_MREMAP = 216,
+    SYS_CLONE = 220,
+    SYS_EXECVE = 221,
+    SYS_MMAP = 222,
+    SYS_SWAPON = 224,
+    SYS_MPROTECT = 226,
+    SYS_MSYNC = 227,
+    SYS_MADVISE = 233,
+    SYS_WAIT4 = 260,
+    SYS_PRLIMIT64 = 261,
+    SYS_GETRANDOM = 278,
+    SYS_MEMFD_CREATE = 279,
+    SYS_MEMBARRIER = 283,
+    SYS_STATX = 291,
+    SYS_CLONE3 = 435,
+}
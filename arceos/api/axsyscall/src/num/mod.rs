@@ -0,0 +1,28 @@
+//! Syscall numbers of the Linux ABI of the target architecture.
+
+/// Defines the syscall number constants, and [`syscall_name`] to look up
+/// their names.
+macro_rules! syscall_numbers {
+    ($($name: ident = $num: literal,)*) => {
+        $(pub const $name: usize = $num;)*
+
+        /// Returns the name of the constant of syscall `num` (e.g.,
+        /// `"SYS_OPENAT"`), or `None` if it is unknown.
+        pub fn syscall_name(num: usize) -> Option<&'static str> {
+            match num {
+                $($num => Some(stringify!($name)),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+mod generic;
+#[cfg(target_arch = "x86_64")]
+mod x86_64;
+
+#[cfg(not(target_arch = "x86_64"))]
+pub use self::generic::*;
+#[cfg(target_arch = "x86_64")]
+pub use self::x86_64::*;
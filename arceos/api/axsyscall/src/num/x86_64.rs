@@ -0,0 +1,137 @@
+//! Syscall numbers of the x86_64 Linux ABI.
+//!
+//! See <https://github.com/torvalds/linux/blob/master/arch/x86/entry/syscalls/syscall_64.tbl>.
+
+#![allow(missing_docs)]
+
+syscall_numbers! {
+    SYS_READ = 0,
+    SYS_WRITE = 1,
+    SYS_OPEN = 2,
+    SYS_CLOSE = 3,
+    SYS_STAT = 4,
+    SYS_FSTAT = 5,
+    SYS_LSTAT = 6,
+    SYS_POLL = 7,
+    SYS_LSEEK = 8,
+    SYS_MMAP = 9,
+    SYS_MPROTECT = 10,
+    SYS_MUNMAP = 11,
+    SYS_BRK = 12,
+    SYS_RT_SIGACTION = 13,
+    SYS_RT_SIGPROCMASK = 14,
+    SYS_RT_SIGRETURN = 15,
+    SYS_IOCTL = 16,
+    SYS_PREAD64 = 17,
+    SYS_PWRITE64 = 18,
+    SYS_READV = 19,
+    SYS_WRITEV = 20,
+    SYS_ACCESS = 21,
+    SYS_PIPE = 22,
+    SYS_SCHED_YIELD = 24,
+    SYS_MREMAP = 25,
+    SYS_MSYNC = 26,
+    SYS_MADVISE = 28,
+    SYS_SHMGET = 29,
+    SYS_SHMAT = 30,
+    SYS_SHMCTL = 31,
+    SYS_DUP = 32,
+    SYS_DUP2 = 33,
+    SYS_NANOSLEEP = 35,
+    SYS_SETITIMER = 38,
+    SYS_GETPID = 39,
+    SYS_SENDFILE = 40,
+    SYS_SOCKET = 41,
+    SYS_CONNECT = 42,
+    SYS_ACCEPT = 43,
+    SYS_SENDTO = 44,
+    SYS_RECVFROM = 45,
+    SYS_BIND = 49,
+    SYS_LISTEN = 50,
+    SYS_CLONE = 56,
+    SYS_FORK = 57,
+    SYS_VFORK = 58,
+    SYS_EXECVE = 59,
+    SYS_EXIT = 60,
+    SYS_WAIT4 = 61,
+    SYS_KILL = 62,
+    SYS_UNAME = 63,
+    SYS_SHMDT = 67,
+    SYS_FCNTL = 72,
+    SYS_FSYNC = 74,
+    SYS_FTRUNCATE = 77,
+    SYS_GETCWD = 79,
+    SYS_CHDIR = 80,
+    SYS_MKDIR = 83,
+    SYS_UNLINK = 87,
+    SYS_READLINK = 89,
+    SYS_UMASK = 95,
+    SYS_GETTIMEOFDAY = 96,
+    SYS_GETRLIMIT = 97,
+    SYS_GETRUSAGE = 98,
+    SYS_SYSINFO = 99,
+    SYS_TIMES = 100,
+    SYS_GETUID = 102,
+    SYS_SYSLOG = 103,
+    SYS_GETGID = 104,
+    SYS_SETUID = 105,
+    SYS_SETGID = 106,
+    SYS_GETEUID = 107,
+    SYS_GETEGID = 108,
+    SYS_SETPGID = 109,
+    SYS_GETPPID = 110,
+    SYS_GETPGRP = 111,
+    SYS_SETSID = 112,
+    SYS_GETPGID = 121,
+    SYS_GETSID = 124,
+    SYS_RT_SIGPENDING = 127,
+    SYS_RT_SIGTIMEDWAIT = 128,
+    SYS_RT_SIGSUSPEND = 130,
+    SYS_SIGALTSTACK = 131,
+    SYS_STATFS = 137,
+    SYS_PRCTL = 157,
+    SYS_ARCH_PRCTL = 158,
+    SYS_SETRLIMIT = 160,
+    SYS_SYNC = 162,
+    SYS_MOUNT = 165,
+    SYS_UMOUNT2 = 166,
+    SYS_SWAPON = 167,
+    SYS_GETTID = 186,
+    SYS_TKILL = 200,
+    SYS_FUTEX = 202,
+    SYS_GETDENTS64 = 217,
+    SYS_SET_TID_ADDRESS = 218,
+    SYS_CLOCK_GETTIME = 228,
+    SYS_CLOCK_GETRES = 229,
+    SYS_CLOCK_NANOSLEEP = 230,
+    SYS_EXIT_GROUP = 231,
+    SYS_EPOLL_CTL = 233,
+    SYS_TGKILL = 234,
+    SYS_WAITID = 247,
+    SYS_OPENAT = 257,
+    SYS_MKDIRAT = 258,
+    SYS_FCHOWNAT = 260,
+    SYS_NEWFSTATAT = 262,
+    SYS_UNLINKAT = 263,
+    SYS_RENAMEAT = 264,
+    SYS_LINKAT = 265,
+    SYS_READLINKAT = 267,
+    SYS_FCHMODAT = 268,
+    SYS_FACCESSAT = 269,
+    SYS_PSELECT6 = 270,
+    SYS_PPOLL = 271,
+    SYS_SET_ROBUST_LIST = 273,
+    SYS_GET_ROBUST_LIST = 274,
+    SYS_UTIMENSAT = 280,
+    SYS_EPOLL_PWAIT = 281,
+    SYS_EVENTFD2 = 290,
+    SYS_EPOLL_CREATE1 = 291,
+    SYS_DUP3 = 292,
+    SYS_PIPE2 = 293,
+    SYS_PRLIMIT64 = 302,
+    SYS_GETRANDOM = 318,
+    SYS_MEMFD_CREATE = 319,
+    SYS_MEMBARRIER = 324,
+    SYS_STATX = 332,
+    SYS_CLONE3 = 435,
+}
@@ -0,0 +1,656 @@
+//! Processes of the monolithic kernel.
+//!
+//! Each process records its parent and children, so that the exit status of a
+//! child can be collected by its parent (see [`Process::wait_child`]). A
+//! process becomes a *zombie* after it exits, until it is reaped by its
+//! parent. Children of an exited process are handed over to the init process,
+//! which is the first user process spawned by the kernel.
+//!
+//! The exit status of a process is kept in the format of `wait4`, so that it
+//! also tells whether the process was killed by a signal. A process stopped
+//! or continued by a signal notifies its parent as well, which can wait for
+//! that with `WUNTRACED` or `WCONTINUED`.
+//!
+//! A process may have several threads (tasks created by `clone` with
+//! `CLONE_THREAD`), which share its address space and signal actions. The PID
+//! of a process is the TID of its first thread. The process exits when its
+//! last thread exits, or when any thread calls `exit_group` (or is terminated
+//! by a signal), which kills all other threads as well.
+//!
+//! Processes are organized into process groups, and process groups into
+//! sessions, which are used for job control by the terminals. A child starts
+//! in the process group and the session of its parent.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicUsize, Ordering};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::arch::TrapFrame;
+use axtask::{AxTaskRef, TaskExtRef, TaskInner, WaitQueue};
+use spin::{Mutex, Once, RwLock};
+
+use crate::mm::Heap;
+use crate::shm::ShmAttachments;
+use crate::signal::{self, ProcessSignals};
+
+/// Process ID.
+pub type Pid = u64;
+
+/// The init process.
+static INIT_PROC: Once<Arc<Process>> = Once::new();
+
+/// All processes that have not been reaped, indexed by their PIDs.
+static PROCESS_TABLE: RwLock<BTreeMap<Pid, Weak<Process>>> = RwLock::new(BTreeMap::new());
+
+/// The children that [`Process::wait_child`] waits for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitTarget {
+    /// Any child.
+    Any,
+    /// The child with the given PID.
+    Pid(Pid),
+    /// Any child in the given process group.
+    Group(Pid),
+}
+
+bitflags::bitflags! {
+    /// Options for [`Process::wait_child`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct WaitOptions: u32 {
+        /// Returns at once if no child has changed its state.
+        const WNOHANG = 1;
+        /// Also reports the children stopped by signals.
+        const WUNTRACED = 2;
+        /// Also reports the stopped children continued by `SIGCONT`.
+        const WCONTINUED = 8;
+    }
+}
+
+/// The status in the format of `wait4` of a process continued by `SIGCONT`.
+const CONTINUED_STATUS: i32 = 0xffff;
+
+/// Returns the status in the format of `wait4` of a process stopped by the
+/// signal `signo`.
+const fn stopped_status(signo: usize) -> i32 {
+    ((signo as i32) << 8) | 0x7f
+}
+
+impl WaitTarget {
+    fn matches(&self, child: &Process) -> bool {
+        match *self {
+            Self::Any => true,
+            Self::Pid(pid) => child.pid == pid,
+            Self::Group(pgid) => child.pgid() == pgid,
+        }
+    }
+}
+
+/// A user process.
+pub struct Process {
+    pid: Pid,
+    /// The process group ID.
+    pgid: AtomicU64,
+    /// The session ID.
+    sid: AtomicU64,
+    parent: Mutex<Weak<Process>>,
+    children: Mutex<BTreeMap<Pid, Arc<Process>>>,
+    zombie: AtomicBool,
+    exit_status: AtomicI32,
+    /// The status in the format of `wait4` of the last stop or continue that
+    /// has not been reported to the parent, or 0.
+    job_status: AtomicI32,
+    /// Notified when a child exits, stops or continues.
+    child_exit_wq: WaitQueue,
+    /// Threads that have not exited, indexed by their TIDs.
+    threads: axsync::Mutex<BTreeMap<Pid, AxTaskRef>>,
+    /// Notified when a thread exits.
+    thread_exit_wq: WaitQueue,
+    /// Whether the whole thread group is exiting, see [`Process::exit_group`].
+    group_exiting: AtomicBool,
+    /// Whether the process has called `execve` or exited, which resumes the
+    /// parent suspended by `vfork`.
+    vfork_done: AtomicBool,
+    /// Notified when `vfork_done` is set.
+    vfork_wq: WaitQueue,
+    /// The number of syscalls that failed with `ENOSYS`.
+    enosys_count: AtomicUsize,
+    /// The number of syscalls that failed with `ENOSYS` in the reaped
+    /// children and their reaped descendants.
+    children_enosys_count: AtomicUsize,
+    /// Signal actions and signals sent to the process.
+    pub(crate) signals: ProcessSignals,
+    /// The heap, whose end is the program break.
+    pub(crate) heap: Mutex<Heap>,
+    /// The arguments of the running program, set by `execve`.
+    pub(crate) cmdline: Mutex<Vec<String>>,
+    /// The auxiliary vector of the running program, see [`Process::auxv`].
+    auxv: Mutex<Vec<usize>>,
+    /// System V shared memory segments attached to the process.
+    pub(crate) shm: ShmAttachments,
+}
+
+impl Process {
+    /// Creates a new process with the given PID.
+    ///
+    /// It is added to the children of `parent`, and joins its process group.
+    /// A process created without a parent leads a new session, and the first
+    /// one becomes the init process.
+    pub(crate) fn new(pid: Pid, parent: Option<&Arc<Process>>) -> Arc<Self> {
+        let (pgid, sid) = parent.map_or((pid, pid), |parent| (parent.pgid(), parent.sid()));
+        let proc = Arc::new(Self {
+            pid,
+            pgid: AtomicU64::new(pgid),
+            sid: AtomicU64::new(sid),
+            parent: Mutex::new(parent.map_or_else(Weak::new, Arc::downgrade)),
+            children: Mutex::new(BTreeMap::new()),
+            zombie: AtomicBool::new(false),
+            exit_status: AtomicI32::new(0),
+            job_status: AtomicI32::new(0),
+            child_exit_wq: WaitQueue::new(),
+            threads: axsync::Mutex::new(BTreeMap::new()),
+            thread_exit_wq: WaitQueue::new(),
+            group_exiting: AtomicBool::new(false),
+            vfork_done: AtomicBool::new(false),
+            vfork_wq: WaitQueue::new(),
+            enosys_count: AtomicUsize::new(0),
+            children_enosys_count: AtomicUsize::new(0),
+            signals: ProcessSignals::new(),
+            heap: Mutex::new(Heap::default()),
+            cmdline: Mutex::new(Vec::new()),
+            auxv: Mutex::new(Vec::new()),
+            shm: ShmAttachments::new(),
+        });
+        match parent {
+            Some(parent) => {
+                parent.children.lock().insert(pid, proc.clone());
+            }
+            None => {
+                INIT_PROC.call_once(|| proc.clone());
+            }
+        }
+        PROCESS_TABLE.write().insert(pid, Arc::downgrade(&proc));
+        proc
+    }
+
+    /// Creates a process without threads for the current kernel task, so that
+    /// it can spawn user processes as its children and reap them (see
+    /// [`spawn_user_child`]).
+    ///
+    /// If it is the first process, it becomes the init process, and reaps the
+    /// orphans as well. Its PID is the ID of the kernel task.
+    ///
+    /// [`spawn_user_child`]: crate::task::spawn_user_child
+    pub fn new_kernel() -> Arc<Self> {
+        Self::new(axtask::current().id().as_u64(), None)
+    }
+
+    /// Finds the process with the given PID.
+    ///
+    /// Zombie processes are included, as long as they are not reaped.
+    pub fn find(pid: Pid) -> Option<Arc<Process>> {
+        PROCESS_TABLE.read().get(&pid).and_then(Weak::upgrade)
+    }
+
+    /// Returns the PIDs of all processes that have not been reaped.
+    pub fn all_pids() -> Vec<Pid> {
+        PROCESS_TABLE.read().keys().copied().collect()
+    }
+
+    /// Returns the process ID.
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// Returns the process group ID.
+    pub fn pgid(&self) -> Pid {
+        self.pgid.load(Ordering::Acquire)
+    }
+
+    /// Returns the session ID.
+    pub fn sid(&self) -> Pid {
+        self.sid.load(Ordering::Acquire)
+    }
+
+    /// Moves the process into the process group `pgid`.
+    pub(crate) fn set_pgid(&self, pgid: Pid) {
+        self.pgid.store(pgid, Ordering::Release);
+    }
+
+    /// Makes the process the leader of a new session and a new process group.
+    pub(crate) fn set_sid(&self) {
+        self.sid.store(self.pid, Ordering::Release);
+        self.pgid.store(self.pid, Ordering::Release);
+    }
+
+    /// Returns the processes in the process group `pgid` that have not
+    /// exited.
+    pub fn group_members(pgid: Pid) -> Vec<Arc<Process>> {
+        PROCESS_TABLE
+            .read()
+            .values()
+            .filter_map(Weak::upgrade)
+            .filter(|proc| proc.pgid() == pgid && !proc.is_zombie())
+            .collect()
+    }
+
+    /// Sends the signal `signo` to all processes in the process group
+    /// `pgid`, returns `false` if there is no such process.
+    pub fn signal_group(pgid: Pid, signo: usize) -> bool {
+        let members = Self::group_members(pgid);
+        for proc in &members {
+            proc.send_signal(signo);
+        }
+        !members.is_empty()
+    }
+
+    /// Returns the parent process, or `None` if it has no parent.
+    pub fn parent(&self) -> Option<Arc<Process>> {
+        self.parent.lock().upgrade()
+    }
+
+    /// Whether the process has exited but not been reaped.
+    pub fn is_zombie(&self) -> bool {
+        self.zombie.load(Ordering::Acquire)
+    }
+
+    /// Returns the exit status in the format of `wait4`, which is only
+    /// meaningful for zombies.
+    pub fn exit_status(&self) -> i32 {
+        self.exit_status.load(Ordering::Acquire)
+    }
+
+    /// Returns the number of syscalls made by the process that failed with
+    /// `ENOSYS`, which tells how many times it hit unimplemented features.
+    pub fn enosys_count(&self) -> usize {
+        self.enosys_count.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn count_enosys(&self) {
+        self.enosys_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the number of syscalls that failed with `ENOSYS` in the
+    /// children reaped by the process, including those in their reaped
+    /// descendants (like `RUSAGE_CHILDREN`).
+    pub fn children_enosys_count(&self) -> usize {
+        self.children_enosys_count.load(Ordering::Relaxed)
+    }
+
+    /// Sends the signal `signo` to the process.
+    ///
+    /// It is delivered the next time one of its threads returns to user space.
+    /// A thread that does not block it is woken up if it is blocked in a
+    /// syscall, and `SIGKILL` wakes up all threads.
+    ///
+    /// A stopped process continued by `SIGCONT` notifies its parent.
+    pub fn send_signal(&self, signo: usize) {
+        if self.signals.send(signo) && signo == signal::SIGCONT {
+            self.report_job_status(CONTINUED_STATUS);
+        }
+        let threads = self.threads.lock();
+        if signo == signal::SIGKILL {
+            for task in threads.values() {
+                task.task_ext().signals.interrupt();
+            }
+        } else if let Some(task) = threads
+            .values()
+            .find(|task| !task.task_ext().signals.blocked().contains(signo))
+        {
+            task.task_ext().signals.interrupt();
+        }
+    }
+
+    /// Spawns `task` as a thread of the process.
+    ///
+    /// The task extended data of `task` should have been initialized.
+    pub(crate) fn spawn_thread(&self, task: TaskInner) -> AxTaskRef {
+        let tid = task.id().as_u64();
+        // Holding the lock, so that the thread cannot exit before it is added.
+        let mut threads = self.threads.lock();
+        let task = axtask::spawn_task(task);
+        threads.insert(tid, task.clone());
+        task
+    }
+
+    /// Finds the thread with the given TID in the process.
+    pub fn thread(&self, tid: Pid) -> Option<AxTaskRef> {
+        self.threads.lock().get(&tid).cloned()
+    }
+
+    /// Returns the threads that have not exited, in ascending order of TIDs.
+    pub fn threads(&self) -> Vec<AxTaskRef> {
+        self.threads.lock().values().cloned().collect()
+    }
+
+    /// Returns the name of the program, which is the file name of the first
+    /// argument (or the name of the task if it has not called `execve`).
+    pub fn comm(&self) -> String {
+        if let Some(arg0) = self.cmdline.lock().first() {
+            return arg0.rsplit('/').next().unwrap_or_default().into();
+        }
+        self.threads()
+            .first()
+            .map_or_else(String::new, |task| task.name().into())
+    }
+
+    /// Returns the auxiliary vector passed to the running program, as
+    /// key-value pairs terminated by `AT_NULL`, or an empty vector if it is
+    /// unknown.
+    pub fn auxv(&self) -> Vec<usize> {
+        self.auxv.lock().clone()
+    }
+
+    /// Saves the auxiliary vector after the program is loaded (see
+    /// [`crate::mm::read_auxv`]).
+    pub(crate) fn set_auxv(&self, auxv: Vec<usize>) {
+        *self.auxv.lock() = auxv;
+    }
+
+    /// Returns the number of threads that have not exited.
+    pub fn thread_count(&self) -> usize {
+        self.threads.lock().len()
+    }
+
+    /// Removes the exiting thread `tid`, returns whether it is the last one.
+    fn remove_thread(&self, tid: Pid) -> bool {
+        let mut threads = self.threads.lock();
+        threads.remove(&tid);
+        let last = threads.is_empty();
+        drop(threads);
+        self.thread_exit_wq.notify_all(false);
+        last
+    }
+
+    /// Asks all threads except the current one to exit.
+    ///
+    /// The threads exit the next time they return to user space. Threads
+    /// blocked in syscalls or stopped by a signal are woken up for that.
+    pub(crate) fn kill_other_threads(&self) {
+        let curr_tid = axtask::current().id().as_u64();
+        for (&tid, task) in self.threads.lock().iter() {
+            if tid != curr_tid {
+                task.task_ext().signals.kill();
+            }
+        }
+        let _ = self.signals.resume();
+    }
+
+    /// Stops the current thread by the signal `signo`, until the process is
+    /// continued.
+    ///
+    /// The parent is notified when the process becomes stopped.
+    pub(crate) fn stop(&self, signo: usize) {
+        if self.signals.set_stopped() {
+            self.report_job_status(stopped_status(signo));
+        }
+        self.signals.wait_continued();
+    }
+
+    /// Records the stop or continue `status` for `wait4` of the parent, and
+    /// notifies the parent with `SIGCHLD` (unless it sets `SA_NOCLDSTOP`).
+    fn report_job_status(&self, status: i32) {
+        // An unreported stop is superseded by the continue, and vice versa.
+        self.job_status.store(status, Ordering::Release);
+        if let Some(parent) = self.parent() {
+            if parent.signals.action(signal::SIGCHLD).flags & signal::SA_NOCLDSTOP == 0 {
+                parent.send_signal(signal::SIGCHLD);
+            }
+            parent.child_exit_wq.notify_all(false);
+        }
+    }
+
+    /// Whether the process has an unreported stop or continue status that is
+    /// waited for with `options`.
+    fn has_job_status(&self, options: WaitOptions) -> bool {
+        match self.job_status.load(Ordering::Acquire) {
+            0 => false,
+            CONTINUED_STATUS => options.contains(WaitOptions::WCONTINUED),
+            _ => options.contains(WaitOptions::WUNTRACED),
+        }
+    }
+
+    /// Takes the stop or continue status if it is waited for with `options`.
+    fn take_job_status(&self, options: WaitOptions) -> Option<i32> {
+        let status = self.job_status.load(Ordering::Acquire);
+        (self.has_job_status(options)
+            && self
+                .job_status
+                .compare_exchange(status, 0, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok())
+        .then_some(status)
+    }
+
+    /// Waits until all threads except the current one have exited.
+    ///
+    /// Returns `false` if the current thread is killed in the meantime.
+    pub(crate) fn wait_other_threads(&self) -> bool {
+        let curr = axtask::current();
+        self.thread_exit_wq
+            .wait_until(|| self.thread_count() == 1 || curr.task_ext().signals.is_killed());
+        !curr.task_ext().signals.is_killed()
+    }
+
+    /// Makes the whole thread group exit with `exit_status` (in the format of
+    /// `wait4`), by killing all threads except the current one.
+    ///
+    /// Only the first call takes effect, so the exit status is not overridden
+    /// by the threads exiting later. Returns whether this call takes effect.
+    pub(crate) fn exit_group(&self, exit_status: i32) -> bool {
+        if self.group_exiting.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        self.exit_status.store(exit_status, Ordering::Release);
+        self.kill_other_threads();
+        true
+    }
+
+    /// Resumes the parent suspended by `vfork`, as the process is calling
+    /// `execve` or exiting.
+    pub(crate) fn release_vfork(&self) {
+        self.vfork_done.store(true, Ordering::Release);
+        self.vfork_wq.notify_all(false);
+    }
+
+    /// Suspends the current task (the parent) until the process calls
+    /// `execve` or exits, see [`Process::release_vfork`].
+    ///
+    /// The wait is interrupted by signals, which is harmless as the child
+    /// does not share the memory of the parent.
+    pub(crate) fn wait_vfork_done(&self) {
+        let _ = signal::wait_interruptible(&self.vfork_wq, None, || {
+            self.vfork_done.load(Ordering::Acquire)
+        });
+    }
+
+    /// Marks the process as exited with `exit_status` (in the format of
+    /// `wait4`), after its last thread exits.
+    ///
+    /// The status given to [`Process::exit_group`] takes precedence, if any.
+    /// Its children are handed over to the init process (or removed if it has
+    /// exited as well), and the parent is notified with `SIGCHLD`. A session
+    /// leader takes the controlling terminal of its session away.
+    fn exit(self: &Arc<Self>, exit_status: i32) {
+        if !self.group_exiting.load(Ordering::Acquire) {
+            self.exit_status.store(exit_status, Ordering::Release);
+        }
+        self.zombie.store(true, Ordering::Release);
+        self.release_vfork();
+        #[cfg(feature = "fd")]
+        if self.pid == self.sid() {
+            crate::tty::release_session(self.pid);
+        }
+
+        let mut children = core::mem::take(&mut *self.children.lock());
+        if let Some(init) = INIT_PROC.get().filter(|init| !Arc::ptr_eq(init, self)) {
+            if !children.is_empty() {
+                // The init process takes its children after it becomes a
+                // zombie, so it is checked with the lock held.
+                let mut init_children = init.children.lock();
+                if !init.is_zombie() {
+                    for child in children.values() {
+                        *child.parent.lock() = Arc::downgrade(init);
+                    }
+                    init_children.append(&mut children);
+                    drop(init_children);
+                    // Some of the new children may be zombies already.
+                    init.child_exit_wq.notify_all(false);
+                }
+            }
+        }
+        if !children.is_empty() {
+            // Nobody is going to reap them.
+            let mut table = PROCESS_TABLE.write();
+            for pid in children.keys() {
+                table.remove(pid);
+            }
+        }
+
+        match self.parent() {
+            Some(parent) => {
+                parent.send_signal(signal::SIGCHLD);
+                parent.child_exit_wq.notify_all(false);
+            }
+            None => {
+                PROCESS_TABLE.write().remove(&self.pid);
+            }
+        }
+    }
+
+    /// Waits for a child to exit, and reaps it.
+    ///
+    /// Only the children selected by `target` are waited for. The process
+    /// group of a child is checked each time, as it may change during the
+    /// wait. With `WUNTRACED` or `WCONTINUED` in `options`, a child that is
+    /// stopped or continued is reported as well (only once), but not reaped.
+    /// With `WNOHANG`, returns `None` immediately when no child has changed
+    /// its state yet.
+    ///
+    /// Returns the PID and the status in the format of `wait4` of the child,
+    /// `ECHILD` if there is no such child, or `EINTR` if the wait is
+    /// interrupted by a signal.
+    pub fn wait_child(
+        &self,
+        target: WaitTarget,
+        options: WaitOptions,
+    ) -> LinuxResult<Option<(Pid, i32)>> {
+        let matches = |child: &Arc<Process>| target.matches(child);
+        loop {
+            {
+                let mut children = self.children.lock();
+                if !children.values().any(matches) {
+                    return Err(LinuxError::ECHILD);
+                }
+                let zombie = children
+                    .values()
+                    .find(|child| matches(child) && child.is_zombie())
+                    .map(|child| child.pid);
+                if let Some(pid) = zombie {
+                    let child = children.remove(&pid).unwrap();
+                    PROCESS_TABLE.write().remove(&pid);
+                    self.children_enosys_count.fetch_add(
+                        child.enosys_count() + child.children_enosys_count(),
+                        Ordering::Relaxed,
+                    );
+                    return Ok(Some((pid, child.exit_status())));
+                }
+                let job_status = children
+                    .values()
+                    .filter(|child| matches(child))
+                    .find_map(|child| Some((child.pid, child.take_job_status(options)?)));
+                if job_status.is_some() {
+                    return Ok(job_status);
+                }
+            }
+            if options.contains(WaitOptions::WNOHANG) {
+                return Ok(None);
+            }
+            signal::wait_interruptible(&self.child_exit_wq, None, || {
+                let children = self.children.lock();
+                // Stop waiting if the child is gone as well.
+                !children.values().any(matches)
+                    || children.values().any(|child| {
+                        matches(child) && (child.is_zombie() || child.has_job_status(options))
+                    })
+            })?;
+        }
+    }
+}
+
+/// Returns the process of the current task.
+pub fn current_process() -> Arc<Process> {
+    axtask::current().task_ext().process.clone()
+}
+
+/// Exits the current thread with `exit_code`.
+///
+/// If it is the last thread of the process, the user memory is released, and
+/// the process is kept as a zombie until its parent reaps it.
+pub fn exit_current(exit_code: i32) -> ! {
+    do_exit((exit_code & 0xff) << 8, exit_code)
+}
+
+/// Exits all threads of the current process with `exit_code`.
+pub fn exit_group_current(exit_code: i32) -> ! {
+    let exit_status = (exit_code & 0xff) << 8;
+    current_process().exit_group(exit_status);
+    do_exit(exit_status, exit_code)
+}
+
+/// Terminates the current process by the signal `signo`.
+///
+/// The exit code of the task is `128 + signo`, as reported by shells.
+pub(crate) fn exit_current_by_signal(signo: usize) -> ! {
+    let status = signo as i32;
+    current_process().exit_group(status);
+    do_exit(status, 128 + signo as i32)
+}
+
+/// Terminates the current process by the signal `signo`, whose default
+/// action is to dump core, with the user context `tf`.
+///
+/// The other threads exit before the core is dumped, so that the memory does
+/// not change while it is written. If another thread is exiting the whole
+/// process at the same time, the current one is killed by it instead.
+pub(crate) fn exit_current_dumping_core(tf: &TrapFrame, signo: usize) -> ! {
+    let process = current_process();
+    if !process.exit_group(signo as i32) || !process.wait_other_threads() {
+        exit_killed();
+    }
+    #[cfg(feature = "coredump")]
+    if crate::coredump::dump_current(tf, signo) {
+        let status = signo as i32 | 0x80;
+        process.exit_status.store(status, Ordering::Release);
+        do_exit(status, 128 + signo as i32)
+    }
+    #[cfg(not(feature = "coredump"))]
+    let _ = tf;
+    do_exit(signo as i32, 128 + signo as i32)
+}
+
+/// Exits the current thread, which is killed by another thread of the
+/// process (see [`Process::kill_other_threads`]).
+pub(crate) fn exit_killed() -> ! {
+    let status = current_process().exit_status();
+    let exit_code = match status & 0x7f {
+        0 => (status >> 8) & 0xff,
+        signo => 128 + signo,
+    };
+    do_exit(status, exit_code)
+}
+
+fn do_exit(exit_status: i32, exit_code: i32) -> ! {
+    crate::trace::trace_no_return();
+    let curr = axtask::current();
+    crate::futex::clear_child_tid();
+    let process = curr.task_ext().process.clone();
+    if process.remove_thread(curr.id().as_u64()) {
+        curr.task_ext().aspace.lock().clear();
+        process.shm.detach_all(process.pid());
+        process.exit(exit_status);
+    }
+    drop(process);
+    axtask::exit(exit_code)
+}
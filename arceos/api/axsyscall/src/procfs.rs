@@ -0,0 +1,234 @@
+//! Entries of the proc filesystem that reflect the state of the kernel and
+//! the user processes.
+//!
+//! Besides the kernel-wide files (`/proc/meminfo`, `/proc/uptime` and
+//! `/proc/cpuinfo`), there is a directory `/proc/[pid]` for each process that
+//! has not been reaped, and `/proc/self` for the calling process. All of them
+//! are generated on read, in the formats of Linux, so that tools like `ps` and
+//! `free` work unmodified.
+
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use axfs::procfs::{ProcDir, ProcDirOps};
+use axfs_vfs::{VfsError, VfsNodeRef, VfsResult};
+use axhal::mem::PAGE_SIZE_4K;
+use axhal::paging::MappingFlags;
+use axtask::TaskExtRef;
+
+use crate::process::{Pid, Process};
+use crate::signal::SIGCHLD;
+
+/// Adds the entries to the root of the proc filesystem.
+///
+/// It is called when the first user task is spawned, and only takes effect
+/// once.
+pub(crate) fn init() {
+    static INIT: spin::Once = spin::Once::new();
+    INIT.call_once(|| {
+        let root = axfs::procfs::root();
+        root.add_file("meminfo", meminfo);
+        root.add_file("uptime", uptime);
+        root.add_file("cpuinfo", cpuinfo);
+        root.set_ops(Arc::new(ProcessDirs));
+    });
+}
+
+fn meminfo() -> VfsResult<String> {
+    let allocator = axalloc::global_allocator();
+    let total = (allocator.used_pages() + allocator.available_pages()) * PAGE_SIZE_4K;
+    let free = allocator.available_pages() * PAGE_SIZE_4K;
+    #[cfg(feature = "swap")]
+    let (swap_total, swap_free) = (axmm::swap::swap_total(), axmm::swap::swap_free());
+    #[cfg(not(feature = "swap"))]
+    let (swap_total, swap_free) = (0, 0);
+    let mut info = String::new();
+    for (name, bytes) in [
+        ("MemTotal", total),
+        ("MemFree", free),
+        ("MemAvailable", free),
+        ("Buffers", 0),
+        ("Cached", 0),
+        ("SwapCached", 0),
+        ("SwapTotal", swap_total),
+        ("SwapFree", swap_free),
+        ("Shmem", 0),
+        ("SReclaimable", 0),
+    ] {
+        let _ = writeln!(info, "{:<15} {:>8} kB", [name, ":"].concat(), bytes / 1024);
+    }
+    Ok(info)
+}
+
+fn uptime() -> VfsResult<String> {
+    let now = axhal::time::monotonic_time();
+    // The idle time is not accounted.
+    Ok(alloc::format!(
+        "{}.{:02} 0.00\n",
+        now.as_secs(),
+        now.subsec_millis() / 10
+    ))
+}
+
+fn cpuinfo() -> VfsResult<String> {
+    let mut info = String::new();
+    for cpu in 0..axconfig::SMP {
+        let _ = writeln!(info, "processor\t: {}", cpu);
+        let _ = writeln!(info, "model name\t: {}\n", axconfig::ARCH);
+    }
+    Ok(info)
+}
+
+/// Generates `/proc/[pid]` and `/proc/self`.
+struct ProcessDirs;
+
+impl ProcDirOps for ProcessDirs {
+    fn list(&self) -> Vec<String> {
+        let mut names = Vec::from(["self".to_string()]);
+        names.extend(Process::all_pids().iter().map(Pid::to_string));
+        names
+    }
+
+    fn lookup(&self, name: &str, parent: &Arc<ProcDir>) -> Option<VfsNodeRef> {
+        let process = if name == "self" {
+            let curr = axtask::current();
+            if unsafe { curr.task_ext_ptr() }.is_null() {
+                return None;
+            }
+            curr.task_ext().process.clone()
+        } else {
+            Process::find(name.parse().ok()?)?
+        };
+        Some(process_dir(&process, parent))
+    }
+}
+
+/// Creates the directory of `process`.
+///
+/// The files only keep a weak reference to the process, and become empty
+/// after it is reaped.
+fn process_dir(process: &Arc<Process>, parent: &Arc<ProcDir>) -> VfsNodeRef {
+    let dir = ProcDir::new(Some(parent), None);
+    let files: [(&str, fn(&Process) -> String); 5] = [
+        ("stat", stat),
+        ("status", status),
+        ("maps", maps),
+        ("cmdline", cmdline),
+        ("comm", |process| process.comm() + "\n"),
+    ];
+    for (name, generate) in files {
+        let process = Arc::downgrade(process);
+        dir.add_file(name, move || {
+            let process = Weak::upgrade(&process).ok_or(VfsError::NotFound)?;
+            Ok(generate(&process))
+        });
+    }
+    dir
+}
+
+/// Returns the state of the process, as a letter and a description.
+fn state(process: &Process) -> (char, &'static str) {
+    if process.is_zombie() {
+        ('Z', "zombie")
+    } else if process.signals.is_stopped() {
+        ('T', "stopped")
+    } else if process.threads().iter().all(|task| task.is_blocked()) {
+        ('S', "sleeping")
+    } else {
+        ('R', "running")
+    }
+}
+
+fn ppid(process: &Process) -> Pid {
+    process.parent().map_or(0, |parent| parent.pid())
+}
+
+/// Returns the virtual memory size and the resident set size of the process.
+fn mem_size(process: &Process) -> (usize, usize) {
+    let Some(task) = process.threads().into_iter().next() else {
+        return (0, 0); // zombie
+    };
+    let aspace = task.task_ext().aspace.lock();
+    let vsize = aspace
+        .areas()
+        .map(|area| area.end.as_usize() - area.start.as_usize())
+        .sum();
+    (vsize, aspace.resident_size())
+}
+
+fn stat(process: &Process) -> String {
+    let pid = process.pid();
+    let (vsize, rss) = mem_size(process);
+    let mut stat = alloc::format!("{} ({}) {}", pid, process.comm(), state(process).0);
+    // From `ppid` (field 4) to `exit_code` (field 52). Times and fault counts
+    // are not accounted.
+    let mut fields = [0; 49];
+    fields[0] = ppid(process) as usize;
+    fields[1] = process.pgid() as usize; // pgrp
+    fields[2] = process.sid() as usize; // session
+    fields[14] = 20; // priority
+    fields[16] = process.thread_count(); // num_threads
+    fields[19] = vsize;
+    fields[20] = rss / PAGE_SIZE_4K;
+    fields[21] = usize::MAX; // rsslim
+    fields[34] = SIGCHLD; // exit_signal
+    for field in fields {
+        let _ = write!(stat, " {}", field);
+    }
+    stat.push('\n');
+    stat
+}
+
+fn status(process: &Process) -> String {
+    let (vsize, rss) = mem_size(process);
+    let (state, state_desc) = state(process);
+    let mut status = String::new();
+    let _ = writeln!(status, "Name:\t{}", process.comm());
+    let _ = writeln!(status, "State:\t{} ({})", state, state_desc);
+    let _ = writeln!(status, "Tgid:\t{}", process.pid());
+    let _ = writeln!(status, "Pid:\t{}", process.pid());
+    let _ = writeln!(status, "PPid:\t{}", ppid(process));
+    let _ = writeln!(status, "VmSize:\t{:>8} kB", vsize / 1024);
+    let _ = writeln!(status, "VmRSS:\t{:>8} kB", rss / 1024);
+    let _ = writeln!(status, "Threads:\t{}", process.thread_count());
+    status
+}
+
+fn maps(process: &Process) -> String {
+    let Some(task) = process.threads().into_iter().next() else {
+        return String::new(); // zombie
+    };
+    let heap_bottom = process.heap.lock().bottom();
+    let aspace = task.task_ext().aspace.lock();
+    let mut maps = String::new();
+    for area in aspace.areas() {
+        let perm = |flag, c| if area.flags.contains(flag) { c } else { '-' };
+        let _ = write!(
+            maps,
+            "{:08x}-{:08x} {}{}{}{} {:08x} 00:00 0",
+            area.start.as_usize(),
+            area.end.as_usize(),
+            perm(MappingFlags::READ, 'r'),
+            perm(MappingFlags::WRITE, 'w'),
+            perm(MappingFlags::EXECUTE, 'x'),
+            if area.shared { 's' } else { 'p' },
+            area.file_offset.unwrap_or(0),
+        );
+        if area.start == heap_bottom {
+            maps.push_str("\t[heap]");
+        }
+        maps.push('\n');
+    }
+    maps
+}
+
+fn cmdline(process: &Process) -> String {
+    let mut cmdline = String::new();
+    for arg in process.cmdline.lock().iter() {
+        cmdline.push_str(arg);
+        cmdline.push('\0');
+    }
+    cmdline
+}
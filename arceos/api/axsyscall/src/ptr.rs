@@ -0,0 +1,273 @@
+//! Pointers to the user memory of the current task.
+//!
+//! Syscall arguments that point to user memory are wrapped in [`UserPtr`],
+//! [`UserSlice`] or [`UserCStr`]. The kernel never dereferences them: the
+//! pointed memory is copied in and out through the page table of the current
+//! task, with its address space locked (see `copy_from_user` and
+//! `copy_to_user`). Pages not populated yet (lazily allocated,
+//! copy-on-write or file-backed) are faulted in, and invalid pointers
+//! (including those unmapped by another thread during the syscall) result in
+//! `EFAULT` instead of kernel page faults.
+
+use alloc::ffi::CString;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of, MaybeUninit};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::mem::PAGE_SIZE_4K;
+
+use crate::mm::{copy_from_user, copy_to_user};
+
+/// The maximum length of a path, including the terminating NUL.
+pub const PATH_MAX: usize = 4096;
+
+fn check_region(addr: usize, align: usize) -> LinuxResult {
+    if addr == 0 || addr % align != 0 {
+        return Err(LinuxError::EFAULT);
+    }
+    Ok(())
+}
+
+/// A pointer to a `T` in user space.
+pub struct UserPtr<T> {
+    addr: usize,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<T> Clone for UserPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for UserPtr<T> {}
+
+impl<T> From<usize> for UserPtr<T> {
+    fn from(addr: usize) -> Self {
+        Self {
+            addr,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for UserPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "UserPtr({:#x})", self.addr)
+    }
+}
+
+impl<T> UserPtr<T> {
+    /// Returns the user address.
+    pub const fn address(&self) -> usize {
+        self.addr
+    }
+
+    /// Whether the pointer is NULL.
+    pub const fn is_null(&self) -> bool {
+        self.addr == 0
+    }
+
+    /// Copies the value from user space.
+    ///
+    /// `T` should be a plain type that is valid for any bytes.
+    pub fn read(&self) -> LinuxResult<T>
+    where
+        T: Copy,
+    {
+        check_region(self.addr, align_of::<T>())?;
+        let mut value = MaybeUninit::<T>::uninit();
+        if !unsafe { copy_from_user(value.as_mut_ptr() as _, self.addr, size_of::<T>()) } {
+            return Err(LinuxError::EFAULT);
+        }
+        Ok(unsafe { value.assume_init() })
+    }
+
+    /// Copies the value to user space.
+    pub fn write(&self, value: T) -> LinuxResult {
+        check_region(self.addr, align_of::<T>())?;
+        if !unsafe { copy_to_user(self.addr, &value as *const T as _, size_of::<T>()) } {
+            return Err(LinuxError::EFAULT);
+        }
+        Ok(())
+    }
+}
+
+/// A slice of `len` elements of `T` in user space.
+pub struct UserSlice<T> {
+    ptr: UserPtr<T>,
+    len: usize,
+}
+
+impl<T> Clone for UserSlice<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for UserSlice<T> {}
+
+impl<T> fmt::Debug for UserSlice<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "UserSlice({:#x}, {})", self.ptr.addr, self.len)
+    }
+}
+
+impl<T> UserSlice<T> {
+    /// Creates a slice of `len` elements starting at `addr`.
+    pub const fn new(addr: usize, len: usize) -> Self {
+        Self {
+            ptr: UserPtr {
+                addr,
+                _marker: PhantomData,
+            },
+            len,
+        }
+    }
+
+    /// Returns the user address of the first element.
+    pub const fn address(&self) -> usize {
+        self.ptr.addr
+    }
+
+    /// Returns the number of elements.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the slice has no elements.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the sub-slice of at most `len` elements starting at the
+    /// element `start`.
+    pub fn sub(&self, start: usize, len: usize) -> Self {
+        let start = start.min(self.len);
+        Self::new(
+            self.ptr.addr.wrapping_add(start * size_of::<T>()),
+            len.min(self.len - start),
+        )
+    }
+
+    /// Returns the size of the slice in bytes, after checking the address.
+    fn check(&self) -> LinuxResult<usize> {
+        let size = self
+            .len
+            .checked_mul(size_of::<T>())
+            .ok_or(LinuxError::EFAULT)?;
+        // Empty slices are never accessed, even if the pointer is NULL.
+        if size != 0 {
+            check_region(self.ptr.addr, align_of::<T>())?;
+        }
+        Ok(size)
+    }
+
+    /// Copies the slice from user space.
+    ///
+    /// `T` should be a plain type that is valid for any bytes.
+    pub fn read(&self) -> LinuxResult<Vec<T>>
+    where
+        T: Copy,
+    {
+        let size = self.check()?;
+        let mut buf = Vec::with_capacity(self.len);
+        if !unsafe { copy_from_user(buf.as_mut_ptr() as _, self.ptr.addr, size) } {
+            return Err(LinuxError::EFAULT);
+        }
+        unsafe { buf.set_len(self.len) };
+        Ok(buf)
+    }
+
+    /// Copies `src` to the start of the slice in user space.
+    ///
+    /// Returns `EFAULT` if `src` is longer than the slice.
+    pub fn write(&self, src: &[T]) -> LinuxResult {
+        if src.len() > self.len {
+            return Err(LinuxError::EFAULT);
+        }
+        let size = self.sub(0, src.len()).check()?;
+        if !unsafe { copy_to_user(self.ptr.addr, src.as_ptr() as _, size) } {
+            return Err(LinuxError::EFAULT);
+        }
+        Ok(())
+    }
+}
+
+/// A NUL-terminated C string in user space.
+#[derive(Clone, Copy)]
+pub struct UserCStr {
+    addr: usize,
+}
+
+impl From<usize> for UserCStr {
+    fn from(addr: usize) -> Self {
+        Self { addr }
+    }
+}
+
+impl fmt::Debug for UserCStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "UserCStr({:#x})", self.addr)
+    }
+}
+
+impl UserCStr {
+    /// Returns the user address.
+    pub const fn address(&self) -> usize {
+        self.addr
+    }
+
+    /// Whether the pointer is NULL.
+    pub const fn is_null(&self) -> bool {
+        self.addr == 0
+    }
+
+    /// Copies the string (without the terminating NUL) into the kernel.
+    ///
+    /// Returns `ENAMETOOLONG` if it is longer than [`PATH_MAX`] bytes
+    /// (including the NUL).
+    pub fn read_cstring(&self) -> LinuxResult<CString> {
+        self.read_cstring_max(PATH_MAX)
+    }
+
+    /// Copies the string (without the terminating NUL) into the kernel.
+    ///
+    /// Returns `ENAMETOOLONG` if it is longer than `max` bytes (including the
+    /// NUL), so that user space cannot make the kernel copy a string of any
+    /// size.
+    pub fn read_cstring_max(&self, max: usize) -> LinuxResult<CString> {
+        check_region(self.addr, 1)?;
+        // Copy the string page by page until the NUL, as the following
+        // pages may not be mapped.
+        let mut bytes = Vec::new();
+        let mut addr = self.addr;
+        while bytes.len() < max {
+            let page_end = (addr | (PAGE_SIZE_4K - 1))
+                .checked_add(1)
+                .ok_or(LinuxError::EFAULT)?;
+            let len = (page_end - addr).min(max - bytes.len());
+            let chunk = UserSlice::<u8>::new(addr, len).read()?;
+            if let Some(pos) = chunk.iter().position(|&b| b == 0) {
+                bytes.extend_from_slice(&chunk[..pos]);
+                return Ok(unsafe { CString::from_vec_unchecked(bytes) });
+            }
+            bytes.extend_from_slice(&chunk);
+            addr += len;
+        }
+        Err(LinuxError::ENAMETOOLONG)
+    }
+
+    /// Copies the string into the kernel.
+    ///
+    /// Returns `ENAMETOOLONG` if it is longer than [`PATH_MAX`] bytes, or
+    /// `EINVAL` if it is not valid UTF-8.
+    pub fn read(&self) -> LinuxResult<String> {
+        self.read_cstring()?
+            .into_string()
+            .map_err(|_| LinuxError::EINVAL)
+    }
+}
@@ -0,0 +1,424 @@
+//! Shared memory between processes: System V shared memory segments and
+//! memfd files.
+//!
+//! Both are backed by [`SharedPages`], which are mapped into the address
+//! spaces with [`AddrSpace::map_shared`], so all mappings of a segment (or a
+//! memfd file) use the same frames.
+//!
+//! A segment is created by `shmget`, and attached to a process by `shmat`.
+//! Attached segments are inherited by forked children, and detached on
+//! `shmdt`, `execve` and exit. A segment removed by `shmctl(IPC_RMID)` can no
+//! longer be found by its key or ID, but it lives on until its last detach.
+//!
+//! [`AddrSpace::map_shared`]: axmm::AddrSpace::map_shared
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::mem::{MemoryAddr, VirtAddr};
+use axhal::paging::MappingFlags;
+use axmm::{AddrSpace, SharedPages};
+use memory_addr::VirtAddrRange;
+use spin::Mutex;
+
+use crate::process::Pid;
+
+/// The key to always create a new segment.
+pub const IPC_PRIVATE: i32 = 0;
+
+/// The minimum size of a segment.
+const SHMMIN: usize = 1;
+/// The maximum size of a segment.
+const SHMMAX: usize = 1 << 30;
+
+/// Status of a segment, see [`ShmSegment::stat`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ShmStat {
+    pub key: i32,
+    pub mode: u32,
+    pub size: usize,
+    /// The last attach, detach and change times, in seconds.
+    pub atime: u64,
+    pub dtime: u64,
+    pub ctime: u64,
+    /// The PID of the creator and the last process that attached or
+    /// detached it.
+    pub cpid: Pid,
+    pub lpid: Pid,
+    pub nattch: usize,
+}
+
+/// A System V shared memory segment.
+pub(crate) struct ShmSegment {
+    id: i32,
+    pages: Arc<SharedPages>,
+    stat: Mutex<ShmStat>,
+    /// Whether it has been removed by `IPC_RMID`.
+    removed: AtomicBool,
+}
+
+impl ShmSegment {
+    pub(crate) fn stat(&self) -> ShmStat {
+        *self.stat.lock()
+    }
+
+    /// Changes the permission bits of the segment.
+    pub(crate) fn set_mode(&self, mode: u32) {
+        let mut stat = self.stat.lock();
+        stat.mode = (stat.mode & !0o777) | (mode & 0o777);
+        stat.ctime = now();
+    }
+
+    /// Updates the attach count and times after an attach or a detach by
+    /// `pid`, and drops the removed segment from the table after the last
+    /// detach.
+    fn update_attach(&self, attach: bool, pid: Pid) {
+        let nattch = {
+            let mut stat = self.stat.lock();
+            if attach {
+                stat.nattch += 1;
+                stat.atime = now();
+            } else {
+                stat.nattch -= 1;
+                stat.dtime = now();
+            }
+            stat.lpid = pid;
+            stat.nattch
+        };
+        if nattch == 0 && self.is_removed() {
+            SHM_TABLE.lock().segments.remove(&self.id);
+        }
+    }
+
+    fn is_removed(&self) -> bool {
+        self.removed.load(Ordering::Acquire)
+    }
+}
+
+struct ShmTable {
+    segments: BTreeMap<i32, Arc<ShmSegment>>,
+    next_id: i32,
+}
+
+/// All segments not removed yet, indexed by their IDs.
+///
+/// Removed segments stay in the table until their last detach, but cannot
+/// be found by [`find_segment`].
+static SHM_TABLE: Mutex<ShmTable> = Mutex::new(ShmTable {
+    segments: BTreeMap::new(),
+    next_id: 0,
+});
+
+fn now() -> u64 {
+    axhal::time::wall_time().as_secs()
+}
+
+/// Returns the ID of the segment with `key`, or creates a new one of `size`
+/// bytes with the permission bits `mode` if `create` is `true`.
+///
+/// A new segment is always created for [`IPC_PRIVATE`]. If `exclusive` is
+/// `true`, it is an error that the segment already exists.
+pub(crate) fn shm_get(
+    key: i32,
+    size: usize,
+    mode: u32,
+    create: bool,
+    exclusive: bool,
+) -> LinuxResult<i32> {
+    let mut table = SHM_TABLE.lock();
+    if key != IPC_PRIVATE {
+        let existing = table
+            .segments
+            .values()
+            .find(|seg| seg.stat().key == key && !seg.is_removed());
+        if let Some(seg) = existing {
+            if create && exclusive {
+                return Err(LinuxError::EEXIST);
+            }
+            if size > seg.stat().size {
+                return Err(LinuxError::EINVAL);
+            }
+            return Ok(seg.id);
+        }
+        if !create {
+            return Err(LinuxError::ENOENT);
+        }
+    }
+    if !(SHMMIN..=SHMMAX).contains(&size) {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let id = table.next_id;
+    table.next_id = table.next_id.checked_add(1).ok_or(LinuxError::ENOSPC)?;
+    let seg = ShmSegment {
+        id,
+        pages: Arc::new(SharedPages::new(size)),
+        stat: Mutex::new(ShmStat {
+            key,
+            mode: mode & 0o777,
+            size,
+            atime: 0,
+            dtime: 0,
+            ctime: now(),
+            cpid: crate::process::current_process().pid(),
+            lpid: 0,
+            nattch: 0,
+        }),
+        removed: AtomicBool::new(false),
+    };
+    table.segments.insert(id, Arc::new(seg));
+    Ok(id)
+}
+
+/// Finds the segment with ID `id`, which has not been removed.
+pub(crate) fn find_segment(id: i32) -> LinuxResult<Arc<ShmSegment>> {
+    SHM_TABLE
+        .lock()
+        .segments
+        .get(&id)
+        .filter(|seg| !seg.is_removed())
+        .cloned()
+        .ok_or(LinuxError::EINVAL)
+}
+
+/// Removes the segment `id`, which is destroyed after its last detach.
+pub(crate) fn shm_remove(id: i32) -> LinuxResult {
+    let seg = find_segment(id)?;
+    seg.removed.store(true, Ordering::Release);
+    if seg.stat().nattch == 0 {
+        SHM_TABLE.lock().segments.remove(&id);
+    }
+    Ok(())
+}
+
+/// Segments attached to a process, indexed by their start addresses.
+pub(crate) struct ShmAttachments {
+    attached: Mutex<BTreeMap<usize, Arc<ShmSegment>>>,
+}
+
+impl ShmAttachments {
+    pub(crate) const fn new() -> Self {
+        Self {
+            attached: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Attaches the segment `id` to `aspace` of the process `pid` with
+    /// `flags`, at `addr` or a free area if `addr` is 0, returns the start
+    /// address.
+    pub(crate) fn attach(
+        &self,
+        aspace: &mut AddrSpace,
+        pid: Pid,
+        id: i32,
+        addr: usize,
+        flags: MappingFlags,
+    ) -> LinuxResult<usize> {
+        let seg = find_segment(id)?;
+        let size = seg.stat().size.align_up_4k();
+        let start = if addr == 0 {
+            aspace
+                .find_free_area(
+                    aspace.mmap_base(),
+                    size,
+                    VirtAddrRange::from_start_size(aspace.base(), aspace.size()),
+                )
+                .ok_or(LinuxError::ENOMEM)?
+        } else {
+            let start = VirtAddr::from(addr);
+            if !start.is_aligned_4k() {
+                return Err(LinuxError::EINVAL);
+            }
+            start
+        };
+        aspace
+            .map_shared(start, size, flags, seg.pages.clone(), 0)
+            .map_err(|_| LinuxError::EINVAL)?;
+        seg.update_attach(true, pid);
+        self.attached.lock().insert(start.as_usize(), seg);
+        Ok(start.as_usize())
+    }
+
+    /// Detaches the segment attached at `addr` from `aspace` of the process
+    /// `pid`.
+    ///
+    /// Only what is left of the mapping of the segment is unmapped, as parts
+    /// of it may have been replaced by other mappings.
+    pub(crate) fn detach(&self, aspace: &mut AddrSpace, pid: Pid, addr: usize) -> LinuxResult {
+        let seg = self
+            .attached
+            .lock()
+            .remove(&addr)
+            .ok_or(LinuxError::EINVAL)?;
+        let areas: Vec<_> = aspace
+            .shared_areas(VirtAddr::from(addr), &seg.pages)
+            .collect();
+        for area in areas {
+            let _ = aspace.unmap(area.start, area.size());
+        }
+        seg.update_attach(false, pid);
+        Ok(())
+    }
+
+    /// Detaches the segments whose mappings have been entirely removed from
+    /// `aspace` of the process `pid` (e.g., by `munmap`, or replaced by a
+    /// `MAP_FIXED` mapping).
+    pub(crate) fn detach_unmapped(&self, aspace: &AddrSpace, pid: Pid) {
+        let mut unmapped = Vec::new();
+        self.attached.lock().retain(|&addr, seg| {
+            let mapped = aspace
+                .shared_areas(VirtAddr::from(addr), &seg.pages)
+                .next()
+                .is_some();
+            if !mapped {
+                unmapped.push(seg.clone());
+            }
+            mapped
+        });
+        for seg in unmapped {
+            seg.update_attach(false, pid);
+        }
+    }
+
+    /// Forgets all attached segments, after the address space is cleared.
+    pub(crate) fn detach_all(&self, pid: Pid) {
+        let attached = core::mem::take(&mut *self.attached.lock());
+        for seg in attached.into_values() {
+            seg.update_attach(false, pid);
+        }
+    }
+
+    /// Inherits the segments attached to the parent, whose mappings have
+    /// been copied by fork.
+    pub(crate) fn inherit(&self, parent: &ShmAttachments, pid: Pid) {
+        let attached = parent.attached.lock().clone();
+        for seg in attached.values() {
+            seg.update_attach(true, pid);
+        }
+        *self.attached.lock() = attached;
+    }
+}
+
+/// Updates the segments attached to the current process after mappings in
+/// `aspace` (its address space) are removed, which detaches the segments
+/// that are no longer mapped.
+///
+/// It should be called by `munmap`, `mremap` and `MAP_FIXED` mappings.
+pub fn update_attachments(aspace: &AddrSpace) {
+    let process = crate::process::current_process();
+    process.shm.detach_unmapped(aspace, process.pid());
+}
+
+#[cfg(feature = "fd")]
+pub use self::memfd::{memfd_pages, Memfd};
+
+#[cfg(feature = "fd")]
+mod memfd {
+    use alloc::string::String;
+    use alloc::sync::Arc;
+    use core::any::Any;
+
+    use arceos_posix_api::{ctypes, get_file_like, FileLike};
+    use axerrno::{LinuxError, LinuxResult};
+    use axio::PollState;
+    use axmm::SharedPages;
+    use spin::Mutex;
+
+    /// An anonymous file in memory created by `memfd_create`.
+    ///
+    /// It can be read, written, resized by `ftruncate`, and mapped with
+    /// `mmap`, where shared mappings use the same frames as the file. Seeking
+    /// is not supported, reads and writes always start from where the last
+    /// one stopped.
+    pub struct Memfd {
+        name: String,
+        pages: Arc<SharedPages>,
+        pos: Mutex<usize>,
+    }
+
+    impl Memfd {
+        /// Creates an empty file with the name `name`, which is only used
+        /// for debugging.
+        pub fn new(name: String) -> Self {
+            Self {
+                name,
+                pages: Arc::new(SharedPages::new(0)),
+                pos: Mutex::new(0),
+            }
+        }
+
+        /// Returns the name given to `memfd_create`.
+        pub fn name(&self) -> &str {
+            &self.name
+        }
+
+        /// Returns the pages of the file.
+        pub fn pages(&self) -> &Arc<SharedPages> {
+            &self.pages
+        }
+
+        /// Returns the memfd file of `fd`.
+        pub fn from_fd(fd: i32) -> LinuxResult<Arc<Self>> {
+            get_file_like(fd)?
+                .into_any()
+                .downcast::<Self>()
+                .map_err(|_| LinuxError::EINVAL)
+        }
+    }
+
+    impl FileLike for Memfd {
+        fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+            let mut pos = self.pos.lock();
+            let n = self.pages.read_at(*pos, buf);
+            *pos += n;
+            Ok(n)
+        }
+
+        fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+            let mut pos = self.pos.lock();
+            let n = self.pages.write_at(*pos, buf)?;
+            *pos += n;
+            Ok(n)
+        }
+
+        fn stat(&self) -> LinuxResult<ctypes::stat> {
+            let size = self.pages.size();
+            Ok(ctypes::stat {
+                st_ino: 1,
+                st_nlink: 1,
+                st_mode: 0o100000 | 0o600u32, // S_IFREG | rw-------
+                st_size: size as _,
+                st_blocks: size.div_ceil(512) as _,
+                st_blksize: 4096,
+                ..Default::default()
+            })
+        }
+
+        fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+            self
+        }
+
+        fn poll(&self) -> LinuxResult<PollState> {
+            Ok(PollState {
+                readable: true,
+                writable: true,
+            })
+        }
+
+        fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+            Ok(())
+        }
+    }
+
+    /// Returns the pages of the memfd file of `fd`, or `None` if `fd` is not
+    /// a memfd file.
+    ///
+    /// This is used by `mmap` to map the file with
+    /// [`AddrSpace::map_shared`](axmm::AddrSpace::map_shared).
+    pub fn memfd_pages(fd: i32) -> Option<Arc<SharedPages>> {
+        Memfd::from_fd(fd).ok().map(|f| f.pages.clone())
+    }
+}
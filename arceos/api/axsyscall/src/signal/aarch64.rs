@@ -0,0 +1,90 @@
+//! The signal frame of the aarch64 Linux ABI.
+
+use axhal::arch::UspaceContext;
+
+use super::{SignalSet, SignalStack, SIGNAL_TRAMPOLINE};
+use crate::num::SYS_RT_SIGRETURN;
+
+/// The code of the trampoline: `mov x8, SYS_RT_SIGRETURN; svc #0`.
+pub(super) const TRAMPOLINE_CODE: [u8; 8] =
+    super::insns([0xd280_0008 | ((SYS_RT_SIGRETURN as u32) << 5), 0xd400_0001]);
+
+/// The condition flags (`NZCV`), the only bits of `PSTATE` that can be
+/// changed by user space.
+const PSTATE_NZCV: u64 = 0xf000_0000;
+
+/// `struct sigcontext` of the aarch64 Linux ABI. The reserved space holds the
+/// FP/SIMD state records, which are not saved for now (an empty record list).
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub(super) struct MContext {
+    fault_address: u64,
+    regs: [u64; 31],
+    sp: u64,
+    pc: u64,
+    pstate: u64,
+    reserved: [u8; 4096],
+}
+
+impl MContext {
+    pub(super) fn save(uctx: &UspaceContext) -> Self {
+        Self {
+            fault_address: 0,
+            regs: uctx.r,
+            sp: uctx.usp,
+            pc: uctx.elr,
+            pstate: uctx.spsr,
+            reserved: [0; 4096],
+        }
+    }
+
+    pub(super) fn restore(&self, uctx: &mut UspaceContext) -> bool {
+        uctx.r = self.regs;
+        uctx.usp = self.sp;
+        uctx.elr = self.pc;
+        // Always return to EL0 with interrupts unmasked.
+        uctx.spsr = self.pstate & PSTATE_NZCV;
+        true
+    }
+}
+
+/// `struct ucontext` of the aarch64 Linux ABI.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(super) struct UContext {
+    flags: usize,
+    link: usize,
+    stack: SignalStack,
+    pub sigmask: SignalSet,
+    _unused: [u8; 120],
+    pub mcontext: MContext,
+}
+
+impl UContext {
+    pub(super) fn new(sigmask: SignalSet, mcontext: MContext) -> Self {
+        Self {
+            flags: 0,
+            link: 0,
+            stack: SignalStack::default(),
+            sigmask,
+            _unused: [0; 120],
+            mcontext,
+        }
+    }
+}
+
+/// Redirects `uctx` to `handler(args[0], args[1], args[2])` on the stack
+/// `sp`, returning to the trampoline.
+pub(super) fn set_handler_context(
+    uctx: &mut UspaceContext,
+    handler: usize,
+    sp: usize,
+    args: [usize; 3],
+) {
+    uctx.set_ip(handler);
+    uctx.set_sp(sp);
+    for (reg, arg) in uctx.r.iter_mut().zip(args) {
+        *reg = arg as _;
+    }
+    uctx.r[30] = SIGNAL_TRAMPOLINE as _;
+}
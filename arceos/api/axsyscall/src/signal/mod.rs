@@ -0,0 +1,755 @@
+//! POSIX signals of user processes.
+//!
+//! Signals sent by `kill` are pending on the target process, while signals
+//! sent to the calling thread itself (e.g., raised by faults) are pending on
+//! the thread. Pending signals are checked each time a user task returns to
+//! user space from a trap (see [`USER_RETURN`]).
+//!
+//! To run a user handler, a signal frame holding the interrupted user context
+//! is pushed onto the user stack, and the task is redirected to the handler.
+//! The handler returns to a trampoline page (see [`SIGNAL_TRAMPOLINE`]) which
+//! calls `rt_sigreturn` to restore the saved context. The layout of the frame
+//! and the trampoline code depend on the architecture.
+//!
+//! Blocking syscalls sleep with [`wait_interruptible`], so that a task
+//! blocked in a syscall is woken up when a signal is sent to it, and the
+//! syscall fails with `EINTR` (syscalls are not restarted, even with
+//! `SA_RESTART`).
+//!
+//! [`USER_RETURN`]: axhal::trap::USER_RETURN
+
+use core::mem::{offset_of, size_of};
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use axerrno::{AxResult, LinuxError, LinuxResult};
+use axhal::arch::{TrapFrame, UspaceContext};
+use axhal::mem::{VirtAddr, PAGE_SIZE_4K};
+use axhal::paging::MappingFlags;
+use axhal::trap::{register_trap_handler, USER_RETURN};
+use axmm::AddrSpace;
+use axtask::{AxTaskRef, TaskExtRef, WaitQueue};
+use kspin::SpinNoIrq;
+use spin::Mutex;
+
+use crate::process;
+use crate::ptr::UserPtr;
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64;
+#[cfg(target_arch = "riscv64")]
+mod riscv64;
+#[cfg(target_arch = "x86_64")]
+mod x86_64;
+
+#[cfg(target_arch = "aarch64")]
+use self::aarch64 as arch;
+#[cfg(target_arch = "riscv64")]
+use self::riscv64 as arch;
+#[cfg(target_arch = "x86_64")]
+use self::x86_64 as arch;
+
+use self::arch::{set_handler_context, MContext, UContext, TRAMPOLINE_CODE};
+
+/// The number of signals (including real-time signals).
+pub const NSIG: usize = 64;
+
+pub const SIGHUP: usize = 1;
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGILL: usize = 4;
+pub const SIGTRAP: usize = 5;
+pub const SIGABRT: usize = 6;
+pub const SIGBUS: usize = 7;
+pub const SIGFPE: usize = 8;
+pub const SIGKILL: usize = 9;
+pub const SIGUSR1: usize = 10;
+pub const SIGSEGV: usize = 11;
+pub const SIGUSR2: usize = 12;
+pub const SIGPIPE: usize = 13;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
+pub const SIGSTKFLT: usize = 16;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGSTOP: usize = 19;
+pub const SIGTSTP: usize = 20;
+pub const SIGTTIN: usize = 21;
+pub const SIGTTOU: usize = 22;
+pub const SIGURG: usize = 23;
+pub const SIGXCPU: usize = 24;
+pub const SIGXFSZ: usize = 25;
+pub const SIGVTALRM: usize = 26;
+pub const SIGPROF: usize = 27;
+pub const SIGWINCH: usize = 28;
+pub const SIGIO: usize = 29;
+pub const SIGPWR: usize = 30;
+pub const SIGSYS: usize = 31;
+
+/// The default action.
+pub const SIG_DFL: usize = 0;
+/// Ignore the signal.
+pub const SIG_IGN: usize = 1;
+
+/// Do not send `SIGCHLD` to the parent when a child stops or continues (only
+/// for `SIGCHLD`).
+pub const SA_NOCLDSTOP: usize = 1;
+/// Do not add the signal to the blocked mask while its handler runs.
+pub const SA_NODEFER: usize = 0x4000_0000;
+/// Restore the default action after the handler has been called once.
+pub const SA_RESETHAND: usize = 0x8000_0000;
+
+/// The address of the trampoline page, which is mapped into the user address
+/// space the first time a signal handler is called.
+///
+/// It contains the code that calls `rt_sigreturn`, and signal handlers return
+/// to it.
+pub const SIGNAL_TRAMPOLINE: usize = 0x3f_fffe_f000;
+
+/// Encodes the 32-bit instructions of the trampoline.
+#[cfg(not(target_arch = "x86_64"))]
+const fn insns(insns: [u32; 2]) -> [u8; 8] {
+    let (a, b) = (insns[0].to_le_bytes(), insns[1].to_le_bytes());
+    [a[0], a[1], a[2], a[3], b[0], b[1], b[2], b[3]]
+}
+
+/// `si_code` of signals sent by `kill`.
+const SI_USER: i32 = 0;
+
+/// A set of signals, where signal `n` is represented by bit `n - 1`.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SignalSet(pub u64);
+
+impl SignalSet {
+    /// Signals that cannot be blocked, ignored or caught.
+    pub const UNBLOCKABLE: Self = Self(1 << (SIGKILL - 1) | 1 << (SIGSTOP - 1));
+
+    /// Whether the set contains `signo`.
+    pub const fn contains(self, signo: usize) -> bool {
+        self.0 & (1 << (signo - 1)) != 0
+    }
+
+    /// Adds `signo` to the set.
+    pub fn add(&mut self, signo: usize) {
+        self.0 |= 1 << (signo - 1);
+    }
+
+    /// Removes `signo` from the set.
+    pub fn remove(&mut self, signo: usize) {
+        self.0 &= !(1 << (signo - 1));
+    }
+}
+
+/// `struct sigaction` of the Linux ABI.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SigAction {
+    /// The handler, or [`SIG_DFL`] or [`SIG_IGN`].
+    pub handler: usize,
+    /// `SA_*` flags.
+    pub flags: usize,
+    /// The function the handler returns to with `SA_RESTORER`, which is
+    /// ignored: handlers always return to [`SIGNAL_TRAMPOLINE`].
+    #[cfg(not(target_arch = "riscv64"))]
+    pub restorer: usize,
+    /// Signals to be blocked while the handler runs.
+    pub mask: SignalSet,
+}
+
+/// The action taken when a signal is delivered with [`SIG_DFL`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefaultAction {
+    /// Terminate the process.
+    Terminate,
+    /// Terminate the process and dump core.
+    CoreDump,
+    /// Ignore the signal.
+    Ignore,
+    /// Stop the process.
+    Stop,
+    /// Continue the process if it is stopped.
+    Continue,
+}
+
+impl DefaultAction {
+    /// Returns the default action of `signo`.
+    ///
+    /// See <https://man7.org/linux/man-pages/man7/signal.7.html>
+    pub const fn of(signo: usize) -> Self {
+        match signo {
+            SIGQUIT | SIGILL | SIGTRAP | SIGABRT | SIGBUS | SIGFPE | SIGSEGV | SIGXCPU
+            | SIGXFSZ | SIGSYS => Self::CoreDump,
+            SIGCHLD | SIGURG | SIGWINCH => Self::Ignore,
+            SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => Self::Stop,
+            SIGCONT => Self::Continue,
+            _ => Self::Terminate,
+        }
+    }
+}
+
+/// Signal states shared by all tasks of a process.
+pub struct ProcessSignals {
+    actions: Mutex<[SigAction; NSIG]>,
+    /// Signals sent to the process.
+    pending: AtomicU64,
+    stopped: AtomicBool,
+    /// Notified when the process continues.
+    cont_wq: WaitQueue,
+}
+
+impl ProcessSignals {
+    pub(crate) fn new() -> Self {
+        Self {
+            actions: Mutex::new([SigAction::default(); NSIG]),
+            pending: AtomicU64::new(0),
+            stopped: AtomicBool::new(false),
+            cont_wq: WaitQueue::new(),
+        }
+    }
+
+    /// Returns the action of `signo`.
+    pub fn action(&self, signo: usize) -> SigAction {
+        self.actions.lock()[signo - 1]
+    }
+
+    /// Sets the action of `signo`, returns the previous one.
+    pub(crate) fn set_action(&self, signo: usize, action: SigAction) -> SigAction {
+        core::mem::replace(&mut self.actions.lock()[signo - 1], action)
+    }
+
+    /// Copies the actions from the parent process (on `fork`).
+    pub(crate) fn inherit(&self, parent: &ProcessSignals) {
+        *self.actions.lock() = *parent.actions.lock();
+    }
+
+    /// Resets handled signals to their default actions (on `execve`).
+    ///
+    /// Ignored signals stay ignored.
+    pub(crate) fn reset_on_exec(&self) {
+        for action in self.actions.lock().iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SigAction::default();
+            }
+        }
+    }
+
+    /// Returns the signals sent to the process but not delivered yet.
+    pub fn pending(&self) -> SignalSet {
+        SignalSet(self.pending.load(Ordering::Acquire))
+    }
+
+    /// Whether `signo` is discarded when it is delivered, i.e., it is
+    /// ignored, or its default action is to ignore it.
+    pub fn is_ignored(&self, signo: usize) -> bool {
+        match self.action(signo).handler {
+            SIG_IGN => true,
+            SIG_DFL => DefaultAction::of(signo) == DefaultAction::Ignore,
+            _ => false,
+        }
+    }
+
+    /// Sends `signo` to the process.
+    ///
+    /// The threads blocked in syscalls are not woken up by it, see
+    /// [`Process::send_signal`](crate::process::Process::send_signal).
+    ///
+    /// `SIGCONT` and `SIGKILL` resume a stopped process at once. `SIGCONT`
+    /// discards the pending stop signals, and vice versa.
+    ///
+    /// Returns whether the process was stopped and is resumed by it.
+    pub(crate) fn send(&self, signo: usize) -> bool {
+        let mut discard = SignalSet::default();
+        match DefaultAction::of(signo) {
+            DefaultAction::Stop => discard.add(SIGCONT),
+            DefaultAction::Continue => {
+                for stop in [SIGSTOP, SIGTSTP, SIGTTIN, SIGTTOU] {
+                    discard.add(stop);
+                }
+            }
+            _ => {}
+        }
+        self.pending.fetch_and(!discard.0, Ordering::AcqRel);
+        self.pending.fetch_or(1 << (signo - 1), Ordering::AcqRel);
+        (signo == SIGCONT || signo == SIGKILL) && self.resume()
+    }
+
+    /// Resumes the process if it is stopped, returns whether it was stopped.
+    pub(crate) fn resume(&self) -> bool {
+        let stopped = self.stopped.swap(false, Ordering::AcqRel);
+        self.cont_wq.notify_all(false);
+        stopped
+    }
+
+    /// Whether the process is stopped by a signal.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    /// Marks the process as stopped, returns whether it was running.
+    pub(crate) fn set_stopped(&self) -> bool {
+        !self.stopped.swap(true, Ordering::AcqRel)
+    }
+
+    /// Blocks the current task until the process is continued.
+    pub(crate) fn wait_continued(&self) {
+        self.cont_wq
+            .wait_until(|| !self.stopped.load(Ordering::Acquire));
+    }
+}
+
+/// A task sleeping in [`wait_interruptible`].
+struct Waiting {
+    wq: *const WaitQueue,
+    task: AxTaskRef,
+}
+
+// SAFETY: the wait queue outlives the record, which is removed before
+// `wait_interruptible` returns.
+unsafe impl Send for Waiting {}
+
+/// Signal states of a task.
+#[derive(Default)]
+pub struct ThreadSignals {
+    /// Signals sent to the task itself.
+    pending: AtomicU64,
+    blocked: AtomicU64,
+    /// The address of the signal frame to be restored, set by `rt_sigreturn`.
+    sigreturn_frame: AtomicUsize,
+    /// Whether the task is killed by another thread of the process.
+    killed: AtomicBool,
+    /// Where the task is sleeping, if it is blocked in a syscall.
+    waiting: SpinNoIrq<Option<Waiting>>,
+}
+
+impl ThreadSignals {
+    /// Returns the blocked signals.
+    pub fn blocked(&self) -> SignalSet {
+        SignalSet(self.blocked.load(Ordering::Acquire))
+    }
+
+    /// Sets the blocked signals. `SIGKILL` and `SIGSTOP` are never blocked.
+    pub(crate) fn set_blocked(&self, set: SignalSet) {
+        self.blocked
+            .store(set.0 & !SignalSet::UNBLOCKABLE.0, Ordering::Release);
+    }
+
+    /// Returns the signals sent to the task but not delivered yet.
+    pub fn pending(&self) -> SignalSet {
+        SignalSet(self.pending.load(Ordering::Acquire))
+    }
+
+    /// Sends `signo` to the task, and wakes it up if it is blocked in a
+    /// syscall and `signo` is not blocked.
+    pub(crate) fn send(&self, signo: usize) {
+        self.pending.fetch_or(1 << (signo - 1), Ordering::AcqRel);
+        if !self.blocked().contains(signo) {
+            self.interrupt();
+        }
+    }
+
+    /// Wakes up the task if it is sleeping in [`wait_interruptible`], so that
+    /// it checks the pending signals.
+    pub(crate) fn interrupt(&self) {
+        if let Some(waiting) = &*self.waiting.lock() {
+            unsafe { &*waiting.wq }.notify_task(false, &waiting.task);
+        }
+    }
+
+    /// Whether the task is killed, see [`ThreadSignals::kill`].
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::Acquire)
+    }
+
+    /// Makes the task exit the next time it returns to user space, and wakes
+    /// it up if it is blocked in a syscall.
+    pub(crate) fn kill(&self) {
+        self.killed.store(true, Ordering::Release);
+        self.interrupt();
+    }
+
+    /// Restores the signal frame before returning to user space.
+    ///
+    /// `sp` is the user stack pointer when the handler returned to the
+    /// trampoline, which points to the `info` field of the frame.
+    pub(crate) fn set_sigreturn_frame(&self, sp: usize) {
+        let frame = sp.wrapping_sub(offset_of!(SignalFrame, info));
+        self.sigreturn_frame.store(frame, Ordering::Release);
+    }
+}
+
+/// Takes the lowest-numbered signal in `pending` that is not in `blocked`.
+fn take_signal(pending: &AtomicU64, blocked: SignalSet) -> Option<usize> {
+    let mut signo = None;
+    let _ = pending.fetch_update(Ordering::AcqRel, Ordering::Acquire, |bits| {
+        let deliverable = bits & !blocked.0;
+        if deliverable == 0 {
+            return None;
+        }
+        let bit = deliverable & deliverable.wrapping_neg();
+        signo = Some(bit.trailing_zeros() as usize + 1);
+        Some(bits & !bit)
+    });
+    signo
+}
+
+/// Whether the current task has a signal to be delivered (except the ignored
+/// ones) or is killed, which interrupts blocking syscalls.
+pub(crate) fn has_pending_signal() -> bool {
+    let curr = axtask::current();
+    let ext = curr.task_ext();
+    if ext.signals.is_killed() {
+        return true;
+    }
+    let pending = ext.signals.pending().0 | ext.process.signals.pending().0;
+    let deliverable = pending & !ext.signals.blocked().0;
+    (1..=NSIG)
+        .filter(|&signo| deliverable & (1 << (signo - 1)) != 0)
+        .any(|signo| !ext.process.signals.is_ignored(signo))
+}
+
+/// Waits on `wq` until `condition` becomes true, or `timeout` (if any)
+/// expires.
+///
+/// The wait is interrupted if the current task has a pending signal (see
+/// [`has_pending_signal`]), including those sent while it is sleeping, as
+/// the senders wake it up.
+///
+/// Returns `Ok(true)` if `condition` is true, `Ok(false)` on timeout, or
+/// `EINTR` if interrupted.
+pub(crate) fn wait_interruptible(
+    wq: &WaitQueue,
+    timeout: Option<Duration>,
+    condition: impl Fn() -> bool,
+) -> LinuxResult<bool> {
+    let curr = axtask::current();
+    let signals = &curr.task_ext().signals;
+    *signals.waiting.lock() = Some(Waiting {
+        wq,
+        task: curr.as_task_ref().clone(),
+    });
+    let woken = || condition() || has_pending_signal();
+    match timeout {
+        Some(dur) => {
+            wq.wait_timeout_until(dur, woken);
+        }
+        None => wq.wait_until(woken),
+    }
+    *signals.waiting.lock() = None;
+
+    if condition() {
+        Ok(true)
+    } else if has_pending_signal() {
+        Err(LinuxError::EINTR)
+    } else {
+        Ok(false)
+    }
+}
+
+/// Sends a signal raised by the current task itself, e.g., `SIGSEGV` on a
+/// page fault.
+///
+/// The signal cannot be ignored or blocked: in that case, its action is reset
+/// to the default one.
+pub(crate) fn force_signal(signo: usize) {
+    let curr = axtask::current();
+    let ext = curr.task_ext();
+    let mut blocked = ext.signals.blocked();
+    let signals = &ext.process.signals;
+    if blocked.contains(signo) || signals.action(signo).handler == SIG_IGN {
+        blocked.remove(signo);
+        ext.signals.set_blocked(blocked);
+        signals.set_action(signo, SigAction::default());
+    }
+    ext.signals.send(signo);
+}
+
+/// `siginfo_t` of the Linux ABI.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SigInfo {
+    signo: i32,
+    errno: i32,
+    code: i32,
+    _pad: i32,
+    fields: [usize; 14],
+}
+
+/// `stack_t` of the Linux ABI.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct SignalStack {
+    sp: usize,
+    flags: i32,
+    size: usize,
+}
+
+/// The frame pushed onto the user stack when calling a signal handler.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SignalFrame {
+    /// The return address of the handler on x86_64, popped by its `ret`.
+    #[cfg(target_arch = "x86_64")]
+    pretcode: usize,
+    info: SigInfo,
+    ucontext: UContext,
+}
+
+/// Maps the trampoline page into `aspace` if it is not mapped yet.
+fn map_trampoline(aspace: &mut AddrSpace) -> AxResult {
+    let vaddr = VirtAddr::from(SIGNAL_TRAMPOLINE);
+    if aspace.page_table().query(vaddr).is_ok() {
+        return Ok(());
+    }
+    aspace.map_alloc(
+        vaddr,
+        PAGE_SIZE_4K,
+        MappingFlags::READ | MappingFlags::EXECUTE | MappingFlags::USER,
+        true,
+    )?;
+    aspace.write(vaddr, &TRAMPOLINE_CODE)
+}
+
+/// Whether `addr` is in the user address space of the current task.
+///
+/// Returning to user space at any other address (e.g., a kernel or
+/// non-canonical one) would fault in the kernel instead of the user task.
+fn is_user_addr(addr: usize) -> bool {
+    let aspace = axtask::current().task_ext().aspace.lock();
+    (aspace.base().as_usize()..aspace.end().as_usize()).contains(&addr)
+}
+
+/// Pushes the signal frame onto the user stack, and redirects the user
+/// context to the handler of `signo`.
+///
+/// Returns `false` if the handler is not a user address, or the frame cannot
+/// be pushed.
+fn call_handler(tf: &mut TrapFrame, signo: usize, action: &SigAction) -> bool {
+    let curr = axtask::current();
+    let ext = curr.task_ext();
+    if !is_user_addr(action.handler) || map_trampoline(&mut ext.aspace.lock()).is_err() {
+        return false;
+    }
+
+    let mut uctx = UspaceContext::from(tf);
+    // On x86_64, skip the red zone below the stack pointer, and align the
+    // frame as if its return address (`pretcode`) had been pushed by a `call`.
+    let (red_zone, bias) = if cfg!(target_arch = "x86_64") {
+        (128, 8)
+    } else {
+        (0, 0)
+    };
+    let frame_addr = (uctx
+        .get_sp()
+        .wrapping_sub(red_zone + size_of::<SignalFrame>())
+        & !0xf)
+        .wrapping_sub(bias);
+    let blocked = ext.signals.blocked();
+    let frame = SignalFrame {
+        #[cfg(target_arch = "x86_64")]
+        pretcode: SIGNAL_TRAMPOLINE,
+        info: SigInfo {
+            signo: signo as _,
+            errno: 0,
+            code: SI_USER,
+            _pad: 0,
+            fields: [0; 14],
+        },
+        ucontext: UContext::new(blocked, MContext::save(&uctx)),
+    };
+    if UserPtr::from(frame_addr).write(frame).is_err() {
+        return false;
+    }
+
+    // handler(signo, &info, &ucontext), returning to the trampoline.
+    let args = [
+        signo,
+        frame_addr + offset_of!(SignalFrame, info),
+        frame_addr + offset_of!(SignalFrame, ucontext),
+    ];
+    set_handler_context(&mut uctx, action.handler, frame_addr, args);
+    *tf = *uctx;
+
+    let mut blocked = SignalSet(blocked.0 | action.mask.0);
+    if action.flags & SA_NODEFER == 0 {
+        blocked.add(signo);
+    }
+    ext.signals.set_blocked(blocked);
+    if action.flags & SA_RESETHAND != 0 {
+        ext.process.signals.set_action(signo, SigAction::default());
+    }
+    true
+}
+
+/// Restores the user context saved in the signal frame at `frame_addr`.
+///
+/// Returns `false` if the frame cannot be read.
+fn restore_frame(tf: &mut TrapFrame, frame_addr: usize) -> bool {
+    let Ok(frame) = UserPtr::<SignalFrame>::from(frame_addr).read() else {
+        return false;
+    };
+    let mut uctx = UspaceContext::from(tf);
+    if !frame.ucontext.mcontext.restore(&mut uctx) {
+        return false;
+    }
+    *tf = *uctx;
+    axtask::current()
+        .task_ext()
+        .signals
+        .set_blocked(frame.ucontext.sigmask);
+    true
+}
+
+/// Forces `SIGSEGV` with the default action, when the signal frame is
+/// corrupted.
+fn force_sigsegv() {
+    let curr = axtask::current();
+    curr.task_ext()
+        .process
+        .signals
+        .set_action(SIGSEGV, SigAction::default());
+    force_signal(SIGSEGV);
+}
+
+/// Delivers the pending signals of the current task before it returns to user
+/// space.
+#[register_trap_handler(USER_RETURN)]
+fn handle_user_return(tf: &mut TrapFrame) {
+    let curr = axtask::current();
+    if unsafe { curr.task_ext_ptr() }.is_null() {
+        return;
+    }
+    let ext = curr.task_ext();
+    let frame_addr = ext.signals.sigreturn_frame.swap(0, Ordering::AcqRel);
+    if frame_addr != 0 && !restore_frame(tf, frame_addr) {
+        warn!("{}: bad signal frame @ {:#x}", curr.id_name(), frame_addr);
+        force_sigsegv();
+    }
+
+    loop {
+        if ext.signals.is_killed() {
+            process::exit_killed();
+        }
+        let blocked = ext.signals.blocked();
+        let Some(signo) = take_signal(&ext.signals.pending, blocked)
+            .or_else(|| take_signal(&ext.process.signals.pending, blocked))
+        else {
+            let ip = UspaceContext::from(tf).get_ip();
+            if is_user_addr(ip) {
+                return;
+            }
+            warn!("{}: bad user return address {:#x}", curr.id_name(), ip);
+            force_sigsegv();
+            continue;
+        };
+        let action = ext.process.signals.action(signo);
+        debug!("{}: deliver signal {}", curr.id_name(), signo);
+        match action.handler {
+            SIG_IGN => {}
+            SIG_DFL => match DefaultAction::of(signo) {
+                DefaultAction::Terminate => process::exit_current_by_signal(signo),
+                DefaultAction::CoreDump => process::exit_current_dumping_core(tf, signo),
+                DefaultAction::Stop => ext.process.stop(signo),
+                DefaultAction::Ignore | DefaultAction::Continue => {}
+            },
+            _ => {
+                if call_handler(tf, signo, &action) {
+                    // Other signals are delivered when the handler returns.
+                    return;
+                }
+                warn!("{}: cannot deliver signal {}", curr.id_name(), signo);
+                force_sigsegv();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signal_set(signals: &[usize]) -> SignalSet {
+        let mut set = SignalSet::default();
+        for &signo in signals {
+            set.add(signo);
+        }
+        set
+    }
+
+    #[test]
+    fn test_take_lowest_unblocked_signal() {
+        let pending = AtomicU64::new(signal_set(&[SIGINT, SIGUSR1, SIGTERM, NSIG]).0);
+        let blocked = signal_set(&[SIGINT]);
+        assert_eq!(take_signal(&pending, blocked), Some(SIGUSR1));
+        assert_eq!(take_signal(&pending, blocked), Some(SIGTERM));
+        assert_eq!(take_signal(&pending, blocked), Some(NSIG));
+        assert_eq!(take_signal(&pending, blocked), None);
+        // Blocked signals stay pending.
+        assert_eq!(pending.load(Ordering::Acquire), blocked.0);
+    }
+
+    #[test]
+    fn test_stop_and_continue_discard_each_other() {
+        let signals = ProcessSignals::new();
+        signals.send(SIGTSTP);
+        signals.send(SIGTTIN);
+        signals.send(SIGUSR1);
+        assert!(!signals.send(SIGCONT));
+        assert_eq!(signals.pending(), signal_set(&[SIGUSR1, SIGCONT]));
+        signals.send(SIGSTOP);
+        assert_eq!(signals.pending(), signal_set(&[SIGUSR1, SIGSTOP]));
+    }
+
+    #[test]
+    fn test_resume_stopped_process() {
+        let signals = ProcessSignals::new();
+        assert!(signals.set_stopped());
+        assert!(!signals.set_stopped());
+        // Other signals stay pending until the process is continued.
+        assert!(!signals.send(SIGUSR1));
+        assert!(signals.is_stopped());
+        assert!(signals.send(SIGKILL));
+        assert!(!signals.is_stopped());
+        assert!(signals.set_stopped());
+        assert!(signals.send(SIGCONT));
+        assert!(!signals.send(SIGCONT));
+    }
+
+    #[test]
+    fn test_reset_actions_on_exec() {
+        let signals = ProcessSignals::new();
+        let handler = SigAction {
+            handler: 0x1000,
+            ..Default::default()
+        };
+        let ignore = SigAction {
+            handler: SIG_IGN,
+            ..Default::default()
+        };
+        signals.set_action(SIGINT, handler);
+        signals.set_action(SIGPIPE, ignore);
+        assert!(!signals.is_ignored(SIGINT));
+        assert!(signals.is_ignored(SIGPIPE));
+        signals.reset_on_exec();
+        assert_eq!(signals.action(SIGINT).handler, SIG_DFL);
+        assert_eq!(signals.action(SIGPIPE).handler, SIG_IGN);
+        // Signals whose default action is to ignore them are discarded too.
+        assert!(signals.is_ignored(SIGCHLD));
+        assert!(!signals.is_ignored(SIGINT));
+    }
+
+    #[test]
+    fn test_kill_and_stop_are_not_blocked() {
+        let signals = ThreadSignals::default();
+        signals.set_blocked(SignalSet(u64::MAX));
+        assert_eq!(signals.blocked().0, !SignalSet::UNBLOCKABLE.0);
+        signals.send(SIGINT);
+        signals.send(SIGKILL);
+        assert_eq!(signals.pending(), signal_set(&[SIGINT, SIGKILL]));
+        assert_eq!(
+            take_signal(&signals.pending, signals.blocked()),
+            Some(SIGKILL)
+        );
+        assert_eq!(take_signal(&signals.pending, signals.blocked()), None);
+    }
+}
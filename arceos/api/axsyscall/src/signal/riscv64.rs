@@ -0,0 +1,82 @@
+//! The signal frame of the riscv64 Linux ABI.
+
+use axhal::arch::UspaceContext;
+
+use super::{SignalSet, SignalStack, SIGNAL_TRAMPOLINE};
+use crate::num::SYS_RT_SIGRETURN;
+
+/// The code of the trampoline: `li a7, SYS_RT_SIGRETURN; ecall`.
+pub(super) const TRAMPOLINE_CODE: [u8; 8] =
+    super::insns([0x0000_0893 | ((SYS_RT_SIGRETURN as u32) << 20), 0x0000_0073]);
+
+/// `struct sigcontext` of the riscv64 Linux ABI: the program counter, the
+/// general registers, and the floating-point state (not saved for now).
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub(super) struct MContext {
+    regs: [usize; 32],
+    fpstate: [u8; 528],
+}
+
+impl MContext {
+    pub(super) fn save(uctx: &UspaceContext) -> Self {
+        let mut regs = [0; 32];
+        regs[0] = uctx.get_ip();
+        // `GeneralRegisters` has the same layout as `user_regs_struct`
+        // without `pc`.
+        let gregs: [usize; 31] = unsafe { core::mem::transmute(uctx.regs) };
+        regs[1..].copy_from_slice(&gregs);
+        Self {
+            regs,
+            fpstate: [0; 528],
+        }
+    }
+
+    pub(super) fn restore(&self, uctx: &mut UspaceContext) -> bool {
+        uctx.set_ip(self.regs[0]);
+        let gregs: [usize; 31] = self.regs[1..].try_into().unwrap();
+        uctx.regs = unsafe { core::mem::transmute(gregs) };
+        true
+    }
+}
+
+/// `struct ucontext` of the riscv64 Linux ABI.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(super) struct UContext {
+    flags: usize,
+    link: usize,
+    stack: SignalStack,
+    pub sigmask: SignalSet,
+    _unused: [u8; 120],
+    pub mcontext: MContext,
+}
+
+impl UContext {
+    pub(super) fn new(sigmask: SignalSet, mcontext: MContext) -> Self {
+        Self {
+            flags: 0,
+            link: 0,
+            stack: SignalStack::default(),
+            sigmask,
+            _unused: [0; 120],
+            mcontext,
+        }
+    }
+}
+
+/// Redirects `uctx` to `handler(args[0], args[1], args[2])` on the stack
+/// `sp`, returning to the trampoline.
+pub(super) fn set_handler_context(
+    uctx: &mut UspaceContext,
+    handler: usize,
+    sp: usize,
+    args: [usize; 3],
+) {
+    uctx.set_ip(handler);
+    uctx.set_sp(sp);
+    uctx.regs.a0 = args[0];
+    uctx.regs.a1 = args[1];
+    uctx.regs.a2 = args[2];
+    uctx.regs.ra = SIGNAL_TRAMPOLINE;
+}
@@ -0,0 +1,152 @@
+//! The signal frame of the x86_64 Linux ABI.
+
+use axhal::arch::UspaceContext;
+
+use super::{SignalSet, SignalStack};
+use crate::num::SYS_RT_SIGRETURN;
+
+/// The code of the trampoline: `mov eax, SYS_RT_SIGRETURN; syscall`.
+pub(super) const TRAMPOLINE_CODE: [u8; 7] = {
+    let n = (SYS_RT_SIGRETURN as u32).to_le_bytes();
+    [0xb8, n[0], n[1], n[2], n[3], 0x0f, 0x05]
+};
+
+/// The bits of `RFLAGS` that can be changed by user space (`AC`, `OF`, `DF`,
+/// `TF`, `SF`, `ZF`, `AF`, `PF`, `CF` and `RF`).
+const RFLAGS_USER: u64 = 0x5_0dd5;
+
+/// `struct sigcontext` of the x86_64 Linux ABI. The FP state is not saved for
+/// now (`fpstate` is NULL).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(super) struct MContext {
+    r8: u64,
+    r9: u64,
+    r10: u64,
+    r11: u64,
+    r12: u64,
+    r13: u64,
+    r14: u64,
+    r15: u64,
+    rdi: u64,
+    rsi: u64,
+    rbp: u64,
+    rbx: u64,
+    rdx: u64,
+    rax: u64,
+    rcx: u64,
+    rsp: u64,
+    rip: u64,
+    rflags: u64,
+    cs: u16,
+    gs: u16,
+    fs: u16,
+    ss: u16,
+    err: u64,
+    trapno: u64,
+    oldmask: u64,
+    cr2: u64,
+    fpstate: u64,
+    reserved: [u64; 8],
+}
+
+impl MContext {
+    pub(super) fn save(uctx: &UspaceContext) -> Self {
+        Self {
+            r8: uctx.r8,
+            r9: uctx.r9,
+            r10: uctx.r10,
+            r11: uctx.r11,
+            r12: uctx.r12,
+            r13: uctx.r13,
+            r14: uctx.r14,
+            r15: uctx.r15,
+            rdi: uctx.rdi,
+            rsi: uctx.rsi,
+            rbp: uctx.rbp,
+            rbx: uctx.rbx,
+            rdx: uctx.rdx,
+            rax: uctx.rax,
+            rcx: uctx.rcx,
+            rsp: uctx.rsp,
+            rip: uctx.rip,
+            rflags: uctx.rflags,
+            cs: uctx.cs as _,
+            gs: 0,
+            fs: 0,
+            ss: uctx.ss as _,
+            err: 0,
+            trapno: 0,
+            oldmask: 0,
+            cr2: 0,
+            fpstate: 0,
+            reserved: [0; 8],
+        }
+    }
+
+    /// Returns `false` if the saved `rip` is not a user address.
+    pub(super) fn restore(&self, uctx: &mut UspaceContext) -> bool {
+        // Returning to a non-canonical address faults in the kernel.
+        if self.rip >= 1 << 47 {
+            return false;
+        }
+        uctx.r8 = self.r8;
+        uctx.r9 = self.r9;
+        uctx.r10 = self.r10;
+        uctx.r11 = self.r11;
+        uctx.r12 = self.r12;
+        uctx.r13 = self.r13;
+        uctx.r14 = self.r14;
+        uctx.r15 = self.r15;
+        uctx.rdi = self.rdi;
+        uctx.rsi = self.rsi;
+        uctx.rbp = self.rbp;
+        uctx.rbx = self.rbx;
+        uctx.rdx = self.rdx;
+        uctx.rax = self.rax;
+        uctx.rcx = self.rcx;
+        uctx.rsp = self.rsp;
+        uctx.rip = self.rip;
+        // The segments are left unchanged.
+        uctx.rflags = (uctx.rflags & !RFLAGS_USER) | (self.rflags & RFLAGS_USER);
+        true
+    }
+}
+
+/// `struct ucontext` of the x86_64 Linux ABI.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(super) struct UContext {
+    flags: usize,
+    link: usize,
+    stack: SignalStack,
+    pub mcontext: MContext,
+    pub sigmask: SignalSet,
+}
+
+impl UContext {
+    pub(super) fn new(sigmask: SignalSet, mcontext: MContext) -> Self {
+        Self {
+            flags: 0,
+            link: 0,
+            stack: SignalStack::default(),
+            mcontext,
+            sigmask,
+        }
+    }
+}
+
+/// Redirects `uctx` to `handler(args[0], args[1], args[2])` on the stack
+/// `sp`, whose top holds the return address (the trampoline).
+pub(super) fn set_handler_context(
+    uctx: &mut UspaceContext,
+    handler: usize,
+    sp: usize,
+    args: [usize; 3],
+) {
+    uctx.set_ip(handler);
+    uctx.set_sp(sp);
+    uctx.rdi = args[0] as _;
+    uctx.rsi = args[1] as _;
+    uctx.rdx = args[2] as _;
+}
@@ -0,0 +1,89 @@
+//! The syscall dispatch table.
+
+use axerrno::LinuxError;
+use axhal::arch::TrapFrame;
+use axhal::trap::{register_trap_handler, SYSCALL};
+use axtask::TaskExtRef;
+use spin::RwLock;
+
+/// The maximum syscall number (exclusive) that can be registered.
+pub const MAX_SYSCALL_NUM: usize = 512;
+
+/// The type of syscall handlers.
+///
+/// A handler decodes its arguments from the given [`TrapFrame`] and returns
+/// the value to put in the return value register (a negative `errno` on
+/// failure).
+pub type SyscallHandler = fn(&TrapFrame) -> isize;
+
+/// A numbered table of syscall handlers.
+pub(crate) struct SyscallTable([Option<SyscallHandler>; MAX_SYSCALL_NUM]);
+
+impl SyscallTable {
+    /// Creates an empty table.
+    pub const fn new() -> Self {
+        Self([None; MAX_SYSCALL_NUM])
+    }
+
+    /// Sets the handler of syscall `num`, returns the previous one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num` is not less than [`MAX_SYSCALL_NUM`].
+    pub fn set(&mut self, num: usize, handler: SyscallHandler) -> Option<SyscallHandler> {
+        assert!(num < MAX_SYSCALL_NUM, "syscall number {} out of range", num);
+        self.0[num].replace(handler)
+    }
+
+    /// Gets the handler of syscall `num`.
+    pub fn get(&self, num: usize) -> Option<SyscallHandler> {
+        self.0.get(num).copied().flatten()
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref SYSCALL_TABLE: RwLock<SyscallTable> = RwLock::new(crate::imp::linux_syscall_table());
+}
+
+/// Registers `handler` for syscall `num`.
+///
+/// It replaces the built-in handler (if any), and returns the previous one so
+/// that the new handler can fall back to it.
+///
+/// # Panics
+///
+/// Panics if `num` is not less than [`MAX_SYSCALL_NUM`].
+pub fn register_syscall(num: usize, handler: SyscallHandler) -> Option<SyscallHandler> {
+    SYSCALL_TABLE.write().set(num, handler)
+}
+
+/// Returns the handler currently registered for syscall `num`.
+pub fn syscall_handler(num: usize) -> Option<SyscallHandler> {
+    SYSCALL_TABLE.read().get(num)
+}
+
+#[register_trap_handler(SYSCALL)]
+fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
+    debug!("handle_syscall [{}] ...", syscall_num);
+    if crate::trace::is_traced() {
+        crate::trace::trace_syscall(tf, syscall_num, |tf| dispatch(tf, syscall_num))
+    } else {
+        dispatch(tf, syscall_num)
+    }
+}
+
+fn dispatch(tf: &TrapFrame, syscall_num: usize) -> isize {
+    // Do not hold the lock during the syscall, as the handler may block or
+    // never return (e.g. `exit`).
+    let ret = match syscall_handler(syscall_num) {
+        Some(handler) => handler(tf),
+        None => {
+            warn!("Unimplemented syscall: {}", syscall_num);
+            -LinuxError::ENOSYS.code() as _
+        }
+    };
+    if ret == -LinuxError::ENOSYS.code() as isize {
+        axtask::current().task_ext().process.count_enosys();
+    }
+    ret
+}
@@ -0,0 +1,266 @@
+//! Task extended data and user task creation for monolithic kernels.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use alloc::sync::Arc;
+
+#[cfg(feature = "fd")]
+use arceos_posix_api::FdTable;
+use axhal::arch::UspaceContext;
+use axhal::mem::VirtAddr;
+use axmm::AddrSpace;
+use axsync::Mutex;
+use axtask::{AxTaskRef, TaskExtRef, TaskInner};
+
+use crate::mm::Heap;
+use crate::process::Process;
+use crate::ptr::UserPtr;
+use crate::signal::ThreadSignals;
+
+/// The kernel stack size of user tasks.
+pub const KERNEL_STACK_SIZE: usize = 0x40000; // 256 KiB
+
+/// Task extended data for the monolithic kernel.
+pub struct TaskExt {
+    /// The process that the task belongs to.
+    pub process: Arc<Process>,
+    /// The clear thread tid field
+    ///
+    /// See <https://manpages.debian.org/unstable/manpages-dev/set_tid_address.2.en.html#clear_child_tid>
+    ///
+    /// When the thread exits, the kernel clears the word at this address if it is not NULL.
+    clear_child_tid: AtomicU64,
+    /// The address to store the TID when the task enters user space for the
+    /// first time (`CLONE_CHILD_SETTID`), or 0.
+    set_child_tid: AtomicU64,
+    /// The user space context.
+    pub uctx: UspaceContext,
+    /// The virtual memory address space.
+    pub aspace: Arc<Mutex<AddrSpace>>,
+    /// The file descriptor table, which may be shared with other tasks.
+    #[cfg(feature = "fd")]
+    fd_table: spin::RwLock<Arc<spin::RwLock<FdTable>>>,
+    /// The signal mask and the signals sent to the task.
+    pub signals: ThreadSignals,
+    /// Whether the syscalls of the task are traced, see [`crate::trace`].
+    traced: AtomicBool,
+}
+
+impl TaskExt {
+    /// Creates the task extended data, with a new file descriptor table
+    /// where only the standard streams are opened.
+    pub fn new(process: Arc<Process>, uctx: UspaceContext, aspace: Arc<Mutex<AddrSpace>>) -> Self {
+        Self {
+            process,
+            uctx,
+            clear_child_tid: AtomicU64::new(0),
+            set_child_tid: AtomicU64::new(0),
+            aspace,
+            #[cfg(feature = "fd")]
+            fd_table: spin::RwLock::new(Arc::new(spin::RwLock::new(FdTable::new()))),
+            signals: ThreadSignals::default(),
+            traced: AtomicBool::new(false),
+        }
+    }
+
+    pub fn clear_child_tid(&self) -> u64 {
+        self.clear_child_tid.load(Ordering::Relaxed)
+    }
+
+    pub fn set_clear_child_tid(&self, clear_child_tid: u64) {
+        self.clear_child_tid
+            .store(clear_child_tid, Ordering::Relaxed);
+    }
+
+    /// Whether the syscalls of the task are traced.
+    pub fn is_traced(&self) -> bool {
+        self.traced.load(Ordering::Relaxed)
+    }
+
+    /// Enables or disables tracing the syscalls of the task. The tasks
+    /// created by it afterwards inherit the setting.
+    pub fn set_traced(&self, traced: bool) {
+        self.traced.store(traced, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_set_child_tid(&self, set_child_tid: u64) {
+        self.set_child_tid.store(set_child_tid, Ordering::Relaxed);
+    }
+
+    /// Returns the file descriptor table.
+    #[cfg(feature = "fd")]
+    pub fn fd_table(&self) -> Arc<spin::RwLock<FdTable>> {
+        self.fd_table.read().clone()
+    }
+
+    /// Replaces the file descriptor table.
+    #[cfg(feature = "fd")]
+    pub(crate) fn set_fd_table(&self, fd_table: Arc<spin::RwLock<FdTable>>) {
+        *self.fd_table.write() = fd_table;
+    }
+}
+
+axtask::def_task_ext!(TaskExt);
+
+#[cfg(feature = "fd")]
+struct FdTableIfImpl;
+
+#[cfg(feature = "fd")]
+#[crate_interface::impl_interface]
+impl arceos_posix_api::FdTableIf for FdTableIfImpl {
+    fn current_fd_table() -> Arc<spin::RwLock<FdTable>> {
+        lazy_static::lazy_static! {
+            /// The table of kernel tasks, which have no task extended data.
+            static ref KERNEL_FD_TABLE: Arc<spin::RwLock<FdTable>> =
+                Arc::new(spin::RwLock::new(FdTable::new()));
+        }
+        let curr = axtask::current();
+        if unsafe { curr.task_ext_ptr() }.is_null() {
+            KERNEL_FD_TABLE.clone()
+        } else {
+            curr.task_ext().fd_table()
+        }
+    }
+}
+
+#[cfg(feature = "fd")]
+struct InterruptIfImpl;
+
+#[cfg(feature = "fd")]
+#[crate_interface::impl_interface]
+impl arceos_posix_api::InterruptIf for InterruptIfImpl {
+    fn is_interrupted() -> bool {
+        // Kernel tasks never receive signals.
+        !unsafe { axtask::current().task_ext_ptr() }.is_null()
+            && crate::signal::has_pending_signal()
+    }
+}
+
+#[cfg(feature = "fs")]
+struct PageCacheIfImpl;
+
+#[cfg(feature = "fs")]
+#[crate_interface::impl_interface]
+impl arceos_posix_api::PageCacheIf for PageCacheIfImpl {
+    fn file_read(path: &str, offset: usize, buf: &mut [u8]) {
+        axmm::read_file_cache(path, offset, buf);
+    }
+
+    fn file_written(path: &str, offset: usize, data: &[u8]) {
+        axmm::write_file_cache(path, offset, data);
+    }
+
+    fn file_truncated(path: &str, size: usize) {
+        axmm::truncate_file_cache(path, size);
+    }
+
+    fn file_removed(path: &str) {
+        axmm::remove_file_cache(path);
+    }
+
+    fn file_renamed(old: &str, new: &str) {
+        axmm::rename_file_cache(old, new);
+    }
+}
+
+/// Creates a task that enters user space when it starts to run.
+///
+/// The user space context is taken from the task extended data, which should
+/// be initialized by the caller (with [`TaskInner::init_task_ext`]) before the
+/// task is spawned.
+pub fn new_user_task(name: &str) -> TaskInner {
+    TaskInner::new(
+        || {
+            let curr = axtask::current();
+            let kstack_top = curr.kernel_stack_top().unwrap();
+            let set_child_tid = curr.task_ext().set_child_tid.swap(0, Ordering::Relaxed);
+            if set_child_tid != 0 {
+                // Errors are ignored, as on Linux.
+                let tid = curr.id().as_u64() as i32;
+                let _ = UserPtr::<i32>::from(set_child_tid as usize).write(tid);
+            }
+            info!(
+                "Enter user space: entry={:#x}, ustack={:#x}, kstack={:#x}",
+                curr.task_ext().uctx.get_ip(),
+                curr.task_ext().uctx.get_sp(),
+                kstack_top,
+            );
+            unsafe { curr.task_ext().uctx.enter_uspace(kstack_top) };
+        },
+        name.into(),
+        KERNEL_STACK_SIZE,
+    )
+}
+
+/// Spawns a task that enters user space with the given context and address
+/// space.
+///
+/// The task is the first thread of a new process without parent, whose heap
+/// starts at `brk` (usually the end of the loaded image, see
+/// [`ElfImage::brk`]). The first process spawned in this way becomes the init
+/// process.
+///
+/// With the `fd` feature, the standard streams of the process are the console
+/// terminal, which becomes the controlling terminal of its session if it is
+/// not controlling another session (see [`tty`](crate::tty)).
+///
+/// [`ElfImage::brk`]: crate::loader::ElfImage::brk
+pub fn spawn_user_task(
+    aspace: Arc<Mutex<AddrSpace>>,
+    uctx: UspaceContext,
+    brk: VirtAddr,
+) -> AxTaskRef {
+    spawn_user_process(None, aspace, uctx, brk)
+}
+
+/// Spawns a task like [`spawn_user_task`], but as the first thread of a new
+/// child process of `parent` (see [`Process::new_kernel`]).
+///
+/// The child leads a new process group in the session of `parent`. If the
+/// console terminal is the controlling terminal of the session (or of no
+/// session, where it is acquired for the session), the group becomes its
+/// foreground process group, as a shell does for a job.
+pub fn spawn_user_child(
+    parent: &Arc<Process>,
+    aspace: Arc<Mutex<AddrSpace>>,
+    uctx: UspaceContext,
+    brk: VirtAddr,
+) -> AxTaskRef {
+    spawn_user_process(Some(parent), aspace, uctx, brk)
+}
+
+fn spawn_user_process(
+    parent: Option<&Arc<Process>>,
+    aspace: Arc<Mutex<AddrSpace>>,
+    uctx: UspaceContext,
+    brk: VirtAddr,
+) -> AxTaskRef {
+    #[cfg(feature = "fs")]
+    crate::procfs::init();
+    let mut task = new_user_task("userboot");
+    task.ctx_mut()
+        .set_page_table_root(aspace.lock().page_table_root());
+    #[cfg(feature = "swap")]
+    axmm::swap::register_aspace(&aspace);
+    let process = Process::new(task.id().as_u64(), parent);
+    process.set_pgid(process.pid());
+    *process.heap.lock() = Heap::new(brk);
+    process.set_auxv(crate::mm::read_auxv(&mut aspace.lock(), uctx.get_sp()).unwrap_or_default());
+    #[allow(unused_mut)]
+    let mut ext = TaskExt::new(process.clone(), uctx, aspace);
+    #[cfg(feature = "fd")]
+    {
+        let console = crate::tty::console();
+        let sid = process.sid();
+        let acquire = console.session() == 0 && crate::tty::controlling_tty(sid).is_none();
+        if acquire || (parent.is_some() && console.session() == sid) {
+            console.set_controlling(sid, process.pgid());
+        }
+        let file: Arc<dyn arceos_posix_api::FileLike> =
+            Arc::new(crate::tty::TtyFile::new(console.clone()));
+        let stdio = [file.clone(), file.clone(), file];
+        ext.set_fd_table(Arc::new(spin::RwLock::new(FdTable::with_stdio(stdio))));
+    }
+    task.init_task_ext(ext);
+    process.spawn_thread(task)
+}
@@ -0,0 +1,418 @@
+//! Syscall tracing, like `strace`.
+//!
+//! Each syscall of a traced task is logged with its name, decoded arguments,
+//! return value (or `errno` name) and elapsed time, e.g.:
+//!
+//! ```text
+//! [9] openat(AT_FDCWD, "/etc/passwd", O_RDONLY|O_CLOEXEC, 0) = 3 <0.000120>
+//! [9] read(3, 0x3fffffe000, 4096) = -1 EBADF (Bad file descriptor) <0.000004>
+//! ```
+//!
+//! All tasks are traced if the `strace` feature is enabled. Otherwise,
+//! tracing is enabled per task by [`TaskExt::set_traced`], and inherited by
+//! the tasks it creates. The log is printed to the console, or written to a
+//! file set by [`set_trace_file`] (with the `fs` feature) so that runs can be
+//! compared.
+//!
+//! [`TaskExt::set_traced`]: crate::task::TaskExt::set_traced
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use core::fmt::Write;
+
+use axerrno::LinuxError;
+use axhal::arch::TrapFrame;
+use axtask::TaskExtRef;
+
+use crate::num::*;
+use crate::ptr::UserCStr;
+
+/// How a syscall argument is decoded.
+#[derive(Clone, Copy)]
+enum Arg {
+    /// A signed integer.
+    Int,
+    /// An integer in hexadecimal, e.g., an address or an opaque value.
+    Hex,
+    /// An integer in octal, e.g., a file mode.
+    Oct,
+    /// A file descriptor.
+    Fd,
+    /// A file descriptor that may be `AT_FDCWD`.
+    DirFd,
+    /// A NULL-terminated string in user memory, e.g., a path.
+    Str,
+    /// Flags of `open`.
+    OpenFlags,
+    /// Protection flags of `mmap`.
+    Prot,
+    /// Flags of `mmap`.
+    MapFlags,
+    /// A signal number.
+    Signal,
+}
+
+/// Returns how the arguments of syscall `num` are decoded.
+///
+/// All 6 arguments are shown in hexadecimal for the unknown syscalls.
+fn arg_kinds(num: usize) -> &'static [Arg] {
+    use Arg::*;
+    match num {
+        SYS_READ | SYS_WRITE | SYS_READV | SYS_WRITEV | SYS_GETDENTS64 => &[Fd, Hex, Int],
+        SYS_PREAD64 | SYS_PWRITE64 => &[Fd, Hex, Int, Int],
+        SYS_OPENAT => &[DirFd, Str, OpenFlags, Oct],
+        SYS_CLOSE | SYS_DUP | SYS_FSYNC => &[Fd],
+        SYS_DUP3 => &[Fd, Fd, OpenFlags],
+        SYS_FCNTL | SYS_IOCTL => &[Fd, Hex, Hex],
+        SYS_LSEEK => &[Fd, Int, Int],
+        SYS_FSTAT => &[Fd, Hex],
+        SYS_FTRUNCATE => &[Fd, Int],
+        SYS_NEWFSTATAT => &[DirFd, Str, Hex, Hex],
+        SYS_STATX => &[DirFd, Str, Hex, Hex, Hex],
+        SYS_MKDIRAT => &[DirFd, Str, Oct],
+        SYS_UNLINKAT => &[DirFd, Str, Hex],
+        SYS_FACCESSAT | SYS_FCHMODAT => &[DirFd, Str, Oct, Hex],
+        SYS_READLINKAT => &[DirFd, Str, Hex, Int],
+        SYS_RENAMEAT => &[DirFd, Str, DirFd, Str],
+        SYS_LINKAT => &[DirFd, Str, DirFd, Str, Hex],
+        SYS_CHDIR => &[Str],
+        SYS_GETCWD => &[Hex, Int],
+        SYS_PIPE2 => &[Hex, OpenFlags],
+        SYS_PPOLL => &[Hex, Int, Hex, Hex],
+        SYS_MMAP => &[Hex, Int, Prot, MapFlags, Fd, Hex],
+        SYS_MPROTECT => &[Hex, Int, Prot],
+        SYS_MUNMAP => &[Hex, Int],
+        SYS_MSYNC => &[Hex, Int, Hex],
+        SYS_MREMAP => &[Hex, Int, Int, Hex, Hex],
+        SYS_MADVISE => &[Hex, Int, Int],
+        SYS_SHMGET => &[Hex, Int, Oct],
+        SYS_SHMAT => &[Int, Hex, Hex],
+        SYS_SHMDT => &[Hex],
+        SYS_SHMCTL => &[Int, Int, Hex],
+        SYS_MEMFD_CREATE => &[Str, Hex],
+        SYS_BRK | SYS_SET_TID_ADDRESS | SYS_UNAME => &[Hex],
+        SYS_EXECVE => &[Str, Hex, Hex],
+        SYS_EXIT | SYS_EXIT_GROUP => &[Int],
+        SYS_CLONE => &[Hex, Hex, Hex, Hex, Hex],
+        SYS_WAIT4 => &[Int, Hex, Hex, Hex],
+        SYS_KILL | SYS_TKILL => &[Int, Signal],
+        SYS_GETPGID | SYS_GETSID => &[Int],
+        SYS_SETPGID => &[Int, Int],
+        SYS_TGKILL => &[Int, Int, Signal],
+        SYS_RT_SIGACTION => &[Signal, Hex, Hex, Int],
+        SYS_RT_SIGPROCMASK => &[Int, Hex, Hex, Int],
+        SYS_FUTEX => &[Hex, Hex, Int, Hex, Hex, Int],
+        SYS_NANOSLEEP => &[Hex, Hex],
+        SYS_CLOCK_GETTIME => &[Int, Hex],
+        SYS_GETPID | SYS_GETPPID | SYS_GETTID | SYS_SETSID | SYS_SCHED_YIELD | SYS_RT_SIGRETURN => {
+            &[]
+        }
+        #[cfg(target_arch = "x86_64")]
+        SYS_OPEN => &[Str, OpenFlags, Oct],
+        #[cfg(target_arch = "x86_64")]
+        SYS_STAT | SYS_LSTAT => &[Str, Hex],
+        #[cfg(target_arch = "x86_64")]
+        SYS_ACCESS | SYS_MKDIR => &[Str, Oct],
+        #[cfg(target_arch = "x86_64")]
+        SYS_UNLINK => &[Str],
+        #[cfg(target_arch = "x86_64")]
+        SYS_READLINK => &[Str, Hex, Int],
+        #[cfg(target_arch = "x86_64")]
+        SYS_PIPE => &[Hex],
+        #[cfg(target_arch = "x86_64")]
+        SYS_DUP2 => &[Fd, Fd],
+        #[cfg(target_arch = "x86_64")]
+        SYS_POLL => &[Hex, Int, Int],
+        #[cfg(target_arch = "x86_64")]
+        SYS_FORK | SYS_VFORK => &[],
+        _ => &[Hex; 6],
+    }
+}
+
+/// Syscalls that do not return on success.
+fn no_return(num: usize) -> bool {
+    matches!(num, SYS_EXIT | SYS_EXIT_GROUP | SYS_EXECVE)
+}
+
+/// Syscalls whose return values are addresses.
+fn returns_addr(num: usize) -> bool {
+    matches!(num, SYS_MMAP | SYS_MREMAP | SYS_BRK | SYS_SHMAT)
+}
+
+/// Names of the signals 1 to 31.
+const SIGNAL_NAMES: [&str; 31] = [
+    "SIGHUP",
+    "SIGINT",
+    "SIGQUIT",
+    "SIGILL",
+    "SIGTRAP",
+    "SIGABRT",
+    "SIGBUS",
+    "SIGFPE",
+    "SIGKILL",
+    "SIGUSR1",
+    "SIGSEGV",
+    "SIGUSR2",
+    "SIGPIPE",
+    "SIGALRM",
+    "SIGTERM",
+    "SIGSTKFLT",
+    "SIGCHLD",
+    "SIGCONT",
+    "SIGSTOP",
+    "SIGTSTP",
+    "SIGTTIN",
+    "SIGTTOU",
+    "SIGURG",
+    "SIGXCPU",
+    "SIGXFSZ",
+    "SIGVTALRM",
+    "SIGPROF",
+    "SIGWINCH",
+    "SIGIO",
+    "SIGPWR",
+    "SIGSYS",
+];
+
+/// Writes `bits` as names of `flags` joined by `|`, followed by the unknown
+/// bits in hexadecimal (if any).
+fn write_flags(out: &mut String, bits: usize, flags: &[(&str, usize)]) {
+    let mut rest = bits;
+    let mut first = true;
+    for &(name, flag) in flags {
+        if flag != 0 && rest & flag == flag {
+            rest &= !flag;
+            if !first {
+                out.push('|');
+            }
+            out.push_str(name);
+            first = false;
+        }
+    }
+    if rest != 0 || first {
+        if !first {
+            out.push('|');
+        }
+        let _ = write!(out, "{:#x}", rest);
+    }
+}
+
+fn write_arg(out: &mut String, kind: Arg, value: usize) {
+    use arceos_posix_api::ctypes;
+
+    match kind {
+        Arg::Int => {
+            let _ = write!(out, "{}", value as isize);
+        }
+        Arg::Hex if value == 0 => out.push_str("NULL"),
+        Arg::Hex => {
+            let _ = write!(out, "{:#x}", value);
+        }
+        Arg::Oct => {
+            let _ = write!(out, "{:#o}", value);
+        }
+        Arg::Fd => {
+            let _ = write!(out, "{}", value as i32);
+        }
+        Arg::DirFd if value as i32 == ctypes::AT_FDCWD => out.push_str("AT_FDCWD"),
+        Arg::DirFd => {
+            let _ = write!(out, "{}", value as i32);
+        }
+        Arg::Str => match UserCStr::from(value).read() {
+            Ok(s) if s.chars().count() > 64 => {
+                let s: String = s.chars().take(64).collect();
+                let _ = write!(out, "{:?}...", s);
+            }
+            Ok(s) => {
+                let _ = write!(out, "{:?}", s);
+            }
+            Err(_) => {
+                let _ = write!(out, "{:#x}", value);
+            }
+        },
+        Arg::OpenFlags => {
+            out.push_str(match value & 0o3 {
+                0 => "O_RDONLY",
+                1 => "O_WRONLY",
+                _ => "O_RDWR",
+            });
+            let flags = value & !0o3;
+            if flags != 0 {
+                out.push('|');
+                write_flags(
+                    out,
+                    flags,
+                    &[
+                        ("O_CREAT", ctypes::O_CREAT as _),
+                        ("O_EXCL", ctypes::O_EXCL as _),
+                        ("O_NOCTTY", ctypes::O_NOCTTY as _),
+                        ("O_TRUNC", ctypes::O_TRUNC as _),
+                        ("O_APPEND", ctypes::O_APPEND as _),
+                        ("O_NONBLOCK", ctypes::O_NONBLOCK as _),
+                        ("O_DIRECTORY", ctypes::O_DIRECTORY as _),
+                        ("O_NOFOLLOW", ctypes::O_NOFOLLOW as _),
+                        ("O_CLOEXEC", ctypes::O_CLOEXEC as _),
+                        ("O_LARGEFILE", ctypes::O_LARGEFILE as _),
+                    ],
+                );
+            }
+        }
+        Arg::Prot if value == 0 => out.push_str("PROT_NONE"),
+        Arg::Prot => write_flags(
+            out,
+            value,
+            &[("PROT_READ", 1), ("PROT_WRITE", 2), ("PROT_EXEC", 4)],
+        ),
+        Arg::MapFlags => write_flags(
+            out,
+            value,
+            &[
+                ("MAP_SHARED", 0x1),
+                ("MAP_PRIVATE", 0x2),
+                ("MAP_FIXED", 0x10),
+                ("MAP_ANONYMOUS", 0x20),
+                ("MAP_NORESERVE", 0x4000),
+                ("MAP_POPULATE", 0x8000),
+                ("MAP_STACK", 0x20000),
+            ],
+        ),
+        Arg::Signal => match SIGNAL_NAMES.get(value.wrapping_sub(1)) {
+            Some(name) => out.push_str(name),
+            None => {
+                let _ = write!(out, "{}", value);
+            }
+        },
+    }
+}
+
+/// Writes the name and the decoded arguments of syscall `num`.
+fn write_call(out: &mut String, tf: &TrapFrame, num: usize) {
+    match syscall_name(num) {
+        Some(name) => {
+            for c in name.trim_start_matches("SYS_").chars() {
+                out.push(c.to_ascii_lowercase());
+            }
+        }
+        None => {
+            let _ = write!(out, "syscall_{}", num);
+        }
+    }
+    out.push('(');
+    let args = [
+        tf.arg0(),
+        tf.arg1(),
+        tf.arg2(),
+        tf.arg3(),
+        tf.arg4(),
+        tf.arg5(),
+    ];
+    for (i, (&kind, &value)) in arg_kinds(num).iter().zip(args.iter()).enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_arg(out, kind, value);
+    }
+    out.push(')');
+}
+
+/// Writes the return value, with the name and description of the error.
+fn write_ret(out: &mut String, num: usize, ret: isize) {
+    let err = (-4095..0)
+        .contains(&ret)
+        .then(|| LinuxError::try_from(-ret as i32).ok())
+        .flatten();
+    match err {
+        Some(err) => {
+            let _ = write!(out, " = -1 {:?} ({})", err, err.as_str());
+        }
+        None if returns_addr(num) => {
+            let _ = write!(out, " = {:#x}", ret);
+        }
+        None => {
+            let _ = write!(out, " = {}", ret);
+        }
+    }
+}
+
+#[cfg(feature = "fs")]
+static TRACE_FILE: axsync::Mutex<Option<axfs::api::File>> = axsync::Mutex::new(None);
+
+/// Writes the trace log to the file at `path` (truncated if it exists)
+/// instead of the console, or back to the console if `path` is `None`.
+#[cfg(feature = "fs")]
+pub fn set_trace_file(path: Option<&str>) -> axerrno::AxResult {
+    *TRACE_FILE.lock() = path.map(axfs::api::File::create).transpose()?;
+    Ok(())
+}
+
+fn output(line: &str) {
+    #[cfg(feature = "fs")]
+    if let Some(file) = TRACE_FILE.lock().as_mut() {
+        use axio::Write;
+        let _ = file.write_all(line.as_bytes());
+        let _ = file.write_all(b"\n");
+        return;
+    }
+    axlog::ax_println!("{}", line);
+}
+
+/// Whether the syscalls of the current task are traced.
+pub(crate) fn is_traced() -> bool {
+    if cfg!(feature = "strace") {
+        return true;
+    }
+    let curr = axtask::current();
+    !unsafe { curr.task_ext_ptr() }.is_null() && curr.task_ext().is_traced()
+}
+
+/// The lines of the calls to the syscalls that do not return on success (see
+/// [`no_return`]) in progress, indexed by the TIDs of the callers.
+static NO_RETURN_CALLS: spin::Mutex<BTreeMap<u64, String>> = spin::Mutex::new(BTreeMap::new());
+
+/// Logs the traced call of the current task to a syscall that does not
+/// return on success, as it is succeeding (e.g., the task is exiting).
+///
+/// It does nothing if there is no such call.
+pub(crate) fn trace_no_return() {
+    let tid = axtask::current().id().as_u64();
+    if let Some(line) = NO_RETURN_CALLS.lock().remove(&tid) {
+        output(&[line.as_str(), " = ?"].concat());
+    }
+}
+
+/// Calls `handler` for syscall `num`, and logs the call.
+///
+/// The calls that do not return are logged by [`trace_no_return`] instead.
+pub(crate) fn trace_syscall(
+    tf: &TrapFrame,
+    num: usize,
+    handler: impl FnOnce(&TrapFrame) -> isize,
+) -> isize {
+    let tid = axtask::current().id().as_u64();
+    // Arguments are decoded beforehand, as the user memory may be changed by
+    // the syscall (e.g., `execve`).
+    let mut line = String::new();
+    let _ = write!(line, "[{}] ", tid);
+    write_call(&mut line, tf, num);
+    if no_return(num) {
+        NO_RETURN_CALLS.lock().insert(tid, line.clone());
+    }
+
+    let start = axhal::time::monotonic_time();
+    let ret = handler(tf);
+    let elapsed = axhal::time::monotonic_time().saturating_sub(start);
+    if no_return(num) {
+        // It has failed, the line is completed below.
+        NO_RETURN_CALLS.lock().remove(&tid);
+    }
+
+    write_ret(&mut line, num, ret);
+    let _ = write!(
+        line,
+        " <{}.{:06}>",
+        elapsed.as_secs(),
+        elapsed.subsec_micros()
+    );
+    output(&line);
+    ret
+}
@@ -0,0 +1,480 @@
+//! The line discipline (`N_TTY`), which sits between the terminal driver and
+//! the readers and writers of the terminal.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use super::termios::*;
+use crate::signal::{SIGINT, SIGQUIT, SIGTSTP};
+
+/// The maximum length of a line in canonical mode, and the maximum number of
+/// bytes buffered for reading.
+const BUF_SIZE: usize = 4096;
+
+/// The input state of a terminal.
+///
+/// In canonical mode, input is edited line by line, and a line is available
+/// for reading after it is terminated by a newline, `VEOL`, `VEOL2` or
+/// `VEOF`. In non-canonical (raw) mode, each byte is available at once.
+#[derive(Default)]
+pub(crate) struct LineDiscipline {
+    /// Input that is available for reading.
+    ready: VecDeque<u8>,
+    /// The lengths of the complete lines in `ready`, in canonical mode. A
+    /// line terminated by `VEOF` does not contain the terminator, so an empty
+    /// one means end of file.
+    lines: VecDeque<usize>,
+    /// The line being edited, in canonical mode.
+    line: Vec<u8>,
+    /// Whether the next byte is taken literally (after `VLNEXT`).
+    literal: bool,
+}
+
+impl LineDiscipline {
+    /// Processes an input byte `c` from the driver.
+    ///
+    /// The bytes to be echoed are appended to `echo`. Returns the signal to
+    /// be sent to the foreground process group, if `c` generates one.
+    pub(crate) fn receive(
+        &mut self,
+        c: u8,
+        termios: &Termios,
+        echo: &mut Vec<u8>,
+    ) -> Option<usize> {
+        let c = match c {
+            b'\r' if termios.iflag(IGNCR) => return None,
+            b'\r' if termios.iflag(ICRNL) => b'\n',
+            b'\n' if termios.iflag(INLCR) => b'\r',
+            c => c,
+        };
+        let literal = core::mem::take(&mut self.literal);
+        if !literal && termios.lflag(ISIG) {
+            let signo = if termios.is_cc(VINTR, c) {
+                Some(SIGINT)
+            } else if termios.is_cc(VQUIT, c) {
+                Some(SIGQUIT)
+            } else if termios.is_cc(VSUSP, c) {
+                Some(SIGTSTP)
+            } else {
+                None
+            };
+            if signo.is_some() {
+                if !termios.lflag(NOFLSH) {
+                    self.flush();
+                }
+                echo_char(c, termios, echo);
+                return signo;
+            }
+        }
+        if !termios.lflag(ICANON) {
+            if self.ready.len() < BUF_SIZE {
+                self.ready.push_back(c);
+            }
+            echo_char(c, termios, echo);
+            return None;
+        }
+
+        if !literal {
+            if termios.lflag(IEXTEN) && termios.is_cc(VLNEXT, c) {
+                self.literal = true;
+                return None;
+            }
+            if termios.is_cc(VERASE, c) {
+                self.erase(1, termios, echo);
+                return None;
+            }
+            if termios.lflag(IEXTEN) && termios.is_cc(VWERASE, c) {
+                let trailing_spaces = self.line.iter().rev().take_while(|b| **b == b' ').count();
+                let word = self.line[..self.line.len() - trailing_spaces]
+                    .iter()
+                    .rev()
+                    .take_while(|b| **b != b' ')
+                    .count();
+                self.erase(trailing_spaces + word, termios, echo);
+                return None;
+            }
+            if termios.is_cc(VKILL, c) {
+                if termios.lflag(ECHOKE) || !termios.lflag(ECHOK) {
+                    self.erase(self.line.len(), termios, echo);
+                } else {
+                    self.line.clear();
+                    echo_char(c, termios, echo);
+                    if termios.lflag(ECHO) {
+                        echo.push(b'\n');
+                    }
+                }
+                return None;
+            }
+            if termios.lflag(IEXTEN) && termios.is_cc(VREPRINT, c) {
+                echo_char(c, termios, echo);
+                if termios.lflag(ECHO) {
+                    echo.push(b'\n');
+                    echo.extend_from_slice(&self.line);
+                }
+                return None;
+            }
+            if termios.is_cc(VEOF, c) {
+                self.end_line(false);
+                return None;
+            }
+        }
+        if self.line.len() >= BUF_SIZE - 1 && c != b'\n' {
+            return None; // The line is full, wait for the end of it.
+        }
+        self.line.push(c);
+        let eol = !literal && (c == b'\n' || termios.is_cc(VEOL, c) || termios.is_cc(VEOL2, c));
+        if c == b'\n' && termios.lflag(ECHONL) && !termios.lflag(ECHO) {
+            echo.push(b'\n');
+        } else {
+            echo_char(c, termios, echo);
+        }
+        if eol {
+            self.end_line(true);
+        }
+        None
+    }
+
+    /// Makes the line being edited available for reading.
+    ///
+    /// The line is truncated to fit in the input buffer, keeping its
+    /// terminator if `terminated`. It is dropped only if the buffer is full,
+    /// as an empty line would be read as end of file.
+    fn end_line(&mut self, terminated: bool) {
+        let mut line = core::mem::take(&mut self.line);
+        let room = BUF_SIZE - self.ready.len();
+        if line.len() > room {
+            if room == 0 {
+                return;
+            }
+            if terminated {
+                line[room - 1] = *line.last().unwrap();
+            }
+            line.truncate(room);
+        }
+        self.lines.push_back(line.len());
+        self.ready.extend(line);
+    }
+
+    /// Erases the last `count` characters of the line being edited.
+    fn erase(&mut self, count: usize, termios: &Termios, echo: &mut Vec<u8>) {
+        for _ in 0..count.min(self.line.len()) {
+            let c = self.line.pop().unwrap();
+            if termios.lflag(ECHO) && termios.lflag(ECHOE) {
+                // Control characters are echoed as two columns (`^X`).
+                let width = if is_ctl(c) && termios.lflag(ECHOCTL) {
+                    2
+                } else {
+                    1
+                };
+                for _ in 0..width {
+                    echo.extend_from_slice(b"\x08 \x08");
+                }
+            }
+        }
+    }
+
+    /// Moves the line being edited to the available input after the terminal
+    /// leaves canonical mode, or treats all available input as a line after
+    /// it enters canonical mode.
+    pub(crate) fn set_canonical(&mut self, canonical: bool) {
+        if canonical {
+            self.lines.clear();
+            if !self.ready.is_empty() {
+                self.lines.push_back(self.ready.len());
+            }
+        } else {
+            let line = core::mem::take(&mut self.line);
+            self.ready.extend(line);
+            self.lines.clear();
+        }
+    }
+
+    /// Discards all input.
+    pub(crate) fn flush(&mut self) {
+        self.ready.clear();
+        self.lines.clear();
+        self.line.clear();
+    }
+
+    /// Returns the number of bytes available for reading.
+    pub(crate) fn available(&self, termios: &Termios) -> usize {
+        if termios.lflag(ICANON) {
+            self.lines.iter().sum()
+        } else {
+            self.ready.len()
+        }
+    }
+
+    /// Whether a read would return at once with `min` bytes (or end of file).
+    pub(crate) fn is_readable(&self, termios: &Termios, min: usize) -> bool {
+        if termios.lflag(ICANON) {
+            !self.lines.is_empty()
+        } else {
+            self.ready.len() >= min
+        }
+    }
+
+    /// Reads the available input into `buf`, returns the number of bytes
+    /// read.
+    ///
+    /// In canonical mode, at most one line is read, and the rest of it is
+    /// kept for the next read.
+    pub(crate) fn read(&mut self, buf: &mut [u8], termios: &Termios) -> usize {
+        let len = if termios.lflag(ICANON) {
+            let Some(line_len) = self.lines.front_mut() else {
+                return 0;
+            };
+            let len = buf.len().min(*line_len);
+            *line_len -= len;
+            if *line_len == 0 {
+                self.lines.pop_front();
+            }
+            len
+        } else {
+            buf.len().min(self.ready.len())
+        };
+        for (dst, src) in buf.iter_mut().zip(self.ready.drain(..len)) {
+            *dst = src;
+        }
+        len
+    }
+}
+
+/// Whether `c` is echoed as `^X` with `ECHOCTL`.
+fn is_ctl(c: u8) -> bool {
+    (c < b' ' && c != b'\t' && c != b'\n') || c == 0x7f
+}
+
+/// Appends the echo of the input byte `c` to `echo`, if `ECHO` is set.
+fn echo_char(c: u8, termios: &Termios, echo: &mut Vec<u8>) {
+    if !termios.lflag(ECHO) {
+        return;
+    }
+    if is_ctl(c) && termios.lflag(ECHOCTL) {
+        echo.extend_from_slice(&[b'^', c ^ 0x40]);
+    } else {
+        echo.push(c);
+    }
+}
+
+/// Processes the output bytes `buf` according to the output flags, and
+/// appends the result to `out`.
+pub(crate) fn process_output(buf: &[u8], termios: &Termios, out: &mut Vec<u8>) {
+    if !termios.oflag(OPOST) {
+        out.extend_from_slice(buf);
+        return;
+    }
+    for &c in buf {
+        match c {
+            b'\n' if termios.oflag(ONLCR) => out.extend_from_slice(b"\r\n"),
+            b'\r' if termios.oflag(OCRNL) => out.push(b'\n'),
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds `input` to `ldisc`, returns the echo and the signals generated.
+    fn feed(ldisc: &mut LineDiscipline, termios: &Termios, input: &[u8]) -> (Vec<u8>, Vec<usize>) {
+        let mut echo = Vec::new();
+        let signals = input
+            .iter()
+            .filter_map(|&c| ldisc.receive(c, termios, &mut echo))
+            .collect();
+        (echo, signals)
+    }
+
+    /// Reads the next line (or all input in non-canonical mode).
+    fn read(ldisc: &mut LineDiscipline, termios: &Termios) -> Vec<u8> {
+        let mut buf = [0; BUF_SIZE];
+        let len = ldisc.read(&mut buf, termios);
+        buf[..len].to_vec()
+    }
+
+    #[test]
+    fn test_canonical_line() {
+        let termios = Termios::default();
+        let mut ldisc = LineDiscipline::default();
+        let (echo, _) = feed(&mut ldisc, &termios, b"ab");
+        assert_eq!(echo, b"ab");
+        assert!(!ldisc.is_readable(&termios, 1));
+        // `\r` is turned into `\n` with `ICRNL`.
+        feed(&mut ldisc, &termios, b"\rcd\n");
+        assert_eq!(ldisc.available(&termios), 6);
+        assert_eq!(read(&mut ldisc, &termios), b"ab\n");
+        assert_eq!(read(&mut ldisc, &termios), b"cd\n");
+        assert!(!ldisc.is_readable(&termios, 1));
+    }
+
+    #[test]
+    fn test_partial_read() {
+        let termios = Termios::default();
+        let mut ldisc = LineDiscipline::default();
+        feed(&mut ldisc, &termios, b"abcd\nef\n");
+        let mut buf = [0; 3];
+        assert_eq!(ldisc.read(&mut buf, &termios), 3);
+        assert_eq!(&buf, b"abc");
+        // The rest of the line is read before the next one.
+        assert_eq!(read(&mut ldisc, &termios), b"d\n");
+        assert_eq!(read(&mut ldisc, &termios), b"ef\n");
+    }
+
+    #[test]
+    fn test_erase() {
+        let termios = Termios::default();
+        let mut ldisc = LineDiscipline::default();
+        let (echo, _) = feed(&mut ldisc, &termios, b"abc\x7f\n");
+        assert_eq!(echo, b"abc\x08 \x08\n");
+        assert_eq!(read(&mut ldisc, &termios), b"ab\n");
+        // Erasing an empty line does nothing.
+        feed(&mut ldisc, &termios, b"\x7f\x7fx\n");
+        assert_eq!(read(&mut ldisc, &termios), b"x\n");
+    }
+
+    #[test]
+    fn test_erase_control_char() {
+        let termios = Termios::default();
+        let mut ldisc = LineDiscipline::default();
+        // `^A` is echoed in two columns, which are both erased.
+        let (echo, _) = feed(&mut ldisc, &termios, b"\x01\x7f");
+        assert_eq!(echo, b"^A\x08 \x08\x08 \x08");
+    }
+
+    #[test]
+    fn test_word_erase() {
+        let termios = Termios::default();
+        let mut ldisc = LineDiscipline::default();
+        feed(&mut ldisc, &termios, b"foo bar  \x17\n");
+        assert_eq!(read(&mut ldisc, &termios), b"foo \n");
+    }
+
+    #[test]
+    fn test_kill() {
+        let termios = Termios::default();
+        let mut ldisc = LineDiscipline::default();
+        feed(&mut ldisc, &termios, b"abc\x15x\n");
+        assert_eq!(read(&mut ldisc, &termios), b"x\n");
+    }
+
+    #[test]
+    fn test_eof() {
+        let termios = Termios::default();
+        let mut ldisc = LineDiscipline::default();
+        // `VEOF` ends the line without a terminator, and an empty line means
+        // end of file.
+        feed(&mut ldisc, &termios, b"ab\x04\x04");
+        assert_eq!(read(&mut ldisc, &termios), b"ab");
+        assert!(ldisc.is_readable(&termios, 1));
+        assert_eq!(read(&mut ldisc, &termios), b"");
+        assert!(!ldisc.is_readable(&termios, 1));
+    }
+
+    #[test]
+    fn test_signals() {
+        let termios = Termios::default();
+        let mut ldisc = LineDiscipline::default();
+        let (echo, signals) = feed(&mut ldisc, &termios, b"ab\x03");
+        assert_eq!(signals, [SIGINT]);
+        assert_eq!(echo, b"ab^C");
+        // The input is flushed without `NOFLSH`.
+        feed(&mut ldisc, &termios, b"\n");
+        assert_eq!(read(&mut ldisc, &termios), b"\n");
+        let (_, signals) = feed(&mut ldisc, &termios, b"\x1c\x1a");
+        assert_eq!(signals, [SIGQUIT, SIGTSTP]);
+    }
+
+    #[test]
+    fn test_literal_next() {
+        let termios = Termios::default();
+        let mut ldisc = LineDiscipline::default();
+        let (_, signals) = feed(&mut ldisc, &termios, b"\x16\x03\x16\x7f\n");
+        assert!(signals.is_empty());
+        assert_eq!(read(&mut ldisc, &termios), b"\x03\x7f\n");
+    }
+
+    #[test]
+    fn test_no_echo() {
+        let mut termios = Termios::default();
+        termios.c_lflag &= !ECHO;
+        let mut ldisc = LineDiscipline::default();
+        let (echo, _) = feed(&mut ldisc, &termios, b"secret\n");
+        assert!(echo.is_empty());
+        termios.c_lflag |= ECHONL;
+        let (echo, _) = feed(&mut ldisc, &termios, b"secret\n");
+        assert_eq!(echo, b"\n");
+    }
+
+    #[test]
+    fn test_overlong_line() {
+        let termios = Termios::default();
+        let mut ldisc = LineDiscipline::default();
+        // The line being edited stops growing, but still ends.
+        feed(&mut ldisc, &termios, &[b'a'; BUF_SIZE + 10]);
+        feed(&mut ldisc, &termios, b"\n");
+        let line = read(&mut ldisc, &termios);
+        assert_eq!(line.len(), BUF_SIZE);
+        assert_eq!(line.last(), Some(&b'\n'));
+    }
+
+    #[test]
+    fn test_line_truncated_when_full() {
+        let termios = Termios::default();
+        let mut ldisc = LineDiscipline::default();
+        feed(&mut ldisc, &termios, &[b'a'; BUF_SIZE - 11]);
+        feed(&mut ldisc, &termios, b"\n");
+        // There is room for 10 bytes only, and the terminator is kept.
+        feed(&mut ldisc, &termios, &[b'b'; 20]);
+        feed(&mut ldisc, &termios, b"\n");
+        assert_eq!(ldisc.available(&termios), BUF_SIZE);
+        read(&mut ldisc, &termios);
+        assert_eq!(read(&mut ldisc, &termios), b"bbbbbbbbb\n");
+        // A line is dropped only if the buffer is full.
+        feed(&mut ldisc, &termios, &[b'c'; BUF_SIZE - 1]);
+        feed(&mut ldisc, &termios, b"\nd\n");
+        assert_eq!(read(&mut ldisc, &termios).len(), BUF_SIZE);
+        assert!(!ldisc.is_readable(&termios, 1));
+    }
+
+    #[test]
+    fn test_non_canonical() {
+        let mut termios = Termios::default();
+        termios.c_lflag &= !(ICANON | ECHO);
+        let mut ldisc = LineDiscipline::default();
+        feed(&mut ldisc, &termios, b"ab\x7f");
+        assert!(ldisc.is_readable(&termios, 3));
+        assert!(!ldisc.is_readable(&termios, 4));
+        assert_eq!(read(&mut ldisc, &termios), b"ab\x7f");
+    }
+
+    #[test]
+    fn test_set_canonical() {
+        let mut termios = Termios::default();
+        let mut ldisc = LineDiscipline::default();
+        feed(&mut ldisc, &termios, b"ab");
+        // The line being edited becomes available after leaving canonical
+        // mode.
+        termios.c_lflag &= !ICANON;
+        ldisc.set_canonical(false);
+        assert_eq!(ldisc.available(&termios), 2);
+        // And is read as a line after entering it again.
+        termios.c_lflag |= ICANON;
+        ldisc.set_canonical(true);
+        assert!(ldisc.is_readable(&termios, 1));
+        assert_eq!(read(&mut ldisc, &termios), b"ab");
+    }
+
+    #[test]
+    fn test_process_output() {
+        let mut termios = Termios::default();
+        let mut out = Vec::new();
+        process_output(b"a\nb", &termios, &mut out);
+        assert_eq!(out, b"a\r\nb");
+        termios.c_oflag &= !OPOST;
+        out.clear();
+        process_output(b"a\nb", &termios, &mut out);
+        assert_eq!(out, b"a\nb");
+    }
+}
@@ -0,0 +1,635 @@
+//! Terminals: the console and pseudo-terminals.
+//!
+//! A terminal ([`Tty`]) passes the input from its driver through the line
+//! discipline, which edits lines in canonical mode, echoes the input, and
+//! turns the control characters into signals, according to the attributes
+//! set by `TCSETS` (see [`termios`]). The output written to it is processed
+//! and passed to the driver.
+//!
+//! The console terminal is driven by the [`axhal::console`] UART, whose input
+//! is received by its interrupt handler and passed to the line discipline by
+//! a kernel task (which polls the UART instead on platforms without a console
+//! interrupt). It is the standard input, output and error of the user
+//! processes spawned by [`spawn_user_task`], and the controlling terminal of
+//! their session.
+//! Pseudo-terminal pairs are created by opening `/dev/ptmx`, where the
+//! master side drives the slave side (`/dev/pts/N`), see [`pty`].
+//!
+//! A terminal is the controlling terminal of at most one session, and its
+//! foreground process group receives the signals generated by the control
+//! characters. Processes of other process groups in the session are in the
+//! background: they receive `SIGTTIN` when reading from the terminal, and
+//! `SIGTTOU` when writing to it with `TOSTOP` set.
+//!
+//! Blocking reads are woken up by new input, and interrupted by signals with
+//! `EINTR` (see [`wait_interruptible`]).
+//!
+//! [`spawn_user_task`]: crate::task::spawn_user_task
+//! [`wait_interruptible`]: crate::signal::wait_interruptible
+
+mod ldisc;
+pub mod pty;
+pub mod termios;
+
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use arceos_posix_api::{ctypes, FileLike};
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use axtask::{TaskExtRef, WaitQueue};
+use kspin::SpinNoIrq;
+use spin::{Mutex, Once};
+
+use self::ldisc::{process_output, LineDiscipline};
+use self::termios::{Termios, WinSize, ICANON, TOSTOP, VMIN, VTIME};
+use crate::process::{current_process, Pid, Process};
+use crate::ptr::UserPtr;
+use crate::signal::{self, SIGCONT, SIGHUP, SIGTTIN, SIGTTOU, SIGWINCH, SIG_IGN};
+
+// ioctl requests.
+pub const TCGETS: usize = 0x5401;
+pub const TCSETS: usize = 0x5402;
+pub const TCSETSW: usize = 0x5403;
+pub const TCSETSF: usize = 0x5404;
+pub const TCFLSH: usize = 0x540b;
+pub const TIOCSCTTY: usize = 0x540e;
+pub const TIOCGPGRP: usize = 0x540f;
+pub const TIOCSPGRP: usize = 0x5410;
+pub const TIOCGWINSZ: usize = 0x5413;
+pub const TIOCSWINSZ: usize = 0x5414;
+pub const FIONREAD: usize = 0x541b;
+pub const FIONBIO: usize = 0x5421;
+pub const TIOCNOTTY: usize = 0x5422;
+pub const TIOCGSID: usize = 0x5429;
+pub const TIOCGPTN: usize = 0x8004_5430;
+pub const TIOCSPTLCK: usize = 0x4004_5431;
+
+/// `TCFLSH` arguments that flush the input.
+const TCIFLUSH: usize = 0;
+const TCIOFLUSH: usize = 2;
+
+/// How often the console is polled, if it has no input interrupt.
+const CONSOLE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The maximum number of console input bytes buffered by the interrupt
+/// handler.
+const CONSOLE_INPUT_SIZE: usize = 4096;
+
+/// The device of a terminal, which receives its output.
+pub(crate) trait TtyDriver: Send + Sync {
+    /// Writes the processed output (including the echo) to the device.
+    fn write_output(&self, buf: &[u8]);
+}
+
+struct TtyState {
+    termios: Termios,
+    winsize: WinSize,
+    ldisc: LineDiscipline,
+}
+
+/// A terminal.
+pub struct Tty {
+    /// The name under `/dev`, e.g., `console` or `pts/0`.
+    name: String,
+    driver: Arc<dyn TtyDriver>,
+    state: Mutex<TtyState>,
+    /// The session that it is the controlling terminal of, or 0.
+    session: AtomicU64,
+    /// The foreground process group, or 0.
+    fg_pgrp: AtomicU64,
+    /// Whether the device is gone (e.g., the master side of a pseudo-terminal
+    /// is closed), after which reads return end of file, and writes fail.
+    hung_up: AtomicBool,
+    /// The number of open files of the terminal.
+    opened: AtomicUsize,
+    /// Whether all files of the terminal have been closed since it was last
+    /// opened.
+    closed: AtomicBool,
+    /// Readers waiting for input.
+    read_wq: WaitQueue,
+    /// Bumped whenever the readers should check the input again.
+    read_gen: AtomicUsize,
+}
+
+impl Tty {
+    pub(crate) fn new(name: String, driver: Arc<dyn TtyDriver>) -> Self {
+        Self {
+            name,
+            driver,
+            state: Mutex::new(TtyState {
+                termios: Termios::default(),
+                winsize: WinSize::default(),
+                ldisc: LineDiscipline::default(),
+            }),
+            session: AtomicU64::new(0),
+            fg_pgrp: AtomicU64::new(0),
+            hung_up: AtomicBool::new(false),
+            opened: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            read_wq: WaitQueue::new(),
+            read_gen: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the name under `/dev`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the session that it is the controlling terminal of, or 0.
+    pub fn session(&self) -> Pid {
+        self.session.load(Ordering::Acquire)
+    }
+
+    /// Returns the foreground process group, or 0.
+    pub fn fg_pgrp(&self) -> Pid {
+        self.fg_pgrp.load(Ordering::Acquire)
+    }
+
+    /// Returns the terminal attributes.
+    pub fn termios(&self) -> Termios {
+        self.state.lock().termios
+    }
+
+    /// Makes it the controlling terminal of the session `sid`, with the
+    /// process group `pgid` in the foreground.
+    pub(crate) fn set_controlling(&self, sid: Pid, pgid: Pid) {
+        self.session.store(sid, Ordering::Release);
+        self.fg_pgrp.store(pgid, Ordering::Release);
+    }
+
+    /// Sends `signo` to the foreground process group.
+    fn signal_fg(&self, signo: usize) {
+        let pgrp = self.fg_pgrp();
+        if pgrp != 0 {
+            Process::signal_group(pgrp, signo);
+        }
+    }
+
+    /// Passes the input `buf` from the driver to the line discipline.
+    pub(crate) fn receive_input(&self, buf: &[u8]) {
+        let mut echo = Vec::new();
+        let mut out = Vec::new();
+        let mut signals = Vec::new();
+        {
+            let mut state = self.state.lock();
+            let state = &mut *state;
+            for &c in buf {
+                if let Some(signo) = state.ldisc.receive(c, &state.termios, &mut echo) {
+                    signals.push(signo);
+                }
+            }
+            process_output(&echo, &state.termios, &mut out);
+        }
+        if !out.is_empty() {
+            self.driver.write_output(&out);
+        }
+        for signo in signals {
+            self.signal_fg(signo);
+        }
+        self.wake_readers();
+    }
+
+    /// Wakes up the readers after the input or the attributes change.
+    fn wake_readers(&self) {
+        self.read_gen.fetch_add(1, Ordering::AcqRel);
+        self.read_wq.notify_all(false);
+    }
+
+    /// Hangs up the terminal: the foreground process group receives `SIGHUP`
+    /// and `SIGCONT`, and it is no longer the controlling terminal.
+    pub(crate) fn hang_up(&self) {
+        self.hung_up.store(true, Ordering::Release);
+        self.disassociate();
+        self.wake_readers();
+    }
+
+    /// Detaches the terminal from its session, after sending `SIGHUP` and
+    /// `SIGCONT` to the foreground process group.
+    fn disassociate(&self) {
+        self.signal_fg(SIGHUP);
+        self.signal_fg(SIGCONT);
+        self.set_controlling(0, 0);
+    }
+
+    fn is_hung_up(&self) -> bool {
+        self.hung_up.load(Ordering::Acquire)
+    }
+
+    /// Whether all files of the terminal have been closed.
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// Checks whether the calling process may access the terminal, if it is
+    /// its controlling terminal.
+    ///
+    /// A process in a background process group is sent `signo` (`SIGTTIN`
+    /// or `SIGTTOU`) and the access is interrupted, unless the signal is
+    /// ignored or blocked, where the access fails with `EIO` for `SIGTTIN`
+    /// and is allowed for `SIGTTOU`.
+    fn check_job_control(&self, signo: usize) -> LinuxResult {
+        let process = current_process();
+        if self.session() != process.sid() || process.pgid() == self.fg_pgrp() {
+            return Ok(());
+        }
+        let blocked = axtask::current().task_ext().signals.blocked();
+        if process.signals.action(signo).handler == SIG_IGN || blocked.contains(signo) {
+            return if signo == SIGTTIN {
+                Err(LinuxError::EIO)
+            } else {
+                Ok(())
+            };
+        }
+        Process::signal_group(process.pgid(), signo);
+        Err(LinuxError::EINTR)
+    }
+
+    /// Reads the input into `buf`.
+    ///
+    /// In canonical mode, it waits for a line. Otherwise, it waits for
+    /// `VMIN` bytes, or `VTIME` tenths of a second after the last byte
+    /// received (after the start of the read if `VMIN` is 0).
+    fn read(&self, buf: &mut [u8], nonblocking: bool) -> LinuxResult<usize> {
+        self.check_job_control(SIGTTIN)?;
+        let mut last_change = (usize::MAX, axhal::time::monotonic_time());
+        loop {
+            let (gen, timeout) = {
+                let gen = self.read_gen.load(Ordering::Acquire);
+                let mut state = self.state.lock();
+                let state = &mut *state;
+                let termios = &state.termios;
+                let (min, time) = if termios.lflag(ICANON) {
+                    (1, 0)
+                } else {
+                    (termios.c_cc[VMIN] as usize, termios.c_cc[VTIME] as u64)
+                };
+                let available = state.ldisc.available(termios);
+                let now = axhal::time::monotonic_time();
+                if available != last_change.0 {
+                    last_change = (available, now);
+                }
+                // The timer runs once a byte is received, or from the start
+                // if `VMIN` is 0.
+                let timer = (time > 0 && (min == 0 || available > 0)).then(|| {
+                    (last_change.1 + Duration::from_millis(time * 100)).saturating_sub(now)
+                });
+                if state.ldisc.is_readable(termios, min.min(buf.len()).max(1))
+                    || timer == Some(Duration::ZERO)
+                    || (min == 0 && time == 0)
+                    || self.is_hung_up()
+                {
+                    return Ok(state.ldisc.read(buf, termios));
+                }
+                (gen, timer)
+            };
+            if nonblocking {
+                return Err(LinuxError::EAGAIN);
+            }
+            signal::wait_interruptible(&self.read_wq, timeout, || {
+                self.read_gen.load(Ordering::Acquire) != gen || self.is_hung_up()
+            })?;
+        }
+    }
+
+    /// Writes `buf` to the device, after output processing.
+    ///
+    /// The output is written synchronously, so a write never blocks, except
+    /// that a background process is stopped by `SIGTTOU` with `TOSTOP` set,
+    /// where a non-blocking write fails with `EAGAIN` instead.
+    fn write(&self, buf: &[u8], nonblocking: bool) -> LinuxResult<usize> {
+        if self.is_hung_up() {
+            return Err(LinuxError::EIO);
+        }
+        let termios = self.termios();
+        if termios.lflag(TOSTOP) {
+            match self.check_job_control(SIGTTOU) {
+                Err(LinuxError::EINTR) if nonblocking => return Err(LinuxError::EAGAIN),
+                ret => ret?,
+            }
+        }
+        let mut out = Vec::with_capacity(buf.len());
+        process_output(buf, &termios, &mut out);
+        self.driver.write_output(&out);
+        Ok(buf.len())
+    }
+
+    fn poll(&self) -> PollState {
+        let state = self.state.lock();
+        PollState {
+            readable: self.is_hung_up()
+                || state.ldisc.is_readable(&state.termios, 1)
+                || (!state.termios.lflag(ICANON) && state.termios.c_cc[VMIN] == 0),
+            writable: true,
+        }
+    }
+
+    /// Handles the `ioctl` requests on the attributes of the terminal, which
+    /// do not depend on the calling process. Returns `None` for the other
+    /// requests.
+    pub(crate) fn ioctl_attrs(&self, op: usize, arg: usize) -> Option<LinuxResult<isize>> {
+        let ret = match op {
+            TCGETS => UserPtr::<Termios>::from(arg).write(self.termios()),
+            TCSETS | TCSETSW | TCSETSF => UserPtr::<Termios>::from(arg).read().map(|termios| {
+                // The output is written synchronously, so there is nothing to
+                // wait for with `TCSETSW`.
+                let mut state = self.state.lock();
+                if op == TCSETSF {
+                    state.ldisc.flush();
+                }
+                let canonical = termios.lflag(ICANON);
+                if canonical != state.termios.lflag(ICANON) {
+                    state.ldisc.set_canonical(canonical);
+                }
+                state.termios = termios;
+                drop(state);
+                self.wake_readers();
+            }),
+            TCFLSH => match arg {
+                TCIFLUSH | TCIOFLUSH => {
+                    self.state.lock().ldisc.flush();
+                    self.wake_readers();
+                    Ok(())
+                }
+                // There is no output buffered.
+                1 => Ok(()),
+                _ => Err(LinuxError::EINVAL),
+            },
+            TIOCGWINSZ => UserPtr::<WinSize>::from(arg).write(self.state.lock().winsize),
+            TIOCSWINSZ => UserPtr::<WinSize>::from(arg).read().map(|winsize| {
+                let changed = {
+                    let mut state = self.state.lock();
+                    core::mem::replace(&mut state.winsize, winsize) != winsize
+                };
+                if changed {
+                    self.signal_fg(SIGWINCH);
+                }
+            }),
+            _ => return None,
+        };
+        Some(ret.map(|_| 0))
+    }
+
+    /// Handles the `ioctl` requests on the terminal.
+    ///
+    /// The job control requests (`TIOCGPGRP`, `TIOCSPGRP`, `TIOCGSID`,
+    /// `TIOCNOTTY`) require it to be the controlling terminal of the calling
+    /// process.
+    fn ioctl(&self, op: usize, arg: usize) -> LinuxResult<isize> {
+        if let Some(ret) = self.ioctl_attrs(op, arg) {
+            return ret;
+        }
+        let process = current_process();
+        let is_controlling = self.session() == process.sid();
+        match op {
+            FIONREAD => {
+                let state = self.state.lock();
+                let available = state.ldisc.available(&state.termios);
+                UserPtr::<i32>::from(arg).write(available as i32)?;
+            }
+            TIOCSCTTY => {
+                if is_controlling {
+                    return Ok(0);
+                }
+                // Only a session leader without a controlling terminal can
+                // acquire one that is not controlling another session.
+                if process.sid() != process.pid()
+                    || controlling_tty(process.sid()).is_some()
+                    || self.session() != 0
+                {
+                    return Err(LinuxError::EPERM);
+                }
+                self.set_controlling(process.sid(), process.pgid());
+            }
+            TIOCGPGRP | TIOCGSID => {
+                if !is_controlling {
+                    return Err(LinuxError::ENOTTY);
+                }
+                let id = if op == TIOCGPGRP {
+                    self.fg_pgrp()
+                } else {
+                    self.session()
+                };
+                UserPtr::<i32>::from(arg).write(id as i32)?;
+            }
+            TIOCSPGRP => {
+                if !is_controlling {
+                    return Err(LinuxError::ENOTTY);
+                }
+                let pgid = UserPtr::<i32>::from(arg).read()?;
+                if pgid < 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                let pgid = pgid as Pid;
+                if !Process::group_members(pgid)
+                    .iter()
+                    .any(|proc| proc.sid() == process.sid())
+                {
+                    return Err(LinuxError::EPERM);
+                }
+                self.fg_pgrp.store(pgid, Ordering::Release);
+            }
+            TIOCNOTTY => {
+                if !is_controlling {
+                    return Err(LinuxError::ENOTTY);
+                }
+                // The controlling terminal belongs to the session, so it can
+                // only be given up by the session leader.
+                if process.pid() == process.sid() {
+                    self.disassociate();
+                }
+            }
+            _ => return Err(LinuxError::ENOTTY),
+        }
+        Ok(0)
+    }
+}
+
+/// A file of a terminal.
+pub struct TtyFile {
+    tty: Arc<Tty>,
+    nonblocking: AtomicBool,
+}
+
+impl TtyFile {
+    /// Opens the terminal `tty`.
+    pub fn new(tty: Arc<Tty>) -> Self {
+        tty.opened.fetch_add(1, Ordering::AcqRel);
+        tty.closed.store(false, Ordering::Release);
+        Self {
+            tty,
+            nonblocking: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the terminal.
+    pub fn tty(&self) -> &Arc<Tty> {
+        &self.tty
+    }
+
+    /// Handles the `ioctl` request `op` with the argument `arg`.
+    pub fn ioctl(&self, op: usize, arg: usize) -> LinuxResult<isize> {
+        self.tty.ioctl(op, arg)
+    }
+}
+
+impl Drop for TtyFile {
+    fn drop(&mut self) {
+        if self.tty.opened.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.tty.closed.store(true, Ordering::Release);
+            pty::notify_closed(&self.tty);
+        }
+    }
+}
+
+impl FileLike for TtyFile {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.tty.read(buf, self.nonblocking.load(Ordering::Relaxed))
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        self.tty
+            .write(buf, self.nonblocking.load(Ordering::Relaxed))
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        Ok(ctypes::stat {
+            st_ino: 1,
+            st_nlink: 1,
+            st_mode: 0o20000 | 0o620u32, // S_IFCHR | rw--w----
+            st_blksize: 1024,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(self.tty.poll())
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+struct ConsoleDriver;
+
+impl TtyDriver for ConsoleDriver {
+    /// Some UART drivers turn `\n` into `\r\n` as well, which prints the same
+    /// after `ONLCR`.
+    fn write_output(&self, buf: &[u8]) {
+        axhal::console::write_bytes(buf);
+    }
+}
+
+static CONSOLE: Once<Arc<Tty>> = Once::new();
+
+/// The console input received by the interrupt handler, to be passed to the
+/// line discipline by the console task.
+static CONSOLE_INPUT: SpinNoIrq<VecDeque<u8>> = SpinNoIrq::new(VecDeque::new());
+static CONSOLE_INPUT_WQ: WaitQueue = WaitQueue::new();
+
+/// The console input interrupt handler.
+///
+/// The input is dropped if the console task falls too far behind.
+fn console_input_handler(c: u8) {
+    let mut input = CONSOLE_INPUT.lock();
+    if input.len() < CONSOLE_INPUT_SIZE {
+        input.push_back(c);
+    }
+    drop(input);
+    CONSOLE_INPUT_WQ.notify_one(false);
+}
+
+/// Returns the console terminal.
+///
+/// It is created on the first call, which also spawns the kernel task that
+/// passes the console input to it.
+pub fn console() -> &'static Arc<Tty> {
+    CONSOLE.call_once(|| {
+        let tty = Arc::new(Tty::new("console".into(), Arc::new(ConsoleDriver)));
+        let input = tty.clone();
+        // Reserved beforehand, so that the handler never allocates.
+        CONSOLE_INPUT.lock().reserve(CONSOLE_INPUT_SIZE);
+        if axhal::console::set_input_handler(console_input_handler) {
+            axtask::spawn(move || loop {
+                CONSOLE_INPUT_WQ.wait_until(|| !CONSOLE_INPUT.lock().is_empty());
+                let buf: Vec<u8> = CONSOLE_INPUT.lock().drain(..).collect();
+                input.receive_input(&buf);
+            });
+        } else {
+            axtask::spawn(move || loop {
+                let mut buf = Vec::new();
+                while let Some(c) = axhal::console::getchar() {
+                    buf.push(c);
+                }
+                if !buf.is_empty() {
+                    input.receive_input(&buf);
+                }
+                axtask::sleep(CONSOLE_POLL_INTERVAL);
+            });
+        }
+        tty
+    })
+}
+
+/// Returns the controlling terminal of the session `sid`, if any.
+pub fn controlling_tty(sid: Pid) -> Option<Arc<Tty>> {
+    CONSOLE
+        .get()
+        .filter(|tty| tty.session() == sid)
+        .cloned()
+        .or_else(|| pty::find_slave(|tty| tty.session() == sid))
+}
+
+/// Detaches the controlling terminal of the session `sid` after its leader
+/// exits.
+pub(crate) fn release_session(sid: Pid) {
+    if let Some(tty) = controlling_tty(sid) {
+        tty.disassociate();
+    }
+}
+
+/// Opens the terminal device at `path`, or returns `None` if it is not a
+/// terminal device.
+///
+/// The devices are `/dev/console`, `/dev/tty` (the controlling terminal of
+/// the calling process), `/dev/ptmx` (a new pseudo-terminal pair, whose
+/// master side is opened), and `/dev/pts/N`.
+pub fn open_device(path: &str) -> Option<LinuxResult<Arc<dyn FileLike>>> {
+    let file: LinuxResult<Arc<dyn FileLike>> = match path {
+        "/dev/console" => Ok(Arc::new(TtyFile::new(console().clone()))),
+        "/dev/tty" => controlling_tty(current_process().sid())
+            .map(|tty| Arc::new(TtyFile::new(tty)) as _)
+            .ok_or(LinuxError::ENXIO),
+        "/dev/ptmx" => pty::open_master().map(|master| master as _),
+        _ => {
+            let index = path.strip_prefix("/dev/pts/")?.parse().ok()?;
+            pty::open_slave(index).map(|tty| Arc::new(TtyFile::new(tty)) as _)
+        }
+    };
+    Some(file)
+}
+
+/// Handles the `ioctl` request `op` with the argument `arg` on the terminal
+/// file `file`, or returns `ENOTTY` if it is not a terminal.
+pub fn ioctl(file: Arc<dyn FileLike>, op: usize, arg: usize) -> LinuxResult<isize> {
+    let file = file.into_any();
+    if let Some(file) = file.downcast_ref::<TtyFile>() {
+        file.ioctl(op, arg)
+    } else if let Some(master) = file.downcast_ref::<pty::PtyMaster>() {
+        master.ioctl(op, arg)
+    } else {
+        Err(LinuxError::ENOTTY)
+    }
+}
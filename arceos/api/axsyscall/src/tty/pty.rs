@@ -0,0 +1,218 @@
+//! Pseudo-terminals.
+//!
+//! Opening `/dev/ptmx` creates a pseudo-terminal pair, and opens its master
+//! side ([`PtyMaster`]). The slave side is a [`Tty`] at `/dev/pts/N`, where
+//! `N` is returned by `TIOCGPTN`. It is locked when created, and can be
+//! opened after it is unlocked by `TIOCSPTLCK` (`unlockpt`).
+//!
+//! What is written to the master is the input of the slave, and what is
+//! written to the slave (or echoed by it) can be read from the master. When
+//! the master is closed, the slave is hung up. When all files of the slave
+//! are closed, reading from the master fails with `EIO`.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::any::Any;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use arceos_posix_api::{ctypes, FileLike};
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use axtask::WaitQueue;
+use spin::Mutex;
+
+use super::{Tty, TtyDriver, FIONREAD, TIOCGPTN, TIOCSPTLCK};
+use crate::ptr::UserPtr;
+use crate::signal;
+
+/// The maximum number of pseudo-terminal pairs.
+const MAX_PTYS: u32 = 256;
+
+/// The output of a slave, to be read from the master.
+struct PtyOutput {
+    buf: Mutex<VecDeque<u8>>,
+    read_wq: WaitQueue,
+}
+
+impl TtyDriver for PtyOutput {
+    fn write_output(&self, buf: &[u8]) {
+        self.buf.lock().extend(buf);
+        self.read_wq.notify_all(false);
+    }
+}
+
+/// The master side of a pseudo-terminal pair.
+pub struct PtyMaster {
+    index: u32,
+    slave: Arc<Tty>,
+    output: Arc<PtyOutput>,
+    /// Whether the slave cannot be opened yet.
+    locked: AtomicBool,
+    nonblocking: AtomicBool,
+}
+
+/// The masters of all pseudo-terminal pairs, indexed by the slave numbers.
+static PTYS: Mutex<BTreeMap<u32, Weak<PtyMaster>>> = Mutex::new(BTreeMap::new());
+
+/// Creates a pseudo-terminal pair with the smallest free number, and
+/// returns its master.
+pub(super) fn open_master() -> LinuxResult<Arc<PtyMaster>> {
+    let mut ptys = PTYS.lock();
+    let index = (0..MAX_PTYS)
+        .find(|index| !ptys.contains_key(index))
+        .ok_or(LinuxError::ENOSPC)?;
+    let output = Arc::new(PtyOutput {
+        buf: Mutex::new(VecDeque::new()),
+        read_wq: WaitQueue::new(),
+    });
+    let master = Arc::new(PtyMaster {
+        index,
+        slave: Arc::new(Tty::new(format!("pts/{}", index), output.clone())),
+        output,
+        locked: AtomicBool::new(true),
+        nonblocking: AtomicBool::new(false),
+    });
+    ptys.insert(index, Arc::downgrade(&master));
+    Ok(master)
+}
+
+/// Returns the slave `/dev/pts/{index}` to be opened.
+pub(super) fn open_slave(index: u32) -> LinuxResult<Arc<Tty>> {
+    let master = PTYS.lock().get(&index).and_then(Weak::upgrade);
+    let master = master.ok_or(LinuxError::ENOENT)?;
+    if master.locked.load(Ordering::Acquire) {
+        return Err(LinuxError::EIO);
+    }
+    Ok(master.slave.clone())
+}
+
+/// Returns the masters of all pseudo-terminal pairs.
+///
+/// They are collected first, as dropping the last reference to a master
+/// takes the lock of the table.
+fn masters() -> Vec<Arc<PtyMaster>> {
+    let masters = PTYS.lock().values().filter_map(Weak::upgrade).collect();
+    masters
+}
+
+/// Finds the slave that satisfies `pred`.
+pub(super) fn find_slave(pred: impl Fn(&Tty) -> bool) -> Option<Arc<Tty>> {
+    masters()
+        .into_iter()
+        .find(|master| pred(&master.slave))
+        .map(|master| master.slave.clone())
+}
+
+/// Wakes up the master readers after all files of `tty` are closed, if it is
+/// a slave.
+pub(super) fn notify_closed(tty: &Tty) {
+    if let Some(master) = masters()
+        .into_iter()
+        .find(|master| core::ptr::eq(&*master.slave, tty))
+    {
+        master.output.read_wq.notify_all(false);
+    }
+}
+
+impl PtyMaster {
+    /// Returns the number of the slave.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Returns the slave.
+    pub fn slave(&self) -> &Arc<Tty> {
+        &self.slave
+    }
+
+    /// Handles the `ioctl` request `op` with the argument `arg`.
+    ///
+    /// Besides `TIOCGPTN` and `TIOCSPTLCK`, the requests on the attributes of
+    /// the terminal are applied to the slave.
+    pub fn ioctl(&self, op: usize, arg: usize) -> LinuxResult<isize> {
+        if let Some(ret) = self.slave.ioctl_attrs(op, arg) {
+            return ret;
+        }
+        match op {
+            TIOCGPTN => UserPtr::<u32>::from(arg).write(self.index)?,
+            TIOCSPTLCK => {
+                let lock = UserPtr::<i32>::from(arg).read()?;
+                self.locked.store(lock != 0, Ordering::Release);
+            }
+            FIONREAD => {
+                let len = self.output.buf.lock().len();
+                UserPtr::<i32>::from(arg).write(len as i32)?;
+            }
+            _ => return Err(LinuxError::ENOTTY),
+        }
+        Ok(0)
+    }
+}
+
+impl Drop for PtyMaster {
+    fn drop(&mut self) {
+        PTYS.lock().remove(&self.index);
+        self.slave.hang_up();
+    }
+}
+
+impl FileLike for PtyMaster {
+    /// Reads the output of the slave.
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        loop {
+            {
+                let mut output = self.output.buf.lock();
+                if !output.is_empty() || buf.is_empty() {
+                    let len = buf.len().min(output.len());
+                    for (dst, src) in buf.iter_mut().zip(output.drain(..len)) {
+                        *dst = src;
+                    }
+                    return Ok(len);
+                }
+            }
+            if self.slave.is_closed() {
+                return Err(LinuxError::EIO);
+            }
+            if self.nonblocking.load(Ordering::Relaxed) {
+                return Err(LinuxError::EAGAIN);
+            }
+            signal::wait_interruptible(&self.output.read_wq, None, || {
+                !self.output.buf.lock().is_empty() || self.slave.is_closed()
+            })?;
+        }
+    }
+
+    /// Writes the input of the slave.
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        self.slave.receive_input(buf);
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        Ok(ctypes::stat {
+            st_ino: 1,
+            st_nlink: 1,
+            st_mode: 0o20000 | 0o666u32, // S_IFCHR | rw-rw-rw-
+            st_blksize: 1024,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: !self.output.buf.lock().is_empty() || self.slave.is_closed(),
+            writable: true,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+}
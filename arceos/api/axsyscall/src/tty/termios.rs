@@ -0,0 +1,125 @@
+//! Terminal attributes (`struct termios`) of the Linux ABI.
+
+/// The number of control characters in [`Termios::c_cc`].
+pub const NCCS: usize = 19;
+
+// Indices of `c_cc`.
+pub const VINTR: usize = 0;
+pub const VQUIT: usize = 1;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const VTIME: usize = 5;
+pub const VMIN: usize = 6;
+pub const VSTART: usize = 8;
+pub const VSTOP: usize = 9;
+pub const VSUSP: usize = 10;
+pub const VEOL: usize = 11;
+pub const VREPRINT: usize = 12;
+pub const VWERASE: usize = 14;
+pub const VLNEXT: usize = 15;
+pub const VEOL2: usize = 16;
+
+// Input flags (`c_iflag`).
+pub const INLCR: u32 = 0o100;
+pub const IGNCR: u32 = 0o200;
+pub const ICRNL: u32 = 0o400;
+pub const IXON: u32 = 0o2000;
+
+// Output flags (`c_oflag`).
+pub const OPOST: u32 = 0o1;
+pub const ONLCR: u32 = 0o4;
+pub const OCRNL: u32 = 0o10;
+
+// Control flags (`c_cflag`).
+pub const B38400: u32 = 0o17;
+pub const CS8: u32 = 0o60;
+pub const CREAD: u32 = 0o200;
+pub const HUPCL: u32 = 0o2000;
+
+// Local flags (`c_lflag`).
+pub const ISIG: u32 = 0o1;
+pub const ICANON: u32 = 0o2;
+pub const ECHO: u32 = 0o10;
+pub const ECHOE: u32 = 0o20;
+pub const ECHOK: u32 = 0o40;
+pub const ECHONL: u32 = 0o100;
+pub const NOFLSH: u32 = 0o200;
+pub const TOSTOP: u32 = 0o400;
+pub const ECHOCTL: u32 = 0o1000;
+pub const ECHOKE: u32 = 0o4000;
+pub const IEXTEN: u32 = 0o100000;
+
+/// `struct termios` of the Linux ABI, used by `TCGETS` and `TCSETS`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Termios {
+    pub c_iflag: u32,
+    pub c_oflag: u32,
+    pub c_cflag: u32,
+    pub c_lflag: u32,
+    pub c_line: u8,
+    pub c_cc: [u8; NCCS],
+}
+
+impl Termios {
+    /// Whether the local flag `flag` is set.
+    pub const fn lflag(&self, flag: u32) -> bool {
+        self.c_lflag & flag != 0
+    }
+
+    /// Whether the input flag `flag` is set.
+    pub const fn iflag(&self, flag: u32) -> bool {
+        self.c_iflag & flag != 0
+    }
+
+    /// Whether the output flag `flag` is set.
+    pub const fn oflag(&self, flag: u32) -> bool {
+        self.c_oflag & flag != 0
+    }
+
+    /// Whether `c` is the control character `index`, which is disabled if it
+    /// is 0.
+    pub const fn is_cc(&self, index: usize, c: u8) -> bool {
+        c != 0 && self.c_cc[index] == c
+    }
+}
+
+impl Default for Termios {
+    /// The attributes of a new terminal: canonical mode with echo and
+    /// signals, as set up by Linux.
+    fn default() -> Self {
+        Self {
+            c_iflag: ICRNL | IXON,
+            c_oflag: OPOST | ONLCR,
+            c_cflag: B38400 | CS8 | CREAD | HUPCL,
+            c_lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL | ECHOKE | IEXTEN,
+            c_line: 0,
+            // ^C ^\ DEL ^U ^D, VTIME 0, VMIN 1, ^Q ^S ^Z, ^R ^O ^W ^V
+            c_cc: *b"\x03\x1c\x7f\x15\x04\x00\x01\x00\x11\x13\x1a\x00\x12\x0f\x17\x16\x00\x00\x00",
+        }
+    }
+}
+
+/// `struct winsize` of the Linux ABI, used by `TIOCGWINSZ` and `TIOCSWINSZ`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WinSize {
+    pub ws_row: u16,
+    pub ws_col: u16,
+    pub ws_xpixel: u16,
+    pub ws_ypixel: u16,
+}
+
+impl Default for WinSize {
+    /// The size of a VT100 terminal, as there is no way to get the real one
+    /// of a serial console.
+    fn default() -> Self {
+        Self {
+            ws_row: 24,
+            ws_col: 80,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        }
+    }
+}
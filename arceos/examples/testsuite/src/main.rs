@@ -0,0 +1,226 @@
+//! Runs a Linux compatibility test suite from the disk image.
+//!
+//! Every file under [`TESTSUITE_DIR`] (searched recursively) is treated as a
+//! static test binary, and executed as its own user process, one at a time.
+//! The tests are children of a runner process (the init process) standing for
+//! the main task, which reaps them and the orphans they leave behind. Each
+//! test leads its own process group, which is killed after the test, so that
+//! no process of it is left running. A test passes if it exits with code 0.
+//!
+//! At the end, a summary is printed between the `TESTSUITE SUMMARY BEGIN` and
+//! `TESTSUITE SUMMARY END` lines, with one line per binary in the form of
+//!
+//! ```text
+//! <path> <PASS|FAIL|TIMEOUT|ERROR> status=<wait status> enosys=<count>
+//! ```
+//!
+//! followed by a line of totals, so that the results can be compared across
+//! commits by scripts.
+
+#![cfg_attr(feature = "axstd", no_std)]
+#![cfg_attr(feature = "axstd", no_main)]
+
+#[macro_use]
+#[cfg(feature = "axstd")]
+extern crate axstd as std;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use axhal::arch::UspaceContext;
+use axsync::Mutex;
+use axsyscall::loader::{init_user_stack, load_user_app};
+use axsyscall::process::{Pid, Process, WaitOptions, WaitTarget};
+use axsyscall::signal::SIGKILL;
+use axtask::TaskExtRef;
+use std::fs;
+use std::io;
+
+/// The directory of the test binaries, which can be overridden at build time
+/// by the `AX_TESTSUITE_DIR` environment variable.
+const TESTSUITE_DIR: &str = match option_env!("AX_TESTSUITE_DIR") {
+    Some(dir) => dir,
+    None => "/testsuite",
+};
+
+/// How long a test may run before it is killed.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the processes of a test may take to exit after they are killed.
+const KILL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often to check whether a test has exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+enum Outcome {
+    Pass,
+    Fail,
+    Timeout,
+    /// The binary could not be loaded.
+    Error,
+}
+
+impl Outcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pass => "PASS",
+            Self::Fail => "FAIL",
+            Self::Timeout => "TIMEOUT",
+            Self::Error => "ERROR",
+        }
+    }
+}
+
+struct TestResult {
+    path: String,
+    outcome: Outcome,
+    /// The exit status in the format of `wait4`.
+    status: i32,
+    /// The number of syscalls that failed with `ENOSYS` in all processes of
+    /// the test.
+    enosys: usize,
+}
+
+/// Collects the paths of all files under `dir`, in lexicographic order.
+fn collect_tests(dir: &str, tests: &mut Vec<String>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| (e.path(), e.file_type().is_dir()))
+        .collect::<Vec<_>>();
+    entries.sort();
+    for (path, is_dir) in entries {
+        if is_dir {
+            collect_tests(&path, tests)?;
+        } else {
+            tests.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Spawns the binary at `path` as a new child process of `runner`.
+fn spawn_test(runner: &Arc<Process>, path: &str) -> io::Result<Arc<Process>> {
+    let mut uspace = axmm::new_user_aspace()?;
+    let image = load_user_app(path, &mut uspace)?;
+    let ustack_top = init_user_stack(&mut uspace, &[String::from(path)], &[], &image)?;
+    let task = axsyscall::task::spawn_user_child(
+        runner,
+        Arc::new(Mutex::new(uspace)),
+        UspaceContext::new(image.entry.as_usize(), ustack_top),
+        image.brk,
+    );
+    Ok(task.task_ext().process.clone())
+}
+
+/// Waits for the child `pid` of `runner` to exit within `timeout`, and reaps
+/// it.
+///
+/// Returns its exit status in the format of `wait4`, or `None` on timeout.
+fn reap_test(runner: &Process, pid: Pid, timeout: Duration) -> Option<i32> {
+    let deadline = axhal::time::monotonic_time() + timeout;
+    loop {
+        if let Ok(Some((_, status))) =
+            runner.wait_child(WaitTarget::Pid(pid), WaitOptions::WNOHANG)
+        {
+            return Some(status);
+        }
+        if axhal::time::monotonic_time() >= deadline {
+            return None;
+        }
+        axtask::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Kills the process group `pgid`, and reaps all children of `runner`,
+/// including the orphans of the group.
+fn kill_test_group(runner: &Process, pgid: Pid) {
+    Process::signal_group(pgid, SIGKILL);
+    let deadline = axhal::time::monotonic_time() + KILL_TIMEOUT;
+    loop {
+        match runner.wait_child(WaitTarget::Any, WaitOptions::WNOHANG) {
+            Ok(Some(_)) => continue,
+            Err(_) => return, // No children left.
+            Ok(None) => {}
+        }
+        if axhal::time::monotonic_time() >= deadline {
+            println!("TESTSUITE some processes of group {} do not exit", pgid);
+            return;
+        }
+        axtask::sleep(POLL_INTERVAL);
+    }
+}
+
+fn run_test(runner: &Arc<Process>, path: String) -> TestResult {
+    println!("TESTSUITE RUN {}", path);
+    // The runner reaps the test process, and the orphans of the test as the
+    // init process, so their ENOSYS counts are all added to the runner's.
+    let enosys_before = runner.children_enosys_count();
+    let process = match spawn_test(runner, &path) {
+        Ok(process) => process,
+        Err(err) => {
+            println!("TESTSUITE cannot run {}: {:?}", path, err);
+            return TestResult {
+                path,
+                outcome: Outcome::Error,
+                status: 0,
+                enosys: 0,
+            };
+        }
+    };
+    let pid = process.pid();
+    let (outcome, status) = match reap_test(runner, pid, TIMEOUT) {
+        Some(0) => (Outcome::Pass, 0),
+        Some(status) => (Outcome::Fail, status),
+        None => {
+            Process::signal_group(pid, SIGKILL);
+            let status = reap_test(runner, pid, KILL_TIMEOUT).unwrap_or(SIGKILL as i32);
+            (Outcome::Timeout, status)
+        }
+    };
+    kill_test_group(runner, pid);
+    TestResult {
+        path,
+        outcome,
+        status,
+        enosys: runner.children_enosys_count() - enosys_before,
+    }
+}
+
+#[cfg_attr(feature = "axstd", no_mangle)]
+fn main() {
+    let mut tests = Vec::new();
+    if let Err(err) = collect_tests(TESTSUITE_DIR, &mut tests) {
+        panic!("Cannot read {}: {:?}", TESTSUITE_DIR, err);
+    }
+    let runner = Process::new_kernel();
+    let results: Vec<_> = tests
+        .into_iter()
+        .map(|path| run_test(&runner, path))
+        .collect();
+
+    let count =
+        |outcome: fn(&Outcome) -> bool| results.iter().filter(|r| outcome(&r.outcome)).count();
+    println!("TESTSUITE SUMMARY BEGIN");
+    for r in &results {
+        println!(
+            "{} {} status={} enosys={}",
+            r.path,
+            r.outcome.as_str(),
+            r.status,
+            r.enosys
+        );
+    }
+    println!(
+        "total={} pass={} fail={} timeout={} error={} enosys={}",
+        results.len(),
+        count(|o| matches!(o, Outcome::Pass)),
+        count(|o| matches!(o, Outcome::Fail)),
+        count(|o| matches!(o, Outcome::Timeout)),
+        count(|o| matches!(o, Outcome::Error)),
+        results.iter().map(|r| r.enosys).sum::<usize>(),
+    );
+    println!("TESTSUITE SUMMARY END");
+}
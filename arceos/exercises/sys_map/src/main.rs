@@ -8,23 +8,13 @@ extern crate alloc;
 #[macro_use]
 extern crate axlog;
 
-mod task;
-mod syscall;
-mod loader;
-
-use axstd::io;
-use axhal::paging::MappingFlags;
 use axhal::arch::UspaceContext;
-use axhal::mem::VirtAddr;
 use axsync::Mutex;
 use alloc::sync::Arc;
 use alloc::string::String;
-use alloc::collections::BTreeMap;
-use axmm::AddrSpace;
-use loader::load_user_app;
+use axsyscall::loader::{init_user_stack, load_user_app};
 
-const USER_STACK_SIZE: usize = 0x10000;
-const KERNEL_STACK_SIZE: usize = 0x40000; // 256 KiB
+const APP_PATH: &str = "/sbin/mapfile";
 
 #[cfg_attr(feature = "axstd", no_mangle)]
 fn main() {
@@ -32,51 +22,24 @@ fn main() {
     let mut uspace = axmm::new_user_aspace().unwrap();
 
     // Load user app binary file into address space.
-    let entry = match load_user_app("/sbin/mapfile", &mut uspace) {
-        Ok(e) => e,
+    let image = match load_user_app(APP_PATH, &mut uspace) {
+        Ok(image) => image,
         Err(err) => panic!("Cannot load app! {:?}", err),
     };
-    ax_println!("entry: {:#x}", entry);
+    ax_println!("entry: {:#x}", image.entry);
 
     // Init user stack.
-    let ustack_top = init_user_stack(&mut uspace, true).unwrap();
+    let ustack_top = init_user_stack(&mut uspace, &[String::from(APP_PATH)], &[], &image).unwrap();
     ax_println!("New user address space: {:#x?}", uspace);
 
     // Let's kick off the user process.
-    let user_task = task::spawn_user_task(
+    let user_task = axsyscall::task::spawn_user_task(
         Arc::new(Mutex::new(uspace)),
-        UspaceContext::new(entry, ustack_top),
+        UspaceContext::new(image.entry.as_usize(), ustack_top),
+        image.brk,
     );
 
     // Wait for user process to exit ...
     let exit_code = user_task.join();
     ax_println!("monolithic kernel exit [{:?}] normally!", exit_code);
 }
-
-fn init_user_stack(uspace: &mut AddrSpace, populating: bool) -> io::Result<VirtAddr> {
-    let ustack_top = uspace.end();
-    let ustack_vaddr = ustack_top - crate::USER_STACK_SIZE;
-    ax_println!(
-        "Mapping user stack: {:#x?} -> {:#x?}",
-        ustack_vaddr, ustack_top
-    );
-    uspace.map_alloc(
-        ustack_vaddr,
-        crate::USER_STACK_SIZE,
-        MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
-        populating,
-    ).unwrap();
-
-    let app_name = "hello";
-    let av = BTreeMap::new();
-    let (stack_data, ustack_pointer) = kernel_elf_parser::get_app_stack_region(
-        &[String::from(app_name)],
-        &[],
-        &av,
-        ustack_vaddr,
-        crate::USER_STACK_SIZE,
-    );
-    uspace.write(VirtAddr::from_usize(ustack_pointer), stack_data.as_slice())?;
-
-    Ok(ustack_pointer.into())
-}
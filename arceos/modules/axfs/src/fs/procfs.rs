@@ -0,0 +1,288 @@
+//! A proc filesystem whose files generate their contents on read.
+//!
+//! The tree consists of [`ProcDir`]s and [`ProcFile`]s. A directory has some
+//! fixed entries, and may also have entries generated on demand by a
+//! [`ProcDirOps`] (e.g., a directory for each process), so the tree always
+//! reflects the live state of the kernel.
+//!
+//! The root directory is global (see [`root`]), so that other modules can add
+//! their entries to it.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
+use axfs_vfs::{VfsError, VfsResult};
+use spin::{Once, RwLock};
+
+/// Generates the content of a [`ProcFile`].
+pub type ProcGenerator = dyn Fn() -> VfsResult<String> + Send + Sync;
+
+/// Entries of a [`ProcDir`] that are generated on demand.
+pub trait ProcDirOps: Send + Sync {
+    /// Returns the names of the entries.
+    fn list(&self) -> Vec<String>;
+
+    /// Returns the entry named `name`, whose parent is `parent`.
+    fn lookup(&self, name: &str, parent: &Arc<ProcDir>) -> Option<VfsNodeRef>;
+}
+
+/// A read-only file whose content is generated each time it is read.
+///
+/// Like Linux, the size of the file is reported as 0, so it should be read
+/// until the end.
+pub struct ProcFile {
+    generate: Box<ProcGenerator>,
+}
+
+impl ProcFile {
+    /// Creates a file whose content is generated by `generate`.
+    pub fn new(generate: impl Fn() -> VfsResult<String> + Send + Sync + 'static) -> Arc<Self> {
+        Arc::new(Self {
+            generate: Box::new(generate),
+        })
+    }
+
+    /// Creates a file with fixed content.
+    pub fn new_const(content: &'static str) -> Arc<Self> {
+        Self::new(move || Ok(content.into()))
+    }
+}
+
+impl VfsNodeOps for ProcFile {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new_file(0, 0))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let content = (self.generate)()?;
+        let content = content.as_bytes();
+        let start = content.len().min(offset as usize);
+        let end = content.len().min(start + buf.len());
+        let src = &content[start..end];
+        buf[..src.len()].copy_from_slice(src);
+        Ok(src.len())
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// A directory of the proc filesystem.
+pub struct ProcDir {
+    this: Weak<ProcDir>,
+    parent: RwLock<Weak<dyn VfsNodeOps>>,
+    entries: RwLock<BTreeMap<String, VfsNodeRef>>,
+    ops: RwLock<Option<Arc<dyn ProcDirOps>>>,
+}
+
+impl ProcDir {
+    /// Creates a directory with no fixed entries.
+    ///
+    /// Entries are generated by `ops` if it is given.
+    pub fn new(parent: Option<&Arc<ProcDir>>, ops: Option<Arc<dyn ProcDirOps>>) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            parent: RwLock::new(match parent {
+                Some(parent) => Arc::downgrade(parent) as _,
+                None => Weak::<Self>::new() as _,
+            }),
+            entries: RwLock::new(BTreeMap::new()),
+            ops: RwLock::new(ops),
+        })
+    }
+
+    fn set_parent(&self, parent: Option<&VfsNodeRef>) {
+        *self.parent.write() = parent.map_or(Weak::<Self>::new() as _, Arc::downgrade);
+    }
+
+    /// Sets the generator of the entries other than the fixed ones.
+    pub fn set_ops(&self, ops: Arc<dyn ProcDirOps>) {
+        *self.ops.write() = Some(ops);
+    }
+
+    /// Adds a fixed entry, replacing the old one with the same name.
+    pub fn add(&self, name: &str, node: VfsNodeRef) {
+        self.entries.write().insert(name.into(), node);
+    }
+
+    /// Adds a file whose content is generated by `generate`.
+    pub fn add_file(
+        &self,
+        name: &str,
+        generate: impl Fn() -> VfsResult<String> + Send + Sync + 'static,
+    ) {
+        self.add(name, ProcFile::new(generate));
+    }
+
+    /// Adds a subdirectory with no fixed entries, or returns the existing one.
+    ///
+    /// Entries are generated by `ops` if it is given.
+    pub fn add_dir(&self, name: &str, ops: Option<Arc<dyn ProcDirOps>>) -> Arc<ProcDir> {
+        let mut entries = self.entries.write();
+        let existing = entries
+            .get(name)
+            .and_then(|node| node.as_any().downcast_ref::<ProcDir>())
+            .and_then(|dir| dir.this.upgrade());
+        if let Some(dir) = existing {
+            return dir;
+        }
+        let dir = Self::new(self.this.upgrade().as_ref(), ops);
+        entries.insert(name.into(), dir.clone());
+        dir
+    }
+
+    fn this(&self) -> Arc<Self> {
+        self.this.upgrade().expect("this node not found")
+    }
+
+    fn find(&self, name: &str) -> Option<VfsNodeRef> {
+        if let Some(node) = self.entries.read().get(name) {
+            return Some(node.clone());
+        }
+        let ops = self.ops.read().clone()?;
+        ops.lookup(name, &self.this())
+    }
+
+    /// Returns the names and types of all entries.
+    fn list(&self) -> Vec<(String, VfsNodeType)> {
+        let mut list: Vec<_> = self
+            .entries
+            .read()
+            .iter()
+            .map(|(name, node)| (name.clone(), node_type(node)))
+            .collect();
+        if let Some(ops) = self.ops.read().clone() {
+            let this = self.this();
+            for name in ops.list() {
+                // The entry may be gone in the meantime.
+                if let Some(node) = ops.lookup(&name, &this) {
+                    list.push((name, node_type(&node)));
+                }
+            }
+        }
+        list
+    }
+}
+
+fn node_type(node: &VfsNodeRef) -> VfsNodeType {
+    node.get_attr()
+        .map_or(VfsNodeType::File, |attr| attr.file_type())
+}
+
+impl VfsNodeOps for ProcDir {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new_dir(0, 0))
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.parent.read().upgrade()
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let (name, rest) = split_path(path);
+        let node = match name {
+            "" | "." => Ok(self.clone() as VfsNodeRef),
+            ".." => self.parent().ok_or(VfsError::NotFound),
+            _ => self.find(name).ok_or(VfsError::NotFound),
+        }?;
+
+        if let Some(rest) = rest {
+            node.lookup(rest)
+        } else {
+            Ok(node)
+        }
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let list = self.list();
+        let mut entries = list.iter().skip(start_idx.max(2) - 2);
+        for (i, ent) in dirents.iter_mut().enumerate() {
+            match i + start_idx {
+                0 => *ent = VfsDirEntry::new(".", VfsNodeType::Dir),
+                1 => *ent = VfsDirEntry::new("..", VfsNodeType::Dir),
+                _ => {
+                    if let Some((name, ty)) = entries.next() {
+                        *ent = VfsDirEntry::new(name, *ty);
+                    } else {
+                        return Ok(i);
+                    }
+                }
+            }
+        }
+        Ok(dirents.len())
+    }
+
+    fn create(&self, path: &str, _ty: VfsNodeType) -> VfsResult {
+        match self.this().lookup(path) {
+            Ok(_) => Ok(()), // already exists
+            Err(_) => Err(VfsError::PermissionDenied),
+        }
+    }
+
+    fn remove(&self, _path: &str) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    axfs_vfs::impl_vfs_dir_default! {}
+}
+
+fn split_path(path: &str) -> (&str, Option<&str>) {
+    let trimmed_path = path.trim_start_matches('/');
+    trimmed_path.find('/').map_or((trimmed_path, None), |n| {
+        (&trimmed_path[..n], Some(&trimmed_path[n + 1..]))
+    })
+}
+
+static PROC_ROOT: Once<Arc<ProcDir>> = Once::new();
+
+/// Returns the root directory of the proc filesystem.
+pub fn root() -> &'static Arc<ProcDir> {
+    PROC_ROOT.call_once(|| ProcDir::new(None, None))
+}
+
+/// The proc filesystem that implements [`axfs_vfs::VfsOps`].
+///
+/// All instances share the global [`root`] directory.
+pub struct ProcFileSystem {
+    parent: Once<VfsNodeRef>,
+}
+
+impl ProcFileSystem {
+    /// Create a new instance.
+    pub const fn new() -> Self {
+        Self {
+            parent: Once::new(),
+        }
+    }
+}
+
+impl VfsOps for ProcFileSystem {
+    fn mount(&self, _path: &str, mount_point: VfsNodeRef) -> VfsResult {
+        if let Some(parent) = mount_point.parent() {
+            root().set_parent(Some(self.parent.call_once(|| parent)));
+        } else {
+            root().set_parent(None);
+        }
+        Ok(())
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        root().clone()
+    }
+}
+
+impl Default for ProcFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
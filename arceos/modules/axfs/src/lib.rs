@@ -10,6 +10,8 @@
 //!    **enabled** by default.
 //! - `ramfs`: Mount [`axfs_ramfs::RamFileSystem`] on `/tmp`. This feature is
 //!    **enabled** by default.
+//! - `procfs`: Mount the [`procfs`] on `/proc`, whose files are generated on
+//!    read. This feature is **enabled** by default.
 //! - `myfs`: Allow users to define their custom filesystems to override the
 //!    default. In this case, [`MyFileSystemIf`] is required to be implemented
 //!    to create and initialize other filesystems. This feature is **disabled** by
@@ -34,6 +36,9 @@ mod root;
 pub mod api;
 pub mod fops;
 
+#[cfg(feature = "procfs")]
+pub use self::fs::procfs;
+
 use axdriver::{prelude::*, AxDeviceContainer};
 
 /// Initializes filesystems by block devices.
@@ -22,29 +22,22 @@ pub(crate) fn ramfs() -> Arc<fs::ramfs::RamFileSystem> {
 }
 
 #[cfg(feature = "procfs")]
-pub(crate) fn procfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
-    let procfs = fs::ramfs::RamFileSystem::new();
-    let proc_root = procfs.root_dir();
+pub(crate) fn procfs() -> Arc<fs::procfs::ProcFileSystem> {
+    let proc_root = fs::procfs::root();
 
     // Create /proc/sys/net/core/somaxconn
-    proc_root.create("sys", VfsNodeType::Dir)?;
-    proc_root.create("sys/net", VfsNodeType::Dir)?;
-    proc_root.create("sys/net/core", VfsNodeType::Dir)?;
-    proc_root.create("sys/net/core/somaxconn", VfsNodeType::File)?;
-    let file_somaxconn = proc_root.clone().lookup("./sys/net/core/somaxconn")?;
-    file_somaxconn.write_at(0, b"4096\n")?;
+    let sys = proc_root.add_dir("sys", None);
+    let net_core = sys.add_dir("net", None).add_dir("core", None);
+    net_core.add("somaxconn", fs::procfs::ProcFile::new_const("4096\n"));
 
     // Create /proc/sys/vm/overcommit_memory
-    proc_root.create("sys/vm", VfsNodeType::Dir)?;
-    proc_root.create("sys/vm/overcommit_memory", VfsNodeType::File)?;
-    let file_over = proc_root.clone().lookup("./sys/vm/overcommit_memory")?;
-    file_over.write_at(0, b"0\n")?;
+    let vm = sys.add_dir("vm", None);
+    vm.add("overcommit_memory", fs::procfs::ProcFile::new_const("0\n"));
 
-    // Create /proc/self/stat
-    proc_root.create("self", VfsNodeType::Dir)?;
-    proc_root.create("self/stat", VfsNodeType::File)?;
+    // Create /proc/mounts
+    proc_root.add_file("mounts", || Ok(crate::root::mounts_info()));
 
-    Ok(Arc::new(procfs))
+    Arc::new(fs::procfs::ProcFileSystem::new())
 }
 
 #[cfg(feature = "sysfs")]
@@ -15,19 +15,21 @@ static CURRENT_DIR: LazyInit<Mutex<VfsNodeRef>> = LazyInit::new();
 
 struct MountPoint {
     path: &'static str,
+    fs_type: &'static str,
     fs: Arc<dyn VfsOps>,
 }
 
 struct RootDirectory {
     main_fs: Arc<dyn VfsOps>,
+    main_fs_type: &'static str,
     mounts: Vec<MountPoint>,
 }
 
 static ROOT_DIR: LazyInit<Arc<RootDirectory>> = LazyInit::new();
 
 impl MountPoint {
-    pub fn new(path: &'static str, fs: Arc<dyn VfsOps>) -> Self {
-        Self { path, fs }
+    pub fn new(path: &'static str, fs_type: &'static str, fs: Arc<dyn VfsOps>) -> Self {
+        Self { path, fs_type, fs }
     }
 }
 
@@ -38,14 +40,20 @@ impl Drop for MountPoint {
 }
 
 impl RootDirectory {
-    pub const fn new(main_fs: Arc<dyn VfsOps>) -> Self {
+    pub const fn new(main_fs: Arc<dyn VfsOps>, main_fs_type: &'static str) -> Self {
         Self {
             main_fs,
+            main_fs_type,
             mounts: Vec::new(),
         }
     }
 
-    pub fn mount(&mut self, path: &'static str, fs: Arc<dyn VfsOps>) -> AxResult {
+    pub fn mount(
+        &mut self,
+        path: &'static str,
+        fs_type: &'static str,
+        fs: Arc<dyn VfsOps>,
+    ) -> AxResult {
         if path == "/" {
             return ax_err!(InvalidInput, "cannot mount root filesystem");
         }
@@ -58,7 +66,7 @@ impl RootDirectory {
         // create the mount point in the main filesystem if it does not exist
         self.main_fs.root_dir().create(path, FileType::Dir)?;
         fs.mount(path, self.main_fs.root_dir().lookup(path)?)?;
-        self.mounts.push(MountPoint::new(path, fs));
+        self.mounts.push(MountPoint::new(path, fs_type, fs));
         Ok(())
     }
 
@@ -147,36 +155,37 @@ pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
     cfg_if::cfg_if! {
         if #[cfg(feature = "myfs")] { // override the default filesystem
             let main_fs = fs::myfs::new_myfs(disk);
+            let main_fs_type = "myfs";
         } else if #[cfg(feature = "fatfs")] {
             static FAT_FS: LazyInit<Arc<fs::fatfs::FatFileSystem>> = LazyInit::new();
             FAT_FS.init_once(Arc::new(fs::fatfs::FatFileSystem::new(disk)));
             FAT_FS.init();
             let main_fs = FAT_FS.clone();
+            let main_fs_type = "vfat";
         }
     }
 
-    let mut root_dir = RootDirectory::new(main_fs);
+    let mut root_dir = RootDirectory::new(main_fs, main_fs_type);
 
     #[cfg(feature = "devfs")]
     root_dir
-        .mount("/dev", mounts::devfs())
+        .mount("/dev", "devfs", mounts::devfs())
         .expect("failed to mount devfs at /dev");
 
     #[cfg(feature = "ramfs")]
     root_dir
-        .mount("/tmp", mounts::ramfs())
+        .mount("/tmp", "tmpfs", mounts::ramfs())
         .expect("failed to mount ramfs at /tmp");
 
-    // Mount another ramfs as procfs
     #[cfg(feature = "procfs")]
-    root_dir // should not fail
-        .mount("/proc", mounts::procfs().unwrap())
+    root_dir
+        .mount("/proc", "proc", mounts::procfs())
         .expect("fail to mount procfs at /proc");
 
     // Mount another ramfs as sysfs
     #[cfg(feature = "sysfs")]
     root_dir // should not fail
-        .mount("/sys", mounts::sysfs().unwrap())
+        .mount("/sys", "sysfs", mounts::sysfs().unwrap())
         .expect("fail to mount sysfs at /sys");
 
     ROOT_DIR.init_once(Arc::new(root_dir));
@@ -184,6 +193,20 @@ pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
     *CURRENT_DIR_PATH.lock() = "/".into();
 }
 
+/// Returns the mounted filesystems in the format of `/proc/mounts`.
+#[cfg(feature = "procfs")]
+pub(crate) fn mounts_info() -> String {
+    use core::fmt::Write;
+
+    let mut info = String::new();
+    let root_dir = ROOT_DIR.clone();
+    let _ = writeln!(info, "rootfs / {} rw 0 0", root_dir.main_fs_type);
+    for mp in &root_dir.mounts {
+        let _ = writeln!(info, "{0} {1} {0} rw 0 0", mp.fs_type, mp.path);
+    }
+    info
+}
+
 fn parent_node_of(dir: Option<&VfsNodeRef>, path: &str) -> VfsNodeRef {
     if path.starts_with('/') {
         ROOT_DIR.clone()
@@ -4,9 +4,11 @@ pub(crate) mod trap;
 use core::arch::asm;
 
 use aarch64_cpu::registers::{DAIF, TPIDR_EL0, TTBR0_EL1, TTBR1_EL1, VBAR_EL1};
-use memory_addr::{PhysAddr, VirtAddr};
+use memory_addr::{PhysAddr, VirtAddr, PAGE_SIZE_4K};
 use tock_registers::interfaces::{Readable, Writeable};
 
+#[cfg(feature = "uspace")]
+pub use self::context::UspaceContext;
 pub use self::context::{FpState, TaskContext, TrapFrame};
 
 /// Allows the current CPU to respond to interrupts.
@@ -98,6 +100,21 @@ pub fn flush_tlb(vaddr: Option<VirtAddr>) {
     }
 }
 
+/// Flushes the TLB entries that map `[start, start + size)` on all CPUs.
+///
+/// The pages are flushed one by one, or the entire TLB (of all CPUs) is
+/// flushed if there are more than 32 pages. TLB entries of huge pages may be
+/// cached as smaller ones, so a huge page is not flushed by its start only.
+pub fn flush_tlb_range(start: VirtAddr, size: usize) {
+    if size > 32 * PAGE_SIZE_4K {
+        unsafe { asm!("dsb ishst; tlbi vmalle1is; dsb ish; isb") };
+    } else {
+        for offset in (0..size).step_by(PAGE_SIZE_4K) {
+            flush_tlb(Some(start + offset));
+        }
+    }
+}
+
 /// Flushes the entire instruction cache.
 #[inline]
 pub fn flush_icache_all() {
@@ -4,7 +4,7 @@ mod macros;
 mod context;
 mod trap;
 
-use memory_addr::{PhysAddr, VirtAddr};
+use memory_addr::{PhysAddr, VirtAddr, PAGE_SIZE_4K};
 use riscv::asm;
 use riscv::register::{satp, sstatus, stvec};
 
@@ -82,6 +82,21 @@ pub fn flush_tlb(vaddr: Option<VirtAddr>) {
     }
 }
 
+/// Flushes the TLB entries that map `[start, start + size)`.
+///
+/// The pages are flushed one by one, or the entire TLB is flushed if there
+/// are more than 32 pages. Only the TLB of the current CPU is flushed, as
+/// there is no TLB shootdown of other CPUs yet.
+pub fn flush_tlb_range(start: VirtAddr, size: usize) {
+    if size > 32 * PAGE_SIZE_4K {
+        flush_tlb(None);
+    } else {
+        for offset in (0..size).step_by(PAGE_SIZE_4K) {
+            flush_tlb(Some(start + offset));
+        }
+    }
+}
+
 /// Writes Supervisor Trap Vector Base Address Register (`stvec`).
 #[inline]
 pub fn set_trap_vector_base(stvec: usize) {
@@ -60,4 +60,8 @@ fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
             );
         }
     }
+    #[cfg(feature = "uspace")]
+    if from_user {
+        crate::trap::handle_user_return(tf);
+    }
 }
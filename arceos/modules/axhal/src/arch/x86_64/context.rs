@@ -1,10 +1,12 @@
 use core::{arch::asm, fmt};
+#[cfg(feature = "uspace")]
+use memory_addr::PhysAddr;
 use memory_addr::VirtAddr;
 
 /// Saved registers when a trap (interrupt or exception) occurs.
 #[allow(missing_docs)]
 #[repr(C)]
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Copy)]
 pub struct TrapFrame {
     pub rax: u64,
     pub rcx: u64,
@@ -39,6 +41,36 @@ impl TrapFrame {
     pub const fn is_user(&self) -> bool {
         self.cs & 0b11 == 3
     }
+
+    /// Gets the 0th syscall argument.
+    pub const fn arg0(&self) -> usize {
+        self.rdi as _
+    }
+
+    /// Gets the 1st syscall argument.
+    pub const fn arg1(&self) -> usize {
+        self.rsi as _
+    }
+
+    /// Gets the 2nd syscall argument.
+    pub const fn arg2(&self) -> usize {
+        self.rdx as _
+    }
+
+    /// Gets the 3rd syscall argument.
+    pub const fn arg3(&self) -> usize {
+        self.r10 as _
+    }
+
+    /// Gets the 4th syscall argument.
+    pub const fn arg4(&self) -> usize {
+        self.r8 as _
+    }
+
+    /// Gets the 5th syscall argument.
+    pub const fn arg5(&self) -> usize {
+        self.r9 as _
+    }
 }
 
 #[repr(C)]
@@ -119,6 +151,7 @@ impl fmt::Debug for ExtendedState {
 /// - Stack pointer register
 /// - Thread pointer register (for thread-local storage, currently unsupported)
 /// - FP/SIMD registers
+/// - Page table root (`CR3`), with the `uspace` feature
 ///
 /// On context switch, current task saves its context from CPU to memory,
 /// and the next task restores its context from memory to CPU.
@@ -141,17 +174,22 @@ pub struct TaskContext {
     /// Extended states, i.e., FP/SIMD states.
     #[cfg(feature = "fp_simd")]
     pub ext_state: ExtendedState,
+    /// The `CR3` register value, i.e., the page table root.
+    #[cfg(feature = "uspace")]
+    pub cr3: PhysAddr,
 }
 
 impl TaskContext {
     /// Creates a new default context for a new task.
-    pub const fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             kstack_top: va!(0),
             rsp: 0,
             fs_base: 0,
             #[cfg(feature = "fp_simd")]
             ext_state: ExtendedState::default(),
+            #[cfg(feature = "uspace")]
+            cr3: crate::paging::kernel_page_table_root(),
         }
     }
 
@@ -177,6 +215,17 @@ impl TaskContext {
         self.fs_base = tls_area.as_usize();
     }
 
+    /// Changes the page table root (`CR3` register for x86_64).
+    ///
+    /// If not set, the kernel page table root is used (obtained by
+    /// [`axhal::paging::kernel_page_table_root`][1]).
+    ///
+    /// [1]: crate::paging::kernel_page_table_root
+    #[cfg(feature = "uspace")]
+    pub fn set_page_table_root(&mut self, cr3: PhysAddr) {
+        self.cr3 = cr3;
+    }
+
     /// Switches to another task.
     ///
     /// It first saves the current task's context from CPU to this place, and then
@@ -187,15 +236,155 @@ impl TaskContext {
             self.ext_state.save();
             next_ctx.ext_state.restore();
         }
-        #[cfg(feature = "tls")]
+        // User tasks keep their TLS pointer in `FS_BASE` while in the kernel.
+        #[cfg(any(feature = "tls", feature = "uspace"))]
         {
             self.fs_base = super::read_thread_pointer();
             unsafe { super::write_thread_pointer(next_ctx.fs_base) };
         }
+        #[cfg(feature = "uspace")]
+        unsafe {
+            if self.cr3 != next_ctx.cr3 {
+                super::write_page_table_root(next_ctx.cr3);
+            }
+            super::gdt::set_kernel_stack(next_ctx.kstack_top.as_usize());
+        }
         unsafe { context_switch(&mut self.rsp, &next_ctx.rsp) }
     }
 }
 
+/// Context to enter user space.
+#[cfg(feature = "uspace")]
+pub struct UspaceContext {
+    tf: TrapFrame,
+    /// The `FS_BASE` register, which points to the thread-local storage.
+    fs_base: usize,
+}
+
+#[cfg(feature = "uspace")]
+impl UspaceContext {
+    /// Creates an empty context with all registers set to zero.
+    pub const fn empty() -> Self {
+        unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+    }
+
+    /// Creates a new context with the given entry point, user stack pointer,
+    /// and the argument.
+    pub fn new(entry: usize, ustack_top: VirtAddr) -> Self {
+        use super::GdtStruct;
+        use x86_64::registers::rflags::RFlags;
+        Self {
+            tf: TrapFrame {
+                rip: entry as _,
+                cs: GdtStruct::UCODE64_SELECTOR.0 as _,
+                rflags: RFlags::INTERRUPT_FLAG.bits() | 0b10, // bit 1 is reserved as 1
+                rsp: ustack_top.as_usize() as _,
+                ss: GdtStruct::UDATA_SELECTOR.0 as _,
+                ..Default::default()
+            },
+            fs_base: 0,
+        }
+    }
+
+    /// Creates a new context from the given [`TrapFrame`].
+    ///
+    /// The TLS pointer is taken from the current `FS_BASE`, which belongs to
+    /// the user task that trapped.
+    pub fn from(trap_frame: &TrapFrame) -> Self {
+        Self {
+            tf: *trap_frame,
+            fs_base: super::read_thread_pointer(),
+        }
+    }
+
+    /// Gets the instruction pointer.
+    pub const fn get_ip(&self) -> usize {
+        self.tf.rip as _
+    }
+
+    /// Gets the stack pointer.
+    pub const fn get_sp(&self) -> usize {
+        self.tf.rsp as _
+    }
+
+    /// Sets the instruction pointer.
+    pub const fn set_ip(&mut self, rip: usize) {
+        self.tf.rip = rip as _;
+    }
+
+    /// Sets the stack pointer.
+    pub const fn set_sp(&mut self, rsp: usize) {
+        self.tf.rsp = rsp as _;
+    }
+
+    /// Sets the return value register.
+    pub const fn set_retval(&mut self, rax: usize) {
+        self.tf.rax = rax as _;
+    }
+
+    /// Sets the `FS_BASE` register, which points to the thread-local storage.
+    pub const fn set_tls(&mut self, tls: usize) {
+        self.fs_base = tls;
+    }
+
+    /// Enters user space.
+    ///
+    /// It restores the user registers and jumps to the user entry point
+    /// (saved in `rip`).
+    /// When an exception or syscall occurs, the kernel stack pointer is
+    /// switched to `kstack_top`.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because it changes processor mode and the stack.
+    #[inline(never)]
+    #[no_mangle]
+    pub unsafe fn enter_uspace(&self, kstack_top: VirtAddr) -> ! {
+        super::disable_irqs();
+        super::gdt::set_kernel_stack(kstack_top.as_usize());
+        super::write_thread_pointer(self.fs_base);
+        asm!("
+            mov     rsp, {tf}
+            pop     rax
+            pop     rcx
+            pop     rdx
+            pop     rbx
+            pop     rbp
+            pop     rsi
+            pop     rdi
+            pop     r8
+            pop     r9
+            pop     r10
+            pop     r11
+            pop     r12
+            pop     r13
+            pop     r14
+            pop     r15
+            add     rsp, 16     # skip vector, error_code
+            swapgs
+            iretq",
+            tf = in(reg) &self.tf,
+            options(noreturn),
+        )
+    }
+}
+
+#[cfg(feature = "uspace")]
+impl core::ops::Deref for UspaceContext {
+    type Target = TrapFrame;
+
+    fn deref(&self) -> &Self::Target {
+        &self.tf
+    }
+}
+
+#[cfg(feature = "uspace")]
+impl core::ops::DerefMut for UspaceContext {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.tf
+    }
+}
+
 #[naked]
 unsafe extern "C" fn context_switch(_current_stack: &mut u64, _next_stack: &u64) {
     asm!(
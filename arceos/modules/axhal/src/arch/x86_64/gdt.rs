@@ -6,6 +6,25 @@ use x86_64::structures::gdt::{Descriptor, DescriptorFlags};
 use x86_64::structures::{tss::TaskStateSegment, DescriptorTablePointer};
 use x86_64::{addr::VirtAddr, PrivilegeLevel};
 
+/// The per-CPU Task State Segment (TSS).
+///
+/// Its `RSP0` field is the kernel stack pointer loaded when a trap or a
+/// syscall comes from user space.
+#[percpu::def_percpu]
+#[no_mangle]
+pub(crate) static TSS: TaskStateSegment = TaskStateSegment::new();
+
+/// Sets the kernel stack used when entering the kernel from user space on
+/// the current CPU.
+///
+/// It must be called with IRQs disabled.
+#[cfg(feature = "uspace")]
+pub(super) fn set_kernel_stack(kstack_top: usize) {
+    unsafe {
+        TSS.current_ref_mut_raw().privilege_stack_table[0] = VirtAddr::new(kstack_top as u64)
+    };
+}
+
 /// A wrapper of the Global Descriptor Table (GDT) with maximum 16 entries.
 #[repr(align(16))]
 pub struct GdtStruct {
@@ -5,17 +5,27 @@ mod idt;
 #[cfg(target_os = "none")]
 mod trap;
 
+#[cfg(all(target_os = "none", feature = "uspace"))]
+mod syscall;
+
 use core::arch::asm;
 
-use memory_addr::{MemoryAddr, PhysAddr, VirtAddr};
+use memory_addr::{MemoryAddr, PhysAddr, VirtAddr, PAGE_SIZE_4K};
 use x86::{controlregs, msr, tlb};
 use x86_64::instructions::interrupts;
 
+#[cfg(feature = "uspace")]
+pub use self::context::UspaceContext;
 pub use self::context::{ExtendedState, FxsaveArea, TaskContext, TrapFrame};
 pub use self::gdt::GdtStruct;
 pub use self::idt::IdtStruct;
 pub use x86_64::structures::tss::TaskStateSegment;
 
+#[cfg(target_os = "none")]
+pub(crate) use self::gdt::TSS;
+#[cfg(all(target_os = "none", feature = "uspace"))]
+pub(crate) use self::syscall::init_syscall;
+
 /// Allows the current CPU to respond to interrupts.
 #[inline]
 pub fn enable_irqs() {
@@ -89,6 +99,21 @@ pub fn flush_tlb(vaddr: Option<VirtAddr>) {
     }
 }
 
+/// Flushes the TLB entries that map `[start, start + size)`.
+///
+/// The pages are flushed one by one, or the entire TLB is flushed if there
+/// are more than 32 pages. Only the TLB of the current CPU is flushed, as
+/// there is no TLB shootdown of other CPUs yet.
+pub fn flush_tlb_range(start: VirtAddr, size: usize) {
+    if size > 32 * PAGE_SIZE_4K {
+        flush_tlb(None);
+    } else {
+        for offset in (0..size).step_by(PAGE_SIZE_4K) {
+            flush_tlb(Some(start + offset));
+        }
+    }
+}
+
 /// Reads the thread pointer of the current CPU.
 ///
 /// It is used to implement TLS (Thread Local Storage).
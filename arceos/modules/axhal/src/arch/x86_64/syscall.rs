@@ -0,0 +1,52 @@
+use x86::msr;
+use x86_64::registers::rflags::RFlags;
+use x86_64::structures::tss::TaskStateSegment;
+
+use super::{GdtStruct, TrapFrame};
+
+/// The user `RSP` saved on syscall entry, before the kernel stack is loaded.
+#[percpu::def_percpu]
+#[no_mangle]
+static USER_RSP: usize = 0;
+
+core::arch::global_asm!(
+    include_str!("syscall.S"),
+    tss_rsp0_offset = const core::mem::offset_of!(TaskStateSegment, privilege_stack_table),
+    ucode64_selector = const GdtStruct::UCODE64_SELECTOR.0,
+    udata_selector = const GdtStruct::UDATA_SELECTOR.0,
+);
+
+#[no_mangle]
+fn x86_syscall_handler(tf: &mut TrapFrame) {
+    tf.rax = crate::trap::handle_syscall(tf, tf.rax as usize) as u64;
+    crate::trap::handle_user_return(tf);
+}
+
+/// Enables the `syscall` instruction on the current CPU.
+///
+/// `STAR` holds the selectors loaded by `syscall` (`KCODE64`, `KDATA`) and by
+/// `sysret` (`UCODE64`, `UDATA`), which are derived from the 32-bit user code
+/// selector by the CPU. Interrupts are disabled on entry.
+pub(crate) fn init_syscall() {
+    extern "C" {
+        fn syscall_entry();
+    }
+    const EFER_SCE: u64 = 1;
+    let star = ((GdtStruct::UCODE32_SELECTOR.0 as u64) << 48)
+        | ((GdtStruct::KCODE64_SELECTOR.0 as u64) << 32);
+    let fmask = RFlags::TRAP_FLAG
+        | RFlags::INTERRUPT_FLAG
+        | RFlags::DIRECTION_FLAG
+        | RFlags::IOPL_LOW
+        | RFlags::IOPL_HIGH
+        | RFlags::ALIGNMENT_CHECK
+        | RFlags::NESTED_TASK;
+    unsafe {
+        msr::wrmsr(msr::IA32_STAR, star);
+        msr::wrmsr(msr::IA32_LSTAR, syscall_entry as usize as u64);
+        msr::wrmsr(msr::IA32_FMASK, fmask.bits());
+        msr::wrmsr(msr::IA32_EFER, msr::rdmsr(msr::IA32_EFER) | EFER_SCE);
+        // The `GS` base of user space, swapped in on `swapgs`.
+        msr::wrmsr(msr::IA32_KERNEL_GSBASE, 0);
+    }
+}
@@ -33,15 +33,21 @@ pub fn current_task_ptr<T>() -> *const T {
         // on x86, only one instruction is needed to read the per-CPU task pointer from `gs:[off]`.
         CURRENT_TASK_PTR.read_current_raw() as _
     }
-    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    #[cfg(any(
+        target_arch = "riscv32",
+        target_arch = "riscv64",
+        all(target_arch = "aarch64", feature = "uspace")
+    ))]
     unsafe {
-        // on RISC-V, reading `CURRENT_TASK_PTR` requires multiple instruction, so we disable local IRQs.
+        // on RISC-V (and ARM64 with `uspace`), reading `CURRENT_TASK_PTR` requires multiple
+        // instructions, so we disable local IRQs.
         let _guard = kernel_guard::IrqSave::new();
         CURRENT_TASK_PTR.read_current_raw() as _
     }
-    #[cfg(target_arch = "aarch64")]
+    #[cfg(all(target_arch = "aarch64", not(feature = "uspace")))]
     {
-        // on ARM64, we use `SP_EL0` to store the task pointer.
+        // on ARM64, we use `SP_EL0` to store the task pointer, unless it is
+        // used as the user stack pointer.
         use tock_registers::interfaces::Readable;
         aarch64_cpu::registers::SP_EL0.get() as _
     }
@@ -61,12 +67,16 @@ pub unsafe fn set_current_task_ptr<T>(ptr: *const T) {
     {
         CURRENT_TASK_PTR.write_current_raw(ptr as usize)
     }
-    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    #[cfg(any(
+        target_arch = "riscv32",
+        target_arch = "riscv64",
+        all(target_arch = "aarch64", feature = "uspace")
+    ))]
     {
         let _guard = kernel_guard::IrqSave::new();
         CURRENT_TASK_PTR.write_current_raw(ptr as usize)
     }
-    #[cfg(target_arch = "aarch64")]
+    #[cfg(all(target_arch = "aarch64", not(feature = "uspace")))]
     {
         use tock_registers::interfaces::Writeable;
         aarch64_cpu::registers::SP_EL0.set(ptr as u64)
@@ -20,6 +20,8 @@
 //! - `fp_simd`: Enable floating-point and SIMD support.
 //! - `paging`: Enable page table manipulation.
 //! - `irq`: Enable interrupt handling support.
+//! - `uspace`: Enable user space support (`UspaceContext` and the syscall
+//!    entry of each architecture).
 //!
 //! [ArceOS]: https://github.com/arceos-org/arceos
 //! [cargo test]: https://doc.rust-lang.org/cargo/guide/tests.html
@@ -68,6 +70,35 @@ pub mod console {
             putchar(*c);
         }
     }
+
+    #[cfg(feature = "irq")]
+    static INPUT_HANDLER: lazyinit::LazyInit<fn(u8)> = lazyinit::LazyInit::new();
+
+    /// Registers `handler` to receive the console input byte by byte, which
+    /// is called by the interrupt handler of the console.
+    ///
+    /// Returns `false` if the console of the platform has no input interrupt
+    /// (where the input has to be polled by [`getchar`]), or a handler has
+    /// been registered.
+    #[cfg(feature = "irq")]
+    pub fn set_input_handler(handler: fn(u8)) -> bool {
+        if INPUT_HANDLER.is_inited() {
+            return false;
+        }
+        INPUT_HANDLER.init_once(handler);
+        super::platform::console::init_input_irq()
+    }
+
+    /// Passes the available input to the registered handler, if any.
+    #[cfg(feature = "irq")]
+    #[allow(dead_code)]
+    pub(crate) fn handle_input_irq() {
+        if let Some(handler) = INPUT_HANDLER.get() {
+            while let Some(c) = getchar() {
+                handler(c);
+            }
+        }
+    }
 }
 
 /// Miscellaneous operation, e.g. terminate the system.
@@ -2,6 +2,8 @@
 
 use axalloc::global_allocator;
 use lazyinit::LazyInit;
+use memory_addr::{MemoryAddr, VirtAddrRange};
+use page_table_entry::GenericPTE;
 use page_table_multiarch::PagingHandler;
 
 use crate::mem::{phys_to_virt, virt_to_phys, MemRegionFlags, PhysAddr, VirtAddr, PAGE_SIZE_4K};
@@ -57,15 +59,117 @@ cfg_if::cfg_if! {
     if #[cfg(target_arch = "x86_64")] {
         /// The architecture-specific page table.
         pub type PageTable = page_table_multiarch::x86_64::X64PageTable<PagingHandlerImpl>;
+        type PageTableEntry = page_table_entry::x86_64::X64PTE;
+        const PAGE_TABLE_LEVELS: usize = 4;
     } else if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
         /// The architecture-specific page table.
         pub type PageTable = page_table_multiarch::riscv::Sv39PageTable<PagingHandlerImpl>;
+        type PageTableEntry = page_table_entry::riscv::Rv64PTE;
+        const PAGE_TABLE_LEVELS: usize = 3;
     } else if #[cfg(target_arch = "aarch64")]{
         /// The architecture-specific page table.
         pub type PageTable = page_table_multiarch::aarch64::A64PageTable<PagingHandlerImpl>;
+        type PageTableEntry = page_table_entry::aarch64::A64PTE;
+        const PAGE_TABLE_LEVELS: usize = 4;
     }
 }
 
+/// Calls `f` with the start address, target, flags and size of each page
+/// (i.e., leaf entry) of `pt` overlapping `range`, in the ascending order of
+/// addresses.
+///
+/// As with [`PageTable::query`], entries that are not present but not unused
+/// either are included. The missing page tables are skipped as a whole, so it
+/// is much cheaper than querying `range` page by page when it is sparsely
+/// mapped.
+pub fn for_each_page(
+    pt: &PageTable,
+    range: VirtAddrRange,
+    mut f: impl FnMut(VirtAddr, PhysAddr, MappingFlags, PageSize),
+) {
+    walk_table(pt.root_paddr(), PAGE_TABLE_LEVELS - 1, range, &mut f);
+}
+
+/// Walks the pages within `range` of the table at `table_paddr`, whose entries
+/// are of the given `level` (0 for 4 KiB pages).
+fn walk_table(
+    table_paddr: PhysAddr,
+    level: usize,
+    range: VirtAddrRange,
+    f: &mut impl FnMut(VirtAddr, PhysAddr, MappingFlags, PageSize),
+) {
+    let table = unsafe {
+        core::slice::from_raw_parts(
+            phys_to_virt(table_paddr).as_ptr() as *const PageTableEntry,
+            PAGE_SIZE_4K / core::mem::size_of::<PageTableEntry>(),
+        )
+    };
+    let shift = 12 + 9 * level;
+    let mut vaddr = range.start;
+    while vaddr < range.end {
+        let entry = &table[(vaddr.as_usize() >> shift) & 0x1ff];
+        let next: VirtAddr = vaddr
+            .align_down(1usize << shift)
+            .as_usize()
+            .saturating_add(1 << shift)
+            .min(range.end.as_usize())
+            .into();
+        if !entry.is_unused() {
+            if level == 0 || entry.is_huge() {
+                let page_size = match level {
+                    0 => PageSize::Size4K,
+                    1 => PageSize::Size2M,
+                    _ => PageSize::Size1G,
+                };
+                let start = vaddr.align_down(page_size);
+                f(start, entry.paddr(), entry.flags(), page_size);
+            } else {
+                walk_table(entry.paddr(), level - 1, VirtAddrRange::new(vaddr, next), f);
+            }
+        }
+        vaddr = next;
+    }
+}
+
+/// Splits the huge page (2 MiB or 1 GiB) at `start` into pages of the next
+/// smaller size, which map the same frame with the same flags.
+///
+/// It is done with break-before-make: the huge page is unmapped and its TLB
+/// entries are flushed before the smaller pages are mapped, as replacing a
+/// valid huge page entry with a table entry directly is not allowed on some
+/// architectures (e.g., aarch64). So the memory is unmapped for a while, and
+/// page faults on it (from other CPUs) should be retried.
+///
+/// Returns the size of the smaller pages.
+pub fn split_huge_page(pt: &mut PageTable, start: VirtAddr) -> PagingResult<PageSize> {
+    let (frame, flags, page_size) = pt.query(start)?;
+    let sub_size = match page_size {
+        PageSize::Size1G => PageSize::Size2M,
+        PageSize::Size2M => PageSize::Size4K,
+        PageSize::Size4K => return Ok(page_size),
+    };
+    if !start.is_aligned(page_size) {
+        return Err(PagingError::NotAligned);
+    }
+
+    pt.unmap(start)?.2.ignore();
+    crate::arch::flush_tlb_range(start, page_size.into());
+    let sub_pages = usize::from(page_size) / usize::from(sub_size);
+    let res = (0..sub_pages).try_for_each(|i| {
+        let offset = i * usize::from(sub_size);
+        // TLB flush on map is unnecessary, as there are no outdated mappings.
+        pt.map(start + offset, frame + offset, sub_size, flags)
+            .map(|tlb| tlb.ignore())
+    });
+    if let Err(err) = res {
+        // Only the table of the smaller pages can fail to be allocated, before
+        // any of them is mapped.
+        pt.map(start, frame, page_size, flags)?.ignore();
+        return Err(err);
+    }
+    Ok(sub_size)
+}
+
 static KERNEL_PAGE_TABLE_ROOT: LazyInit<PhysAddr> = LazyInit::new();
 
 /// Saves the root physical address of the kernel page table, which may be used
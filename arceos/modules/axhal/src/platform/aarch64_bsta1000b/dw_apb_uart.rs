@@ -38,7 +38,15 @@ pub fn init_irq() {
     crate::irq::register_handler(crate::platform::irq::UART_IRQ_NUM, handle);
 }
 
+/// The UART IRQ handler is registered by [`init_irq`] already.
+#[cfg(feature = "irq")]
+pub(crate) fn init_input_irq() -> bool {
+    true
+}
+
 /// UART IRQ Handler
 pub fn handle() {
     trace!("Uart IRQ Handler");
+    #[cfg(feature = "irq")]
+    crate::console::handle_input_irq();
 }
@@ -39,13 +39,21 @@ pub fn init() {
     crate::irq::set_enable(crate::platform::irq::UART_IRQ_NUM, true);
 }
 
+/// Registers the UART IRQ handler, which passes the input to
+/// [`crate::console::set_input_handler`].
+#[cfg(feature = "irq")]
+pub(crate) fn init_input_irq() -> bool {
+    crate::irq::register_handler(crate::platform::irq::UART_IRQ_NUM, handle)
+}
+
 /// UART IRQ Handler
 pub fn handle() {
     let is_receive_interrupt = UART.lock().is_receive_interrupt();
     UART.lock().ack_interrupts();
+    #[cfg(feature = "irq")]
     if is_receive_interrupt {
-        while let Some(c) = getchar() {
-            putchar(c);
-        }
+        crate::console::handle_input_irq();
     }
+    #[cfg(not(feature = "irq"))]
+    let _ = is_receive_interrupt;
 }
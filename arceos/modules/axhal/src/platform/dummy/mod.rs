@@ -11,6 +11,12 @@ pub mod console {
     pub fn getchar() -> Option<u8> {
         unimplemented!()
     }
+
+    /// Enables the console input interrupt, returns whether there is one.
+    #[cfg(feature = "irq")]
+    pub(crate) fn init_input_irq() -> bool {
+        false
+    }
 }
 
 pub mod misc {
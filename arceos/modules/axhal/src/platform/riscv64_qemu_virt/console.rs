@@ -12,3 +12,9 @@ pub fn getchar() -> Option<u8> {
         c => Some(c as u8),
     }
 }
+
+/// The SBI console has no input interrupt.
+#[cfg(feature = "irq")]
+pub(crate) fn init_input_irq() -> bool {
+    false
+}
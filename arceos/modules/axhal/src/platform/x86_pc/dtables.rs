@@ -1,13 +1,10 @@
 //! Description tables (per-CPU GDT, per-CPU ISS, IDT)
 
-use crate::arch::{GdtStruct, IdtStruct, TaskStateSegment};
+use crate::arch::{GdtStruct, IdtStruct, TSS};
 use lazyinit::LazyInit;
 
 static IDT: LazyInit<IdtStruct> = LazyInit::new();
 
-#[percpu::def_percpu]
-static TSS: LazyInit<TaskStateSegment> = LazyInit::new();
-
 #[percpu::def_percpu]
 static GDT: LazyInit<GdtStruct> = LazyInit::new();
 
@@ -16,11 +13,12 @@ fn init_percpu() {
         IDT.load();
         let tss = TSS.current_ref_mut_raw();
         let gdt = GDT.current_ref_mut_raw();
-        tss.init_once(TaskStateSegment::new());
         gdt.init_once(GdtStruct::new(tss));
         gdt.load();
         gdt.load_tss();
     }
+    #[cfg(feature = "uspace")]
+    crate::arch::init_syscall();
 }
 
 /// Initializes IDT, GDT on the primary CPU.
@@ -100,6 +100,12 @@ pub fn getchar() -> Option<u8> {
     COM1.lock().getchar()
 }
 
+/// The input interrupt of COM1 is not routed by the I/O APIC yet.
+#[cfg(feature = "irq")]
+pub(crate) fn init_input_irq() -> bool {
+    false
+}
+
 pub(super) fn init() {
     COM1.lock().init(115200);
 }
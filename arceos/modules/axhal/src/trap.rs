@@ -22,6 +22,13 @@ pub static PAGE_FAULT: [fn(VirtAddr, MappingFlags, bool) -> bool];
 #[def_trap_handler]
 pub static SYSCALL: [fn(&TrapFrame, usize) -> isize];
 
+/// A slice of functions called before returning to user space from a trap.
+///
+/// They can modify the saved user context, e.g., to deliver signals.
+#[cfg(feature = "uspace")]
+#[def_trap_handler]
+pub static USER_RETURN: [fn(&mut TrapFrame)];
+
 #[allow(unused_macros)]
 macro_rules! handle_trap {
     ($trap:ident, $($args:tt)*) => {{
@@ -43,3 +50,11 @@ macro_rules! handle_trap {
 pub(crate) fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
     SYSCALL[0](tf, syscall_num)
 }
+
+/// Call all the handlers registered to run before returning to user space.
+#[cfg(feature = "uspace")]
+pub(crate) fn handle_user_return(tf: &mut TrapFrame) {
+    for handler in USER_RETURN {
+        handler(tf);
+    }
+}
@@ -1,24 +1,50 @@
 use core::fmt;
 
-use crate::backend::Backend;
+use crate::backend::{
+    is_populated, populated_frame, share_page, split_huge_page_at, split_huge_pages, Backend,
+    FileCache, SharedPages,
+};
 use crate::mapping_err_to_ax_err;
 use crate::paging_err_to_ax_err;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use axerrno::{ax_err, AxError, AxResult};
+use axfs_vfs::VfsNodeRef;
 use axhal::{
     mem::phys_to_virt,
-    paging::{MappingFlags, PageTable},
+    paging::{for_each_page, MappingFlags, PageSize, PageTable},
 };
 use memory_addr::{
-    is_aligned_4k, pa, MemoryAddr, PageIter4K, PhysAddr, VirtAddr, VirtAddrRange, PAGE_SIZE_4K,
+    is_aligned_4k, MemoryAddr, PageIter4K, PhysAddr, VirtAddr, VirtAddrRange, PAGE_SIZE_4K,
 };
 use memory_set::{MemoryArea, MemorySet};
 
+/// Information about a mapped area, see [`AddrSpace::areas`].
+#[derive(Debug, Clone, Copy)]
+pub struct AreaInfo {
+    /// The start address of the area.
+    pub start: VirtAddr,
+    /// The end address (exclusive) of the area.
+    pub end: VirtAddr,
+    /// The mapping flags of the area.
+    pub flags: MappingFlags,
+    /// The file offset mapped at `start`, or `None` if the area is not
+    /// file-backed.
+    pub file_offset: Option<usize>,
+    /// Whether the area is a shared mapping.
+    pub shared: bool,
+}
+
 /// The virtual memory address space.
 pub struct AddrSpace {
     va_range: VirtAddrRange,
     areas: MemorySet<Backend>,
     pt: PageTable,
+    /// Where the search for free areas starts, see [`AddrSpace::mmap_base`].
+    mmap_base: VirtAddr,
+    /// Where the clock stopped last time, see [`AddrSpace::reclaim`].
+    #[cfg(feature = "swap")]
+    clock_hand: VirtAddr,
 }
 
 impl AddrSpace {
@@ -37,6 +63,21 @@ impl AddrSpace {
         self.va_range.size()
     }
 
+    /// Returns where mappings without a given address are placed, i.e., the
+    /// hint of [`AddrSpace::find_free_area`] for `mmap` and the like.
+    ///
+    /// It is the address space base by default, and is usually set (and
+    /// randomized) by the program loader.
+    pub const fn mmap_base(&self) -> VirtAddr {
+        self.mmap_base
+    }
+
+    /// Sets the base of the mappings without a given address, see
+    /// [`AddrSpace::mmap_base`].
+    pub fn set_mmap_base(&mut self, mmap_base: VirtAddr) {
+        self.mmap_base = mmap_base;
+    }
+
     /// Returns the reference to the inner page table.
     pub const fn page_table(&self) -> &PageTable {
         &self.pt
@@ -59,6 +100,9 @@ impl AddrSpace {
             va_range: VirtAddrRange::from_start_size(base, size),
             areas: MemorySet::new(),
             pt: PageTable::try_new().map_err(|_| AxError::NoMemory)?,
+            mmap_base: base,
+            #[cfg(feature = "swap")]
+            clock_hand: base,
         })
     }
 
@@ -77,6 +121,146 @@ impl AddrSpace {
         Ok(())
     }
 
+    /// Duplicates all memory areas of this address space into `child` with
+    /// copy-on-write.
+    ///
+    /// The populated pages are shared with `child` instead of being copied.
+    /// Writable ones are made read-only in both address spaces, and will be
+    /// copied on the first write to them (see [`AddrSpace::handle_page_fault`]).
+    /// Pages of shared file mappings and shared memory are simply shared with
+    /// the same flags. Huge pages are shared (and copied on write) as a whole,
+    /// so they are not split. Linear mappings are mapped to the same physical
+    /// memory in `child`, and are not copy-on-write.
+    ///
+    /// Returns an error if `child` already has mappings in the same range.
+    pub fn clone_areas_cow(&mut self, child: &mut AddrSpace) -> AxResult {
+        for area in self.areas.iter() {
+            // Frames of the child are shared rather than allocated, so the
+            // child area is always mapped lazily.
+            let backend = match *area.backend() {
+                Backend::Alloc { huge, .. } => Backend::Alloc {
+                    populate: false,
+                    huge,
+                },
+                ref backend => backend.clone(),
+            };
+            let new_area = MemoryArea::new(area.start(), area.size(), area.flags(), backend);
+            child
+                .areas
+                .map(new_area, &mut child.pt, false)
+                .map_err(mapping_err_to_ax_err)?;
+            // The linear mapping is fully mapped in `child` by the backend.
+            if matches!(area.backend(), Backend::Linear { .. }) {
+                continue;
+            }
+
+            let mut vaddr = area.start();
+            while vaddr < area.end() {
+                #[cfg(feature = "swap")]
+                if area.backend().is_alloc()
+                    && !crate::swap::ensure_resident(&mut self.pt, vaddr, area.flags())
+                {
+                    return ax_err!(NoMemory, "failed to swap in");
+                }
+                let Some((frame, flags, page_size)) = populated_frame(&self.pt, vaddr) else {
+                    vaddr += PAGE_SIZE_4K; // not populated yet
+                    continue;
+                };
+                let cow_flags = if area.backend().is_shared() {
+                    flags
+                } else {
+                    flags - MappingFlags::WRITE
+                };
+                if cow_flags != flags {
+                    self.pt
+                        .remap(vaddr, frame, cow_flags)
+                        .map_err(paging_err_to_ax_err)?
+                        .1
+                        .flush();
+                }
+                child
+                    .pt
+                    .map(vaddr, frame, page_size, cow_flags)
+                    .map_err(paging_err_to_ax_err)?
+                    .ignore();
+                share_page(frame, page_size);
+                vaddr += usize::from(page_size);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the information of all mapped areas, in ascending order of
+    /// their addresses.
+    pub fn areas(&self) -> impl Iterator<Item = AreaInfo> + '_ {
+        self.areas.iter().map(|area| {
+            let file_offset = match *area.backend() {
+                Backend::FileBacked {
+                    file_offset,
+                    area_start,
+                    ..
+                } => Some(file_offset + (area.start().as_usize() - area_start.as_usize())),
+                _ => None,
+            };
+            AreaInfo {
+                start: area.start(),
+                end: area.end(),
+                flags: area.flags(),
+                file_offset,
+                shared: area.backend().is_shared(),
+            }
+        })
+    }
+
+    /// Returns the ranges of the areas that remain of the shared memory
+    /// mapping of `pages` added at `area_start` by [`AddrSpace::map_shared`],
+    /// which may have been split or partially unmapped since.
+    pub fn shared_areas<'a>(
+        &'a self,
+        area_start: VirtAddr,
+        pages: &'a Arc<SharedPages>,
+    ) -> impl Iterator<Item = VirtAddrRange> + 'a {
+        self.areas
+            .iter()
+            .filter(move |area| match area.backend() {
+                Backend::Shared {
+                    pages: p,
+                    area_start: s,
+                    ..
+                } => *s == area_start && Arc::ptr_eq(p, pages),
+                _ => false,
+            })
+            .map(|area| area.va_range())
+    }
+
+    /// Returns the total size of the pages that are backed by physical frames
+    /// (i.e., the resident set size).
+    ///
+    /// Only the populated parts of the page table are walked, so large lazy
+    /// areas that are barely touched cost little.
+    pub fn resident_size(&self) -> usize {
+        let mut total = 0;
+        for area in self.areas.iter() {
+            for_each_page(&self.pt, area.va_range(), |vaddr, frame, flags, size| {
+                if is_populated(frame, flags) {
+                    // Only the part of a huge page within the area counts.
+                    total += (vaddr + size.into()).min(area.end()) - vaddr.max(area.start());
+                }
+            });
+        }
+        total
+    }
+
+    /// Removes all mappings in the address space.
+    ///
+    /// A failure (e.g., writing back a dirty page of a file mapping) is only
+    /// logged, as the mappings are removed when the process exits.
+    pub fn clear(&mut self) {
+        if let Err(err) = self.areas.clear(&mut self.pt) {
+            error!("failed to clear the address space: {:?}", err);
+        }
+    }
+
     /// Finds a free area that can accommodate the given size.
     ///
     /// The search starts from the given hint address, and the area should be within the given limit range.
@@ -94,7 +278,8 @@ impl AddrSpace {
     /// Add a new linear mapping.
     ///
     /// The mapping is linear, i.e., `start_vaddr` is mapped to `start_paddr`,
-    /// and `start_vaddr + size` is mapped to `start_paddr + size`.
+    /// and `start_vaddr + size` is mapped to `start_paddr + size`. Huge pages
+    /// are used where both addresses are aligned to them.
     ///
     /// The `flags` parameter indicates the mapping permissions and attributes.
     ///
@@ -115,23 +300,20 @@ impl AddrSpace {
         }
 
         let offset = start_vaddr.as_usize() - start_paddr.as_usize();
-        self.pt
-            .map_region(
-                start_vaddr,
-                |va| pa!(va.as_usize() - offset),
-                size,
-                flags,
-                false, // allow_huge
-                false, // flush_tlb_by_page
-            )
-            .map_err(paging_err_to_ax_err)?
-            .flush_all();
+        let area = MemoryArea::new(start_vaddr, size, flags, Backend::new_linear(offset));
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
         Ok(())
     }
 
     /// Add a new allocation mapping.
     ///
-    /// See [`Backend`] for more details about the mapping backends.
+    /// If `populate` is `true`, the frames are allocated at once, in huge
+    /// pages where the alignment allows. Otherwise they are allocated on
+    /// demand, in 4 KiB pages unless huge pages are enabled later by
+    /// [`AddrSpace::advise_huge_pages`]. See [`Backend`] for more details
+    /// about the mapping backends.
     ///
     /// The `flags` parameter indicates the mapping permissions and attributes.
     ///
@@ -158,22 +340,121 @@ impl AddrSpace {
         Ok(())
     }
 
+    /// Add a new file-backed mapping, where `file_offset` of `file` is
+    /// mapped at `vaddr`.
+    ///
+    /// `path` is the absolute path of the file, by which the cached pages of
+    /// the file are found and shared between the mappings of the file. Other
+    /// accesses to the file are to be reported to the cache, see
+    /// [`write_file_cache`](crate::write_file_cache) and the functions next to
+    /// it. Pages cached before the size of the file changes without being
+    /// reported are not shared with the new mapping.
+    ///
+    /// The pages are read from the file on demand. If `shared` is `true`, the
+    /// frames are shared with other shared mappings of the same file pages,
+    /// and written pages are written back to the file (see
+    /// [`AddrSpace::sync`]). Otherwise, the mapping is private, and the pages
+    /// are shared with other mappings of the file until they are written.
+    ///
+    /// Returns an error if the address range is out of the address space or not
+    /// aligned.
     pub fn mmap_file(
         &mut self,
         vaddr: VirtAddr,
         length: usize,
         prot: MappingFlags,
+        file: VfsNodeRef,
+        path: &str,
         file_offset: usize,
-        reader: alloc::sync::Arc<dyn Fn(usize, &mut [u8]) -> bool + Send + Sync>,
+        shared: bool,
     ) -> AxResult {
-        let backend = Backend::new_file_backed(reader, file_offset, vaddr);
+        if !self.contains_range(vaddr, length) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !vaddr.is_aligned_4k() || !is_aligned_4k(length) || !is_aligned_4k(file_offset) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+
+        let cache = FileCache::of_path(path, &file);
+        let backend = Backend::new_file_backed(file, cache, file_offset, vaddr, shared);
         let area = MemoryArea::new(vaddr, length, prot, backend);
-        self.areas.map(area, &mut self.pt, false).map_err(mapping_err_to_ax_err)?;
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
+        Ok(())
+    }
+
+    /// Add a new shared memory mapping, where the byte `offset` of `pages` is
+    /// mapped at `vaddr`.
+    ///
+    /// The pages are mapped on demand, to the same frames as all other
+    /// mappings of `pages`. Accessing the pages beyond the end of `pages` is a
+    /// real fault.
+    ///
+    /// Returns an error if the address range is out of the address space or not
+    /// aligned.
+    pub fn map_shared(
+        &mut self,
+        vaddr: VirtAddr,
+        length: usize,
+        prot: MappingFlags,
+        pages: Arc<SharedPages>,
+        offset: usize,
+    ) -> AxResult {
+        if !self.contains_range(vaddr, length) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !vaddr.is_aligned_4k() || !is_aligned_4k(length) || !is_aligned_4k(offset) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+
+        let backend = Backend::new_shared(pages, offset, vaddr);
+        let area = MemoryArea::new(vaddr, length, prot, backend);
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
+        Ok(())
+    }
+
+    /// Writes the written pages of the shared file mappings within the
+    /// specified virtual address range back to the files.
+    ///
+    /// The pages are clean after that, unless they are also mapped by other
+    /// address spaces.
+    ///
+    /// Returns an error if the address range is out of the address space, not
+    /// aligned, or not fully mapped.
+    pub fn sync(&mut self, start: VirtAddr, size: usize) -> AxResult {
+        if !self.contains_range(start, size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !start.is_aligned_4k() || !is_aligned_4k(size) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+        if !self.is_range_mapped(start, size) {
+            return ax_err!(NoMemory, "address not mapped");
+        }
+
+        let end = start + size;
+        for area in self.areas.iter() {
+            let sync_start = area.start().max(start);
+            let sync_end = area.end().min(end);
+            if sync_start < sync_end
+                && !area
+                    .backend()
+                    .sync(sync_start, sync_end - sync_start, &mut self.pt)
+            {
+                return ax_err!(Io, "failed to write back");
+            }
+        }
         Ok(())
     }
 
     /// Removes mappings within the specified virtual address range.
     ///
+    /// Areas partially covered by the range are shrunk or split, and holes in
+    /// the range are skipped.
+    ///
     /// Returns an error if the address range is out of the address space or not
     /// aligned.
     pub fn unmap(&mut self, start: VirtAddr, size: usize) -> AxResult {
@@ -184,17 +465,165 @@ impl AddrSpace {
             return ax_err!(InvalidInput, "address not aligned");
         }
 
-        self.pt
-            .unmap_region(start, size, true)
-            .map_err(paging_err_to_ax_err)?
-            .ignore();
+        self.areas
+            .unmap(start, size, &mut self.pt)
+            .map_err(mapping_err_to_ax_err)?;
+        Ok(())
+    }
+
+    /// Grows the area ending at `end` by `size` bytes in place.
+    ///
+    /// The new pages are mapped with the flags and the backend of the area,
+    /// so that they behave as part of it (e.g., are faulted in as huge pages
+    /// after [`AddrSpace::advise_huge_pages`]). They are kept as a separate
+    /// area internally, as areas cannot be resized in place.
+    ///
+    /// Returns an error if no area (other than a linear mapping) ends at
+    /// `end`, or the following range is out of the address space, not
+    /// aligned, or overlaps another area.
+    pub fn grow_area(&mut self, end: VirtAddr, size: usize) -> AxResult {
+        if !self.contains_range(end, size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !end.is_aligned_4k() || !is_aligned_4k(size) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+        let (flags, backend) = match self.areas.find(end.wrapping_sub(1)) {
+            Some(area)
+                if area.end() == end && !matches!(area.backend(), Backend::Linear { .. }) =>
+            {
+                (area.flags(), area.backend().clone())
+            }
+            _ => return ax_err!(BadAddress, "no area to grow"),
+        };
+        if self
+            .areas
+            .overlaps(VirtAddrRange::from_start_size(end, size))
+        {
+            return ax_err!(AlreadyExists, "overlaps another area");
+        }
+        // The backend works for the following pages as well.
+        let area = MemoryArea::new(end, size, flags, backend);
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
         Ok(())
     }
 
+    /// Resizes the mapping `[old_start, old_start + old_size)` to `new_size`.
+    ///
+    /// The mapping is shrunk or expanded in place if possible. Otherwise, if
+    /// `may_move` is `true`, it is moved to a free area together with the
+    /// populated pages.
+    ///
+    /// Returns the start address of the resized mapping. Returns an error if
+    /// the old range is not within a single area (or is a linear mapping), or
+    /// there is no room for the new one.
+    pub fn remap(
+        &mut self,
+        old_start: VirtAddr,
+        old_size: usize,
+        new_size: usize,
+        may_move: bool,
+    ) -> AxResult<VirtAddr> {
+        if !self.contains_range(old_start, old_size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !old_start.is_aligned_4k() || !is_aligned_4k(old_size) || !is_aligned_4k(new_size) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+        if old_size == 0 || new_size == 0 {
+            return ax_err!(InvalidInput, "empty mapping");
+        }
+        let old_end = old_start + old_size;
+        let (flags, backend) = match self.areas.find(old_start) {
+            Some(area)
+                if old_end <= area.end() && !matches!(area.backend(), Backend::Linear { .. }) =>
+            {
+                (area.flags(), area.backend().clone())
+            }
+            _ => return ax_err!(BadAddress, "not in a single area"),
+        };
+
+        if new_size <= old_size {
+            self.unmap(old_start + new_size, old_size - new_size)?;
+            return Ok(old_start);
+        }
+        let grow_size = new_size - old_size;
+        if self.contains_range(old_end, grow_size)
+            && !self
+                .areas
+                .overlaps(VirtAddrRange::from_start_size(old_end, grow_size))
+        {
+            self.grow_area(old_end, grow_size)?;
+            return Ok(old_start);
+        }
+        if !may_move {
+            return ax_err!(NoMemory, "cannot expand in place");
+        }
+
+        let new_start = self
+            .find_free_area(self.mmap_base, new_size, self.va_range)
+            .ok_or(AxError::NoMemory)?;
+        let new_backend = match backend {
+            Backend::FileBacked {
+                file,
+                cache,
+                file_offset,
+                area_start,
+                shared,
+            } => {
+                let file_offset = file_offset + (old_start - area_start);
+                Backend::new_file_backed(file, cache, file_offset, new_start, shared)
+            }
+            Backend::Shared {
+                pages,
+                offset,
+                area_start,
+            } => Backend::new_shared(pages, offset + (old_start - area_start), new_start),
+            // Moved pages are mapped by hand, the rest are faulted in.
+            Backend::Alloc { huge, .. } => Backend::Alloc {
+                populate: false,
+                huge,
+            },
+            Backend::Linear { .. } => unreachable!(),
+        };
+        let area = MemoryArea::new(new_start, new_size, flags, new_backend);
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)?;
+        // Pages are moved one by one.
+        if !split_huge_pages(&mut self.pt, old_start, old_end) {
+            return ax_err!(NoMemory, "failed to split huge pages");
+        }
+        for (i, vaddr) in PageIter4K::new(old_start, old_end).unwrap().enumerate() {
+            #[cfg(feature = "swap")]
+            if !crate::swap::ensure_resident(&mut self.pt, vaddr, flags) {
+                return ax_err!(NoMemory, "failed to swap in");
+            }
+            let Some((frame, page_flags, _)) = populated_frame(&self.pt, vaddr) else {
+                continue;
+            };
+            // Take the page out of the old mapping, so that the frame is not
+            // freed when the old mapping is removed.
+            if let Ok((_, _, tlb)) = self.pt.unmap(vaddr) {
+                tlb.flush();
+            }
+            let new_vaddr = new_start + i * PAGE_SIZE_4K;
+            self.pt
+                .map(new_vaddr, frame, PageSize::Size4K, page_flags)
+                .map_err(paging_err_to_ax_err)?
+                .ignore();
+        }
+        self.unmap(old_start, old_size)?;
+        Ok(new_start)
+    }
+
     /// To process data in this area with the given function.
     ///
     /// Now it supports reading and writing data in the given interval.
-    fn process_area_data<F>(&self, start: VirtAddr, size: usize, mut f: F) -> AxResult
+    /// Swapped-out pages are swapped in first.
+    fn process_area_data<F>(&mut self, start: VirtAddr, size: usize, mut f: F) -> AxResult
     where
         F: FnMut(VirtAddr, usize, usize),
     {
@@ -207,6 +636,12 @@ impl AddrSpace {
         for vaddr in PageIter4K::new(start.align_down_4k(), end_align_up)
             .expect("Failed to create page iterator")
         {
+            #[cfg(feature = "swap")]
+            if let Some(area) = self.areas.find(vaddr) {
+                if !crate::swap::ensure_resident(&mut self.pt, vaddr, area.flags()) {
+                    return ax_err!(NoMemory, "failed to swap in");
+                }
+            }
             let (mut paddr, _, _) = self.pt.query(vaddr).map_err(|_| AxError::BadAddress)?;
 
             let mut copy_size = (size - cnt).min(PAGE_SIZE_4K);
@@ -231,7 +666,7 @@ impl AddrSpace {
     ///
     /// * `start` - The start virtual address to read.
     /// * `buf` - The buffer to store the data.
-    pub fn read(&self, start: VirtAddr, buf: &mut [u8]) -> AxResult {
+    pub fn read(&mut self, start: VirtAddr, buf: &mut [u8]) -> AxResult {
         self.process_area_data(start, buf.len(), |src, offset, read_size| unsafe {
             core::ptr::copy_nonoverlapping(src.as_ptr(), buf.as_mut_ptr().add(offset), read_size);
         })
@@ -243,7 +678,7 @@ impl AddrSpace {
     ///
     /// * `start_vaddr` - The start virtual address to write.
     /// * `buf` - The buffer to write to the address space.
-    pub fn write(&self, start: VirtAddr, buf: &[u8]) -> AxResult {
+    pub fn write(&mut self, start: VirtAddr, buf: &[u8]) -> AxResult {
         self.process_area_data(start, buf.len(), |dst, offset, write_size| unsafe {
             core::ptr::copy_nonoverlapping(buf.as_ptr().add(offset), dst.as_mut_ptr(), write_size);
         })
@@ -251,8 +686,11 @@ impl AddrSpace {
 
     /// Updates mapping within the specified virtual address range.
     ///
-    /// Returns an error if the address range is out of the address space or not
-    /// aligned.
+    /// Areas partially covered by the range are split, so that only the pages
+    /// in the range get the new flags.
+    ///
+    /// Returns an error if the address range is out of the address space, not
+    /// aligned, or not fully mapped.
     pub fn protect(&mut self, start: VirtAddr, size: usize, flags: MappingFlags) -> AxResult {
         if !self.contains_range(start, size) {
             return ax_err!(InvalidInput, "address out of range");
@@ -260,14 +698,106 @@ impl AddrSpace {
         if !start.is_aligned_4k() || !is_aligned_4k(size) {
             return ax_err!(InvalidInput, "address not aligned");
         }
+        if !self.is_range_mapped(start, size) {
+            return ax_err!(NoMemory, "address not mapped");
+        }
+
+        self.areas
+            .protect(start, size, |_| Some(flags), &mut self.pt)
+            .map_err(mapping_err_to_ax_err)?;
+        Ok(())
+    }
+
+    /// Sets whether the pages of the lazy allocation mappings within the
+    /// specified virtual address range are allocated as 2 MiB huge pages,
+    /// like `madvise` with `MADV_HUGEPAGE` or `MADV_NOHUGEPAGE`.
+    ///
+    /// With huge pages, a page fault maps the whole huge page around the fault
+    /// address if it is within the area and none of its pages has been
+    /// mapped yet. The pages already mapped are kept as they are. Other kinds
+    /// of mappings in the range are left alone, as populated mappings use huge
+    /// pages anyway.
+    ///
+    /// Returns an error if the address range is out of the address space, not
+    /// aligned, or not fully mapped.
+    pub fn advise_huge_pages(&mut self, start: VirtAddr, size: usize, huge: bool) -> AxResult {
+        if !self.contains_range(start, size) {
+            return ax_err!(InvalidInput, "address out of range");
+        }
+        if !start.is_aligned_4k() || !is_aligned_4k(size) {
+            return ax_err!(InvalidInput, "address not aligned");
+        }
+        if !self.is_range_mapped(start, size) {
+            return ax_err!(NoMemory, "address not mapped");
+        }
 
-        self.pt
-            .protect_region(start, size, flags, true)
-            .map_err(paging_err_to_ax_err)?
-            .ignore();
+        let end = start + size;
+        let parts: Vec<_> = self
+            .areas
+            .iter()
+            .filter(|area| {
+                matches!(*area.backend(), Backend::Alloc { populate: false, huge: h } if h != huge)
+            })
+            .map(|area| (area.start().max(start), area.end().min(end), area.flags()))
+            .filter(|(part_start, part_end, _)| part_start < part_end)
+            .collect();
+        // The backend of an area cannot be changed, so the part in the range
+        // is replaced by a new area, and the pages are moved to it.
+        for (part_start, part_end, flags) in parts {
+            if !split_huge_page_at(&mut self.pt, part_start)
+                || !split_huge_page_at(&mut self.pt, part_end)
+            {
+                return ax_err!(NoMemory, "failed to split huge pages");
+            }
+            // Take the entries (including swapped-out ones) out of the old
+            // area, so that the pages are not freed when it is removed.
+            let mut entries = Vec::new();
+            let mut vaddr = part_start;
+            while vaddr < part_end {
+                let mut page_size = PageSize::Size4K;
+                if let Ok((paddr, page_flags, size)) = self.pt.query(vaddr) {
+                    if let Ok((_, _, tlb)) = self.pt.unmap(vaddr) {
+                        tlb.flush();
+                    }
+                    entries.push((vaddr, paddr, page_flags, size));
+                    page_size = size;
+                }
+                vaddr += usize::from(page_size);
+            }
+            self.areas
+                .unmap(part_start, part_end - part_start, &mut self.pt)
+                .map_err(mapping_err_to_ax_err)?;
+            let backend = Backend::Alloc {
+                populate: false,
+                huge,
+            };
+            let area = MemoryArea::new(part_start, part_end - part_start, flags, backend);
+            self.areas
+                .map(area, &mut self.pt, false)
+                .map_err(mapping_err_to_ax_err)?;
+            for (vaddr, paddr, page_flags, page_size) in entries {
+                self.pt
+                    .map(vaddr, paddr, page_size, page_flags)
+                    .map_err(paging_err_to_ax_err)?
+                    .ignore();
+            }
+        }
         Ok(())
     }
 
+    /// Checks if the given address range is fully covered by memory areas.
+    fn is_range_mapped(&self, start: VirtAddr, size: usize) -> bool {
+        let end = start + size;
+        let mut vaddr = start;
+        while vaddr < end {
+            match self.areas.find(vaddr) {
+                Some(area) => vaddr = area.end(),
+                None => return false,
+            }
+        }
+        true
+    }
+
     /// Handles a page fault at the given address.
     ///
     /// `access_flags` indicates the access type that caused the page fault.
@@ -278,17 +808,142 @@ impl AddrSpace {
         if !self.va_range.contains(vaddr) {
             return false;
         }
+        #[cfg(feature = "swap")]
+        crate::swap::reclaim_if_low(self);
         if let Some(area) = self.areas.find(vaddr) {
             let orig_flags = area.flags();
             if orig_flags.contains(access_flags) {
-                return area
-                    .backend()
-                    .handle_page_fault(vaddr, orig_flags, &mut self.pt);
+                // The page may have been mapped again since the fault, e.g.,
+                // when another CPU split the huge page containing it.
+                if self
+                    .pt
+                    .query(vaddr)
+                    .is_ok_and(|(_, flags, _)| flags.contains(access_flags))
+                {
+                    return true;
+                }
+                #[cfg(feature = "swap")]
+                if area.backend().is_alloc() {
+                    match crate::swap::fault_in(&mut self.pt, vaddr, orig_flags) {
+                        Some(false) => return false,
+                        // Writes to pages still shared are handled below.
+                        Some(true)
+                            if self
+                                .pt
+                                .query(vaddr)
+                                .is_ok_and(|(_, flags, _)| flags.contains(access_flags)) =>
+                        {
+                            return true
+                        }
+                        _ => {}
+                    }
+                }
+                // Writes to file mappings are handled by the backend, as the
+                // frames may be in the page cache.
+                if access_flags.contains(MappingFlags::WRITE) && area.backend().is_alloc() {
+                    // The frame of the page, rather than of the fault address.
+                    let page = vaddr.align_down_4k();
+                    if let Ok((frame, flags, page_size)) = self.pt.query(page) {
+                        if !flags.is_empty() && !flags.contains(MappingFlags::WRITE) {
+                            // A copy-on-write page shared with other address spaces.
+                            if page_size.is_huge() {
+                                return Backend::handle_huge_cow_fault(
+                                    page,
+                                    frame,
+                                    page_size,
+                                    orig_flags,
+                                    &mut self.pt,
+                                );
+                            }
+                            return Backend::handle_cow_fault(
+                                page,
+                                frame,
+                                orig_flags,
+                                &mut self.pt,
+                            );
+                        }
+                    }
+                }
+                if let Backend::Alloc {
+                    populate: false,
+                    huge: true,
+                } = area.backend()
+                {
+                    if Backend::handle_huge_page_fault(
+                        vaddr,
+                        area.va_range(),
+                        orig_flags,
+                        &mut self.pt,
+                    ) {
+                        return true;
+                    }
+                }
+                return area.backend().handle_page_fault(
+                    vaddr,
+                    access_flags,
+                    orig_flags,
+                    &mut self.pt,
+                );
             }
         }
         false
     }
 
+    /// Swaps out up to `max` pages of the lazily allocated areas, returns the
+    /// number of pages swapped out.
+    ///
+    /// The clock hand sweeps the pages in address order from where it stopped
+    /// last time, at most twice around. An accessible page is made
+    /// inaccessible, and swapped out if it is still inaccessible (i.e., not
+    /// accessed) the next time the hand comes to it.
+    #[cfg(feature = "swap")]
+    pub(crate) fn reclaim(&mut self, max: usize) -> usize {
+        if max == 0 {
+            return 0;
+        }
+        let accessible = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE;
+        let ranges: Vec<_> = self
+            .areas
+            .iter()
+            .filter(|area| area.backend().is_alloc() && area.flags().intersects(accessible))
+            .map(|area| (area.start(), area.end()))
+            .collect();
+        let hand = self.clock_hand;
+        // Start from the area containing the hand (or the next one), and come
+        // back to the pages before the hand at last.
+        let first = ranges.iter().position(|r| r.1 > hand).unwrap_or(0);
+        let mut sweep = Vec::with_capacity(ranges.len() + 1);
+        for i in 0..ranges.len() {
+            let (start, end) = ranges[(first + i) % ranges.len()];
+            if i == 0 {
+                sweep.push((start.max(hand).min(end), end));
+            } else {
+                sweep.push((start, end));
+            }
+        }
+        if let Some(&(start, end)) = ranges.get(first) {
+            if hand > start {
+                sweep.push((start, hand.min(end)));
+            }
+        }
+
+        let mut reclaimed = 0;
+        for _ in 0..2 {
+            for &(start, end) in &sweep {
+                for vaddr in PageIter4K::new(start, end).unwrap() {
+                    if crate::swap::clock_visit(&mut self.pt, vaddr) {
+                        reclaimed += 1;
+                        if reclaimed == max {
+                            self.clock_hand = vaddr + PAGE_SIZE_4K;
+                            return reclaimed;
+                        }
+                    }
+                }
+            }
+        }
+        reclaimed
+    }
+
     pub fn translated_byte_buffer(
         &self,
         vaddr: VirtAddr,
@@ -338,6 +993,14 @@ impl AddrSpace {
     }
 }
 
+impl Drop for AddrSpace {
+    fn drop(&mut self) {
+        if let Err(err) = self.areas.clear(&mut self.pt) {
+            error!("failed to clear the address space: {:?}", err);
+        }
+    }
+}
+
 impl fmt::Debug for AddrSpace {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("AddrSpace")
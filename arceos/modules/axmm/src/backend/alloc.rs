@@ -1,9 +1,92 @@
+use alloc::collections::BTreeMap;
+
 use axalloc::global_allocator;
 use axhal::mem::{phys_to_virt, virt_to_phys};
-use axhal::paging::{MappingFlags, PageSize, PageTable};
-use memory_addr::{PageIter4K, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+use axhal::paging::{MappingFlags, PageSize, PageTable, PagingError};
+use kspin::SpinNoIrq;
+use memory_addr::{MemoryAddr, PageIter4K, PhysAddr, VirtAddr, VirtAddrRange, PAGE_SIZE_4K};
+
+use super::{split_huge_page, split_huge_page_at, Backend};
+
+/// Reference counts of the frames shared by more than one mapping (e.g.,
+/// copy-on-write pages after fork).
+///
+/// Frames not in the map have exactly one owner. A huge page holds a
+/// reference to each 4 KiB frame of its huge frame, so that the references
+/// stay right when it is split.
+struct FrameRefs(BTreeMap<PhysAddr, usize>);
+
+impl FrameRefs {
+    const fn new() -> Self {
+        Self(BTreeMap::new())
+    }
 
-use super::Backend;
+    /// Adds a reference to the 4 KiB frame.
+    fn share(&mut self, frame: PhysAddr) {
+        *self.0.entry(frame).or_insert(1) += 1;
+    }
+
+    /// Adds a reference to each 4 KiB frame of the page of `size`.
+    fn share_page(&mut self, frame: PhysAddr, size: usize) {
+        for offset in (0..size).step_by(PAGE_SIZE_4K) {
+            self.share(frame + offset);
+        }
+    }
+
+    /// Drops a reference to the 4 KiB frame, and returns whether it was the
+    /// last one.
+    fn release(&mut self, frame: PhysAddr) -> bool {
+        let Some(count) = self.0.get_mut(&frame) else {
+            return true;
+        };
+        *count -= 1;
+        if *count == 1 {
+            self.0.remove(&frame);
+        }
+        false
+    }
+
+    /// Drops a reference to each 4 KiB frame of the page of `size`, and calls
+    /// `free` with the frames (and their numbers of 4 KiB frames) whose last
+    /// references were dropped.
+    ///
+    /// A page none of whose frames is shared is freed as a whole.
+    fn release_page(
+        &mut self,
+        frame: PhysAddr,
+        size: usize,
+        mut free: impl FnMut(PhysAddr, usize),
+    ) {
+        if !self.is_shared(frame, size) {
+            free(frame, size / PAGE_SIZE_4K);
+            return;
+        }
+        for offset in (0..size).step_by(PAGE_SIZE_4K) {
+            if self.release(frame + offset) {
+                free(frame + offset, 1);
+            }
+        }
+    }
+
+    /// Returns the number of references to the 4 KiB frame.
+    fn count(&self, frame: PhysAddr) -> usize {
+        self.0.get(&frame).copied().unwrap_or(1)
+    }
+
+    /// Whether any 4 KiB frame of the page of `size` is shared.
+    fn is_shared(&self, frame: PhysAddr, size: usize) -> bool {
+        self.0.range(frame..frame + size).next().is_some()
+    }
+}
+
+/// Reference counts of the frames of all address spaces.
+static SHARED_FRAMES: SpinNoIrq<FrameRefs> = SpinNoIrq::new(FrameRefs::new());
+
+/// Frees `num_frames` contiguous 4 KiB frames starting at `frame`.
+fn free_frames(frame: PhysAddr, num_frames: usize) {
+    let vaddr = phys_to_virt(frame);
+    global_allocator().dealloc_pages(vaddr.as_usize(), num_frames);
+}
 
 pub fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
     let vaddr = VirtAddr::from(global_allocator().alloc_pages(1, PAGE_SIZE_4K).ok()?);
@@ -14,15 +97,129 @@ pub fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
     Some(paddr)
 }
 
-fn dealloc_frame(frame: PhysAddr) {
-    let vaddr = phys_to_virt(frame);
-    global_allocator().dealloc_pages(vaddr.as_usize(), 1);
+/// Drops a reference to the frame, and deallocates it if it was the last one.
+pub(crate) fn dealloc_frame(frame: PhysAddr) {
+    let last = SHARED_FRAMES.lock().release(frame);
+    if last {
+        free_frames(frame, 1);
+    }
+}
+
+/// Allocates a huge frame of `page_size`, which is physically contiguous and
+/// aligned to its size.
+fn alloc_huge_frame(page_size: PageSize, zeroed: bool) -> Option<PhysAddr> {
+    let size = usize::from(page_size);
+    let vaddr = VirtAddr::from(
+        global_allocator()
+            .alloc_pages(size / PAGE_SIZE_4K, size)
+            .ok()?,
+    );
+    let paddr = virt_to_phys(vaddr);
+    if !paddr.is_aligned(size) {
+        global_allocator().dealloc_pages(vaddr.as_usize(), size / PAGE_SIZE_4K);
+        return None;
+    }
+    if zeroed {
+        unsafe { core::ptr::write_bytes(vaddr.as_mut_ptr(), 0, size) };
+    }
+    Some(paddr)
+}
+
+/// Drops a reference to each 4 KiB frame of a huge frame of `page_size`, and
+/// deallocates those that were the last ones.
+fn dealloc_huge_frame(frame: PhysAddr, page_size: PageSize) {
+    SHARED_FRAMES
+        .lock()
+        .release_page(frame, page_size.into(), free_frames);
+}
+
+/// Deallocates the frame of a page of `page_size`.
+fn dealloc_page(frame: PhysAddr, page_size: PageSize) {
+    if page_size.is_huge() {
+        dealloc_huge_frame(frame, page_size);
+    } else {
+        dealloc_frame(frame);
+    }
+}
+
+/// Allocates a zeroed frame for the largest page that starts at `vaddr` and
+/// ends before `end`, returns the frame and the size of the page.
+///
+/// Huge pages are tried first, and 4 KiB pages are used if the alignment does
+/// not allow them or there is not enough contiguous memory.
+fn alloc_page_at(vaddr: VirtAddr, end: VirtAddr) -> Option<(PhysAddr, PageSize)> {
+    for page_size in [PageSize::Size1G, PageSize::Size2M] {
+        if vaddr.is_aligned(page_size) && end - vaddr >= usize::from(page_size) {
+            if let Some(frame) = alloc_huge_frame(page_size, true) {
+                return Some((frame, page_size));
+            }
+        }
+    }
+    Some((alloc_frame(true)?, PageSize::Size4K))
+}
+
+/// Adds a reference to the frame, as it is going to be shared by one more
+/// mapping.
+pub(crate) fn share_frame(frame: PhysAddr) {
+    SHARED_FRAMES.lock().share(frame);
+}
+
+/// Returns the number of mappings that the frame is shared by.
+pub(crate) fn frame_ref_count(frame: PhysAddr) -> usize {
+    SHARED_FRAMES.lock().count(frame)
+}
+
+/// Adds a reference to each 4 KiB frame of the page of `page_size` mapped to
+/// `frame`, as it is going to be shared by one more mapping.
+pub(crate) fn share_page(frame: PhysAddr, page_size: PageSize) {
+    SHARED_FRAMES.lock().share_page(frame, page_size.into());
+}
+
+/// Whether any 4 KiB frame of the page of `page_size` mapped to `frame` is
+/// shared with other mappings.
+pub(crate) fn is_page_shared(frame: PhysAddr, page_size: PageSize) -> bool {
+    SHARED_FRAMES.lock().is_shared(frame, page_size.into())
+}
+
+/// Whether the page table entry of `frame` and `flags` (as returned by
+/// [`PageTable::query`]) is of a populated page.
+///
+/// Pages of lazy mappings have no entries until they are populated (see
+/// [`Backend::map_alloc`]), while inaccessible pages keep their frames with
+/// empty flags (see [`Backend::protect_alloc`]). Swapped-out pages are not
+/// populated either.
+pub(crate) fn is_populated(frame: PhysAddr, flags: MappingFlags) -> bool {
+    #[cfg(feature = "swap")]
+    if crate::swap::is_swap_entry(frame) {
+        return false;
+    }
+    !flags.is_empty() || frame.as_usize() != 0
+}
+
+/// Returns the frame, the flags and the size of the page mapped at `vaddr`,
+/// or `None` if the page is not populated yet (see [`is_populated`]).
+///
+/// For a huge page, the frame is the part of the huge frame mapped at
+/// `vaddr`.
+pub(crate) fn populated_frame(
+    pt: &PageTable,
+    vaddr: VirtAddr,
+) -> Option<(PhysAddr, MappingFlags, PageSize)> {
+    match pt.query(vaddr) {
+        Ok((frame, flags, page_size)) if is_populated(frame, flags) => {
+            Some((frame, flags, page_size))
+        }
+        _ => None,
+    }
 }
 
 impl Backend {
     /// Creates a new allocation mapping backend.
     pub const fn new_alloc(populate: bool) -> Self {
-        Self::Alloc { populate }
+        Self::Alloc {
+            populate,
+            huge: false,
+        }
     }
 
     pub(crate) fn map_alloc(
@@ -42,23 +239,26 @@ impl Backend {
         );
         if populate {
             // allocate all possible physical frames for populated mapping.
-            for addr in PageIter4K::new(start, start + size).unwrap() {
-                if let Some(frame) = alloc_frame(true) {
-                    if let Ok(tlb) = pt.map(addr, frame, PageSize::Size4K, flags) {
-                        tlb.ignore(); // TLB flush on map is unnecessary, as there are no outdated mappings.
-                    } else {
+            let end = start + size;
+            let mut addr = start;
+            while addr < end {
+                let Some((frame, page_size)) = alloc_page_at(addr, end) else {
+                    return false;
+                };
+                match pt.map(addr, frame, page_size, flags) {
+                    Ok(tlb) => tlb.ignore(), // TLB flush on map is unnecessary, as there are no outdated mappings.
+                    Err(_) => {
+                        dealloc_page(frame, page_size);
                         return false;
                     }
                 }
+                addr += usize::from(page_size);
             }
-            true
-        } else {
-            // Map to a empty entry for on-demand mapping.
-            let flags = MappingFlags::empty();
-            pt.map_region(start, |_| 0.into(), size, flags, false, false)
-                .map(|tlb| tlb.ignore())
-                .is_ok()
         }
+        // Pages of lazy mappings are mapped by the page fault handler. Page
+        // table entries are not created until then, so that a huge page can
+        // be mapped where no page has been accessed.
+        true
     }
 
     pub(crate) fn unmap_alloc(
@@ -69,18 +269,88 @@ impl Backend {
         _populate: bool,
     ) -> bool {
         debug!("unmap_alloc: [{:#x}, {:#x})", start, start + size);
-        for addr in PageIter4K::new(start, start + size).unwrap() {
-            if let Ok((frame, page_size, tlb)) = pt.unmap(addr) {
-                // Deallocate the physical frame if there is a mapping in the
-                // page table.
-                if page_size.is_huge() {
+        let end = start + size;
+        // Huge pages partially in the range are split first.
+        if !split_huge_page_at(pt, start) || !split_huge_page_at(pt, end) {
+            return false;
+        }
+        let mut addr = start;
+        while addr < end {
+            // Inaccessible pages are not present in the page table, so their
+            // frames are looked up before unmapping.
+            let populated = populated_frame(pt, addr);
+            #[cfg(feature = "swap")]
+            if let Some(slot) = crate::swap::swap_slot(pt, addr) {
+                crate::swap::free_slot(slot);
+            }
+            let mut page_size = PageSize::Size4K;
+            if let Ok((_, size, tlb)) = pt.unmap(addr) {
+                page_size = size;
+                tlb.flush();
+            }
+            // Deallocation is needn't if the page is not populated.
+            if let Some((frame, _, _)) = populated {
+                dealloc_page(frame, page_size);
+            }
+            addr += usize::from(page_size);
+        }
+        true
+    }
+
+    /// Changes the flags of the populated pages in `[start, start + size)`.
+    ///
+    /// Pages not populated yet are left alone, as they get the flags of the
+    /// area when they are faulted in. Frames still shared with copy-on-write
+    /// stay read-only, and inaccessible pages (without any of `READ`, `WRITE`
+    /// and `EXECUTE`) keep their frames with empty flags. Huge pages made
+    /// inaccessible are split, as only 4 KiB entries can keep their frames
+    /// without being present on all architectures.
+    pub(crate) fn protect_alloc(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        new_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        debug!(
+            "protect_alloc: [{:#x}, {:#x}) {:?}",
+            start,
+            start + size,
+            new_flags
+        );
+        let end = start + size;
+        if !split_huge_page_at(pt, start) || !split_huge_page_at(pt, end) {
+            return false;
+        }
+        let accessible =
+            new_flags.intersects(MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE);
+        let mut addr = start;
+        while addr < end {
+            let Some((frame, _, page_size)) = populated_frame(pt, addr) else {
+                addr += PAGE_SIZE_4K;
+                continue;
+            };
+            if page_size.is_huge() && !accessible {
+                if !split_huge_page(pt, addr) {
                     return false;
                 }
-                tlb.flush();
-                dealloc_frame(frame);
+                continue; // visit the 4 KiB pages again
+            }
+            let flags = if !accessible {
+                MappingFlags::empty()
+            } else if is_page_shared(frame, page_size) {
+                new_flags - MappingFlags::WRITE
             } else {
-                // Deallocation is needn't if the page is not mapped.
+                new_flags
+            };
+            if pt
+                .remap(addr, frame, flags)
+                .map(|(_, tlb)| tlb.flush())
+                .is_err()
+            {
+                return false;
             }
+            addr += usize::from(page_size);
         }
         true
     }
@@ -93,16 +363,257 @@ impl Backend {
         populate: bool,
     ) -> bool {
         if populate {
-            false // Populated mappings should not trigger page faults.
-        } else if let Some(frame) = alloc_frame(true) {
-            // Allocate a physical frame lazily and map it to the fault address.
-            // `vaddr` does not need to be aligned. It will be automatically
-            // aligned during `pt.remap` regardless of the page size.
-            pt.remap(vaddr, frame, orig_flags)
-                .map(|(_, tlb)| tlb.flush())
-                .is_ok()
+            return false; // Populated mappings should not trigger page faults.
+        }
+        // Allocate a physical frame lazily and map it to the fault address.
+        let Some(frame) = alloc_frame(true) else {
+            return false;
+        };
+        match pt.map(vaddr.align_down_4k(), frame, PageSize::Size4K, orig_flags) {
+            Ok(tlb) => {
+                tlb.flush();
+                true
+            }
+            Err(err) => {
+                dealloc_frame(frame);
+                // Another thread may have faulted it in just now.
+                matches!(err, PagingError::AlreadyMapped)
+                    && pt
+                        .query(vaddr)
+                        .is_ok_and(|(_, flags, _)| flags.contains(orig_flags))
+            }
+        }
+    }
+
+    /// Maps the 2 MiB huge page containing the fault address `vaddr`, in a
+    /// lazy mapping of `area` that allows huge pages.
+    ///
+    /// Returns `false` if the huge page is not within `area`, if some pages
+    /// within it have been mapped, or if there is not enough contiguous
+    /// memory. The fault should then be handled with a 4 KiB page.
+    pub(crate) fn handle_huge_page_fault(
+        vaddr: VirtAddr,
+        area: VirtAddrRange,
+        orig_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        let page_size = PageSize::Size2M;
+        let start = vaddr.align_down(page_size);
+        if !area.contains_range(VirtAddrRange::from_start_size(start, page_size.into())) {
+            return false;
+        }
+        // A page table for the 4 KiB pages is there if any of them has been
+        // mapped, and mapping the huge page fails then.
+        if PageIter4K::new(start, start + usize::from(page_size))
+            .unwrap()
+            .any(|addr| pt.query(addr).is_ok())
+        {
+            return false;
+        }
+        let Some(frame) = alloc_huge_frame(page_size, true) else {
+            return false;
+        };
+        match pt.map(start, frame, page_size, orig_flags) {
+            Ok(tlb) => {
+                trace!("huge page fault: {:#x}", start);
+                tlb.flush();
+                true
+            }
+            Err(_) => {
+                dealloc_huge_frame(frame, page_size);
+                false
+            }
+        }
+    }
+
+    /// Handles a write fault on a copy-on-write page mapped to `frame`.
+    ///
+    /// If the frame is still shared, its content is copied to a new frame,
+    /// which is then mapped writable at `vaddr` (with `orig_flags`). Otherwise
+    /// the frame is simply made writable again.
+    pub(crate) fn handle_cow_fault(
+        vaddr: VirtAddr,
+        frame: PhysAddr,
+        orig_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        let new_frame = if frame_ref_count(frame) == 1 {
+            frame
         } else {
-            false
+            let Some(new_frame) = alloc_frame(false) else {
+                return false;
+            };
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    phys_to_virt(frame).as_ptr(),
+                    phys_to_virt(new_frame).as_mut_ptr(),
+                    PAGE_SIZE_4K,
+                )
+            };
+            dealloc_frame(frame);
+            new_frame
+        };
+        pt.remap(vaddr, new_frame, orig_flags)
+            .map(|(_, tlb)| tlb.flush())
+            .is_ok()
+    }
+
+    /// Handles a write fault at `vaddr` on a copy-on-write huge page of
+    /// `page_size`, where `vaddr` is mapped to `frame`.
+    ///
+    /// If the huge frame is still shared, it is copied to a new huge frame.
+    /// If there is not enough contiguous memory for that, the huge page is
+    /// split, and only the 4 KiB page at `vaddr` is copied.
+    pub(crate) fn handle_huge_cow_fault(
+        vaddr: VirtAddr,
+        frame: PhysAddr,
+        page_size: PageSize,
+        orig_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        let vaddr = vaddr.align_down_4k();
+        let start = vaddr.align_down(page_size);
+        let huge_frame = frame - (vaddr - start);
+        if !is_page_shared(huge_frame, page_size) {
+            return pt
+                .remap(start, huge_frame, orig_flags)
+                .map(|(_, tlb)| tlb.flush())
+                .is_ok();
+        }
+        let Some(new_frame) = alloc_huge_frame(page_size, false) else {
+            return split_huge_page(pt, vaddr)
+                && Self::handle_cow_fault(vaddr, frame, orig_flags, pt);
+        };
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                phys_to_virt(huge_frame).as_ptr(),
+                phys_to_virt(new_frame).as_mut_ptr(),
+                page_size.into(),
+            )
+        };
+        match pt.remap(start, new_frame, orig_flags) {
+            Ok((_, tlb)) => {
+                tlb.flush();
+                dealloc_huge_frame(huge_frame, page_size);
+                true
+            }
+            Err(_) => {
+                dealloc_huge_frame(new_frame, page_size);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use super::*;
+
+    const SIZE_2M: usize = 0x20_0000;
+
+    fn huge_frame() -> PhysAddr {
+        PhysAddr::from(0x8000_0000)
+    }
+
+    /// Releases the page of `size` at `frame`, and returns the freed 4 KiB
+    /// frames.
+    fn release_page(refs: &mut FrameRefs, frame: PhysAddr, size: usize) -> Vec<PhysAddr> {
+        let mut freed = Vec::new();
+        refs.release_page(frame, size, |frame, num| {
+            freed.extend((0..num).map(|i| frame + i * PAGE_SIZE_4K))
+        });
+        freed
+    }
+
+    fn all_frames(frame: PhysAddr, size: usize) -> Vec<PhysAddr> {
+        (0..size / PAGE_SIZE_4K)
+            .map(|i| frame + i * PAGE_SIZE_4K)
+            .collect()
+    }
+
+    #[test]
+    fn test_share_and_release_frame() {
+        let mut refs = FrameRefs::new();
+        let frame = PhysAddr::from(0x1000);
+        assert_eq!(refs.count(frame), 1);
+        refs.share(frame);
+        refs.share(frame);
+        assert_eq!(refs.count(frame), 3);
+        assert!(!refs.release(frame));
+        assert!(!refs.release(frame));
+        // Frames with one owner are not kept in the map.
+        assert!(refs.0.is_empty());
+        assert!(refs.release(frame));
+    }
+
+    #[test]
+    fn test_unmap_forked_huge_page() {
+        let mut refs = FrameRefs::new();
+        let frame = huge_frame();
+        refs.share_page(frame, SIZE_2M);
+        assert!(refs.is_shared(frame, SIZE_2M));
+        // The parent unmaps the huge page, which the child still maps.
+        assert!(release_page(&mut refs, frame, SIZE_2M).is_empty());
+        assert!(!refs.is_shared(frame, SIZE_2M));
+        // The child unmaps it then, and it is freed as a whole.
+        let mut freed = Vec::new();
+        refs.release_page(frame, SIZE_2M, |frame, num| freed.push((frame, num)));
+        assert_eq!(freed, [(frame, SIZE_2M / PAGE_SIZE_4K)]);
+    }
+
+    #[test]
+    fn test_unmap_split_huge_page() {
+        let mut refs = FrameRefs::new();
+        let frame = huge_frame();
+        refs.share_page(frame, SIZE_2M);
+        // The child splits the huge page, and unmaps a 4 KiB page of it.
+        let unmapped = frame + 3 * PAGE_SIZE_4K;
+        assert!(!refs.release(unmapped));
+        // The parent unmaps the huge page, and only the frame that the child
+        // has unmapped is freed.
+        assert_eq!(release_page(&mut refs, frame, SIZE_2M), [unmapped]);
+        // The child unmaps the other 4 KiB pages, and each frame is freed
+        // exactly once in total.
+        let mut freed = vec![unmapped];
+        for page in all_frames(frame, SIZE_2M) {
+            if page != unmapped {
+                freed.extend(release_page(&mut refs, page, PAGE_SIZE_4K));
+            }
         }
+        freed.sort();
+        assert_eq!(freed, all_frames(frame, SIZE_2M));
+        assert!(refs.0.is_empty());
+    }
+
+    #[test]
+    fn test_cow_of_forked_huge_page() {
+        let mut refs = FrameRefs::new();
+        let frame = huge_frame();
+        refs.share_page(frame, SIZE_2M);
+        // The first writer copies the huge page, and drops its references.
+        assert!(refs.is_shared(frame, SIZE_2M));
+        assert!(release_page(&mut refs, frame, SIZE_2M).is_empty());
+        // The second writer owns the huge frame alone, and reuses it.
+        assert!(!refs.is_shared(frame, SIZE_2M));
+        assert!(all_frames(frame, SIZE_2M)
+            .iter()
+            .all(|&page| refs.count(page) == 1));
+    }
+
+    #[test]
+    fn test_unmap_huge_page_with_pinned_frame() {
+        let mut refs = FrameRefs::new();
+        let frame = huge_frame();
+        let pinned = frame + SIZE_2M - PAGE_SIZE_4K;
+        refs.share(pinned);
+        // The huge page is unmapped while a 4 KiB frame of it is pinned, and
+        // all the other frames are freed.
+        let freed = release_page(&mut refs, frame, SIZE_2M);
+        assert_eq!(freed.len(), SIZE_2M / PAGE_SIZE_4K - 1);
+        assert!(!freed.contains(&pinned));
+        // The pinned frame is freed when it is unpinned.
+        assert!(refs.release(pinned));
     }
 }
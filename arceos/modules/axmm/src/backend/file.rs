@@ -0,0 +1,558 @@
+use alloc::collections::btree_map::{BTreeMap, Entry};
+use alloc::format;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use axfs_vfs::VfsNodeRef;
+use axhal::mem::phys_to_virt;
+use axhal::paging::{MappingFlags, PageSize, PageTable};
+use kspin::SpinNoIrq;
+use memory_addr::{align_down_4k, MemoryAddr, PageIter4K, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+use super::alloc::{alloc_frame, dealloc_frame, frame_ref_count, populated_frame, share_frame};
+use super::Backend;
+
+/// A cached file page.
+struct CachedPage {
+    frame: PhysAddr,
+    /// Whether the page has been written through any of the mappings.
+    dirty: bool,
+}
+
+/// The cached pages of a mapped file, shared by all mappings of the file.
+///
+/// Shared mappings of the same file page always use the cached frame. Private
+/// mappings use it until they write to the page, so that the read-only pages
+/// (e.g., the code of executables) are shared between processes.
+///
+/// The pages are indexed by their offsets in the file. The entries do not
+/// hold references to the frames. An entry is removed when the last mapping
+/// of the page goes away.
+pub struct FileCache {
+    pages: SpinNoIrq<BTreeMap<usize, CachedPage>>,
+    /// Bumped each time the file is written or truncated, before the cached
+    /// pages are updated, so that a page read from the file before that is
+    /// not cached.
+    version: AtomicU64,
+    /// The size of the file as last seen by the cache.
+    size: AtomicUsize,
+}
+
+/// The caches of the mapped files, indexed by the absolute paths.
+///
+/// The path is used rather than the VFS node, as some file systems create a
+/// new node each time a file is opened. The cache of a path is detached from
+/// it when the file is removed or replaced (see [`remove_file_cache`] and
+/// [`rename_file_cache`]), so that the mappings of the previous file keep its
+/// pages, and those of the new file do not get them.
+static FILE_CACHES: SpinNoIrq<BTreeMap<Arc<str>, Weak<FileCache>>> =
+    SpinNoIrq::new(BTreeMap::new());
+
+impl FileCache {
+    /// Returns the cache of `file` at the absolute `path`, which is created
+    /// if the file is not mapped yet.
+    ///
+    /// A new cache is also created if the size of `file` differs from the one
+    /// seen by the cache of the path, as the file has then been changed in
+    /// ways not reported to it (e.g., through another path). The mappings of
+    /// the previous contents keep the old cache.
+    pub(crate) fn of_path(path: &str, file: &VfsNodeRef) -> Arc<Self> {
+        let size = file.get_attr().map_or(0, |attr| attr.size() as usize);
+        let mut caches = FILE_CACHES.lock();
+        if let Some(cache) = caches.get(path).and_then(Weak::upgrade) {
+            if cache.size.load(Ordering::Acquire) == size {
+                return cache;
+            }
+        }
+        caches.retain(|_, cache| cache.strong_count() > 0);
+        let cache = Arc::new(Self {
+            pages: SpinNoIrq::new(BTreeMap::new()),
+            version: AtomicU64::new(0),
+            size: AtomicUsize::new(size),
+        });
+        caches.insert(path.into(), Arc::downgrade(&cache));
+        cache
+    }
+
+    /// Whether the page at `offset` is cached and has been written through
+    /// shared mappings.
+    fn is_dirty(&self, offset: usize) -> bool {
+        self.pages
+            .lock()
+            .get(&offset)
+            .is_some_and(|page| page.dirty)
+    }
+
+    /// Calls `f` with each cached page overlapping `range` of the file, and
+    /// the part of `range` within it, relative to `range.start` and to the
+    /// page respectively.
+    fn for_each_page(
+        &self,
+        range: Range<usize>,
+        mut f: impl FnMut(&CachedPage, Range<usize>, Range<usize>),
+    ) {
+        let pages = self.pages.lock();
+        for (&offset, page) in pages.range(align_down_4k(range.start)..range.end) {
+            let start = range.start.max(offset);
+            let end = range.end.min(offset + PAGE_SIZE_4K);
+            f(
+                page,
+                start - range.start..end - range.start,
+                start - offset..end - offset,
+            );
+        }
+    }
+}
+
+/// Returns the cache of the file at the absolute `path`, if it is mapped.
+fn cache_of(path: &str) -> Option<Arc<FileCache>> {
+    FILE_CACHES.lock().get(path)?.upgrade()
+}
+
+/// Returns the bytes of the frame.
+///
+/// # Safety
+///
+/// The frame must not be accessed in other ways at the same time.
+unsafe fn frame_bytes<'a>(frame: PhysAddr) -> &'a mut [u8] {
+    core::slice::from_raw_parts_mut(phys_to_virt(frame).as_mut_ptr(), PAGE_SIZE_4K)
+}
+
+/// Copies `data`, which has been written to the file at the absolute `path`
+/// at `offset`, into the cached pages of the file, so that the mappings of
+/// the file see it.
+pub fn write_file_cache(path: &str, offset: usize, data: &[u8]) {
+    let Some(cache) = cache_of(path) else {
+        return;
+    };
+    cache.version.fetch_add(1, Ordering::AcqRel);
+    cache.size.fetch_max(offset + data.len(), Ordering::AcqRel);
+    cache.for_each_page(offset..offset + data.len(), |page, src, dst| {
+        let bytes = unsafe { frame_bytes(page.frame) };
+        bytes[dst].copy_from_slice(&data[src]);
+    });
+}
+
+/// Copies the cached pages of the file at the absolute `path` that have been
+/// written through shared mappings over `buf`, which has been read from the
+/// file at `offset`, so that the writes are seen before they are written back
+/// to the file.
+pub fn read_file_cache(path: &str, offset: usize, buf: &mut [u8]) {
+    let Some(cache) = cache_of(path) else {
+        return;
+    };
+    cache.for_each_page(offset..offset + buf.len(), |page, dst, src| {
+        if page.dirty {
+            let bytes = unsafe { frame_bytes(page.frame) };
+            buf[dst].copy_from_slice(&bytes[src]);
+        }
+    });
+}
+
+/// Clears the cached pages of the file at the absolute `path` beyond `size`,
+/// after the file is truncated to `size` bytes.
+pub fn truncate_file_cache(path: &str, size: usize) {
+    let Some(cache) = cache_of(path) else {
+        return;
+    };
+    cache.version.fetch_add(1, Ordering::AcqRel);
+    cache.size.store(size, Ordering::Release);
+    cache.for_each_page(size..usize::MAX, |page, _, range| {
+        let bytes = unsafe { frame_bytes(page.frame) };
+        bytes[range].fill(0);
+    });
+}
+
+/// Detaches the cached pages from the absolute `path` of a removed file, so
+/// that a file created at the path later is not mapped with them. The
+/// existing mappings of the removed file keep them.
+pub fn remove_file_cache(path: &str) {
+    FILE_CACHES.lock().remove(path);
+}
+
+/// Moves the cached pages of the file at the absolute path `old`, or of the
+/// files under it if it is a directory, to `new` after it is renamed.
+///
+/// The pages of the file replaced at `new` are detached from it, see
+/// [`remove_file_cache`].
+pub fn rename_file_cache(old: &str, new: &str) {
+    let mut caches = FILE_CACHES.lock();
+    caches.remove(new);
+    let moved: Vec<_> = caches
+        .keys()
+        .filter(|path| {
+            path.strip_prefix(old)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+        .cloned()
+        .collect();
+    for path in moved {
+        let cache = caches.remove(&path).unwrap();
+        let new_path = format!("{}{}", new, &path[old.len()..]);
+        caches.insert(new_path.into(), cache);
+    }
+}
+
+/// Reads the file page at `offset` into a new frame.
+///
+/// The bytes beyond the end of the file are zero.
+fn read_page(file: &VfsNodeRef, offset: usize) -> Option<PhysAddr> {
+    let frame = alloc_frame(true)?;
+    let buf =
+        unsafe { core::slice::from_raw_parts_mut(phys_to_virt(frame).as_mut_ptr(), PAGE_SIZE_4K) };
+    let mut read = 0;
+    while read < PAGE_SIZE_4K {
+        match file.read_at((offset + read) as u64, &mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(err) => {
+                warn!("failed to read file page at {:#x}: {:?}", offset, err);
+                dealloc_frame(frame);
+                return None;
+            }
+        }
+    }
+    Some(frame)
+}
+
+/// Writes the frame back to the file page at `offset`.
+///
+/// The file is not extended, i.e., the bytes beyond the end of the file are
+/// discarded.
+fn write_page(file: &VfsNodeRef, offset: usize, frame: PhysAddr) -> bool {
+    let file_size = match file.get_attr() {
+        Ok(attr) => attr.size() as usize,
+        Err(_) => return false,
+    };
+    if offset >= file_size {
+        return true;
+    }
+    let len = (file_size - offset).min(PAGE_SIZE_4K);
+    let buf = unsafe { core::slice::from_raw_parts(phys_to_virt(frame).as_ptr(), len) };
+    match file.write_at(offset as u64, buf) {
+        Ok(n) if n == len => true,
+        res => {
+            warn!("failed to write file page at {:#x}: {:?}", offset, res);
+            false
+        }
+    }
+}
+
+/// Returns the frame of the cached file page at `offset` and whether it is
+/// dirty, reading it in if it is not cached yet.
+///
+/// A reference to the frame is added for the new mapping. The page is marked
+/// dirty if `write` is `true`.
+fn get_cached_page(
+    file: &VfsNodeRef,
+    cache: &FileCache,
+    offset: usize,
+    write: bool,
+) -> Option<(PhysAddr, bool)> {
+    // Read the page without holding the lock, and drop it if another mapping
+    // has read the same page, or the file has been changed, in the meantime.
+    loop {
+        let version = cache.version.load(Ordering::Acquire);
+        let new_frame = if cache.pages.lock().contains_key(&offset) {
+            None
+        } else {
+            Some(read_page(file, offset)?)
+        };
+        let mut pages = cache.pages.lock();
+        let page = match pages.entry(offset) {
+            Entry::Occupied(entry) => {
+                if let Some(frame) = new_frame {
+                    dealloc_frame(frame);
+                }
+                share_frame(entry.get().frame);
+                entry.into_mut()
+            }
+            Entry::Vacant(entry) => match new_frame {
+                Some(frame) if cache.version.load(Ordering::Acquire) == version => {
+                    entry.insert(CachedPage {
+                        frame,
+                        dirty: false,
+                    })
+                }
+                Some(frame) => {
+                    dealloc_frame(frame);
+                    continue;
+                }
+                // The page has been removed in the meantime.
+                None => continue,
+            },
+        };
+        page.dirty |= write;
+        return Some((page.frame, page.dirty));
+    }
+}
+
+/// Whether `frame` is the cached frame of the file page at `offset`.
+fn is_cached(cache: &FileCache, offset: usize, frame: PhysAddr) -> bool {
+    cache
+        .pages
+        .lock()
+        .get(&offset)
+        .is_some_and(|page| page.frame == frame)
+}
+
+/// Drops a reference to `frame` mapped at the file page at `offset`, and
+/// removes the page from the cache if it is the last one.
+fn release_frame(cache: &FileCache, offset: usize, frame: PhysAddr) {
+    let mut pages = cache.pages.lock();
+    if frame_ref_count(frame) == 1 && pages.get(&offset).is_some_and(|page| page.frame == frame) {
+        pages.remove(&offset);
+    }
+    dealloc_frame(frame);
+}
+
+/// Handles a write fault on a page of a private mapping that is mapped
+/// read-only, which is either a cached page or a copy-on-write page.
+///
+/// Cached pages are always copied, as the cache still refers to them.
+fn handle_private_write_fault(
+    vaddr: VirtAddr,
+    cache: &FileCache,
+    offset: usize,
+    frame: PhysAddr,
+    orig_flags: MappingFlags,
+    pt: &mut PageTable,
+) -> bool {
+    if !is_cached(cache, offset, frame) {
+        return Backend::handle_cow_fault(vaddr, frame, orig_flags, pt);
+    }
+    let Some(new_frame) = alloc_frame(false) else {
+        return false;
+    };
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            phys_to_virt(frame).as_ptr(),
+            phys_to_virt(new_frame).as_mut_ptr(),
+            PAGE_SIZE_4K,
+        )
+    };
+    release_frame(cache, offset, frame);
+    pt.remap(vaddr, new_frame, orig_flags)
+        .map(|(_, tlb)| tlb.flush())
+        .is_ok()
+}
+
+impl Backend {
+    /// Creates a new file-backed mapping backend, where `file_offset` of
+    /// `file` (whose pages are cached in `cache`) is mapped at `area_start`.
+    pub fn new_file_backed(
+        file: VfsNodeRef,
+        cache: Arc<FileCache>,
+        file_offset: usize,
+        area_start: VirtAddr,
+        shared: bool,
+    ) -> Self {
+        Self::FileBacked {
+            file,
+            cache,
+            file_offset,
+            area_start,
+            shared,
+        }
+    }
+
+    /// Returns the file offset of the page mapped at `vaddr`.
+    fn page_offset(&self, vaddr: VirtAddr) -> usize {
+        match self {
+            Self::FileBacked {
+                file_offset,
+                area_start,
+                ..
+            } => file_offset + (vaddr.align_down_4k() - *area_start),
+            _ => unreachable!(),
+        }
+    }
+
+    pub(crate) fn handle_page_fault_file(
+        &self,
+        vaddr: VirtAddr,
+        access_flags: MappingFlags,
+        orig_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        let Self::FileBacked {
+            file,
+            cache,
+            shared,
+            ..
+        } = self
+        else {
+            unreachable!()
+        };
+        let vaddr = vaddr.align_down_4k();
+        let offset = self.page_offset(vaddr);
+        let write = access_flags.contains(MappingFlags::WRITE);
+        if let Some((frame, flags, _)) = populated_frame(pt, vaddr) {
+            if !write {
+                // Only writes are trapped on populated pages, this one may
+                // come from a stale TLB entry.
+                return flags.contains(access_flags);
+            }
+            if !shared {
+                return handle_private_write_fault(vaddr, cache, offset, frame, orig_flags, pt);
+            }
+            // The first write to a page mapped read-only.
+            if let Some(page) = cache.pages.lock().get_mut(&offset) {
+                page.dirty = true;
+            }
+            return pt
+                .remap(vaddr, frame, orig_flags)
+                .map(|(_, tlb)| tlb.flush())
+                .is_ok();
+        }
+
+        let (frame, flags) = if !shared && write {
+            // It is going to be written at once, so get a private copy.
+            let Some(frame) = read_page(file, offset) else {
+                return false;
+            };
+            (frame, orig_flags)
+        } else {
+            let Some((frame, dirty)) = get_cached_page(file, cache, offset, *shared && write)
+            else {
+                return false;
+            };
+            // Clean pages of shared mappings are mapped read-only, so that
+            // the first write to them marks them dirty. Cached pages are
+            // always read-only for private mappings, and are copied on write.
+            if *shared && dirty {
+                (frame, orig_flags)
+            } else {
+                (frame, orig_flags - MappingFlags::WRITE)
+            }
+        };
+        pt.map(vaddr, frame, PageSize::Size4K, flags)
+            .map(|tlb| tlb.flush())
+            .is_ok()
+    }
+
+    /// Unmaps the populated pages in `[start, start + size)`.
+    ///
+    /// Written pages of shared mappings are written back to the file first.
+    /// A page is removed from the cache when its last mapping goes away.
+    pub(crate) fn unmap_file(&self, start: VirtAddr, size: usize, pt: &mut PageTable) -> bool {
+        let Self::FileBacked {
+            file,
+            cache,
+            shared,
+            ..
+        } = self
+        else {
+            unreachable!()
+        };
+        debug!("unmap_file: [{:#x}, {:#x})", start, start + size);
+        let mut ok = true;
+        for vaddr in PageIter4K::new(start, start + size).unwrap() {
+            let Some((frame, _, _)) = populated_frame(pt, vaddr) else {
+                continue;
+            };
+            if let Ok((_, _, tlb)) = pt.unmap(vaddr) {
+                tlb.flush();
+            }
+            let offset = self.page_offset(vaddr);
+            let dirty = *shared
+                && cache
+                    .pages
+                    .lock()
+                    .get(&offset)
+                    .is_some_and(|page| page.frame == frame && page.dirty);
+            if dirty && !write_page(file, offset, frame) {
+                ok = false;
+            }
+            release_frame(cache, offset, frame);
+        }
+        ok
+    }
+
+    /// Changes the flags of the populated pages in `[start, start + size)`.
+    ///
+    /// Like [`Backend::protect_alloc`], except that pages of shared mappings
+    /// not written yet stay read-only, so that writes to them are still
+    /// tracked, and cached pages stay read-only in private mappings, so that
+    /// they are still copied on write.
+    pub(crate) fn protect_file(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        new_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        let Self::FileBacked { cache, shared, .. } = self else {
+            unreachable!()
+        };
+        let accessible =
+            new_flags.intersects(MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE);
+        for vaddr in PageIter4K::new(start, start + size).unwrap() {
+            let Some((frame, _, _)) = populated_frame(pt, vaddr) else {
+                continue;
+            };
+            let offset = self.page_offset(vaddr);
+            let read_only = if *shared {
+                !cache.is_dirty(offset)
+            } else {
+                frame_ref_count(frame) > 1 || is_cached(cache, offset, frame)
+            };
+            let flags = if !accessible {
+                MappingFlags::empty()
+            } else if read_only {
+                new_flags - MappingFlags::WRITE
+            } else {
+                new_flags
+            };
+            if pt
+                .remap(vaddr, frame, flags)
+                .map(|(_, tlb)| tlb.flush())
+                .is_err()
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Writes the dirty pages in `[start, start + size)` back to the file.
+    ///
+    /// A page mapped only here becomes clean, and read-only again so that the
+    /// next write marks it dirty. Pages also mapped elsewhere stay dirty, as
+    /// the writes through the other mappings would not be noticed.
+    pub(crate) fn sync_shared_file(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        pt: &mut PageTable,
+    ) -> bool {
+        let Self::FileBacked { file, cache, .. } = self else {
+            unreachable!()
+        };
+        let mut ok = true;
+        for vaddr in PageIter4K::new(start, start + size).unwrap() {
+            let Some((frame, flags, _)) = populated_frame(pt, vaddr) else {
+                continue;
+            };
+            let offset = self.page_offset(vaddr);
+            if !cache.is_dirty(offset) {
+                continue;
+            }
+            if !write_page(file, offset, frame) {
+                ok = false;
+                continue;
+            }
+            if frame_ref_count(frame) == 1 {
+                if let Some(page) = cache.pages.lock().get_mut(&offset) {
+                    page.dirty = false;
+                }
+                if let Ok((_, tlb)) = pt.remap(vaddr, frame, flags - MappingFlags::WRITE) {
+                    tlb.flush();
+                }
+            }
+        }
+        ok
+    }
+}
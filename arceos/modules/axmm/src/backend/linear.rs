@@ -1,7 +1,7 @@
 use axhal::paging::{MappingFlags, PageTable};
 use memory_addr::{PhysAddr, VirtAddr};
 
-use super::Backend;
+use super::{split_huge_page_at, Backend};
 
 impl Backend {
     /// Creates a new linear mapping backend.
@@ -26,7 +26,7 @@ impl Backend {
             va_to_pa(start + size),
             flags
         );
-        pt.map_region(start, va_to_pa, size, flags, false, false)
+        pt.map_region(start, va_to_pa, size, flags, true, false)
             .map(|tlb| tlb.ignore()) // TLB flush on map is unnecessary, as there are no outdated mappings.
             .is_ok()
     }
@@ -39,8 +39,33 @@ impl Backend {
         _pa_va_offset: usize,
     ) -> bool {
         debug!("unmap_linear: [{:#x}, {:#x})", start, start + size);
+        // Huge pages partially in the range are split first.
+        if !split_huge_page_at(pt, start) || !split_huge_page_at(pt, start + size) {
+            return false;
+        }
         pt.unmap_region(start, size, true)
             .map(|tlb| tlb.ignore()) // flush each page on unmap, do not flush the entire TLB.
             .is_ok()
     }
+
+    pub(crate) fn protect_linear(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        new_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        debug!(
+            "protect_linear: [{:#x}, {:#x}) {:?}",
+            start,
+            start + size,
+            new_flags
+        );
+        if !split_huge_page_at(pt, start) || !split_huge_page_at(pt, start + size) {
+            return false;
+        }
+        pt.protect_region(start, size, new_flags, true)
+            .map(|tlb| tlb.ignore())
+            .is_ok()
+    }
 }
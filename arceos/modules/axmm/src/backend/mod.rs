@@ -1,23 +1,42 @@
 //! Memory mapping backends.
 #![allow(dead_code)]
 
-use axhal::paging::{MappingFlags, PageTable};
+use ::alloc::sync::Arc;
+
+use axfs_vfs::VfsNodeRef;
+use axhal::paging::{MappingFlags, PageSize, PageTable};
 use memory_addr::{MemoryAddr, VirtAddr, PAGE_SIZE_4K};
 use memory_set::MappingBackend;
-use axalloc::global_allocator;
-use axhal::mem::virt_to_phys;
 
 mod alloc;
+mod file;
 mod linear;
+mod shared;
+
+#[cfg(feature = "swap")]
+pub(crate) use self::alloc::{alloc_frame, frame_ref_count};
+pub(crate) use self::alloc::{
+    dealloc_frame, is_populated, populated_frame, share_frame, share_page,
+};
+pub(crate) use self::file::FileCache;
+pub use self::file::{
+    read_file_cache, remove_file_cache, rename_file_cache, truncate_file_cache, write_file_cache,
+};
+pub use self::shared::SharedPages;
 
 /// A unified enum type for different memory mapping backends.
 ///
-/// Currently, two backends are implemented:
+/// Currently, four backends are implemented:
 ///
 /// - **Linear**: used for linear mappings. The target physical frames are
 ///   contiguous and their addresses should be known when creating the mapping.
 /// - **Allocation**: used in general, or for lazy mappings. The target physical
 ///   frames are obtained from the global allocator.
+/// - **File-backed**: used for memory-mapped files. The target physical frames
+///   are filled with the file content on demand.
+/// - **Shared memory**: used for shared memory. The target physical frames
+///   are those of [`SharedPages`], which can be mapped into several address
+///   spaces at once.
 #[derive(Clone)]
 pub enum Backend {
     /// Linear mapping backend.
@@ -25,6 +44,8 @@ pub enum Backend {
     /// The offset between the virtual address and the physical address is
     /// constant, which is specified by `pa_va_offset`. For example, the virtual
     /// address `vaddr` is mapped to the physical address `vaddr - pa_va_offset`.
+    ///
+    /// Huge pages (2 MiB or 1 GiB) are used where both addresses are aligned.
     Linear {
         /// `vaddr - paddr`.
         pa_va_offset: usize,
@@ -33,19 +54,56 @@ pub enum Backend {
     ///
     /// If `populate` is `true`, all physical frames are allocated when the
     /// mapping is created, and no page faults are triggered during the memory
-    /// access. Otherwise, the physical frames are allocated on demand (by
-    /// handling page faults).
+    /// access. Huge pages are used where the alignment allows, if there is
+    /// enough contiguous memory. Otherwise, the physical frames are allocated
+    /// on demand (by handling page faults), one 4 KiB page at a time unless
+    /// `huge` is `true`.
     Alloc {
         /// Whether to populate the physical frames when creating the mapping.
         populate: bool,
+        /// Whether to allocate 2 MiB huge pages on demand where possible
+        /// (see [`AddrSpace::advise_huge_pages`]).
+        ///
+        /// [`AddrSpace::advise_huge_pages`]: crate::AddrSpace::advise_huge_pages
+        huge: bool,
     },
-    /// File-backed mapping backend (lazy load).
+    /// File-backed mapping backend.
+    ///
+    /// The pages are read from `file` (by positional reads) on demand, and
+    /// cached while they are mapped. If `shared` is `true`, all shared
+    /// mappings of the same file page use the same frame, and the written
+    /// pages are written back to the file on [`AddrSpace::sync`] and
+    /// unmapping. Otherwise, the cached pages are mapped read-only, and each
+    /// mapping gets private copies of the pages it writes to.
+    ///
+    /// [`AddrSpace::sync`]: crate::AddrSpace::sync
     FileBacked {
-        reader: ::alloc::sync::Arc<dyn crate::MmapReadFn>,
+        /// The mapped file.
+        file: VfsNodeRef,
+        /// The cached pages of the file, shared by all its mappings.
+        cache: Arc<FileCache>,
+        /// The file offset mapped at `area_start`.
         file_offset: usize,
+        /// The start address of the original mapping, which is kept when the
+        /// area is split.
+        area_start: VirtAddr,
+        /// Whether the mapping is shared (`MAP_SHARED`) or private.
+        shared: bool,
+    },
+    /// Shared memory mapping backend.
+    ///
+    /// The pages are mapped to the frames of `pages` on demand, and the
+    /// frames are shared with all other mappings of them (including those of
+    /// forked children). They are not copied on write.
+    Shared {
+        /// The mapped pages.
+        pages: Arc<SharedPages>,
+        /// The offset in bytes in `pages` mapped at `area_start`.
+        offset: usize,
+        /// The start address of the original mapping, which is kept when the
+        /// area is split.
         area_start: VirtAddr,
     },
-
 }
 
 impl MappingBackend for Backend {
@@ -55,18 +113,18 @@ impl MappingBackend for Backend {
     fn map(&self, start: VirtAddr, size: usize, flags: MappingFlags, pt: &mut PageTable) -> bool {
         match *self {
             Self::Linear { pa_va_offset } => self.map_linear(start, size, flags, pt, pa_va_offset),
-            Self::Alloc { populate } => self.map_alloc(start, size, flags, pt, populate),
-            Self::FileBacked { .. } =>
-                true, // Don't map on creation, rely on lazy fault handler
+            Self::Alloc { populate, .. } => self.map_alloc(start, size, flags, pt, populate),
+            // Pages are read in by the page fault handler.
+            Self::FileBacked { .. } | Self::Shared { .. } => true,
         }
     }
 
     fn unmap(&self, start: VirtAddr, size: usize, pt: &mut PageTable) -> bool {
         match *self {
             Self::Linear { pa_va_offset } => self.unmap_linear(start, size, pt, pa_va_offset),
-            Self::Alloc { populate } => self.unmap_alloc(start, size, pt, populate),
-            Self::FileBacked { .. } =>
-                pt.unmap_region(start, size, true).is_ok(),
+            Self::Alloc { populate, .. } => self.unmap_alloc(start, size, pt, populate),
+            Self::FileBacked { .. } => self.unmap_file(start, size, pt),
+            Self::Shared { .. } => self.unmap_shared(start, size, pt),
         }
     }
 
@@ -77,71 +135,103 @@ impl MappingBackend for Backend {
         new_flags: Self::Flags,
         page_table: &mut Self::PageTable,
     ) -> bool {
-        page_table
-            .protect_region(start, size, new_flags, true)
-            .map(|tlb| tlb.ignore())
-            .is_ok()
+        match *self {
+            Self::Linear { .. } => self.protect_linear(start, size, new_flags, page_table),
+            Self::Alloc { .. } => self.protect_alloc(start, size, new_flags, page_table),
+            Self::FileBacked { .. } => self.protect_file(start, size, new_flags, page_table),
+            Self::Shared { .. } => self.protect_shared(start, size, new_flags, page_table),
+        }
     }
 }
 
 impl Backend {
+    /// Returns whether this is an allocation mapping backend.
+    pub(crate) const fn is_alloc(&self) -> bool {
+        matches!(self, Self::Alloc { .. })
+    }
 
-    pub fn new_file_backed(
-        reader: ::alloc::sync::Arc<dyn crate::MmapReadFn>,
-        file_offset: usize,
-        area_start: VirtAddr,
-    ) -> Self {
-        Self::FileBacked {
-            reader,
-            file_offset,
-            area_start,
-        }
+    /// Returns whether this is a shared file mapping or shared memory
+    /// backend, whose frames are shared with other mappings rather than
+    /// copied on write.
+    pub(crate) const fn is_shared(&self) -> bool {
+        matches!(
+            self,
+            Self::FileBacked { shared: true, .. } | Self::Shared { .. }
+        )
     }
+
     pub(crate) fn handle_page_fault(
         &self,
         vaddr: VirtAddr,
+        access_flags: MappingFlags,
         orig_flags: MappingFlags,
         page_table: &mut PageTable,
     ) -> bool {
-        match self {
+        match *self {
             Self::Linear { .. } => false, // Linear mappings should not trigger page faults.
-            Self::Alloc { populate } => {
-                self.handle_page_fault_alloc(vaddr, orig_flags, page_table, *populate)
+            Self::Alloc { populate, .. } => {
+                self.handle_page_fault_alloc(vaddr, orig_flags, page_table, populate)
             }
-            Self::FileBacked {
-                reader,
-                file_offset,
-                area_start,
-            } => {
-                let va = vaddr.align_down(PAGE_SIZE_4K);
-                let offset = file_offset + (va.as_usize() - area_start.as_usize());
+            Self::FileBacked { .. } => {
+                self.handle_page_fault_file(vaddr, access_flags, orig_flags, page_table)
+            }
+            Self::Shared { .. } => self.handle_page_fault_shared(vaddr, orig_flags, page_table),
+        }
+    }
 
-                // 分配一页虚拟内存
-                let vaddr = match global_allocator().alloc_pages(1, PAGE_SIZE_4K) {
-                    Ok(vaddr) => vaddr,
-                    Err(_) => return false,
-                };
+    /// Writes the written pages of `[start, start + size)` back to the file,
+    /// if this is a shared file mapping.
+    pub(crate) fn sync(&self, start: VirtAddr, size: usize, page_table: &mut PageTable) -> bool {
+        match self {
+            Self::FileBacked { shared: true, .. } => self.sync_shared_file(start, size, page_table),
+            _ => true,
+        }
+    }
+}
 
-                let paddr = virt_to_phys(VirtAddr::from(vaddr));
-                let buf = unsafe {
-                    core::slice::from_raw_parts_mut(axhal::mem::phys_to_virt(paddr).as_mut_ptr(), PAGE_SIZE_4K)
-                };
-                if !(reader)(offset, buf) {
-                    return false;
-                }
+/// Splits the huge page containing `vaddr`, if any, into 4 KiB pages.
+///
+/// The 4 KiB pages are mapped to the parts of the huge frame, with the flags
+/// of the huge page (see [`axhal::paging::split_huge_page`]). Returns `false`
+/// if the page table cannot be updated.
+pub(crate) fn split_huge_page(pt: &mut PageTable, vaddr: VirtAddr) -> bool {
+    // A 1 GiB page is split into 2 MiB pages first.
+    while let Ok((_, _, page_size)) = pt.query(vaddr) {
+        if !page_size.is_huge() {
+            break;
+        }
+        let start = vaddr.align_down(page_size);
+        debug!("split huge page: {:#x} ({:?})", start, page_size);
+        if axhal::paging::split_huge_page(pt, start).is_err() {
+            return false;
+        }
+    }
+    true
+}
 
-                page_table
-                    .map_region(
-                        va,
-                        |_| paddr,
-                        PAGE_SIZE_4K,
-                        orig_flags,
-                        false,
-                        false,
-                    )
-                    .map(|tlb| tlb.ignore())
-                    .is_ok()
-            }
+/// Splits the huge page containing `vaddr`, if `vaddr` is not its start, so
+/// that a range starting or ending at `vaddr` covers whole pages only.
+pub(crate) fn split_huge_page_at(pt: &mut PageTable, vaddr: VirtAddr) -> bool {
+    match pt.query(vaddr) {
+        Ok((_, _, page_size)) if page_size.is_huge() && !vaddr.is_aligned(page_size) => {
+            split_huge_page(pt, vaddr)
+        }
+        _ => true,
+    }
+}
+
+/// Splits all huge pages within `[start, end)` into 4 KiB pages.
+pub(crate) fn split_huge_pages(pt: &mut PageTable, start: VirtAddr, end: VirtAddr) -> bool {
+    let mut vaddr = start;
+    while vaddr < end {
+        if pt
+            .query(vaddr)
+            .is_ok_and(|(_, _, page_size)| page_size.is_huge())
+            && !split_huge_page(pt, vaddr)
+        {
+            return false;
         }
+        vaddr += PAGE_SIZE_4K;
     }
+    true
 }
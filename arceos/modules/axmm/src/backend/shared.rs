@@ -0,0 +1,263 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use axerrno::{ax_err, AxResult};
+use axhal::mem::phys_to_virt;
+use axhal::paging::{MappingFlags, PageSize, PageTable};
+use kspin::SpinNoIrq;
+use memory_addr::{align_up_4k, MemoryAddr, PageIter4K, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+use super::alloc::{alloc_frame, dealloc_frame, populated_frame, share_frame};
+use super::Backend;
+
+struct SharedPagesInner {
+    /// The frames of the pages, allocated on the first access.
+    frames: Vec<Option<PhysAddr>>,
+    /// The size in bytes, which may not be page aligned.
+    size: usize,
+}
+
+/// Physical pages that can be mapped into several address spaces at once
+/// (e.g., System V shared memory segments and memfd files).
+///
+/// The frames are allocated (and zeroed) on the first access. They are
+/// reference counted: the object holds a reference to each of its frames,
+/// and each mapped page holds another one. So the frames are freed when both
+/// the object and all mappings of them are gone.
+pub struct SharedPages {
+    inner: SpinNoIrq<SharedPagesInner>,
+}
+
+impl SharedPages {
+    /// Creates `size` bytes of shared pages.
+    pub fn new(size: usize) -> Self {
+        let mut inner = SharedPagesInner {
+            frames: Vec::new(),
+            size: 0,
+        };
+        inner.resize(size);
+        Self {
+            inner: SpinNoIrq::new(inner),
+        }
+    }
+
+    /// Returns the size in bytes.
+    pub fn size(&self) -> usize {
+        self.inner.lock().size
+    }
+
+    /// Changes the size to `size` bytes.
+    ///
+    /// The frames beyond the new size are released, and the bytes beyond it
+    /// in the last page are cleared. Pages that are still mapped keep their
+    /// frames, which are not shared with the object any more.
+    pub fn resize(&self, size: usize) {
+        self.inner.lock().resize(size);
+    }
+
+    /// Reads the bytes from `offset` into `buf`, returns the number of bytes
+    /// read, which is less than `buf.len()` at the end.
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let inner = self.inner.lock();
+        let len = buf.len().min(inner.size.saturating_sub(offset));
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done;
+            let page_offset = pos % PAGE_SIZE_4K;
+            let n = (len - done).min(PAGE_SIZE_4K - page_offset);
+            let dst = &mut buf[done..done + n];
+            match inner.frames[pos / PAGE_SIZE_4K] {
+                Some(frame) => unsafe {
+                    core::ptr::copy_nonoverlapping(
+                        phys_to_virt(frame + page_offset).as_ptr(),
+                        dst.as_mut_ptr(),
+                        n,
+                    )
+                },
+                // Not accessed yet.
+                None => dst.fill(0),
+            }
+            done += n;
+        }
+        len
+    }
+
+    /// Writes `buf` at `offset`, and extends the pages if it is written
+    /// beyond the end.
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> AxResult<usize> {
+        let Some(end) = offset.checked_add(buf.len()) else {
+            return ax_err!(InvalidInput);
+        };
+        let mut inner = self.inner.lock();
+        if end > inner.size {
+            inner.resize(end);
+        }
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done;
+            let page_offset = pos % PAGE_SIZE_4K;
+            let n = (buf.len() - done).min(PAGE_SIZE_4K - page_offset);
+            let Some(frame) = inner.frame(pos / PAGE_SIZE_4K) else {
+                return ax_err!(NoMemory);
+            };
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    buf[done..].as_ptr(),
+                    phys_to_virt(frame + page_offset).as_mut_ptr(),
+                    n,
+                )
+            };
+            done += n;
+        }
+        Ok(done)
+    }
+
+    /// Returns the frame of the page `index` with a reference added for a
+    /// new mapping, or `None` if the page is beyond the end.
+    fn map_frame(&self, index: usize) -> Option<PhysAddr> {
+        let frame = self.inner.lock().frame(index)?;
+        share_frame(frame);
+        Some(frame)
+    }
+}
+
+impl SharedPagesInner {
+    fn resize(&mut self, size: usize) {
+        let num_pages = align_up_4k(size) / PAGE_SIZE_4K;
+        if num_pages < self.frames.len() {
+            for frame in self.frames.drain(num_pages..).flatten() {
+                dealloc_frame(frame);
+            }
+        }
+        self.frames.resize(num_pages, None);
+        if size < self.size && size % PAGE_SIZE_4K != 0 {
+            if let Some(frame) = self.frames[size / PAGE_SIZE_4K] {
+                let offset = size % PAGE_SIZE_4K;
+                unsafe {
+                    core::ptr::write_bytes(
+                        phys_to_virt(frame + offset).as_mut_ptr(),
+                        0,
+                        PAGE_SIZE_4K - offset,
+                    )
+                };
+            }
+        }
+        self.size = size;
+    }
+
+    /// Returns the frame of the page `index`, allocating it if not yet.
+    fn frame(&mut self, index: usize) -> Option<PhysAddr> {
+        let slot = self.frames.get_mut(index)?;
+        if slot.is_none() {
+            *slot = Some(alloc_frame(true)?);
+        }
+        *slot
+    }
+}
+
+impl Drop for SharedPages {
+    fn drop(&mut self) {
+        for frame in self.inner.get_mut().frames.drain(..).flatten() {
+            dealloc_frame(frame);
+        }
+    }
+}
+
+impl Backend {
+    /// Creates a new shared memory mapping backend, where the byte `offset`
+    /// of `pages` is mapped at `area_start`.
+    pub fn new_shared(pages: Arc<SharedPages>, offset: usize, area_start: VirtAddr) -> Self {
+        Self::Shared {
+            pages,
+            offset,
+            area_start,
+        }
+    }
+
+    pub(crate) fn handle_page_fault_shared(
+        &self,
+        vaddr: VirtAddr,
+        orig_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        let Self::Shared {
+            pages,
+            offset,
+            area_start,
+        } = self
+        else {
+            unreachable!()
+        };
+        let vaddr = vaddr.align_down_4k();
+        if let Some((frame, _, _)) = populated_frame(pt, vaddr) {
+            return pt
+                .remap(vaddr, frame, orig_flags)
+                .map(|(_, tlb)| tlb.flush())
+                .is_ok();
+        }
+        let index = (offset + (vaddr - *area_start)) / PAGE_SIZE_4K;
+        let Some(frame) = pages.map_frame(index) else {
+            // Beyond the end of the pages.
+            return false;
+        };
+        match pt.map(vaddr, frame, PageSize::Size4K, orig_flags) {
+            Ok(tlb) => {
+                tlb.flush();
+                true
+            }
+            Err(_) => {
+                dealloc_frame(frame);
+                false
+            }
+        }
+    }
+
+    /// Unmaps the populated pages in `[start, start + size)`, and drops their
+    /// references to the frames.
+    pub(crate) fn unmap_shared(&self, start: VirtAddr, size: usize, pt: &mut PageTable) -> bool {
+        debug!("unmap_shared: [{:#x}, {:#x})", start, start + size);
+        for vaddr in PageIter4K::new(start, start + size).unwrap() {
+            let Some((frame, _, _)) = populated_frame(pt, vaddr) else {
+                continue;
+            };
+            if let Ok((_, _, tlb)) = pt.unmap(vaddr) {
+                tlb.flush();
+            }
+            dealloc_frame(frame);
+        }
+        true
+    }
+
+    /// Changes the flags of the populated pages in `[start, start + size)`.
+    ///
+    /// Inaccessible pages keep their frames with empty flags, like
+    /// [`Backend::protect_alloc`].
+    pub(crate) fn protect_shared(
+        &self,
+        start: VirtAddr,
+        size: usize,
+        new_flags: MappingFlags,
+        pt: &mut PageTable,
+    ) -> bool {
+        let accessible =
+            new_flags.intersects(MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE);
+        let flags = if accessible {
+            new_flags
+        } else {
+            MappingFlags::empty()
+        };
+        for vaddr in PageIter4K::new(start, start + size).unwrap() {
+            let Some((frame, _, _)) = populated_frame(pt, vaddr) else {
+                continue;
+            };
+            if pt
+                .remap(vaddr, frame, flags)
+                .map(|(_, tlb)| tlb.flush())
+                .is_err()
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
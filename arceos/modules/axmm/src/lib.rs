@@ -1,15 +1,25 @@
 //! [ArceOS](https://github.com/arceos-org/arceos) memory management module.
+//!
+//! # Cargo Features
+//!
+//! - `swap`: Enable swapping out anonymous pages of user address spaces to a
+//!   block device or a file (see [`swap`]).
 
 #![no_std]
-#![feature(trait_alias)]
 #[macro_use]
 extern crate log;
 extern crate alloc;
 
 mod aspace;
 mod backend;
+#[cfg(feature = "swap")]
+pub mod swap;
 
-pub use self::aspace::AddrSpace;
+pub use self::aspace::{AddrSpace, AreaInfo};
+pub use self::backend::{
+    read_file_cache, remove_file_cache, rename_file_cache, truncate_file_cache, write_file_cache,
+    SharedPages,
+};
 
 use axerrno::{AxError, AxResult};
 use axhal::mem::phys_to_virt;
@@ -44,10 +54,41 @@ fn paging_err_to_ax_err(err: PagingError) -> AxError {
     }
 }
 
+/// Takes a reference to the 4 KiB frame `frame` of a user page, so that it is
+/// not freed (and reused) until [`unpin_frame`] is called, even if the page
+/// is unmapped or replaced in the meantime.
+///
+/// It must be called with the address space that maps the frame locked.
+pub fn pin_frame(frame: PhysAddr) {
+    backend::share_frame(frame);
+}
+
+/// Drops the reference taken by [`pin_frame`], and frees the frame if it is
+/// no longer mapped.
+pub fn unpin_frame(frame: PhysAddr) {
+    backend::dealloc_frame(frame);
+}
+
 /// Creates a new address space for user processes.
 pub fn new_user_aspace() -> AxResult<AddrSpace> {
     let mut aspace = AddrSpace::new_empty(VirtAddr::from(USER_ASPACE_BASE), USER_ASPACE_SIZE)?;
-    aspace.copy_mappings_from(&kernel_aspace().lock())?;
+    // On aarch64, the kernel mappings are in a separate page table
+    // (`TTBR1_EL1`), so they need not be copied.
+    if !cfg!(target_arch = "aarch64") {
+        aspace.copy_mappings_from(&kernel_aspace().lock())?;
+    }
+    Ok(aspace)
+}
+
+/// Creates a new address space for a forked user process.
+///
+/// All memory areas of `parent` are shared with the new address space with
+/// copy-on-write. See [`AddrSpace::clone_areas_cow`] for details. The layout
+/// (e.g., [`AddrSpace::mmap_base`]) is inherited as well.
+pub fn fork_user_aspace(parent: &mut AddrSpace) -> AxResult<AddrSpace> {
+    let mut aspace = new_user_aspace()?;
+    aspace.set_mmap_base(parent.mmap_base());
+    parent.clone_areas_cow(&mut aspace)?;
     Ok(aspace)
 }
 
@@ -90,9 +131,3 @@ pub fn init_memory_management() {
 pub fn init_memory_management_secondary() {
     unsafe { axhal::arch::write_page_table_root(kernel_page_table_root()) };
 }
-
-/// Abstract file reader (no dependency on FileLike).
-///
-/// It is implemented by the upper layer to provide file page loading logic.
-/// A trait alias for closure-style mmap reader
-pub trait MmapReadFn = Fn(usize, &mut [u8]) -> bool + Send + Sync;
\ No newline at end of file
@@ -0,0 +1,509 @@
+//! Swapping out anonymous pages of user address spaces.
+//!
+//! When free memory runs low, victim pages are picked from the lazily
+//! allocated areas of the registered user address spaces (see
+//! [`register_aspace`]) with the clock algorithm, and written to the swap
+//! device (see [`swap_on`]). The page table entry of a swapped-out page is
+//! left non-present, with the swap slot encoded in its address, so that the
+//! page is read back in when it is accessed again (see
+//! [`AddrSpace::handle_page_fault`]).
+//!
+//! The page tables do not track accesses in a portable way, so the reference
+//! bit is emulated: the clock hand first makes a page inaccessible (keeping
+//! its frame), and only swaps it out if it has not been faulted back in when
+//! the hand comes around again.
+//!
+//! Only pages owned by a single mapping are swapped out, i.e., neither the
+//! pages shared with copy-on-write nor those of file mappings.
+
+use alloc::boxed::Box;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use axalloc::global_allocator;
+use axdriver::prelude::*;
+use axerrno::{ax_err, AxError, AxResult};
+use axfs_vfs::VfsNodeRef;
+use axhal::mem::phys_to_virt;
+use axhal::paging::{MappingFlags, PageTable};
+use axsync::Mutex;
+use kspin::SpinNoIrq;
+use lazyinit::LazyInit;
+use memory_addr::{MemoryAddr, PhysAddr, VirtAddr, PAGE_SIZE_4K};
+
+use crate::backend::{alloc_frame, dealloc_frame, frame_ref_count, populated_frame};
+use crate::AddrSpace;
+
+/// Swap entries point to the addresses starting from here, which are beyond
+/// any physical memory, so that they are not mistaken for frames.
+const SWAP_ENTRY_BASE: usize = 1 << 40;
+
+/// Pages are reclaimed when the number of free pages drops below this.
+const LOW_WATERMARK: usize = 256;
+
+/// The number of pages to reclaim each time.
+const RECLAIM_BATCH: usize = 32;
+
+/// The minimum interval between two reclaims in nanoseconds, so that page
+/// faults do not keep scanning the address spaces while memory stays low.
+const RECLAIM_INTERVAL_NANOS: u64 = 1_000_000;
+
+/// A device that stores the swapped-out pages, in page-sized slots.
+pub trait SwapDevice: Send + Sync {
+    /// Returns the number of slots.
+    fn num_slots(&self) -> usize;
+
+    /// Reads the slot into `buf`, which is a page.
+    fn read_slot(&self, slot: usize, buf: &mut [u8]) -> AxResult;
+
+    /// Writes `buf`, which is a page, into the slot.
+    fn write_slot(&self, slot: usize, buf: &[u8]) -> AxResult;
+}
+
+/// A swap partition, i.e., a range of blocks of a block device.
+pub struct SwapPartition {
+    dev: Mutex<AxBlockDevice>,
+    start_block: u64,
+    num_slots: usize,
+    blocks_per_slot: usize,
+}
+
+impl SwapPartition {
+    /// Uses the blocks `[start_block, start_block + num_blocks)` of `dev` as
+    /// the swap partition.
+    pub fn new(dev: AxBlockDevice, start_block: u64, num_blocks: u64) -> AxResult<Self> {
+        let block_size = dev.block_size();
+        if block_size == 0 || PAGE_SIZE_4K % block_size != 0 {
+            return ax_err!(Unsupported, "unsupported block size");
+        }
+        if start_block
+            .checked_add(num_blocks)
+            .map_or(true, |end| end > dev.num_blocks())
+        {
+            return ax_err!(InvalidInput, "partition out of device");
+        }
+        let blocks_per_slot = PAGE_SIZE_4K / block_size;
+        Ok(Self {
+            dev: Mutex::new(dev),
+            start_block,
+            num_slots: num_blocks as usize / blocks_per_slot,
+            blocks_per_slot,
+        })
+    }
+
+    /// Uses the whole `dev` as the swap partition.
+    pub fn whole(dev: AxBlockDevice) -> AxResult<Self> {
+        let num_blocks = dev.num_blocks();
+        Self::new(dev, 0, num_blocks)
+    }
+
+    fn slot_blocks(&self, slot: usize) -> impl Iterator<Item = u64> {
+        let first = self.start_block + (slot * self.blocks_per_slot) as u64;
+        first..first + self.blocks_per_slot as u64
+    }
+}
+
+impl SwapDevice for SwapPartition {
+    fn num_slots(&self) -> usize {
+        self.num_slots
+    }
+
+    fn read_slot(&self, slot: usize, buf: &mut [u8]) -> AxResult {
+        let mut dev = self.dev.lock();
+        let block_size = dev.block_size();
+        for (block, buf) in self.slot_blocks(slot).zip(buf.chunks_mut(block_size)) {
+            dev.read_block(block, buf).map_err(|_| AxError::Io)?;
+        }
+        Ok(())
+    }
+
+    fn write_slot(&self, slot: usize, buf: &[u8]) -> AxResult {
+        let mut dev = self.dev.lock();
+        let block_size = dev.block_size();
+        for (block, buf) in self.slot_blocks(slot).zip(buf.chunks(block_size)) {
+            dev.write_block(block, buf).map_err(|_| AxError::Io)?;
+        }
+        Ok(())
+    }
+}
+
+/// A swap file, e.g., enabled by the `swapon` syscall.
+pub struct SwapFile {
+    file: VfsNodeRef,
+    num_slots: usize,
+}
+
+impl SwapFile {
+    /// Uses `file` as the swap file, which is resized to `size` bytes.
+    pub fn new(file: VfsNodeRef, size: usize) -> AxResult<Self> {
+        file.truncate(size as u64)?;
+        Ok(Self {
+            file,
+            num_slots: size / PAGE_SIZE_4K,
+        })
+    }
+}
+
+impl SwapDevice for SwapFile {
+    fn num_slots(&self) -> usize {
+        self.num_slots
+    }
+
+    fn read_slot(&self, slot: usize, mut buf: &mut [u8]) -> AxResult {
+        let mut offset = (slot * PAGE_SIZE_4K) as u64;
+        while !buf.is_empty() {
+            match self.file.read_at(offset, buf)? {
+                0 => return ax_err!(Io, "swap file truncated"),
+                n => {
+                    offset += n as u64;
+                    buf = &mut buf[n..];
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_slot(&self, slot: usize, mut buf: &[u8]) -> AxResult {
+        let mut offset = (slot * PAGE_SIZE_4K) as u64;
+        while !buf.is_empty() {
+            match self.file.write_at(offset, buf)? {
+                0 => return ax_err!(Io, "failed to write swap file"),
+                n => {
+                    offset += n as u64;
+                    buf = &buf[n..];
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+static SWAP_DEVICE: LazyInit<Box<dyn SwapDevice>> = LazyInit::new();
+
+/// The free slots of the swap device.
+static FREE_SLOTS: SpinNoIrq<Vec<u32>> = SpinNoIrq::new(Vec::new());
+
+/// The user address spaces whose pages may be swapped out.
+static ASPACES: SpinNoIrq<Vec<Weak<Mutex<AddrSpace>>>> = SpinNoIrq::new(Vec::new());
+
+/// The index in [`ASPACES`] of the address space to reclaim pages from next.
+static NEXT_ASPACE: AtomicUsize = AtomicUsize::new(0);
+
+/// The time of the last reclaim, see [`RECLAIM_INTERVAL_NANOS`].
+static LAST_RECLAIM_NANOS: AtomicU64 = AtomicU64::new(0);
+
+/// Enables swapping to `dev`.
+///
+/// Returns an error if swapping is already enabled.
+pub fn swap_on(dev: impl SwapDevice + 'static) -> AxResult {
+    if SWAP_DEVICE.is_inited() {
+        return ax_err!(AlreadyExists, "swap is already enabled");
+    }
+    let num_slots = dev.num_slots().min(u32::MAX as usize);
+    info!("Swap on: {} KiB", num_slots * PAGE_SIZE_4K / 1024);
+    // Slots with lower indices are used first.
+    *FREE_SLOTS.lock() = (0..num_slots as u32).rev().collect();
+    SWAP_DEVICE.init_once(Box::new(dev));
+    Ok(())
+}
+
+/// Returns the total size of the swap device in bytes, or 0 if swapping is
+/// not enabled.
+pub fn swap_total() -> usize {
+    match SWAP_DEVICE.get() {
+        Some(dev) => dev.num_slots().min(u32::MAX as usize) * PAGE_SIZE_4K,
+        None => 0,
+    }
+}
+
+/// Returns the size of the free slots of the swap device in bytes.
+pub fn swap_free() -> usize {
+    FREE_SLOTS.lock().len() * PAGE_SIZE_4K
+}
+
+/// Allows the pages of the user address space to be swapped out.
+pub fn register_aspace(aspace: &Arc<Mutex<AddrSpace>>) {
+    let mut aspaces = ASPACES.lock();
+    aspaces.retain(|aspace| aspace.strong_count() > 0);
+    aspaces.push(Arc::downgrade(aspace));
+}
+
+/// Returns the address in the page table entry of a page swapped out to
+/// `slot`.
+fn slot_entry(slot: usize) -> PhysAddr {
+    PhysAddr::from(SWAP_ENTRY_BASE + slot * PAGE_SIZE_4K)
+}
+
+/// Returns the slot of the swap entry `paddr`, the inverse of [`slot_entry`].
+fn entry_slot(paddr: PhysAddr) -> usize {
+    (paddr.as_usize() - SWAP_ENTRY_BASE) / PAGE_SIZE_4K
+}
+
+/// Whether the address in a page table entry is a swap entry.
+pub(crate) fn is_swap_entry(paddr: PhysAddr) -> bool {
+    paddr.as_usize() >= SWAP_ENTRY_BASE
+}
+
+/// Returns the swap slot of the page at `vaddr`, if it is swapped out.
+pub(crate) fn swap_slot(pt: &PageTable, vaddr: VirtAddr) -> Option<usize> {
+    match pt.query(vaddr) {
+        Ok((paddr, flags, _)) if flags.is_empty() && is_swap_entry(paddr) => {
+            Some(entry_slot(paddr))
+        }
+        _ => None,
+    }
+}
+
+/// Frees the swap slot, after the swapped-out page is unmapped.
+pub(crate) fn free_slot(slot: usize) {
+    FREE_SLOTS.lock().push(slot as u32);
+}
+
+/// Writes the page at `vaddr` mapped to `frame` to a free slot, and frees
+/// the frame.
+fn swap_out(pt: &mut PageTable, vaddr: VirtAddr, frame: PhysAddr) -> bool {
+    let Some(dev) = SWAP_DEVICE.get() else {
+        return false;
+    };
+    let Some(slot) = FREE_SLOTS.lock().pop() else {
+        return false;
+    };
+    let slot = slot as usize;
+    let buf = unsafe { core::slice::from_raw_parts(phys_to_virt(frame).as_ptr(), PAGE_SIZE_4K) };
+    let swapped = dev.write_slot(slot, buf).is_ok()
+        && pt
+            .remap(vaddr, slot_entry(slot), MappingFlags::empty())
+            .map(|(_, tlb)| tlb.flush())
+            .is_ok();
+    if swapped {
+        trace!("swap out {:#x} to slot {}", vaddr, slot);
+        dealloc_frame(frame);
+    } else {
+        free_slot(slot);
+    }
+    swapped
+}
+
+/// Reads the page at `vaddr` back from `slot`, and maps it with `flags`.
+fn swap_in(pt: &mut PageTable, vaddr: VirtAddr, slot: usize, flags: MappingFlags) -> bool {
+    let Some(dev) = SWAP_DEVICE.get() else {
+        return false;
+    };
+    let Some(frame) = alloc_frame(false) else {
+        return false;
+    };
+    let buf =
+        unsafe { core::slice::from_raw_parts_mut(phys_to_virt(frame).as_mut_ptr(), PAGE_SIZE_4K) };
+    if let Err(err) = dev.read_slot(slot, buf) {
+        warn!("failed to swap in {:#x}: {:?}", vaddr, err);
+        dealloc_frame(frame);
+        return false;
+    }
+    if pt
+        .remap(vaddr, frame, flags)
+        .map(|(_, tlb)| tlb.flush())
+        .is_err()
+    {
+        dealloc_frame(frame);
+        return false;
+    }
+    trace!("swap in {:#x} from slot {}", vaddr, slot);
+    free_slot(slot);
+    true
+}
+
+/// Brings back the page at `vaddr` of a lazily allocated area with
+/// `orig_flags`, if it is swapped out or made inaccessible by the clock.
+///
+/// Returns `None` if it is neither, or whether it is brought back.
+pub(crate) fn fault_in(
+    pt: &mut PageTable,
+    vaddr: VirtAddr,
+    orig_flags: MappingFlags,
+) -> Option<bool> {
+    let vaddr = vaddr.align_down_4k();
+    if let Some(slot) = swap_slot(pt, vaddr) {
+        return Some(swap_in(pt, vaddr, slot, orig_flags));
+    }
+    match populated_frame(pt, vaddr) {
+        Some((frame, flags, _)) if flags.is_empty() => {
+            // Pages still shared with copy-on-write stay read-only.
+            let flags = if frame_ref_count(frame) > 1 {
+                orig_flags - MappingFlags::WRITE
+            } else {
+                orig_flags
+            };
+            Some(
+                pt.remap(vaddr, frame, flags)
+                    .map(|(_, tlb)| tlb.flush())
+                    .is_ok(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Swaps in the page at `vaddr` with `flags` if it is swapped out.
+///
+/// Returns `false` if it fails to be swapped in.
+pub(crate) fn ensure_resident(pt: &mut PageTable, vaddr: VirtAddr, flags: MappingFlags) -> bool {
+    match swap_slot(pt, vaddr) {
+        Some(slot) => swap_in(pt, vaddr, slot, flags),
+        None => true,
+    }
+}
+
+/// Visits the page at `vaddr` with the clock hand, returns whether it is
+/// swapped out.
+///
+/// An accessible page is made inaccessible, and an inaccessible one (i.e.,
+/// not accessed since the last visit) is swapped out.
+pub(crate) fn clock_visit(pt: &mut PageTable, vaddr: VirtAddr) -> bool {
+    let Some((frame, flags, page_size)) = populated_frame(pt, vaddr) else {
+        return false;
+    };
+    if page_size.is_huge() || frame_ref_count(frame) > 1 {
+        return false;
+    }
+    if flags.is_empty() {
+        return swap_out(pt, vaddr, frame);
+    }
+    if let Ok((_, tlb)) = pt.remap(vaddr, frame, MappingFlags::empty()) {
+        tlb.flush();
+    }
+    false
+}
+
+/// Swaps out some pages if free memory runs low, at most once per
+/// [`RECLAIM_INTERVAL_NANOS`].
+///
+/// Other registered address spaces are tried first, skipping those in use.
+/// `curr` is the address space locked by the caller.
+pub(crate) fn reclaim_if_low(curr: &mut AddrSpace) {
+    if !SWAP_DEVICE.is_inited() || global_allocator().available_pages() >= LOW_WATERMARK {
+        return;
+    }
+    let now = axhal::time::monotonic_time_nanos();
+    let last = LAST_RECLAIM_NANOS.load(Ordering::Relaxed);
+    // Only one of the concurrent callers reclaims.
+    if now.saturating_sub(last) < RECLAIM_INTERVAL_NANOS
+        || LAST_RECLAIM_NANOS
+            .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+    {
+        return;
+    }
+    let aspaces: Vec<_> = {
+        let mut aspaces = ASPACES.lock();
+        aspaces.retain(|aspace| aspace.strong_count() > 0);
+        let start = NEXT_ASPACE.load(Ordering::Relaxed) % aspaces.len().max(1);
+        let (before, after) = aspaces.split_at(start);
+        after
+            .iter()
+            .chain(before)
+            .filter_map(Weak::upgrade)
+            .collect()
+    };
+    let mut reclaimed = 0;
+    for aspace in &aspaces {
+        NEXT_ASPACE.fetch_add(1, Ordering::Relaxed);
+        if let Some(mut aspace) = aspace.try_lock() {
+            reclaimed += aspace.reclaim(RECLAIM_BATCH - reclaimed);
+        }
+        if reclaimed >= RECLAIM_BATCH {
+            break;
+        }
+    }
+    if reclaimed < RECLAIM_BATCH {
+        reclaimed += curr.reclaim(RECLAIM_BATCH - reclaimed);
+    }
+    debug!("reclaimed {} pages", reclaimed);
+}
+
+#[cfg(test)]
+mod tests {
+    use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeOps, VfsResult};
+
+    use super::*;
+
+    /// An in-memory file that reads and writes at most `MAX_IO` bytes at a
+    /// time.
+    struct ChunkedFile(SpinNoIrq<Vec<u8>>);
+
+    const MAX_IO: usize = 1000;
+
+    impl VfsNodeOps for ChunkedFile {
+        fn truncate(&self, size: u64) -> VfsResult {
+            self.0.lock().resize(size as usize, 0);
+            Ok(())
+        }
+
+        fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+            let content = self.0.lock();
+            let start = content.len().min(offset as usize);
+            let end = content.len().min(start + buf.len().min(MAX_IO));
+            buf[..end - start].copy_from_slice(&content[start..end]);
+            Ok(end - start)
+        }
+
+        fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+            let mut content = self.0.lock();
+            let start = content.len().min(offset as usize);
+            let end = content.len().min(start + buf.len().min(MAX_IO));
+            content[start..end].copy_from_slice(&buf[..end - start]);
+            Ok(end - start)
+        }
+
+        impl_vfs_non_dir_default! {}
+    }
+
+    fn new_swap_file(size: usize) -> (Arc<ChunkedFile>, SwapFile) {
+        let file = Arc::new(ChunkedFile(SpinNoIrq::new(Vec::new())));
+        let swap_file = SwapFile::new(file.clone(), size).unwrap();
+        (file, swap_file)
+    }
+
+    #[test]
+    fn test_slot_entry_round_trip() {
+        for slot in [0, 1, 4096, u32::MAX as usize] {
+            let entry = slot_entry(slot);
+            assert!(is_swap_entry(entry));
+            assert!(entry.is_aligned_4k());
+            assert_eq!(entry_slot(entry), slot);
+        }
+    }
+
+    #[test]
+    fn test_swap_file_slots() {
+        // The partial slot at the end is not used.
+        let (file, swap_file) = new_swap_file(3 * PAGE_SIZE_4K + 100);
+        assert_eq!(file.0.lock().len(), 3 * PAGE_SIZE_4K + 100);
+        assert_eq!(swap_file.num_slots(), 3);
+
+        let page1: Vec<u8> = (0..PAGE_SIZE_4K).map(|i| i as u8).collect();
+        let page2 = [0xa5; PAGE_SIZE_4K];
+        swap_file.write_slot(1, &page1).unwrap();
+        swap_file.write_slot(2, &page2).unwrap();
+
+        let mut buf = [0xff; PAGE_SIZE_4K];
+        swap_file.read_slot(0, &mut buf).unwrap();
+        assert!(buf.iter().all(|&b| b == 0));
+        swap_file.read_slot(1, &mut buf).unwrap();
+        assert_eq!(buf[..], page1[..]);
+        swap_file.read_slot(2, &mut buf).unwrap();
+        assert_eq!(buf, page2);
+        assert_eq!(file.0.lock()[PAGE_SIZE_4K..2 * PAGE_SIZE_4K], page1[..]);
+    }
+
+    #[test]
+    fn test_truncated_swap_file() {
+        let (file, swap_file) = new_swap_file(2 * PAGE_SIZE_4K);
+        file.truncate((PAGE_SIZE_4K + PAGE_SIZE_4K / 2) as u64)
+            .unwrap();
+        let mut buf = [0; PAGE_SIZE_4K];
+        assert!(swap_file.read_slot(0, &mut buf).is_ok());
+        assert_eq!(swap_file.read_slot(1, &mut buf), Err(AxError::Io));
+        assert_eq!(swap_file.write_slot(1, &buf), Err(AxError::Io));
+    }
+}
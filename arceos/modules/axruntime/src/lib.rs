@@ -7,6 +7,7 @@
 //!
 //! - `alloc`: Enable global memory allocator.
 //! - `paging`: Enable page table manipulation support.
+//! - `swap`: Enable swapping out user pages to the second block device.
 //! - `irq`: Enable interrupt handling support.
 //! - `multitask`: Enable multi-threading support.
 //! - `smp`: Enable SMP (symmetric multiprocessing) support.
@@ -153,14 +154,26 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
     #[cfg(feature = "multitask")]
     axtask::init_scheduler();
 
-    #[cfg(any(feature = "fs", feature = "net", feature = "display"))]
+    #[cfg(any(feature = "fs", feature = "net", feature = "display", feature = "swap"))]
     {
         #[allow(unused_variables)]
         let all_devices = axdriver::init_drivers();
 
-        #[cfg(feature = "fs")]
+        #[cfg(all(feature = "fs", not(feature = "swap")))]
         axfs::init_filesystems(all_devices.block);
 
+        #[cfg(feature = "swap")]
+        {
+            // The first block device holds the root filesystem, and the next
+            // one is used for swapping.
+            let mut block_devs = all_devices.block;
+            #[cfg(feature = "fs")]
+            axfs::init_filesystems(axdriver::AxDeviceContainer::from_one(
+                block_devs.take_one().expect("No block device found!"),
+            ));
+            init_swap(block_devs);
+        }
+
         #[cfg(feature = "net")]
         axnet::init_network(all_devices.net);
 
@@ -259,6 +272,23 @@ fn init_allocator() {
     }
 }
 
+/// Enables swapping to the first of `block_devs`, if any.
+///
+/// More than one block device is only supported with the `dyn` feature of
+/// `axdriver`.
+#[cfg(feature = "swap")]
+fn init_swap(mut block_devs: axdriver::AxDeviceContainer<axdriver::AxBlockDevice>) {
+    use axmm::swap::{swap_on, SwapPartition};
+
+    let Some(dev) = block_devs.take_one() else {
+        info!("No swap device found.");
+        return;
+    };
+    if let Err(err) = SwapPartition::whole(dev).and_then(swap_on) {
+        warn!("Failed to enable swap: {:?}", err);
+    }
+}
+
 #[cfg(feature = "irq")]
 fn init_interrupt() {
     use axhal::time::TIMER_IRQ_NUM;
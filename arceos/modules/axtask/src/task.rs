@@ -229,8 +229,10 @@ impl TaskInner {
         matches!(self.state(), TaskState::Ready)
     }
 
+    /// Whether the task is blocked (e.g., waiting on a wait queue or
+    /// sleeping).
     #[inline]
-    pub(crate) fn is_blocked(&self) -> bool {
+    pub fn is_blocked(&self) -> bool {
         matches!(self.state(), TaskState::Blocked)
     }
 
@@ -309,8 +311,15 @@ impl TaskInner {
         self.wait_for_exit.notify_all_locked(false, rq);
     }
 
+    /// Returns a raw pointer to the task context.
+    ///
+    /// # Safety
+    ///
+    /// The context is saved and restored on task switches, so it may only be
+    /// modified by the task itself while it is running (e.g., to change its
+    /// own page table root).
     #[inline]
-    pub(crate) const unsafe fn ctx_mut_ptr(&self) -> *mut TaskContext {
+    pub const unsafe fn ctx_mut_ptr(&self) -> *mut TaskContext {
         self.ctx.get()
     }
 
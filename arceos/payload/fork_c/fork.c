@@ -0,0 +1,59 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <sys/wait.h>
+
+char data[8192] = "parent data";
+
+void check(const char *what, const char *buf, const char *expected)
+{
+    if (strcmp(buf, expected) != 0) {
+        printf("Fork: %s changed to \"%s\"!\n", what, buf);
+        exit(-1);
+    }
+}
+
+int main()
+{
+    char stack[64] = "parent stack";
+    char *heap;
+    pid_t pid;
+    int status;
+
+    printf("Fork ...\n");
+
+    heap = malloc(64);
+    strcpy(heap, "parent heap");
+
+    pid = fork();
+    if (pid < 0) {
+        printf("Fork error!\n");
+        exit(-1);
+    }
+    if (pid == 0) {
+        /* Write in the middle of the pages, not at their starts. */
+        strcpy(data + 100, "child data");
+        strcpy(data, "child data");
+        strcpy(stack, "child stack");
+        strcpy(heap, "child heap");
+        check("child data", data, "child data");
+        exit(0);
+    }
+
+    if (waitpid(pid, &status, 0) != pid || !WIFEXITED(status) || WEXITSTATUS(status) != 0) {
+        printf("Wait child error!\n");
+        exit(-1);
+    }
+    check("data", data, "parent data");
+    check("data", data + 100, "");
+    check("stack", stack, "parent stack");
+    check("heap", heap, "parent heap");
+
+    /* The parent gets its own copy when writing after the child exits. */
+    strcpy(data, "parent again");
+    check("data", data, "parent again");
+
+    printf("Fork ok!\n");
+    return 0;
+}
@@ -0,0 +1,120 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <sys/mman.h>
+
+#define PAGE_SIZE 4096
+
+void fail(const char *msg)
+{
+    printf("%s\n", msg);
+    exit(-1);
+}
+
+void create_file(const char *fname, char c)
+{
+    int fd;
+    char content[PAGE_SIZE];
+
+    memset(content, c, sizeof(content));
+    fd = creat(fname, 0600);
+    if (fd < 0) {
+        fail("Create file error!");
+    }
+    if (write(fd, content, sizeof(content)) != sizeof(content)) {
+        fail("Write file error!");
+    }
+    close(fd);
+}
+
+char *map_file(int fd, int prot, int flags)
+{
+    char *addr = mmap(NULL, PAGE_SIZE, prot, flags, fd, 0);
+    if (addr == MAP_FAILED) {
+        fail("Map file error!");
+    }
+    return addr;
+}
+
+void write_at(int fd, off_t offset, const char *data)
+{
+    if (lseek(fd, offset, SEEK_SET) != offset) {
+        fail("Seek file error!");
+    }
+    if (write(fd, data, strlen(data)) != strlen(data)) {
+        fail("Write file error!");
+    }
+}
+
+void read_at(int fd, off_t offset, char *buf, size_t len)
+{
+    if (lseek(fd, offset, SEEK_SET) != offset) {
+        fail("Seek file error!");
+    }
+    if (read(fd, buf, len) != len) {
+        fail("Read file error!");
+    }
+}
+
+int main()
+{
+    int fd;
+    char *shared, *private, *addr;
+    char buf[16];
+    char fname[] = "coherence_file";
+
+    printf("MapCoherence ...\n");
+
+    create_file(fname, 'a');
+    fd = open(fname, O_RDWR);
+    if (fd < 0) {
+        fail("Open file error!");
+    }
+    shared = map_file(fd, PROT_READ | PROT_WRITE, MAP_SHARED);
+    private = map_file(fd, PROT_READ, MAP_PRIVATE);
+    if (shared[0] != 'a' || private[0] != 'a') {
+        fail("Mapped content error!");
+    }
+
+    /* Writes through the fd are seen by the existing mappings. */
+    write_at(fd, 100, "written");
+    if (memcmp(shared + 100, "written", 7) || memcmp(private + 100, "written", 7)) {
+        fail("Written content is not seen through the mappings!");
+    }
+
+    /* Stores through a shared mapping are seen by read() before msync. */
+    memcpy(shared + 200, "stored", 6);
+    read_at(fd, 200, buf, 6);
+    if (memcmp(buf, "stored", 6)) {
+        fail("Stored content is not seen by read!");
+    }
+
+    /* Truncation zeroes the mapped bytes beyond the end of the file. */
+    if (ftruncate(fd, 150) < 0) {
+        fail("Truncate file error!");
+    }
+    if (memcmp(shared + 100, "written", 7) || shared[200] != 0 || private[200] != 0) {
+        fail("Truncated content error!");
+    }
+    close(fd);
+
+    /* A recreated file is not mapped with the pages of the removed one. */
+    if (unlink(fname) < 0) {
+        fail("Unlink file error!");
+    }
+    create_file(fname, 'b');
+    fd = open(fname, O_RDONLY);
+    if (fd < 0) {
+        fail("Open file error!");
+    }
+    addr = map_file(fd, PROT_READ, MAP_PRIVATE);
+    if (addr[0] != 'b' || shared[0] != 'a') {
+        fail("Recreated content error!");
+    }
+    close(fd);
+
+    printf("MapCoherence ok!\n");
+    return 0;
+}
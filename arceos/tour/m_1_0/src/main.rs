@@ -9,24 +9,19 @@ extern crate axstd as std;
 extern crate axlog;
 
 mod loader;
-mod syscall;
-mod task;
 
 use alloc::sync::Arc;
 use core::result;
 use axhal::arch::UspaceContext;
 use axhal::cpu::current_task_ptr;
-use axhal::mem::VirtAddr;
+use axhal::mem::{VirtAddr, PAGE_SIZE_4K};
 use axhal::paging::MappingFlags;
-use axhal::trap::{register_trap_handler, PAGE_FAULT};
 use axmm::AddrSpace;
 use axstd::io;
 use axsync::Mutex;
-use axtask::TaskExtRef;
 use loader::load_user_app;
 
 const USER_STACK_SIZE: usize = 0x10000;
-const KERNEL_STACK_SIZE: usize = 0x40000; // 256 KiB
 const APP_ENTRY: usize = 0x1000;
 
 #[cfg_attr(feature = "axstd", no_mangle)]
@@ -44,9 +39,11 @@ fn main() {
     ax_println!("New user address space: {:#x?}", uspace);
 
     // Let's kick off the user process.
-    let user_task = task::spawn_user_task(
+    let user_task = axsyscall::task::spawn_user_task(
         Arc::new(Mutex::new(uspace)),
         UspaceContext::new(APP_ENTRY.into(), ustack_top),
+        // The heap starts right after the app page.
+        VirtAddr::from(APP_ENTRY + PAGE_SIZE_4K),
     );
 
     // Wait for user process to exit ...
@@ -72,20 +69,3 @@ fn init_user_stack(uspace: &mut AddrSpace, populating: bool) -> io::Result<VirtA
         .unwrap();
     Ok(ustack_top)
 }
-
-#[register_trap_handler(PAGE_FAULT)]
-fn page_fault_handler(fault_addr: VirtAddr, access_flags: MappingFlags, is_user: bool) -> bool {
-    if is_user {
-        let result=axtask::current().task_ext().aspace.lock().handle_page_fault(fault_addr, access_flags);
-        if result {
-            ax_println!("Page fault handled successfully: {:#x?}", fault_addr);
-        } else {
-            ax_println!("Failed to handle page fault: {:#x?}", fault_addr);
-            // Handle the error (e.g., terminate the task)
-            axtask::exit(-1);
-        }
-        true
-    } else {
-        false
-    }
-}
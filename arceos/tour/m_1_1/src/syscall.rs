@@ -1,23 +0,0 @@
-#![allow(dead_code)]
-
-use axhal::arch::TrapFrame;
-use axhal::trap::{register_trap_handler, SYSCALL};
-use axerrno::LinuxError;
-
-const SYS_EXIT: usize = 93;
-
-#[register_trap_handler(SYSCALL)]
-fn handle_syscall(tf: &TrapFrame, syscall_num: usize) -> isize {
-    ax_println!("handle_syscall ...");
-    let ret = match syscall_num {
-        SYS_EXIT => {
-            ax_println!("[SYS_EXIT]: process is exiting ..");
-            axtask::exit(tf.arg0() as _)
-        },
-        _ => {
-            ax_println!("Unimplemented syscall: {}", syscall_num);
-            -LinuxError::ENOSYS.code() as _
-        }
-    };
-    ret
-}
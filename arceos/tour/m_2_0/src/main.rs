@@ -8,23 +8,18 @@ extern crate alloc;
 #[macro_use]
 extern crate axlog;
 
-mod task;
-mod syscall;
 mod loader;
 
 use axstd::io;
 use axhal::paging::MappingFlags;
 use axhal::arch::UspaceContext;
-use axhal::mem::VirtAddr;
+use axhal::mem::{VirtAddr, PAGE_SIZE_4K};
 use axsync::Mutex;
 use alloc::sync::Arc;
 use axmm::AddrSpace;
 use loader::load_user_app;
-use axtask::TaskExtRef;
-use axhal::trap::{register_trap_handler, PAGE_FAULT};
 
 const USER_STACK_SIZE: usize = 0x10000;
-const KERNEL_STACK_SIZE: usize = 0x40000; // 256 KiB
 const APP_ENTRY: usize = 0x1000;
 
 #[cfg_attr(feature = "axstd", no_mangle)]
@@ -42,9 +37,11 @@ fn main() {
     ax_println!("New user address space: {:#x?}", uspace);
 
     // Let's kick off the user process.
-    let user_task = task::spawn_user_task(
+    let user_task = axsyscall::task::spawn_user_task(
         Arc::new(Mutex::new(uspace)),
         UspaceContext::new(APP_ENTRY.into(), ustack_top),
+        // The heap starts right after the app page.
+        VirtAddr::from(APP_ENTRY + PAGE_SIZE_4K),
     );
 
     // Wait for user process to exit ...
@@ -67,23 +64,3 @@ fn init_user_stack(uspace: &mut AddrSpace, populating: bool) -> io::Result<VirtA
     ).unwrap();
     Ok(ustack_top)
 }
-
-#[register_trap_handler(PAGE_FAULT)]
-fn handle_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool) -> bool {
-    if is_user {
-        if !axtask::current()
-            .task_ext()
-            .aspace
-            .lock()
-            .handle_page_fault(vaddr, access_flags)
-        {
-            ax_println!("{}: segmentation fault, exit!", axtask::current().id_name());
-            axtask::exit(-1);
-        } else {
-            ax_println!("{}: handle page fault OK!", axtask::current().id_name());
-        }
-        true
-    } else {
-        false
-    }
-}